@@ -0,0 +1,436 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    error::Error,
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+/*
+ * A small bakery: pies know when they were baked and how long they stay fresh, the inventory keeps them in stock.
+ *
+ * Whether a pie is still fresh depends on the current time. Instead of calling SystemTime::now() everywhere,
+ * the time comes from a Clock. SystemClock is the real one, FakeClock only moves when told to,
+ * so the smart pointers lesson (or a test) can fast-forward a few days without waiting.
+ */
+
+pub trait Clock {
+    fn now(&self) -> SystemTime;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+// Clones share the same time, so a clone kept outside the inventory can move the inventory's clock
+#[derive(Clone)]
+pub struct FakeClock {
+    now: Arc<Mutex<SystemTime>>,
+}
+
+impl FakeClock {
+    pub fn new(start: SystemTime) -> Self {
+        FakeClock {
+            now: Arc::new(Mutex::new(start)),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().expect("clock poisoned") += duration;
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().expect("clock poisoned")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pie {
+    pub name: String,
+    pub baked_at: SystemTime,
+    pub shelf_life: Duration,
+}
+
+impl Pie {
+    // None when the shelf life reaches past what SystemTime can represent: the pie never expires
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        self.baked_at.checked_add(self.shelf_life)
+    }
+
+    // None once the pie has expired
+    pub fn remaining_life(&self, now: SystemTime) -> Option<Duration> {
+        let Some(expires_at) = self.expires_at() else {
+            return Some(Duration::MAX);
+        };
+        expires_at
+            .duration_since(now)
+            .ok()
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    pub fn is_fresh(&self, now: SystemTime) -> bool {
+        self.remaining_life(now).is_some()
+    }
+
+    pub fn eat(&self, clock: &dyn Clock) -> Result<(), NotFreshError> {
+        let now = clock.now();
+        if self.is_fresh(now) {
+            return Ok(());
+        }
+
+        Err(self.not_fresh(now))
+    }
+
+    fn not_fresh(&self, now: SystemTime) -> NotFreshError {
+        NotFreshError {
+            name: self.name.clone(),
+            expired_for: self
+                .expires_at()
+                .and_then(|expires_at| now.duration_since(expires_at).ok())
+                .unwrap_or_default(),
+        }
+    }
+
+    // Sorts by expiry, soonest first and pies that never expire last
+    fn expiry_key(&self) -> (bool, Option<SystemTime>) {
+        let expires_at = self.expires_at();
+        (expires_at.is_none(), expires_at)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotFreshError {
+    pub name: String,
+    pub expired_for: Duration,
+}
+
+impl fmt::Display for NotFreshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "This {} pie is not fresh, it expired {} hours ago",
+            self.name,
+            self.expired_for.as_secs() / 3600
+        )
+    }
+}
+
+impl Error for NotFreshError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BakeryError {
+    OutOfStock { name: String },
+    NotFresh(NotFreshError),
+}
+
+impl fmt::Display for BakeryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BakeryError::OutOfStock { name } => write!(f, "No {} pie left in stock", name),
+            BakeryError::NotFresh(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for BakeryError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        // NotFresh is transparent: it already prints the wrapped error, so it must not list it again as its source
+        match self {
+            BakeryError::NotFresh(e) => e.source(),
+            BakeryError::OutOfStock { .. } => None,
+        }
+    }
+}
+
+impl From<NotFreshError> for BakeryError {
+    fn from(error: NotFreshError) -> Self {
+        BakeryError::NotFresh(error)
+    }
+}
+
+pub struct Inventory<C: Clock = SystemClock> {
+    clock: C,
+    stock: BTreeMap<String, VecDeque<Pie>>, // Per kind of pie, the one that expires first at the front
+}
+
+impl<C: Clock> Inventory<C> {
+    pub fn new(clock: C) -> Self {
+        Inventory {
+            clock,
+            stock: BTreeMap::new(),
+        }
+    }
+
+    pub fn bake(&mut self, name: &str, shelf_life: Duration) {
+        let pie = Pie {
+            name: name.to_string(),
+            baked_at: self.clock.now(),
+            shelf_life,
+        };
+        self.add(pie);
+    }
+
+    pub fn add(&mut self, pie: Pie) {
+        let pies = self.stock.entry(pie.name.clone()).or_default();
+        // Sorted by expiry rather than age, a pie with a long shelf life may be baked earlier and still last longer
+        let position = pies.partition_point(|other| other.expiry_key() <= pie.expiry_key());
+        pies.insert(position, pie);
+    }
+
+    pub fn count(&self, name: &str) -> usize {
+        self.stock.get(name).map_or(0, VecDeque::len)
+    }
+
+    pub fn total(&self) -> usize {
+        self.stock.values().map(VecDeque::len).sum()
+    }
+
+    // Takes the pie of that kind that expires first out of stock, whether it is still fresh or not
+    pub fn take(&mut self, name: &str) -> Result<Pie, BakeryError> {
+        self.stock
+            .get_mut(name)
+            .and_then(VecDeque::pop_front)
+            .ok_or_else(|| BakeryError::OutOfStock {
+                name: name.to_string(),
+            })
+    }
+
+    // Eats the fresh pie of that kind that expires first. Expired pies in front of it are thrown away on the way,
+    // and only when none of them is fresh does it fail, with the pie that expired last
+    pub fn eat(&mut self, name: &str) -> Result<Pie, BakeryError> {
+        let now = self.clock.now();
+        let mut stale = None;
+        while let Ok(pie) = self.take(name) {
+            if pie.is_fresh(now) {
+                return Ok(pie);
+            }
+            stale = Some(pie);
+        }
+        match stale {
+            Some(pie) => Err(pie.not_fresh(now).into()),
+            None => Err(BakeryError::OutOfStock {
+                name: name.to_string(),
+            }),
+        }
+    }
+
+    // Throws away every expired pie and returns them
+    pub fn remove_expired(&mut self) -> Vec<Pie> {
+        let now = self.clock.now();
+        let mut expired = Vec::new();
+        for pies in self.stock.values_mut() {
+            let (fresh, stale): (VecDeque<Pie>, VecDeque<Pie>) =
+                pies.drain(..).partition(|pie| pie.is_fresh(now));
+            *pies = fresh;
+            expired.extend(stale);
+        }
+        self.stock.retain(|_, pies| !pies.is_empty());
+        expired
+    }
+
+    // Fresh pies that expire within `within`, soonest first
+    pub fn expiring_within(&self, within: Duration) -> Vec<(&Pie, Duration)> {
+        let now = self.clock.now();
+        let mut expiring: Vec<(&Pie, Duration)> = self
+            .stock
+            .values()
+            .flatten()
+            .filter_map(|pie| pie.remaining_life(now).map(|remaining| (pie, remaining)))
+            .filter(|(_, remaining)| *remaining <= within)
+            .collect();
+        expiring.sort_by_key(|(_, remaining)| *remaining);
+        expiring
+    }
+
+    pub fn report(&self) -> InventoryReport {
+        let now = self.clock.now();
+        let lines = self
+            .stock
+            .iter()
+            .map(|(name, pies)| {
+                let fresh = pies.iter().filter(|pie| pie.is_fresh(now)).count();
+                (name.clone(), fresh, pies.len() - fresh)
+            })
+            .collect();
+        InventoryReport { lines }
+    }
+}
+
+pub struct InventoryReport {
+    pub lines: Vec<(String, usize, usize)>, // (name, fresh, expired)
+}
+
+impl fmt::Display for InventoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, fresh, expired) in &self.lines {
+            writeln!(f, "{:>10}: {} fresh, {} expired", name, fresh, expired)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    fn bakery() -> (Inventory<FakeClock>, FakeClock) {
+        let clock = FakeClock::new(SystemTime::UNIX_EPOCH + 1000 * HOUR);
+        let mut inventory = Inventory::new(clock.clone());
+        inventory.bake("apple", 24 * HOUR);
+        clock.advance(HOUR);
+        inventory.bake("apple", 48 * HOUR);
+        inventory.bake("cherry", 2 * HOUR);
+        (inventory, clock)
+    }
+
+    #[test]
+    fn eat_takes_the_pie_that_expires_first() {
+        let (mut inventory, _) = bakery();
+        let first = inventory.eat("apple").unwrap();
+        assert_eq!(first.shelf_life, 24 * HOUR);
+        assert_eq!(inventory.count("apple"), 1);
+        assert_eq!(inventory.eat("apple").unwrap().shelf_life, 48 * HOUR);
+        assert_eq!(
+            inventory.eat("apple"),
+            Err(BakeryError::OutOfStock {
+                name: "apple".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn an_expired_pie_is_not_eaten_but_still_taken() {
+        let (mut inventory, clock) = bakery();
+        clock.advance(5 * HOUR);
+        let err = inventory.eat("cherry").unwrap_err();
+        assert_eq!(
+            err,
+            BakeryError::NotFresh(NotFreshError {
+                name: "cherry".to_string(),
+                expired_for: 3 * HOUR
+            })
+        );
+        assert_eq!(
+            err.to_string(),
+            "This cherry pie is not fresh, it expired 3 hours ago"
+        );
+        assert!(err.source().is_none());
+        assert_eq!(inventory.count("cherry"), 0);
+    }
+
+    #[test]
+    fn a_pie_expires_exactly_at_the_end_of_its_shelf_life() {
+        let clock = FakeClock::new(SystemTime::UNIX_EPOCH);
+        let pie = Pie {
+            name: "pecan".to_string(),
+            baked_at: clock.now(),
+            shelf_life: HOUR,
+        };
+        clock.advance(HOUR - Duration::from_secs(1));
+        assert!(pie.eat(&clock).is_ok());
+        clock.advance(Duration::from_secs(1));
+        assert!(pie.eat(&clock).is_err());
+        assert_eq!(pie.remaining_life(clock.now()), None);
+    }
+
+    #[test]
+    fn expiring_within_lists_fresh_pies_soonest_first() {
+        let (inventory, clock) = bakery();
+        let names = |within| -> Vec<(String, Duration)> {
+            inventory
+                .expiring_within(within)
+                .into_iter()
+                .map(|(pie, remaining)| (pie.name.clone(), remaining))
+                .collect()
+        };
+        assert_eq!(names(HOUR), []);
+        assert_eq!(
+            names(24 * HOUR),
+            [
+                ("cherry".to_string(), 2 * HOUR),
+                ("apple".to_string(), 23 * HOUR)
+            ]
+        );
+
+        clock.advance(3 * HOUR); // The cherry pie expired and is not listed any more
+        assert_eq!(names(48 * HOUR).len(), 2);
+        assert_eq!(names(48 * HOUR)[0], ("apple".to_string(), 20 * HOUR));
+    }
+
+    #[test]
+    fn remove_expired_only_throws_away_stale_pies() {
+        let (mut inventory, clock) = bakery();
+        assert!(inventory.remove_expired().is_empty());
+
+        clock.advance(30 * HOUR);
+        let mut expired: Vec<String> = inventory
+            .remove_expired()
+            .into_iter()
+            .map(|pie| pie.name)
+            .collect();
+        expired.sort();
+        assert_eq!(expired, ["apple", "cherry"]);
+        assert_eq!(inventory.total(), 1);
+        assert_eq!(inventory.count("cherry"), 0);
+        assert_eq!(inventory.report().lines, [("apple".to_string(), 1, 0)]);
+    }
+
+    #[test]
+    fn stock_is_ordered_by_expiry_not_age() {
+        let (mut inventory, clock) = bakery();
+        // Baked before every other apple pie, but lasts longer than both
+        inventory.add(Pie {
+            name: "apple".to_string(),
+            baked_at: clock.now() - 10 * HOUR,
+            shelf_life: 100 * HOUR,
+        });
+        // Baked last, but expires first
+        inventory.bake("apple", HOUR);
+        let order: Vec<Duration> = std::iter::from_fn(|| inventory.take("apple").ok())
+            .map(|pie| pie.shelf_life)
+            .collect();
+        assert_eq!(order, [HOUR, 24 * HOUR, 48 * HOUR, 100 * HOUR]);
+    }
+
+    #[test]
+    fn eat_throws_away_expired_pies_and_finds_a_fresh_one() {
+        let (mut inventory, clock) = bakery();
+        clock.advance(30 * HOUR); // The 24 hour apple pie expired, the 48 hour one is fresh
+        assert_eq!(inventory.eat("apple").unwrap().shelf_life, 48 * HOUR);
+        assert_eq!(inventory.count("apple"), 0);
+
+        inventory.bake("cherry", HOUR);
+        clock.advance(2 * HOUR);
+        // Neither cherry pie is fresh: the error is about the one that expired last
+        assert_eq!(
+            inventory.eat("cherry"),
+            Err(BakeryError::NotFresh(NotFreshError {
+                name: "cherry".to_string(),
+                expired_for: HOUR
+            }))
+        );
+        assert_eq!(inventory.count("cherry"), 0);
+    }
+
+    #[test]
+    fn a_shelf_life_past_the_end_of_time_never_expires() {
+        let (mut inventory, clock) = bakery();
+        inventory.bake("fruitcake", Duration::MAX);
+        inventory.bake("fruitcake", HOUR);
+        assert_eq!(inventory.take("fruitcake").unwrap().shelf_life, HOUR);
+
+        clock.advance(100_000 * HOUR);
+        let pie = inventory.eat("fruitcake").unwrap();
+        assert_eq!(pie.expires_at(), None);
+        assert_eq!(pie.remaining_life(clock.now()), Some(Duration::MAX));
+        assert!(pie.eat(&clock).is_ok());
+    }
+}
@@ -0,0 +1,244 @@
+use std::{error::Error, fmt};
+
+/*
+ * Safe decoding of numbers from raw bytes.
+ *
+ * Casting a *const u8 to *const f32 and dereferencing it is undefined behavior whenever the bytes are not 4-byte aligned,
+ * and the result silently depends on the endianness of the machine. The from_le_bytes / from_be_bytes constructors
+ * of the primitive types copy the bytes instead, so alignment does not matter and the byte order is explicit.
+ *
+ * Decode wraps those constructors for every numeric type, and ByteCursor walks a byte slice with bounds-checked reads.
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+impl Endian {
+    pub const NATIVE: Endian = if cfg!(target_endian = "little") {
+        Endian::Little
+    } else {
+        Endian::Big
+    };
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    UnexpectedEnd {
+        position: usize,
+        needed: usize,
+        available: usize,
+    },
+    LengthMismatch {
+        expected: usize,
+        actual: usize,
+    },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnexpectedEnd {
+                position,
+                needed,
+                available,
+            } => write!(
+                f,
+                "unexpected end of input at byte {}: needed {} bytes but only {} left",
+                position, needed, available
+            ),
+            DecodeError::LengthMismatch { expected, actual } => {
+                write!(f, "expected exactly {} bytes, got {}", expected, actual)
+            }
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+pub trait Decode: Sized {
+    const SIZE: usize;
+
+    // `bytes` must be exactly SIZE long
+    fn decode(bytes: &[u8], endian: Endian) -> Result<Self, DecodeError>;
+}
+
+macro_rules! impl_decode {
+    ($($t:ty),*) => {
+        $(
+            impl Decode for $t {
+                const SIZE: usize = std::mem::size_of::<$t>();
+
+                fn decode(bytes: &[u8], endian: Endian) -> Result<Self, DecodeError> {
+                    let array: [u8; std::mem::size_of::<$t>()] =
+                        bytes.try_into().map_err(|_| DecodeError::LengthMismatch {
+                            expected: Self::SIZE,
+                            actual: bytes.len(),
+                        })?;
+                    Ok(match endian {
+                        Endian::Little => <$t>::from_le_bytes(array),
+                        Endian::Big => <$t>::from_be_bytes(array),
+                    })
+                }
+            }
+        )*
+    };
+}
+
+impl_decode!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+pub fn from_le_bytes<T: Decode>(bytes: &[u8]) -> Result<T, DecodeError> {
+    T::decode(bytes, Endian::Little)
+}
+
+pub fn from_be_bytes<T: Decode>(bytes: &[u8]) -> Result<T, DecodeError> {
+    T::decode(bytes, Endian::Big)
+}
+
+// Reads values one after another from a byte slice, never past its end
+pub struct ByteCursor<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    endian: Endian,
+}
+
+impl<'a> ByteCursor<'a> {
+    pub fn new(bytes: &'a [u8], endian: Endian) -> Self {
+        ByteCursor {
+            bytes,
+            position: 0,
+            endian,
+        }
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.bytes.len() - self.position
+    }
+
+    pub fn set_endian(&mut self, endian: Endian) {
+        self.endian = endian;
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if len > self.remaining() {
+            return Err(DecodeError::UnexpectedEnd {
+                position: self.position,
+                needed: len,
+                available: self.remaining(),
+            });
+        }
+        let bytes = &self.bytes[self.position..self.position + len];
+        self.position += len;
+        Ok(bytes)
+    }
+
+    pub fn skip(&mut self, len: usize) -> Result<(), DecodeError> {
+        self.read_bytes(len).map(|_| ())
+    }
+
+    // Reads with the cursor's endianness
+    pub fn read<T: Decode>(&mut self) -> Result<T, DecodeError> {
+        self.read_with(self.endian)
+    }
+
+    // Reads with an explicit endianness, e.g. a big-endian field in an otherwise little-endian format
+    pub fn read_with<T: Decode>(&mut self, endian: Endian) -> Result<T, DecodeError> {
+        let bytes = self.read_bytes(T::SIZE)?;
+        T::decode(bytes, endian)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_both_byte_orders() {
+        assert_eq!(
+            from_le_bytes::<u32>(&[0x78, 0x56, 0x34, 0x12]),
+            Ok(0x12345678)
+        );
+        assert_eq!(
+            from_be_bytes::<u32>(&[0x12, 0x34, 0x56, 0x78]),
+            Ok(0x12345678)
+        );
+        assert_eq!(from_be_bytes::<i16>(&[0xff, 0xfe]), Ok(-2));
+        assert_eq!(from_le_bytes::<f32>(&1.5f32.to_le_bytes()), Ok(1.5));
+        assert_eq!(from_be_bytes::<f64>(&(-0.25f64).to_be_bytes()), Ok(-0.25));
+        assert_eq!(
+            u64::decode(&7u64.to_ne_bytes(), Endian::NATIVE),
+            Ok(7),
+            "native order round trips"
+        );
+    }
+
+    #[test]
+    fn the_length_must_match_exactly() {
+        assert_eq!(
+            from_le_bytes::<u32>(&[1, 2, 3]),
+            Err(DecodeError::LengthMismatch {
+                expected: 4,
+                actual: 3
+            })
+        );
+        assert_eq!(
+            from_le_bytes::<u16>(&[1, 2, 3]),
+            Err(DecodeError::LengthMismatch {
+                expected: 2,
+                actual: 3
+            })
+        );
+    }
+
+    #[test]
+    fn decoding_does_not_care_about_alignment() {
+        let bytes = [0u8, 1, 0, 0, 0, 0, 0, 0, 0];
+        // Starting at an odd address, where dereferencing a *const u64 would be undefined behavior
+        assert_eq!(from_le_bytes::<u64>(&bytes[1..]), Ok(1));
+    }
+
+    #[test]
+    fn the_cursor_reads_in_order_and_stops_at_the_end() {
+        let bytes = [0xAB, 0x01, 0x00, 0x00, 0x02, 0xFF];
+        let mut cursor = ByteCursor::new(&bytes, Endian::Little);
+        assert_eq!(cursor.read::<u8>(), Ok(0xAB));
+        assert_eq!(cursor.read::<u16>(), Ok(1));
+        cursor.set_endian(Endian::Big);
+        assert_eq!(cursor.read::<u16>(), Ok(2));
+        assert_eq!(cursor.position(), 5);
+        assert_eq!(cursor.remaining(), 1);
+
+        assert_eq!(
+            cursor.read_with::<u32>(Endian::Little),
+            Err(DecodeError::UnexpectedEnd {
+                position: 5,
+                needed: 4,
+                available: 1
+            })
+        );
+        // A failed read consumes nothing
+        assert_eq!(cursor.position(), 5);
+        assert_eq!(cursor.read_bytes(1), Ok(&[0xFF][..]));
+        assert!(cursor.skip(1).is_err());
+        assert_eq!(cursor.skip(0), Ok(()));
+    }
+
+    #[test]
+    fn errors_say_where_and_how_much() {
+        let error = DecodeError::UnexpectedEnd {
+            position: 5,
+            needed: 4,
+            available: 1,
+        };
+        assert_eq!(
+            error.to_string(),
+            "unexpected end of input at byte 5: needed 4 bytes but only 1 left"
+        );
+    }
+}
@@ -0,0 +1,673 @@
+use crate::json_schema::{Schema, ValidationError};
+use crate::url::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeSet, HashMap},
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+/*
+ * The favorite websites of the hashmap lesson, grown into a bookmark manager that is saved to a file.
+ *
+ * Bookmarks are kept in a HashMap by name, like fav_websites, so a name is unique. Each one can carry tags
+ * and sit in a folder, written as a path like ["Dev", "Rust"]. A HashMap iterates in a random order,
+ * so everything that lists bookmarks (list, search, both export formats) sorts them by folder, then name.
+ *
+ * The store is saved as a JSON list of bookmarks, checked against Bookmark::list_schema() whenever one is read. It also imports and exports the Netscape bookmark file
+ * format, the HTML every browser can export to and import from. Imported URLs are normalized (see url.rs), so the
+ * same site is stored under one spelling, except the ones Url cannot parse, like place: or javascript: links:
+ *
+ *   <DT><H3>Dev</H3>
+ *   <DL><p>
+ *       <DT><A HREF="https://www.rust-lang.org" ADD_DATE="1700000000" TAGS="rust,lang">Rust</A>
+ *   </DL><p>
+ */
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub name: String,
+    pub url: String,
+    #[serde(default)]
+    pub tags: BTreeSet<String>,
+    #[serde(default)]
+    pub folder: Vec<String>, // Empty for the top level
+    #[serde(default)]
+    pub added: Option<u64>, // Seconds since the Unix epoch
+}
+
+impl Bookmark {
+    // A bookmarks file: a JSON array of bookmarks, as written by BookmarkStore::to_json()
+    pub fn list_schema() -> Schema {
+        Schema::compile(&json!({
+            "type": "array",
+            "items": {
+                "type": "object",
+                "required": ["name", "url"],
+                "properties": {
+                    "name": { "type": "string", "minLength": 1 },
+                    "url": { "type": "string", "minLength": 1 },
+                    "tags": { "type": "array", "items": { "type": "string", "minLength": 1 } },
+                    "folder": { "type": "array", "items": { "type": "string", "minLength": 1 } },
+                    "added": { "type": ["integer", "null"], "minimum": 0 }
+                },
+                "additionalProperties": false
+            }
+        }))
+        .expect("the bookmark schema is valid")
+    }
+
+    pub fn new(name: impl Into<String>, url: impl Into<String>) -> Bookmark {
+        Bookmark {
+            name: name.into(),
+            url: url.into(),
+            tags: BTreeSet::new(),
+            folder: Vec::new(),
+            added: None,
+        }
+    }
+
+    pub fn tagged(mut self, tag: &str) -> Bookmark {
+        self.tags.insert(tag.to_string());
+        self
+    }
+
+    // "Dev/Rust" puts the bookmark into the Rust folder inside the Dev folder
+    pub fn in_folder(mut self, folder: &str) -> Bookmark {
+        self.folder = parse_folder(folder);
+        self
+    }
+
+    pub fn added_at(mut self, seconds: u64) -> Bookmark {
+        self.added = Some(seconds);
+        self
+    }
+
+    fn matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        self.name.to_lowercase().contains(&query)
+            || self.url.to_lowercase().contains(&query)
+            || self.tags.iter().any(|tag| tag.to_lowercase() == query)
+    }
+
+    // Folder first, so the bookmarks of a folder end up next to each other. Each folder sorts case-insensitively,
+    // then by its exact name, so "Dev" and "dev" are two folders side by side instead of one mixed up one
+    fn sort_key(&self) -> (Vec<(String, &str)>, String, &str) {
+        let folder = self
+            .folder
+            .iter()
+            .map(|f| (f.to_lowercase(), f.as_str()))
+            .collect();
+        (folder, self.name.to_lowercase(), &self.name)
+    }
+}
+
+impl fmt::Display for Bookmark {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.folder.is_empty() {
+            write!(f, "{}/", self.folder.join("/"))?;
+        }
+        write!(f, "{} -> {}", self.name, self.url)?;
+        if !self.tags.is_empty() {
+            let tags: Vec<&str> = self.tags.iter().map(String::as_str).collect();
+            write!(f, " [{}]", tags.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+pub fn parse_folder(folder: &str) -> Vec<String> {
+    folder
+        .split('/')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[derive(Debug)]
+pub enum BookmarkError {
+    AlreadyExists(String),
+    NotFound(String),
+    Io { path: PathBuf, source: io::Error },
+    Json(serde_json::Error),
+    Invalid(ValidationError), // Valid JSON, but not a list of bookmarks
+    Html { line: usize, message: String },
+}
+
+impl fmt::Display for BookmarkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BookmarkError::AlreadyExists(name) => {
+                write!(f, "There already is a bookmark {:?}", name)
+            }
+            BookmarkError::NotFound(name) => write!(f, "There is no bookmark {:?}", name),
+            BookmarkError::Io { path, .. } => write!(f, "Failed to access {}", path.display()),
+            BookmarkError::Json(_) => write!(f, "Invalid bookmark JSON"),
+            BookmarkError::Invalid(_) => write!(f, "The JSON is not a list of bookmarks"),
+            BookmarkError::Html { line, message } => {
+                write!(f, "Invalid bookmark HTML on line {}: {}", line, message)
+            }
+        }
+    }
+}
+
+impl Error for BookmarkError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BookmarkError::Io { source, .. } => Some(source),
+            BookmarkError::Json(source) => Some(source),
+            BookmarkError::Invalid(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ImportSummary {
+    pub added: usize,
+    pub replaced: usize, // Had the name of a bookmark already in the store
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BookmarkStore {
+    bookmarks: HashMap<String, Bookmark>,
+}
+
+impl BookmarkStore {
+    // A file that does not exist yet is an empty store, it is created by the first save()
+    pub fn open(path: &Path) -> Result<BookmarkStore, BookmarkError> {
+        match fs::read_to_string(path) {
+            Ok(text) => BookmarkStore::from_json(&text),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BookmarkStore::default()),
+            Err(source) => Err(BookmarkError::Io {
+                path: path.to_path_buf(),
+                source,
+            }),
+        }
+    }
+
+    // Written next to the old file first and then renamed over it, so a crash never leaves half a file
+    pub fn save(&self, path: &Path) -> Result<(), BookmarkError> {
+        let io_error = |source| BookmarkError::Io {
+            path: path.to_path_buf(),
+            source,
+        };
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        fs::write(&temporary, self.to_json()).map_err(io_error)?;
+        fs::rename(&temporary, path).map_err(io_error)
+    }
+
+    pub fn len(&self) -> usize {
+        self.bookmarks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bookmarks.is_empty()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Bookmark> {
+        self.bookmarks.get(name)
+    }
+
+    pub fn add(&mut self, bookmark: Bookmark) -> Result<(), BookmarkError> {
+        if self.bookmarks.contains_key(&bookmark.name) {
+            return Err(BookmarkError::AlreadyExists(bookmark.name));
+        }
+        self.bookmarks.insert(bookmark.name.clone(), bookmark);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<Bookmark, BookmarkError> {
+        self.bookmarks
+            .remove(name)
+            .ok_or_else(|| BookmarkError::NotFound(name.to_string()))
+    }
+
+    pub fn rename(&mut self, name: &str, new_name: &str) -> Result<(), BookmarkError> {
+        if self.bookmarks.contains_key(new_name) {
+            return Err(BookmarkError::AlreadyExists(new_name.to_string()));
+        }
+        let mut bookmark = self.remove(name)?;
+        bookmark.name = new_name.to_string();
+        self.bookmarks.insert(bookmark.name.clone(), bookmark);
+        Ok(())
+    }
+
+    fn get_mut(&mut self, name: &str) -> Result<&mut Bookmark, BookmarkError> {
+        self.bookmarks
+            .get_mut(name)
+            .ok_or_else(|| BookmarkError::NotFound(name.to_string()))
+    }
+
+    pub fn tag(&mut self, name: &str, tag: &str) -> Result<(), BookmarkError> {
+        self.get_mut(name)?.tags.insert(tag.to_string());
+        Ok(())
+    }
+
+    // Removing a tag the bookmark does not have is fine, only the bookmark has to exist
+    pub fn untag(&mut self, name: &str, tag: &str) -> Result<(), BookmarkError> {
+        self.get_mut(name)?.tags.remove(tag);
+        Ok(())
+    }
+
+    pub fn move_to(&mut self, name: &str, folder: &str) -> Result<(), BookmarkError> {
+        self.get_mut(name)?.folder = parse_folder(folder);
+        Ok(())
+    }
+
+    // Sorted by folder, then name
+    pub fn list(&self) -> Vec<&Bookmark> {
+        let mut bookmarks: Vec<&Bookmark> = self.bookmarks.values().collect();
+        bookmarks.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+        bookmarks
+    }
+
+    // Case-insensitive: a part of the name or URL, or a whole tag
+    pub fn search(&self, query: &str) -> Vec<&Bookmark> {
+        self.list()
+            .into_iter()
+            .filter(|bookmark| bookmark.matches(query))
+            .collect()
+    }
+
+    // Every folder in use, parents included
+    pub fn folders(&self) -> BTreeSet<Vec<String>> {
+        self.bookmarks
+            .values()
+            .flat_map(|bookmark| (1..=bookmark.folder.len()).map(|n| bookmark.folder[..n].to_vec()))
+            .collect()
+    }
+
+    pub fn from_json(text: &str) -> Result<BookmarkStore, BookmarkError> {
+        let mut store = BookmarkStore::default();
+        store.import_json(text)?;
+        Ok(store)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.list()).expect("bookmarks are plain data")
+    }
+
+    pub fn import_json(&mut self, text: &str) -> Result<ImportSummary, BookmarkError> {
+        let value: Value = serde_json::from_str(text).map_err(BookmarkError::Json)?;
+        Bookmark::list_schema()
+            .check(&value)
+            .map_err(BookmarkError::Invalid)?;
+        let bookmarks: Vec<Bookmark> =
+            serde_json::from_value(value).map_err(BookmarkError::Json)?;
+        Ok(self.import(bookmarks))
+    }
+
+    pub fn import_html(&mut self, html: &str) -> Result<ImportSummary, BookmarkError> {
+        Ok(self.import(parse_netscape(html)?))
+    }
+
+    fn import(&mut self, bookmarks: Vec<Bookmark>) -> ImportSummary {
+        let mut summary = ImportSummary::default();
+        for mut bookmark in bookmarks {
+            if let Ok(url) = Url::parse(&bookmark.url) {
+                bookmark.url = url.to_string();
+            }
+            match self.bookmarks.insert(bookmark.name.clone(), bookmark) {
+                Some(_) => summary.replaced += 1,
+                None => summary.added += 1,
+            }
+        }
+        summary
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut html = String::from(
+            "<!DOCTYPE NETSCAPE-Bookmark-file-1>\n\
+             <META HTTP-EQUIV=\"Content-Type\" CONTENT=\"text/html; charset=UTF-8\">\n\
+             <TITLE>Bookmarks</TITLE>\n\
+             <H1>Bookmarks</H1>\n\
+             <DL><p>\n",
+        );
+        // The folders that are open at the moment. Sorting by folder means every folder is opened exactly once
+        let mut open: Vec<&String> = Vec::new();
+        for bookmark in self.list() {
+            let shared = open
+                .iter()
+                .zip(&bookmark.folder)
+                .take_while(|(a, b)| **a == *b)
+                .count();
+            while open.len() > shared {
+                open.pop();
+                html.push_str(&format!("{}</DL><p>\n", indent(open.len() + 1)));
+            }
+            for folder in &bookmark.folder[shared..] {
+                let depth = open.len() + 1;
+                html.push_str(&format!(
+                    "{}<DT><H3>{}</H3>\n",
+                    indent(depth),
+                    escape(folder)
+                ));
+                html.push_str(&format!("{}<DL><p>\n", indent(depth)));
+                open.push(folder);
+            }
+
+            html.push_str(&format!(
+                "{}<DT><A HREF=\"{}\"",
+                indent(open.len() + 1),
+                escape(&bookmark.url)
+            ));
+            if let Some(added) = bookmark.added {
+                html.push_str(&format!(" ADD_DATE=\"{added}\""));
+            }
+            if !bookmark.tags.is_empty() {
+                let tags: Vec<&str> = bookmark.tags.iter().map(String::as_str).collect();
+                html.push_str(&format!(" TAGS=\"{}\"", escape(&tags.join(","))));
+            }
+            html.push_str(&format!(">{}</A>\n", escape(&bookmark.name)));
+        }
+        while open.pop().is_some() {
+            html.push_str(&format!("{}</DL><p>\n", indent(open.len() + 1)));
+        }
+        html.push_str("</DL><p>\n");
+        html
+    }
+}
+
+fn indent(depth: usize) -> String {
+    "    ".repeat(depth)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+// The value of NAME="value" in the inside of a tag, names are case-insensitive
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let upper = tag.to_ascii_uppercase();
+    let needle = format!(" {name}=\"");
+    let start = upper.find(&needle)? + needle.len();
+    let end = start + tag[start..].find('"')?;
+    Some(unescape(&tag[start..end]))
+}
+
+// Browsers write this format loosely, so the parser only looks at the H3, A and DL tags and skips the rest
+fn parse_netscape(html: &str) -> Result<Vec<Bookmark>, BookmarkError> {
+    // Same byte offsets as `html`, for finding tags whatever their case
+    let upper = html.to_ascii_uppercase();
+    let line_of = |position: usize| 1 + html[..position].matches('\n').count();
+    // Text up to the closing tag, e.g. the name between <A ...> and </A>
+    let text_until = |from: usize, closing: &str| -> Result<(String, usize), BookmarkError> {
+        let end = upper[from..]
+            .find(closing)
+            .map(|offset| from + offset)
+            .ok_or_else(|| BookmarkError::Html {
+                line: line_of(from),
+                message: format!("missing {closing}"),
+            })?;
+        Ok((unescape(html[from..end].trim()), end + closing.len()))
+    };
+
+    let mut bookmarks = Vec::new();
+    let mut folder: Vec<String> = Vec::new();
+    // One entry per open <DL>: whether it opened a folder (the outermost list does not)
+    let mut lists: Vec<bool> = Vec::new();
+    let mut pending_folder = None;
+    let mut position = 0;
+
+    while let Some(offset) = html[position..].find('<') {
+        let start = position + offset;
+        let end = start
+            + html[start..].find('>').ok_or_else(|| BookmarkError::Html {
+                line: line_of(start),
+                message: "a tag is never closed".to_string(),
+            })?;
+        let tag = &html[start + 1..end];
+        let name = tag
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_ascii_uppercase();
+        position = end + 1;
+
+        match name.as_str() {
+            "H3" => {
+                let (title, next) = text_until(position, "</H3>")?;
+                pending_folder = Some(title);
+                position = next;
+            }
+            "DL" => {
+                let opens_folder = pending_folder.is_some();
+                folder.extend(pending_folder.take());
+                lists.push(opens_folder);
+            }
+            "/DL" => match lists.pop() {
+                Some(true) => {
+                    folder.pop();
+                }
+                Some(false) => {}
+                None => {
+                    return Err(BookmarkError::Html {
+                        line: line_of(start),
+                        message: "</DL> without a <DL>".to_string(),
+                    })
+                }
+            },
+            "A" => {
+                let Some(url) = attribute(tag, "HREF") else {
+                    return Err(BookmarkError::Html {
+                        line: line_of(start),
+                        message: "a link without HREF".to_string(),
+                    });
+                };
+                let (title, next) = text_until(position, "</A>")?;
+                position = next;
+                let mut bookmark = Bookmark::new(title, url);
+                bookmark.folder = folder.clone();
+                bookmark.added = attribute(tag, "ADD_DATE").and_then(|date| date.parse().ok());
+                if let Some(tags) = attribute(tag, "TAGS") {
+                    bookmark.tags = tags
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|tag| !tag.is_empty())
+                        .map(str::to_string)
+                        .collect();
+                }
+                bookmarks.push(bookmark);
+            }
+            _ => {}
+        }
+    }
+    Ok(bookmarks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> BookmarkStore {
+        let mut store = BookmarkStore::default();
+        for bookmark in [
+            Bookmark::new("Rust", "https://www.rust-lang.org/")
+                .in_folder("Dev/Rust")
+                .tagged("lang")
+                .tagged("rust")
+                .added_at(1700000000),
+            Bookmark::new("Tokio", "https://tokio.rs/").in_folder("dev"),
+            Bookmark::new("Crates", "https://crates.io/?q=a&b=c").in_folder("Dev"),
+            Bookmark::new("<News>", "https://news.ycombinator.com/"),
+            Bookmark::new("Docs", "https://docs.rs/").in_folder("Dev"),
+        ] {
+            store.add(bookmark).unwrap();
+        }
+        store
+    }
+
+    #[test]
+    fn list_sorts_by_folder_then_name() {
+        let store = store();
+        let names: Vec<&str> = store.list().iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, ["<News>", "Crates", "Docs", "Rust", "Tokio"]);
+    }
+
+    #[test]
+    fn html_export_opens_every_folder_once() {
+        let html = store().to_html();
+        assert_eq!(html.matches("<H3>Dev</H3>").count(), 1);
+        assert_eq!(html.matches("<H3>dev</H3>").count(), 1);
+        assert_eq!(
+            html.matches("<DL><p>").count(),
+            html.matches("</DL><p>").count()
+        );
+        assert!(html.contains("&lt;News&gt;"));
+        assert!(html.contains("q=a&amp;b=c"));
+    }
+
+    #[test]
+    fn html_export_then_import_round_trips() {
+        let original = store();
+        let mut imported = BookmarkStore::default();
+        let summary = imported.import_html(&original.to_html()).unwrap();
+        assert_eq!(
+            summary,
+            ImportSummary {
+                added: 5,
+                replaced: 0
+            }
+        );
+        assert_eq!(imported.list(), original.list());
+
+        let again = imported.import_html(&original.to_html()).unwrap();
+        assert_eq!(again.replaced, 5);
+    }
+
+    #[test]
+    fn json_round_trips_and_saves_atomically() {
+        let original = store();
+        assert_eq!(
+            BookmarkStore::from_json(&original.to_json())
+                .unwrap()
+                .list(),
+            original.list()
+        );
+
+        let path = std::env::temp_dir().join(format!("bookmarks-test-{}.json", std::process::id()));
+        assert!(BookmarkStore::open(&path).unwrap().is_empty());
+        original.save(&path).unwrap();
+        assert_eq!(BookmarkStore::open(&path).unwrap().list(), original.list());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn bookmark_files_are_checked_against_the_schema() {
+        let invalid = |text: &str| match BookmarkStore::from_json(text) {
+            Err(BookmarkError::Invalid(e)) => e
+                .violations
+                .into_iter()
+                .map(|violation| violation.pointer)
+                .collect::<Vec<_>>(),
+            other => panic!("{text} gave {other:?}"),
+        };
+        assert_eq!(invalid(r#"{"name": "Rust"}"#), [""]);
+        assert_eq!(invalid(r#"[{"name": "Rust"}]"#), ["/0"]);
+        assert_eq!(
+            invalid(r#"[{"name": "", "url": "x", "tags": [1], "added": -1}]"#),
+            ["/0/added", "/0/name", "/0/tags/0"]
+        );
+        assert_eq!(
+            invalid(r#"[{"name": "a", "url": "x", "folders": []}]"#),
+            ["/0/folders"]
+        );
+        assert!(matches!(
+            BookmarkStore::from_json("[{"),
+            Err(BookmarkError::Json(_))
+        ));
+
+        let store = BookmarkStore::from_json(r#"[{"name": "a", "url": "x.org", "added": null}]"#);
+        assert_eq!(store.unwrap().list().len(), 1);
+    }
+
+    #[test]
+    fn browser_exports_are_parsed_loosely() {
+        let html = "<!DOCTYPE NETSCAPE-Bookmark-file-1>\n\
+                    <dl><p>\n\
+                    <dt><h3 ADD_DATE=\"1\">Bar</h3>\n\
+                    <dl><p>\n\
+                    <dt><a href=\"https://a.test/\" add_date=\"12\" tags=\"x, y,\">A &amp; B</a>\n\
+                    </dl><p>\n\
+                    <dt><a href=\"https://b.test/\">B</a>\n\
+                    </dl>\n";
+        let mut store = BookmarkStore::default();
+        store.import_html(html).unwrap();
+        let a = store.get("A & B").unwrap();
+        assert_eq!(a.folder, ["Bar"]);
+        assert_eq!(a.added, Some(12));
+        assert_eq!(a.tags, BTreeSet::from(["x".to_string(), "y".to_string()]));
+        assert!(store.get("B").unwrap().folder.is_empty());
+
+        for (broken, line) in [("<DL>\n<A>x</A>", 2), ("</DL>", 1), ("<A HREF=\"x\">x", 1)] {
+            match BookmarkStore::default().import_html(broken) {
+                Err(BookmarkError::Html { line: found, .. }) => assert_eq!(found, line, "{broken}"),
+                other => panic!("{broken}: {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn imported_urls_are_normalized_unless_they_do_not_parse() {
+        let mut store = BookmarkStore::default();
+        store
+            .import_json(
+                r#"[{"name": "Rust", "url": "HTTPS://www.Rust-Lang.org:443"},
+                    {"name": "Recent", "url": "place:sort=8"}]"#,
+            )
+            .unwrap();
+        store
+            .import_html(r#"<DL><p><DT><A HREF="crates.io/a/../b">Crates</A></DL><p>"#)
+            .unwrap();
+        assert_eq!(store.get("Rust").unwrap().url, "https://www.rust-lang.org/");
+        assert_eq!(store.get("Crates").unwrap().url, "https://crates.io/b");
+        assert_eq!(store.get("Recent").unwrap().url, "place:sort=8");
+    }
+
+    #[test]
+    fn editing_and_searching() {
+        let mut store = store();
+        assert!(matches!(
+            store.add(Bookmark::new("Docs", "https://x.test/")),
+            Err(BookmarkError::AlreadyExists(_))
+        ));
+        store.rename("Docs", "Docs.rs").unwrap();
+        assert!(matches!(
+            store.rename("Docs", "Other"),
+            Err(BookmarkError::NotFound(_))
+        ));
+        store.tag("Tokio", "Async").unwrap();
+        store.untag("Rust", "lang").unwrap();
+        store.move_to("Tokio", " Dev / Async ").unwrap();
+
+        let found: Vec<&str> = store
+            .search("ASYNC")
+            .iter()
+            .map(|b| b.name.as_str())
+            .collect();
+        assert_eq!(found, ["Tokio"]);
+        assert_eq!(store.search("rust-lang").len(), 1);
+        assert!(store.search("asy").is_empty()); // Tags match whole
+        assert!(store
+            .folders()
+            .contains(&vec!["Dev".to_string(), "Async".to_string()]));
+        assert_eq!(store.remove("Docs.rs").unwrap().url, "https://docs.rs/");
+        assert_eq!(store.len(), 4);
+    }
+}
@@ -0,0 +1,588 @@
+use crate::bakery::{Clock, SystemClock};
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    fmt,
+    hash::Hash,
+    time::{Duration, SystemTime},
+};
+
+/*
+ * A HashMap that forgets: a cache with a capacity, evicting the least recently used entry (LRU) when full,
+ * and entries that expire after their time to live (TTL).
+ *
+ * The HashMap finds the entry of a key, but cannot tell which entry was used least recently. So the entries also
+ * form a doubly linked list, most recently used first. The list is not made of pointers: entries live in a Vec,
+ * and prev/next are indices into it, so moving an entry to the front or unlinking it is O(1) with no unsafe code.
+ * Slots of removed entries are reused by the next insert.
+ *
+ * Capacity is either a number of entries, or a total weight measured by a function, e.g. the bytes of the values.
+ * Counting entries is the same as every entry weighing 1. An entry heavier than the whole capacity is still stored,
+ * alone, after every other entry was evicted.
+ *
+ * Expired entries are removed lazily, when looked up or by purge_expired(), and count towards the capacity until then.
+ * Time comes from a Clock (see bakery.rs), so a FakeClock can make an hour pass in a lesson.
+ */
+
+#[derive(Debug, Clone, Copy)]
+pub enum Capacity<K, V> {
+    Entries(usize),
+    Weight {
+        max: usize,
+        weigher: fn(&K, &V) -> usize,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub inserts: u64,
+    pub evictions: u64,   // Removed to make room
+    pub expirations: u64, // Removed because their TTL ran out
+}
+
+impl CacheStats {
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            lookups => self.hits as f64 / lookups as f64,
+        }
+    }
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} hits, {} misses ({:.1}% hit rate), {} inserts, {} evicted, {} expired",
+            self.hits,
+            self.misses,
+            self.hit_rate() * 100.0,
+            self.inserts,
+            self.evictions,
+            self.expirations
+        )
+    }
+}
+
+struct Slot<K, V> {
+    key: K,
+    value: V,
+    weight: usize,
+    expires: Option<SystemTime>,
+    prev: Option<usize>, // Used more recently
+    next: Option<usize>, // Used less recently
+}
+
+pub struct Cache<K, V, C: Clock = SystemClock> {
+    map: HashMap<K, usize>, // Key to index in `slots`
+    slots: Vec<Option<Slot<K, V>>>,
+    free: Vec<usize>,    // Indices of empty slots
+    head: Option<usize>, // Most recently used
+    tail: Option<usize>, // Least recently used, evicted first
+    max_weight: usize,
+    weigher: fn(&K, &V) -> usize,
+    weight: usize,
+    ttl: Option<Duration>, // For insert(), insert_with_ttl() picks its own
+    clock: C,
+    stats: CacheStats,
+}
+
+impl<K: Hash + Eq + Clone, V> Cache<K, V> {
+    pub fn new(capacity: Capacity<K, V>) -> Self {
+        Cache::with_clock(capacity, SystemClock)
+    }
+}
+
+impl<K: Hash + Eq + Clone, V, C: Clock> Cache<K, V, C> {
+    pub fn with_clock(capacity: Capacity<K, V>, clock: C) -> Self {
+        let (max_weight, weigher): (usize, fn(&K, &V) -> usize) = match capacity {
+            Capacity::Entries(max) => (max, |_, _| 1),
+            Capacity::Weight { max, weigher } => (max, weigher),
+        };
+        Cache {
+            map: HashMap::new(),
+            slots: Vec::new(),
+            free: Vec::new(),
+            head: None,
+            tail: None,
+            max_weight,
+            weigher,
+            weight: 0,
+            ttl: None,
+            clock,
+            stats: CacheStats::default(),
+        }
+    }
+
+    // The time to live of entries added by insert() and the entry API, forever without one
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    // The entry count when the capacity is a number of entries
+    pub fn weight(&self) -> usize {
+        self.weight
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    // Counts as a hit or a miss, and makes the entry the most recently used
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.live_index(key);
+        self.count_lookup(index)?;
+        Some(&self.slot(index?).value)
+    }
+
+    // Looks without counting or touching the LRU order
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let slot = self.slot(*self.map.get(key)?);
+        match slot.expires {
+            Some(expires) if expires <= self.clock.now() => None,
+            _ => Some(&slot.value),
+        }
+    }
+
+    // Returns the value it replaced, if any
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.insert_with_ttl(key, value, self.ttl)
+    }
+
+    pub fn insert_with_ttl(&mut self, key: K, value: V, ttl: Option<Duration>) -> Option<V> {
+        let expires = ttl.map(|ttl| self.clock.now() + ttl);
+        let weight = (self.weigher)(&key, &value);
+        self.stats.inserts += 1;
+
+        let replaced = match self.map.get(&key) {
+            Some(&index) => {
+                self.unlink(index);
+                let slot = self.slot_mut(index);
+                let old_weight = std::mem::replace(&mut slot.weight, weight);
+                slot.expires = expires;
+                let old = std::mem::replace(&mut slot.value, value);
+                self.weight = self.weight - old_weight + weight;
+                self.push_front(index);
+                Some(old)
+            }
+            None => {
+                let slot = Slot {
+                    key: key.clone(),
+                    value,
+                    weight,
+                    expires,
+                    prev: None,
+                    next: None,
+                };
+                let index = match self.free.pop() {
+                    Some(index) => {
+                        self.slots[index] = Some(slot);
+                        index
+                    }
+                    None => {
+                        self.slots.push(Some(slot));
+                        self.slots.len() - 1
+                    }
+                };
+                self.map.insert(key, index);
+                self.weight += weight;
+                self.push_front(index);
+                None
+            }
+        };
+
+        // The new entry is at the front, so it is the last one standing
+        while self.weight > self.max_weight && self.map.len() > 1 {
+            let tail = self.tail.expect("a non-empty cache has a tail");
+            self.remove_index(tail);
+            self.stats.evictions += 1;
+        }
+        replaced
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.live_index(key)?;
+        Some(self.remove_index(index).value)
+    }
+
+    // Like HashMap::entry, a lookup that counts as a hit or a miss
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, C> {
+        match self.live_index(&key) {
+            index @ Some(_) => {
+                self.count_lookup(index);
+                Entry::Occupied(OccupiedEntry {
+                    cache: self,
+                    index: index.expect("just matched"),
+                })
+            }
+            None => {
+                self.stats.misses += 1;
+                Entry::Vacant(VacantEntry { cache: self, key })
+            }
+        }
+    }
+
+    // Removes every expired entry, returns how many
+    pub fn purge_expired(&mut self) -> usize {
+        let now = self.clock.now();
+        let expired: Vec<usize> = self
+            .map
+            .values()
+            .copied()
+            .filter(|&index| {
+                self.slot(index)
+                    .expires
+                    .is_some_and(|expires| expires <= now)
+            })
+            .collect();
+        for &index in &expired {
+            self.remove_index(index);
+        }
+        self.stats.expirations += expired.len() as u64;
+        expired.len()
+    }
+
+    // Most recently used first, expired entries included until they are removed
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        std::iter::successors(self.head, |&index| self.slot(index).next)
+            .map(|index| self.slot(index))
+            .map(|slot| (&slot.key, &slot.value))
+    }
+
+    // The index of an entry that has not expired. An expired one is removed on the way
+    fn live_index<Q>(&mut self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = *self.map.get(key)?;
+        if self
+            .slot(index)
+            .expires
+            .is_some_and(|expires| expires <= self.clock.now())
+        {
+            self.remove_index(index);
+            self.stats.expirations += 1;
+            return None;
+        }
+        Some(index)
+    }
+
+    // A hit moves the entry to the front
+    fn count_lookup(&mut self, index: Option<usize>) -> Option<()> {
+        let Some(index) = index else {
+            self.stats.misses += 1;
+            return None;
+        };
+        self.stats.hits += 1;
+        self.unlink(index);
+        self.push_front(index);
+        Some(())
+    }
+
+    fn slot(&self, index: usize) -> &Slot<K, V> {
+        self.slots[index].as_ref().expect("index of a live slot")
+    }
+
+    fn slot_mut(&mut self, index: usize) -> &mut Slot<K, V> {
+        self.slots[index].as_mut().expect("index of a live slot")
+    }
+
+    fn unlink(&mut self, index: usize) {
+        let (prev, next) = {
+            let slot = self.slot(index);
+            (slot.prev, slot.next)
+        };
+        match prev {
+            Some(prev) => self.slot_mut(prev).next = next,
+            None => self.head = next,
+        }
+        match next {
+            Some(next) => self.slot_mut(next).prev = prev,
+            None => self.tail = prev,
+        }
+    }
+
+    fn push_front(&mut self, index: usize) {
+        let head = self.head;
+        let slot = self.slot_mut(index);
+        slot.prev = None;
+        slot.next = head;
+        match head {
+            Some(head) => self.slot_mut(head).prev = Some(index),
+            None => self.tail = Some(index),
+        }
+        self.head = Some(index);
+    }
+
+    fn remove_index(&mut self, index: usize) -> Slot<K, V> {
+        self.unlink(index);
+        let slot = self.slots[index].take().expect("index of a live slot");
+        self.free.push(index);
+        self.map.remove(&slot.key);
+        self.weight -= slot.weight;
+        slot
+    }
+}
+
+pub enum Entry<'a, K, V, C: Clock> {
+    Occupied(OccupiedEntry<'a, K, V, C>),
+    Vacant(VacantEntry<'a, K, V, C>),
+}
+
+pub struct OccupiedEntry<'a, K, V, C: Clock> {
+    cache: &'a mut Cache<K, V, C>,
+    index: usize,
+}
+
+pub struct VacantEntry<'a, K, V, C: Clock> {
+    cache: &'a mut Cache<K, V, C>,
+    key: K,
+}
+
+impl<'a, K: Hash + Eq + Clone, V, C: Clock> Entry<'a, K, V, C> {
+    pub fn or_insert(self, value: V) -> &'a mut V {
+        self.or_insert_with(|| value)
+    }
+
+    // `load` only runs on a miss, which makes this the way to memoize an expensive function
+    pub fn or_insert_with(self, load: impl FnOnce() -> V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(load()),
+        }
+    }
+
+    pub fn and_modify(self, modify: impl FnOnce(&mut V)) -> Self {
+        match self {
+            Entry::Occupied(entry) => {
+                modify(&mut entry.cache.slot_mut(entry.index).value);
+                Entry::Occupied(entry)
+            }
+            vacant => vacant,
+        }
+    }
+}
+
+impl<'a, K: Hash + Eq + Clone, V, C: Clock> OccupiedEntry<'a, K, V, C> {
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.cache.slot_mut(self.index).value
+    }
+
+    pub fn remove(self) -> V {
+        self.cache.remove_index(self.index).value
+    }
+}
+
+impl<'a, K: Hash + Eq + Clone, V, C: Clock> VacantEntry<'a, K, V, C> {
+    // The weight is measured now, changing the value through the returned reference does not update it
+    pub fn insert(self, value: V) -> &'a mut V {
+        self.cache.insert(self.key, value);
+        let head = self
+            .cache
+            .head
+            .expect("the entry just inserted is at the front");
+        &mut self.cache.slot_mut(head).value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bakery::FakeClock;
+
+    fn keys<V, C: Clock>(cache: &Cache<&'static str, V, C>) -> Vec<&'static str> {
+        cache.iter().map(|(key, _)| *key).collect()
+    }
+
+    fn fake_clock() -> FakeClock {
+        FakeClock::new(SystemTime::UNIX_EPOCH)
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used() {
+        let mut cache = Cache::new(Capacity::Entries(3));
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        cache.insert("c", 3);
+        assert_eq!(keys(&cache), ["c", "b", "a"]);
+
+        // get() makes "a" the most recently used, peek() leaves "b" where it is
+        assert_eq!(cache.get("a"), Some(&1));
+        assert_eq!(cache.peek("b"), Some(&2));
+        assert_eq!(keys(&cache), ["a", "c", "b"]);
+
+        cache.insert("d", 4);
+        assert_eq!(keys(&cache), ["d", "a", "c"]);
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.stats().evictions, 1);
+    }
+
+    #[test]
+    fn insert_replaces_and_remove_frees_the_slot() {
+        let mut cache = Cache::new(Capacity::Entries(2));
+        assert_eq!(cache.insert("a", 1), None);
+        cache.insert("b", 2);
+        assert_eq!(cache.insert("a", 10), Some(1));
+        assert_eq!(keys(&cache), ["a", "b"]);
+
+        assert_eq!(cache.remove("b"), Some(2));
+        assert_eq!(cache.remove("b"), None);
+        cache.insert("c", 3);
+        assert_eq!(keys(&cache), ["c", "a"]);
+        assert_eq!(cache.slots.len(), 2, "the slot of b is reused");
+        assert_eq!(cache.stats().evictions, 0);
+    }
+
+    #[test]
+    fn entries_expire_after_their_ttl() {
+        let clock = fake_clock();
+        let mut cache =
+            Cache::with_clock(Capacity::Entries(10), clock.clone()).ttl(Duration::from_secs(60));
+        cache.insert("short", 1);
+        cache.insert_with_ttl("long", 2, Some(Duration::from_secs(3600)));
+        cache.insert_with_ttl("forever", 3, None);
+
+        clock.advance(Duration::from_secs(59));
+        assert_eq!(cache.get("short"), Some(&1));
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(cache.peek("short"), None);
+        // Still stored until looked up or purged
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.get("short"), None);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.stats().expirations, 1);
+
+        clock.advance(Duration::from_secs(3600));
+        assert_eq!(cache.purge_expired(), 1);
+        assert_eq!(keys(&cache), ["forever"]);
+        assert_eq!(cache.stats().expirations, 2);
+    }
+
+    #[test]
+    fn inserting_again_restarts_the_ttl() {
+        let clock = fake_clock();
+        let mut cache =
+            Cache::with_clock(Capacity::Entries(10), clock.clone()).ttl(Duration::from_secs(10));
+        cache.insert("a", 1);
+        clock.advance(Duration::from_secs(8));
+        cache.insert("a", 2);
+        clock.advance(Duration::from_secs(8));
+        assert_eq!(cache.get("a"), Some(&2));
+    }
+
+    #[test]
+    fn weight_capacity_evicts_until_it_fits() {
+        let mut cache: Cache<&str, String> = Cache::new(Capacity::Weight {
+            max: 10,
+            weigher: |_, value| value.len(),
+        });
+        cache.insert("a", "aaaa".to_string());
+        cache.insert("b", "bbbb".to_string());
+        assert_eq!(cache.weight(), 8);
+
+        cache.insert("c", "cccccc".to_string());
+        assert_eq!(keys(&cache), ["c", "b"]);
+        assert_eq!(cache.weight(), 10);
+
+        // Growing an entry in place counts the difference
+        cache.insert("b", "b".to_string());
+        assert_eq!(cache.weight(), 7);
+
+        // Heavier than the whole capacity: stored alone
+        cache.insert("huge", "h".repeat(25));
+        assert_eq!(keys(&cache), ["huge"]);
+        assert_eq!(cache.weight(), 25);
+        assert_eq!(cache.stats().evictions, 3);
+    }
+
+    #[test]
+    fn stats_count_hits_and_misses() {
+        let mut cache = Cache::new(Capacity::Entries(2));
+        assert_eq!(cache.stats().hit_rate(), 0.0);
+        cache.insert("a", 1);
+        cache.get("a");
+        cache.get("a");
+        cache.get("a");
+        cache.get("b");
+        cache.peek("b");
+        let stats = cache.stats();
+        assert_eq!(
+            stats,
+            CacheStats {
+                hits: 3,
+                misses: 1,
+                inserts: 1,
+                evictions: 0,
+                expirations: 0
+            }
+        );
+        assert_eq!(
+            stats.to_string(),
+            "3 hits, 1 misses (75.0% hit rate), 1 inserts, 0 evicted, 0 expired"
+        );
+    }
+
+    #[test]
+    fn entry_loads_only_on_a_miss() {
+        let mut cache = Cache::new(Capacity::Entries(2));
+        let mut loads = 0;
+        for _ in 0..3 {
+            let value = cache.entry("a").or_insert_with(|| {
+                loads += 1;
+                42
+            });
+            assert_eq!(*value, 42);
+        }
+        assert_eq!(loads, 1);
+        assert_eq!((cache.stats().hits, cache.stats().misses), (2, 1));
+
+        *cache.entry("b").and_modify(|v| *v += 1).or_insert(0) += 5;
+        cache.entry("b").and_modify(|v| *v += 1).or_insert(0);
+        assert_eq!(cache.peek("b"), Some(&6));
+
+        match cache.entry("a") {
+            Entry::Occupied(entry) => assert_eq!(entry.remove(), 42),
+            Entry::Vacant(_) => panic!("a is cached"),
+        }
+        assert!(matches!(cache.entry("a"), Entry::Vacant(_)));
+        assert_eq!(keys(&cache), ["b"]);
+    }
+
+    #[test]
+    fn entry_treats_an_expired_entry_as_vacant() {
+        let clock = fake_clock();
+        let mut cache =
+            Cache::with_clock(Capacity::Entries(2), clock.clone()).ttl(Duration::from_secs(1));
+        cache.entry("a").or_insert(1);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(*cache.entry("a").or_insert(2), 2);
+        assert_eq!(cache.stats().expirations, 1);
+        assert!(!cache.is_empty());
+    }
+}
@@ -0,0 +1,427 @@
+use crate::supervisor::CancellationToken;
+use std::{
+    fmt, io,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, Lines},
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpListener, TcpStream, ToSocketAddrs,
+    },
+    sync::broadcast,
+    task::JoinSet,
+};
+
+/*
+ * A line based chat server on tokio's TcpListener, and a client to talk to it.
+ *
+ * Protocol, one message per line:
+ * - the first line a client sends is its name, the server answers "* welcome <name>, <n> online"
+ * - "/echo <text>" is answered to the sender only, with "echo: <text>"
+ * - "/quit" says goodbye and closes the connection
+ * - any other line goes to every other client as "<name>: <text>"
+ * Joins and leaves are announced as "* <name> joined" and "* <name> left".
+ *
+ * Lines are limited to 4096 bytes: a client sending more without a newline is disconnected, instead of making
+ * the server buffer whatever it sends.
+ *
+ * Every connection is its own task. They all share one broadcast channel: a message is sent once
+ * and every connection task receives its own copy, then skips the ones it sent itself.
+ */
+
+const MAX_LINE: usize = 4096;
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone)]
+struct Broadcast {
+    from: usize, // Connection id of the sender
+    text: String,
+}
+
+// Shared by the accept loop and every connection task
+struct Shared {
+    messages: broadcast::Sender<Broadcast>,
+    online: AtomicUsize,
+    relayed: AtomicUsize,
+    shutdown: CancellationToken,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServerStats {
+    pub connections: usize,
+    pub relayed: usize, // Chat lines passed on to other clients
+}
+
+impl fmt::Display for ServerStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} connections, {} messages relayed",
+            self.connections, self.relayed
+        )
+    }
+}
+
+pub struct ChatServer {
+    listener: TcpListener,
+}
+
+impl ChatServer {
+    // Port 0 lets the OS pick a free port, local_addr() tells which one
+    pub async fn bind(addr: impl ToSocketAddrs) -> io::Result<ChatServer> {
+        Ok(ChatServer {
+            listener: TcpListener::bind(addr).await?,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    // Serves clients until `shutdown` is cancelled, then waits for every connection to say goodbye
+    pub async fn run(self, shutdown: CancellationToken) -> io::Result<ServerStats> {
+        // A client more than 64 messages behind skips the oldest ones instead of slowing everybody down
+        let (messages, _) = broadcast::channel(64);
+        let shared = Arc::new(Shared {
+            messages,
+            online: AtomicUsize::new(0),
+            relayed: AtomicUsize::new(0),
+            shutdown,
+        });
+        let mut connections = JoinSet::new();
+        let mut accepted = 0;
+
+        loop {
+            tokio::select! {
+                connection = self.listener.accept() => {
+                    // Accept errors like running out of file descriptors (EMFILE) or a client that hung up before
+                    // being accepted (ECONNABORTED) pass. Returning would drop every connection that is still open
+                    let stream = match connection {
+                        Ok((stream, _)) => stream,
+                        Err(e) => {
+                            eprintln!("[Chat server] accept failed: {e}");
+                            // Give the OS a moment, retrying right away on EMFILE would only spin
+                            tokio::time::sleep(ACCEPT_BACKOFF).await;
+                            continue;
+                        }
+                    };
+                    accepted += 1;
+                    // Subscribe right away, so the client cannot miss messages sent while it is still introducing itself
+                    let receiver = shared.messages.subscribe();
+                    connections.spawn(serve(stream, accepted, receiver, Arc::clone(&shared)));
+                }
+                _ = shared.shutdown.cancelled() => break,
+            }
+        }
+
+        // One broken connection must not take the server down, so its error is only reported
+        while let Some(joined) = connections.join_next().await {
+            match joined {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => eprintln!("[Chat server] connection failed: {e}"),
+                Err(e) => eprintln!("[Chat server] connection task failed: {e}"),
+            }
+        }
+
+        Ok(ServerStats {
+            connections: accepted,
+            relayed: shared.relayed.load(Ordering::Relaxed),
+        })
+    }
+}
+
+async fn serve(
+    stream: TcpStream,
+    id: usize,
+    mut receiver: broadcast::Receiver<Broadcast>,
+    shared: Arc<Shared>,
+) -> io::Result<()> {
+    // Reading and writing happen in the same select! loop, so the stream is split into its two halves
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = LineReader::new(reader);
+    let announce = |text: String| {
+        // Only fails when nobody is subscribed, and this connection itself always is
+        let _ = shared.messages.send(Broadcast { from: id, text });
+    };
+
+    let first_line = tokio::select! {
+        line = lines.next_line() => line?,
+        _ = shared.shutdown.cancelled() => return Ok(()),
+    };
+    let name = match first_line {
+        Some(name) if !name.trim().is_empty() => name.trim().to_string(),
+        _ => return Ok(()), // Hung up (or sent nothing useful) before saying who they are
+    };
+    let online = shared.online.fetch_add(1, Ordering::SeqCst) + 1;
+    announce(format!("* {name} joined"));
+    send_line(&mut writer, &format!("* welcome {name}, {online} online")).await?;
+
+    let result = async {
+        loop {
+            tokio::select! {
+                line = lines.next_line() => {
+                    let Some(line) = line? else { break };
+                    if line == "/quit" {
+                        send_line(&mut writer, "* bye").await?;
+                        break;
+                    } else if let Some(text) = line.strip_prefix("/echo ") {
+                        send_line(&mut writer, &format!("echo: {text}")).await?;
+                    } else {
+                        shared.relayed.fetch_add(1, Ordering::Relaxed);
+                        announce(format!("{name}: {line}"));
+                    }
+                }
+                message = receiver.recv() => match message {
+                    Ok(message) if message.from != id => send_line(&mut writer, &message.text).await?,
+                    Ok(_) => {} // Our own message coming back
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        send_line(&mut writer, &format!("* you missed {missed} messages")).await?
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                _ = shared.shutdown.cancelled() => {
+                    send_line(&mut writer, "* server shutting down").await?;
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+    .await;
+
+    // Announced even if the connection broke, the others should know either way. Not while shutting down:
+    // everyone is leaving then, and already told so
+    shared.online.fetch_sub(1, Ordering::SeqCst);
+    if !shared.shutdown.is_cancelled() {
+        announce(format!("* {name} left"));
+    }
+    result
+}
+
+// Like Lines::next_line, but refuses lines longer than MAX_LINE
+struct LineReader {
+    reader: BufReader<OwnedReadHalf>,
+    buffer: Vec<u8>, // A partial line survives a next_line() cancelled by select!
+}
+
+impl LineReader {
+    fn new(reader: OwnedReadHalf) -> Self {
+        LineReader {
+            reader: BufReader::new(reader),
+            buffer: Vec::new(),
+        }
+    }
+
+    async fn next_line(&mut self) -> io::Result<Option<String>> {
+        // One byte more than allowed, to tell a line of exactly MAX_LINE bytes from a longer one
+        let limit = (MAX_LINE + 1).saturating_sub(self.buffer.len()) as u64;
+        (&mut self.reader)
+            .take(limit)
+            .read_until(b'\n', &mut self.buffer)
+            .await?;
+        if self.buffer.last() != Some(&b'\n') {
+            if self.buffer.len() > MAX_LINE {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line longer than {MAX_LINE} bytes"),
+                ));
+            }
+            if self.buffer.is_empty() {
+                return Ok(None); // Closed between lines
+            }
+        }
+        let mut line = std::mem::take(&mut self.buffer);
+        while matches!(line.last(), Some(b'\n' | b'\r')) {
+            line.pop();
+        }
+        String::from_utf8(line)
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+async fn send_line(writer: &mut OwnedWriteHalf, line: &str) -> io::Result<()> {
+    writer.write_all(format!("{line}\n").as_bytes()).await
+}
+
+pub struct ChatClient {
+    name: String,
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: OwnedWriteHalf,
+}
+
+impl ChatClient {
+    // Connects, introduces itself and waits for the welcome line, which is returned with the client
+    pub async fn connect(addr: SocketAddr, name: &str) -> io::Result<(ChatClient, String)> {
+        let (reader, writer) = TcpStream::connect(addr).await?.into_split();
+        let mut client = ChatClient {
+            name: name.to_string(),
+            lines: BufReader::new(reader).lines(),
+            writer,
+        };
+        client.send(name).await?;
+        let welcome = client
+            .lines
+            .next_line()
+            .await?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "no welcome"))?;
+        Ok((client, welcome))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub async fn send(&mut self, line: &str) -> io::Result<()> {
+        send_line(&mut self.writer, line).await
+    }
+
+    // The next line, or None if nothing arrived within `timeout`
+    pub async fn recv(&mut self, timeout: Duration) -> io::Result<Option<String>> {
+        match tokio::time::timeout(timeout, self.lines.next_line()).await {
+            Ok(Ok(Some(line))) => Ok(Some(line)),
+            Ok(Ok(None)) => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "the server closed the connection",
+            )),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Ok(None),
+        }
+    }
+
+    // Everything received until the connection stays quiet for `quiet`, or the server closes it
+    pub async fn drain(&mut self, quiet: Duration) -> io::Result<Vec<String>> {
+        let mut received = Vec::new();
+        loop {
+            match self.recv(quiet).await {
+                Ok(Some(line)) => received.push(line),
+                Ok(None) => return Ok(received),
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(received),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    // Says goodbye and returns whatever the server sent until it closed the connection
+    pub async fn quit(mut self) -> io::Result<Vec<String>> {
+        self.send("/quit").await?;
+        let mut received = Vec::new();
+        while let Some(line) = self.lines.next_line().await? {
+            received.push(line);
+        }
+        Ok(received)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::task::JoinHandle;
+
+    const QUIET: Duration = Duration::from_millis(100);
+
+    async fn start() -> (
+        SocketAddr,
+        CancellationToken,
+        JoinHandle<io::Result<ServerStats>>,
+    ) {
+        let server = ChatServer::bind("127.0.0.1:0").await.unwrap();
+        let addr = server.local_addr().unwrap();
+        let shutdown = CancellationToken::new();
+        let server = tokio::spawn(server.run(shutdown.clone()));
+        (addr, shutdown, server)
+    }
+
+    #[tokio::test]
+    async fn clients_are_welcomed_and_announced() {
+        let (addr, shutdown, _server) = start().await;
+        let (mut alice, welcome) = ChatClient::connect(addr, "alice").await.unwrap();
+        assert_eq!(welcome, "* welcome alice, 1 online");
+        let (_bob, welcome) = ChatClient::connect(addr, "bob").await.unwrap();
+        assert_eq!(welcome, "* welcome bob, 2 online");
+        assert_eq!(alice.drain(QUIET).await.unwrap(), ["* bob joined"]);
+        shutdown.cancel();
+    }
+
+    #[tokio::test]
+    async fn messages_go_to_everyone_else_and_echo_only_to_the_sender() {
+        let (addr, shutdown, _server) = start().await;
+        let (mut alice, _) = ChatClient::connect(addr, "alice").await.unwrap();
+        let (mut bob, _) = ChatClient::connect(addr, "bob").await.unwrap();
+        alice.drain(QUIET).await.unwrap();
+
+        alice.send("hi bob").await.unwrap();
+        alice.send("/echo ping").await.unwrap();
+        assert_eq!(bob.drain(QUIET).await.unwrap(), ["alice: hi bob"]);
+        assert_eq!(alice.drain(QUIET).await.unwrap(), ["echo: ping"]);
+        shutdown.cancel();
+    }
+
+    #[tokio::test]
+    async fn quit_says_bye_and_the_others_hear_about_it() {
+        let (addr, shutdown, _server) = start().await;
+        let (mut alice, _) = ChatClient::connect(addr, "alice").await.unwrap();
+        let (bob, _) = ChatClient::connect(addr, "bob").await.unwrap();
+        alice.drain(QUIET).await.unwrap();
+
+        assert_eq!(bob.quit().await.unwrap(), ["* bye"]);
+        assert_eq!(alice.drain(QUIET).await.unwrap(), ["* bob left"]);
+        shutdown.cancel();
+    }
+
+    #[tokio::test]
+    async fn shutdown_tells_every_client_and_returns_the_stats() {
+        let (addr, shutdown, server) = start().await;
+        let (mut alice, _) = ChatClient::connect(addr, "alice").await.unwrap();
+        let (mut bob, _) = ChatClient::connect(addr, "bob").await.unwrap();
+        alice.send("hello").await.unwrap();
+        bob.drain(QUIET).await.unwrap();
+        alice.drain(QUIET).await.unwrap();
+
+        shutdown.cancel();
+        let stats = server.await.unwrap().unwrap();
+        assert_eq!(
+            stats,
+            ServerStats {
+                connections: 2,
+                relayed: 1
+            }
+        );
+        // Everything up to the closed connection
+        assert_eq!(
+            alice.drain(QUIET).await.unwrap(),
+            ["* server shutting down"]
+        );
+        assert_eq!(bob.drain(QUIET).await.unwrap(), ["* server shutting down"]);
+    }
+
+    #[tokio::test]
+    async fn a_line_that_is_too_long_closes_the_connection() {
+        let (addr, shutdown, _server) = start().await;
+        let (mut alice, _) = ChatClient::connect(addr, "alice").await.unwrap();
+        let (mut mallory, _) = ChatClient::connect(addr, "mallory").await.unwrap();
+        alice.drain(QUIET).await.unwrap();
+
+        // "alice: " plus the text is longer than MAX_LINE, only what clients send is limited
+        alice.send(&"a".repeat(MAX_LINE)).await.unwrap();
+        let relayed = mallory.drain(QUIET).await.unwrap();
+        assert_eq!(relayed.len(), 1);
+        assert_eq!(relayed[0].len(), "alice: ".len() + MAX_LINE);
+
+        mallory.send(&"m".repeat(MAX_LINE + 1)).await.unwrap();
+        assert!(mallory.drain(QUIET).await.unwrap().is_empty());
+        assert!(matches!(
+            mallory.recv(QUIET).await,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof
+        ));
+        // Nothing of it was relayed
+        assert_eq!(alice.drain(QUIET).await.unwrap(), ["* mallory left"]);
+        shutdown.cancel();
+    }
+}
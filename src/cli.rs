@@ -0,0 +1,527 @@
+use std::{error::Error, fmt, path::PathBuf, time::Duration};
+
+/*
+ * Command line flags and commands, parsed by hand from std::env::args().
+ *
+ * Without a command the program shows the lesson menu as before. Flags only change how it runs,
+ * e.g. how many worker threads the shared Tokio runtime gets. Anything not starting with '-' is
+ * a positional argument: the first one names the command, the rest are its arguments.
+ */
+
+pub const USAGE: &str = "\
+Usage: rust-basic [OPTIONS] [COMMAND]
+
+Without a command, shows the lesson menu. Files are read as JSON, CSV or binary by their extension.
+
+Commands:
+  bookmarks <FILE> <ACTION>  Manage the bookmarks saved in FILE, see Bookmark actions below
+  check [FILE]               Check that the favorite websites, or the bookmarks saved in FILE, are reachable
+  convert <INPUT> <OUTPUT>   Convert between .json, .csv and .bin files, reporting what does not convert losslessly
+  diff <FROM> <TO>           Print the JSON Patch (RFC 6902) that turns one file into the other
+  patch <FILE> <PATCH>       Print a file with a JSON Patch or a JSON Merge Patch (RFC 7386) applied
+  query <EXPRESSION> <FILE>  Print what a JSON path like $.store.book[?(@.price < 10)] selects in a JSON file
+  validate <SCHEMA> <FILE>   Check a file against a JSON schema file, or the built-in schema \"aps\", \"config\" or \"bookmarks\"
+
+Bookmark actions:
+  list                       List every bookmark, sorted by folder and name
+  add <NAME> <URL>           Add a bookmark, URLs are normalized: example.com is saved as https://example.com/
+  remove <NAME>              Remove a bookmark
+  rename <NAME> <NEW_NAME>   Rename a bookmark
+  tag <NAME> <TAG>           Tag a bookmark, untag <NAME> <TAG> removes the tag again
+  move <NAME> <FOLDER>       Move a bookmark into a folder like Dev/Rust, \"/\" is the top level
+  search <QUERY>             List the bookmarks whose name or URL contains QUERY, or that are tagged QUERY
+  import <FILE>              Add the bookmarks of a .json or Netscape .html bookmark file
+  export <FILE>              Write the bookmarks to a .json or Netscape .html bookmark file
+
+Options:
+      --per-host <N>        How many links on one host check tries at once (default: 2)
+      --schema <SCHEMA>     Check every file a command loads against this JSON schema first
+      --timeout <MS>        How long check waits for each link, in milliseconds (default: 5000)
+      --worker-threads <N>  Number of Tokio worker threads (default: one per CPU core)
+  -h, --help                Print this help";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BookmarkAction {
+    List,
+    Add { name: String, url: String },
+    Remove { name: String },
+    Rename { name: String, new_name: String },
+    Tag { name: String, tag: String },
+    Untag { name: String, tag: String },
+    Move { name: String, folder: String },
+    Search { query: String },
+    Import { file: PathBuf },
+    Export { file: PathBuf },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Command {
+    #[default]
+    Menu,
+    Bookmarks {
+        file: PathBuf,
+        action: BookmarkAction,
+    },
+    Check {
+        file: Option<PathBuf>, // A bookmarks file, the favorite websites without one
+    },
+    Convert {
+        input: PathBuf,
+        output: PathBuf,
+    },
+    Diff {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    Patch {
+        file: PathBuf,
+        patch: PathBuf,
+    },
+    Query {
+        expression: String,
+        file: PathBuf,
+    },
+    Validate {
+        schema: String,
+        file: PathBuf,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Args {
+    pub command: Command,
+    pub worker_threads: Option<usize>,
+    pub schema: Option<String>, // A schema file or the name of a built-in one
+    pub timeout: Option<Duration>,
+    pub per_host: Option<usize>,
+    pub help: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CliError {
+    UnknownArgument(String),
+    UnknownCommand(String),
+    WrongArguments {
+        usage: &'static str, // How the command should have been called
+    },
+    MissingValue {
+        flag: &'static str,
+    },
+    InvalidValue {
+        flag: &'static str,
+        value: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::UnknownArgument(argument) => write!(f, "Unknown argument {}", argument),
+            CliError::UnknownCommand(command) => write!(f, "Unknown command {}", command),
+            CliError::WrongArguments { usage } => write!(f, "Usage: rust-basic {}", usage),
+            CliError::MissingValue { flag } => write!(f, "{} needs a value", flag),
+            CliError::InvalidValue {
+                flag,
+                value,
+                reason,
+            } => write!(f, "Invalid value {:?} for {}: {}", value, flag, reason),
+        }
+    }
+}
+
+impl Error for CliError {}
+
+impl Args {
+    // Takes the arguments without the program name, i.e. std::env::args().skip(1)
+    pub fn parse(arguments: impl IntoIterator<Item = String>) -> Result<Args, CliError> {
+        let mut args = Args::default();
+        let mut positionals = Vec::new();
+        let mut arguments = arguments.into_iter();
+
+        while let Some(argument) = arguments.next() {
+            if !argument.starts_with('-') {
+                positionals.push(argument);
+                continue;
+            }
+
+            // Both "--flag value" and "--flag=value" are accepted
+            let (flag, inline_value) = match argument.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+                _ => (argument.as_str(), None),
+            };
+
+            match flag {
+                "-h" | "--help" => args.help = true,
+                "--schema" => {
+                    let value = inline_value
+                        .or_else(|| arguments.next())
+                        .ok_or(CliError::MissingValue { flag: "--schema" })?;
+                    args.schema = Some(value);
+                }
+                "--per-host" => {
+                    args.per_host = Some(positive("--per-host", inline_value, &mut arguments)?);
+                }
+                "--timeout" => {
+                    let millis = positive("--timeout", inline_value, &mut arguments)?;
+                    args.timeout = Some(Duration::from_millis(millis as u64));
+                }
+                "--worker-threads" => {
+                    args.worker_threads =
+                        Some(positive("--worker-threads", inline_value, &mut arguments)?);
+                }
+                _ => return Err(CliError::UnknownArgument(argument.clone())),
+            }
+        }
+
+        args.command = Command::parse(positionals)?;
+        Ok(args)
+    }
+}
+
+// The value of a flag that takes a number above zero
+fn positive(
+    flag: &'static str,
+    inline_value: Option<String>,
+    arguments: &mut impl Iterator<Item = String>,
+) -> Result<usize, CliError> {
+    let value = inline_value
+        .or_else(|| arguments.next())
+        .ok_or(CliError::MissingValue { flag })?;
+    match value.parse::<usize>() {
+        Ok(0) => Err(CliError::InvalidValue {
+            flag,
+            value,
+            reason: "must be at least 1".to_string(),
+        }),
+        Ok(number) => Ok(number),
+        Err(e) => Err(CliError::InvalidValue {
+            flag,
+            reason: e.to_string(),
+            value,
+        }),
+    }
+}
+
+impl Command {
+    fn parse(positionals: Vec<String>) -> Result<Command, CliError> {
+        let mut positionals = positionals.into_iter();
+        let Some(name) = positionals.next() else {
+            return Ok(Command::Menu);
+        };
+        let arguments: Vec<String> = positionals.collect();
+
+        match name.as_str() {
+            "bookmarks" => BookmarkAction::parse(arguments),
+            "check" => match <[String; 1]>::try_from(arguments) {
+                Ok([file]) => Ok(Command::Check {
+                    file: Some(PathBuf::from(file)),
+                }),
+                Err(arguments) if arguments.is_empty() => Ok(Command::Check { file: None }),
+                Err(_) => Err(CliError::WrongArguments {
+                    usage: "check [FILE]",
+                }),
+            },
+            "convert" => match <[String; 2]>::try_from(arguments) {
+                Ok([input, output]) => Ok(Command::Convert {
+                    input: PathBuf::from(input),
+                    output: PathBuf::from(output),
+                }),
+                Err(_) => Err(CliError::WrongArguments {
+                    usage: "convert <INPUT> <OUTPUT>",
+                }),
+            },
+            "diff" => match <[String; 2]>::try_from(arguments) {
+                Ok([from, to]) => Ok(Command::Diff {
+                    from: PathBuf::from(from),
+                    to: PathBuf::from(to),
+                }),
+                Err(_) => Err(CliError::WrongArguments {
+                    usage: "diff <FROM> <TO>",
+                }),
+            },
+            "patch" => match <[String; 2]>::try_from(arguments) {
+                Ok([file, patch]) => Ok(Command::Patch {
+                    file: PathBuf::from(file),
+                    patch: PathBuf::from(patch),
+                }),
+                Err(_) => Err(CliError::WrongArguments {
+                    usage: "patch <FILE> <PATCH>",
+                }),
+            },
+            "query" => match <[String; 2]>::try_from(arguments) {
+                Ok([expression, file]) => Ok(Command::Query {
+                    expression,
+                    file: PathBuf::from(file),
+                }),
+                Err(_) => Err(CliError::WrongArguments {
+                    usage: "query <EXPRESSION> <FILE>",
+                }),
+            },
+            "validate" => match <[String; 2]>::try_from(arguments) {
+                Ok([schema, file]) => Ok(Command::Validate {
+                    schema,
+                    file: PathBuf::from(file),
+                }),
+                Err(_) => Err(CliError::WrongArguments {
+                    usage: "validate <SCHEMA> <FILE>",
+                }),
+            },
+            _ => Err(CliError::UnknownCommand(name)),
+        }
+    }
+}
+
+impl BookmarkAction {
+    // `arguments` is everything after "bookmarks": the file, the action and its arguments
+    fn parse(arguments: Vec<String>) -> Result<Command, CliError> {
+        const USAGE: &str = "bookmarks <FILE> <ACTION> [ARGUMENTS]";
+        let mut arguments = arguments.into_iter();
+        let (Some(file), Some(action)) = (arguments.next(), arguments.next()) else {
+            return Err(CliError::WrongArguments { usage: USAGE });
+        };
+        let rest: Vec<String> = arguments.collect();
+
+        let action = match (action.as_str(), rest.as_slice()) {
+            ("list", []) => BookmarkAction::List,
+            ("add", [name, url]) => BookmarkAction::Add {
+                name: name.clone(),
+                url: url.clone(),
+            },
+            ("remove", [name]) => BookmarkAction::Remove { name: name.clone() },
+            ("rename", [name, new_name]) => BookmarkAction::Rename {
+                name: name.clone(),
+                new_name: new_name.clone(),
+            },
+            ("tag", [name, tag]) => BookmarkAction::Tag {
+                name: name.clone(),
+                tag: tag.clone(),
+            },
+            ("untag", [name, tag]) => BookmarkAction::Untag {
+                name: name.clone(),
+                tag: tag.clone(),
+            },
+            ("move", [name, folder]) => BookmarkAction::Move {
+                name: name.clone(),
+                folder: folder.clone(),
+            },
+            ("search", [query]) => BookmarkAction::Search {
+                query: query.clone(),
+            },
+            ("import", [file]) => BookmarkAction::Import {
+                file: PathBuf::from(file),
+            },
+            ("export", [file]) => BookmarkAction::Export {
+                file: PathBuf::from(file),
+            },
+            (
+                "list" | "add" | "remove" | "rename" | "tag" | "untag" | "move" | "search"
+                | "import" | "export",
+                _,
+            ) => return Err(CliError::WrongArguments { usage: USAGE }),
+            (action, _) => return Err(CliError::UnknownCommand(format!("bookmarks {action}"))),
+        };
+        Ok(Command::Bookmarks {
+            file: PathBuf::from(file),
+            action,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<Args, CliError> {
+        Args::parse(line.split_whitespace().map(String::from))
+    }
+
+    fn command(line: &str) -> Result<Command, CliError> {
+        parse(line).map(|args| args.command)
+    }
+
+    #[test]
+    fn no_arguments_shows_the_menu() {
+        assert_eq!(parse(""), Ok(Args::default()));
+        assert_eq!(command(""), Ok(Command::Menu));
+    }
+
+    #[test]
+    fn flags_take_their_value_either_way() {
+        let args = parse("--worker-threads 2 --timeout=1500 --per-host=3 --schema aps -h").unwrap();
+        assert_eq!(args.worker_threads, Some(2));
+        assert_eq!(args.timeout, Some(Duration::from_millis(1500)));
+        assert_eq!(args.per_host, Some(3));
+        assert_eq!(args.schema.as_deref(), Some("aps"));
+        assert!(args.help);
+        assert_eq!(args.command, Command::Menu);
+
+        // Flags may come between the command and its arguments
+        let args = parse("diff a.json --schema=s.json b.json").unwrap();
+        assert_eq!(
+            args.command,
+            Command::Diff {
+                from: PathBuf::from("a.json"),
+                to: PathBuf::from("b.json")
+            }
+        );
+        assert_eq!(args.schema.as_deref(), Some("s.json"));
+    }
+
+    #[test]
+    fn flag_errors() {
+        assert_eq!(
+            parse("--verbose"),
+            Err(CliError::UnknownArgument("--verbose".to_string()))
+        );
+        assert_eq!(
+            parse("-x=1"),
+            Err(CliError::UnknownArgument("-x=1".to_string()))
+        );
+        assert_eq!(
+            parse("--schema"),
+            Err(CliError::MissingValue { flag: "--schema" })
+        );
+        assert_eq!(
+            parse("--timeout"),
+            Err(CliError::MissingValue { flag: "--timeout" })
+        );
+        assert_eq!(
+            parse("--worker-threads 0"),
+            Err(CliError::InvalidValue {
+                flag: "--worker-threads",
+                value: "0".to_string(),
+                reason: "must be at least 1".to_string()
+            })
+        );
+        let error = parse("--per-host=many").unwrap_err();
+        assert!(matches!(
+            &error,
+            CliError::InvalidValue { flag: "--per-host", value, .. } if value == "many"
+        ));
+        assert_eq!(
+            error.to_string(),
+            "Invalid value \"many\" for --per-host: invalid digit found in string"
+        );
+    }
+
+    #[test]
+    fn commands_and_their_arguments() {
+        assert_eq!(command("check"), Ok(Command::Check { file: None }));
+        assert_eq!(
+            command("check b.json"),
+            Ok(Command::Check {
+                file: Some(PathBuf::from("b.json"))
+            })
+        );
+        assert_eq!(
+            command("query $.a[0] data.json"),
+            Ok(Command::Query {
+                expression: "$.a[0]".to_string(),
+                file: PathBuf::from("data.json")
+            })
+        );
+        assert_eq!(
+            command("validate config c.json"),
+            Ok(Command::Validate {
+                schema: "config".to_string(),
+                file: PathBuf::from("c.json")
+            })
+        );
+        assert_eq!(
+            command("convert a.csv a.bin"),
+            Ok(Command::Convert {
+                input: PathBuf::from("a.csv"),
+                output: PathBuf::from("a.bin")
+            })
+        );
+        assert_eq!(
+            command("patch a.json p.json"),
+            Ok(Command::Patch {
+                file: PathBuf::from("a.json"),
+                patch: PathBuf::from("p.json")
+            })
+        );
+    }
+
+    #[test]
+    fn command_errors() {
+        assert_eq!(
+            command("frobnicate"),
+            Err(CliError::UnknownCommand("frobnicate".to_string()))
+        );
+        let cases = [
+            ("check a b", "check [FILE]"),
+            ("convert a.json", "convert <INPUT> <OUTPUT>"),
+            ("diff a b c", "diff <FROM> <TO>"),
+            ("patch", "patch <FILE> <PATCH>"),
+            ("query $.a", "query <EXPRESSION> <FILE>"),
+            ("validate s", "validate <SCHEMA> <FILE>"),
+        ];
+        for (line, usage) in cases {
+            assert_eq!(
+                command(line),
+                Err(CliError::WrongArguments { usage }),
+                "{line}"
+            );
+        }
+        assert_eq!(
+            CliError::WrongArguments {
+                usage: "patch <FILE> <PATCH>"
+            }
+            .to_string(),
+            "Usage: rust-basic patch <FILE> <PATCH>"
+        );
+    }
+
+    #[test]
+    fn bookmark_actions() {
+        let action = |line: &str| match command(line) {
+            Ok(Command::Bookmarks { file, action }) => {
+                assert_eq!(file, PathBuf::from("b.json"));
+                Ok(action)
+            }
+            Ok(other) => panic!("not a bookmarks command: {other:?}"),
+            Err(e) => Err(e),
+        };
+        assert_eq!(action("bookmarks b.json list"), Ok(BookmarkAction::List));
+        assert_eq!(
+            action("bookmarks b.json add Rust rust-lang.org"),
+            Ok(BookmarkAction::Add {
+                name: "Rust".to_string(),
+                url: "rust-lang.org".to_string()
+            })
+        );
+        assert_eq!(
+            action("bookmarks b.json move Rust Dev/Rust"),
+            Ok(BookmarkAction::Move {
+                name: "Rust".to_string(),
+                folder: "Dev/Rust".to_string()
+            })
+        );
+        assert_eq!(
+            action("bookmarks b.json export b.html"),
+            Ok(BookmarkAction::Export {
+                file: PathBuf::from("b.html")
+            })
+        );
+
+        let usage = "bookmarks <FILE> <ACTION> [ARGUMENTS]";
+        assert_eq!(
+            command("bookmarks b.json"),
+            Err(CliError::WrongArguments { usage })
+        );
+        assert_eq!(
+            action("bookmarks b.json list extra"),
+            Err(CliError::WrongArguments { usage })
+        );
+        assert_eq!(
+            action("bookmarks b.json tag Rust"),
+            Err(CliError::WrongArguments { usage })
+        );
+        assert_eq!(
+            action("bookmarks b.json star Rust"),
+            Err(CliError::UnknownCommand("bookmarks star".to_string()))
+        );
+    }
+}
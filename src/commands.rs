@@ -0,0 +1,226 @@
+use crate::bookmarks::{Bookmark, BookmarkStore};
+use crate::cli::BookmarkAction;
+use crate::error::{AppError, Context};
+use crate::formats::{Encoded, Format, FormatError};
+use crate::json_patch::{self, Operation};
+use crate::json_path::{self, Match};
+use crate::json_schema::Schema;
+use crate::link_check::LinkChecker;
+use crate::network::{Ap, Config};
+use crate::resolver::SystemResolver;
+use crate::url::Url;
+use serde_json::Value;
+use std::{fs, path::Path, time::Duration};
+
+/*
+ * The commands besides the lesson menu, see cli.rs for how they are called.
+ * Each one reads its input files, does one job and prints the result to stdout.
+ */
+
+// JSON, CSV or binary by the file extension, JSON when the extension does not say
+fn read_document(file: &Path) -> Result<Value, AppError> {
+    let format = Format::from_path(file).unwrap_or(Format::Json);
+    let bytes = fs::read(file).with_context(|| format!("Failed to read {}", file.display()))?;
+    format
+        .decode_value(&bytes)
+        .with_context(|| format!("{} is not valid {}", file.display(), format))
+}
+
+// "aps" and "config" name the schemas of network.rs, "bookmarks" the one of bookmarks.rs, anything else is a schema file
+fn load_schema(schema: &str) -> Result<Schema, AppError> {
+    match schema {
+        "aps" => Ok(Ap::list_schema()),
+        "config" => Ok(Config::schema()),
+        "bookmarks" => Ok(Bookmark::list_schema()),
+        file => {
+            let file = Path::new(file);
+            Schema::compile(&read_document(file)?)
+                .with_context(|| format!("{} is not a usable schema", file.display()))
+        }
+    }
+}
+
+// Reads a file, and when a schema is given refuses it unless it matches
+fn load_document(file: &Path, schema: Option<&str>) -> Result<Value, AppError> {
+    let document = read_document(file)?;
+    if let Some(schema) = schema {
+        load_schema(schema)?
+            .check(&document)
+            .with_context(|| format!("{} failed validation", file.display()))?;
+    }
+    Ok(document)
+}
+
+// One line per match: where it was found, then the value as compact JSON
+pub fn query(expression: &str, file: &Path, schema: Option<&str>) -> Result<(), AppError> {
+    let document = load_document(file, schema)?;
+    let matches = json_path::query(expression, &document)?;
+    for Match { path, value } in &matches {
+        println!("{path}: {value}");
+    }
+    if matches.is_empty() {
+        eprintln!("No matches for {expression}");
+    }
+    Ok(())
+}
+
+// The error lists every violation, so the whole file can be fixed in one go
+pub fn validate(schema: &str, file: &Path) -> Result<(), AppError> {
+    load_document(file, Some(schema))?;
+    println!("{} is valid", file.display());
+    Ok(())
+}
+
+// The JSON Patch that turns one file into the other
+pub fn diff(from: &Path, to: &Path, schema: Option<&str>) -> Result<(), AppError> {
+    let operations = json_patch::diff(&load_document(from, schema)?, &load_document(to, schema)?);
+    let patch = serde_json::to_string_pretty(&operations).context("Failed to write the patch")?;
+    println!("{patch}");
+    Ok(())
+}
+
+// A list of operations is a JSON Patch, anything else a JSON Merge Patch. Prints the patched document
+pub fn patch(file: &Path, patch: &Path, schema: Option<&str>) -> Result<(), AppError> {
+    let mut document = load_document(file, schema)?;
+    match read_document(patch)? {
+        operations @ Value::Array(_) => {
+            let operations: Vec<Operation> = serde_json::from_value(operations)
+                .with_context(|| format!("{} is not a JSON Patch", patch.display()))?;
+            json_patch::apply(&mut document, &operations)
+                .with_context(|| format!("Failed to apply {}", patch.display()))?;
+        }
+        merge => json_patch::merge_patch(&mut document, &merge),
+    }
+    // The patched document has to pass the same schema as the original
+    if let Some(schema) = schema {
+        load_schema(schema)?
+            .check(&document)
+            .context("The patched document failed validation")?;
+    }
+    let patched =
+        serde_json::to_string_pretty(&document).context("Failed to write the document")?;
+    println!("{patched}");
+    Ok(())
+}
+
+// Writes the input in the format of the output's extension, and tells what did not survive
+pub fn convert(input: &Path, output: &Path, schema: Option<&str>) -> Result<(), AppError> {
+    let format = Format::from_path(output)
+        .ok_or_else(|| FormatError::UnknownFormat(output.display().to_string()))?;
+    let document = load_document(input, schema)?;
+    let Encoded { bytes, losses } = format
+        .encode_value(&document)
+        .with_context(|| format!("Cannot write {} as {}", input.display(), format))?;
+    fs::write(output, &bytes).with_context(|| format!("Failed to write {}", output.display()))?;
+
+    for loss in &losses {
+        eprintln!("Lossy: {loss}");
+    }
+    println!(
+        "Wrote {} bytes of {} to {}, {} lossy values",
+        bytes.len(),
+        format,
+        output.display(),
+        losses.len()
+    );
+    Ok(())
+}
+
+fn is_html(file: &Path) -> bool {
+    file.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| matches!(extension.to_ascii_lowercase().as_str(), "html" | "htm"))
+}
+
+// Loads the store, does one action, and saves the store again if the action changed it
+pub fn bookmarks(file: &Path, action: BookmarkAction) -> Result<(), AppError> {
+    let mut store = BookmarkStore::open(file)?;
+    let print = |bookmarks: Vec<&Bookmark>| {
+        for bookmark in &bookmarks {
+            println!("{bookmark}");
+        }
+        if bookmarks.is_empty() {
+            eprintln!("No bookmarks");
+        }
+    };
+
+    match action {
+        BookmarkAction::List => {
+            print(store.list());
+            return Ok(());
+        }
+        BookmarkAction::Search { query } => {
+            print(store.search(&query));
+            return Ok(());
+        }
+        BookmarkAction::Export { file: export } => {
+            let text = if is_html(&export) {
+                store.to_html()
+            } else {
+                store.to_json()
+            };
+            fs::write(&export, text)
+                .with_context(|| format!("Failed to write {}", export.display()))?;
+            println!("Exported {} bookmarks to {}", store.len(), export.display());
+            return Ok(());
+        }
+        BookmarkAction::Import { file: import } => {
+            let text = fs::read_to_string(&import)
+                .with_context(|| format!("Failed to read {}", import.display()))?;
+            let summary = if is_html(&import) {
+                store.import_html(&text)?
+            } else {
+                store.import_json(&text)?
+            };
+            println!(
+                "Imported {} new and {} replaced bookmarks from {}",
+                summary.added,
+                summary.replaced,
+                import.display()
+            );
+        }
+        // Normalized, so the same site is always saved the same way
+        BookmarkAction::Add { name, url } => {
+            store.add(Bookmark::new(name, Url::parse(&url)?.to_string()))?
+        }
+        BookmarkAction::Remove { name } => {
+            let removed = store.remove(&name)?;
+            println!("Removed {removed}");
+        }
+        BookmarkAction::Rename { name, new_name } => store.rename(&name, &new_name)?,
+        BookmarkAction::Tag { name, tag } => store.tag(&name, &tag)?,
+        BookmarkAction::Untag { name, tag } => store.untag(&name, &tag)?,
+        BookmarkAction::Move { name, folder } => store.move_to(&name, &folder)?,
+    }
+    store.save(file)?;
+    Ok(())
+}
+
+// The bookmarks of a store as (name, URL) pairs, sorted like bookmarks list shows them.
+// URLs that do not parse are left to the checker, which reports them as dead links
+pub fn bookmark_links(file: &Path) -> Result<Vec<(String, String)>, AppError> {
+    let store = BookmarkStore::open(file)?;
+    Ok(store
+        .list()
+        .into_iter()
+        .map(|bookmark| (bookmark.name.clone(), bookmark.url.clone()))
+        .collect())
+}
+
+// Prints one line per link, and fails after the report if any of them is dead
+pub async fn check(
+    links: Vec<(String, String)>,
+    timeout: Option<Duration>,
+    per_host: Option<usize>,
+) -> Result<(), AppError> {
+    let mut checker = LinkChecker::new(SystemResolver);
+    if let Some(timeout) = timeout {
+        checker = checker.timeout(timeout);
+    }
+    if let Some(per_host) = per_host {
+        checker = checker.per_host(per_host);
+    }
+    let report = checker.check(links).await;
+    println!("{report}");
+    Ok(report.check()?)
+}
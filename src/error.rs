@@ -0,0 +1,365 @@
+use crate::bakery::BakeryError;
+use crate::binary::DecodeError;
+use crate::bookmarks::BookmarkError;
+use crate::cli::CliError;
+use crate::fibonacci::FibonacciError;
+use crate::formats::FormatError;
+use crate::json_patch::PatchError;
+use crate::json_path::JsonPathError;
+use crate::json_schema::{SchemaError, ValidationError};
+use crate::link_check::DeadLinks;
+use crate::llm::{rate_limit::RateLimitError, role::ParseRoleError, template::TemplateError};
+use crate::query_result::QueryResultError;
+use crate::resolver::ResolveError;
+use crate::supervisor::TaskError;
+use crate::url::UrlError;
+use crate::worker_pool::PoolError;
+use std::{
+    backtrace::{Backtrace, BacktraceStatus},
+    error::Error,
+    fmt, io,
+    num::ParseIntError,
+};
+use tokio::task::JoinError;
+
+/*
+ * The error type shared by every lesson.
+ *
+ * Box<dyn Error> (see smart_pointers) is convenient, but the caller can no longer tell which part of the program failed.
+ * AppError keeps one variant per subsystem so callers can match on kind(), and adds what Box<dyn Error> is missing:
+ *
+ * - context: .context("while doing X") wraps an error in a message describing what we were trying to do
+ * - source() chains: every context points to the error it wraps, down to the original error and its own sources
+ * - a backtrace captured where the original error was converted (only when RUST_BACKTRACE or RUST_LIB_BACKTRACE is set)
+ *
+ * report() renders the whole chain, which main() prints when a lesson fails.
+ */
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    Io(io::Error),
+    ParseInt(ParseIntError),
+    Json(serde_json::Error),
+    Role(ParseRoleError),
+    Template(TemplateError),
+    RateLimit(RateLimitError),
+    Decode(DecodeError),
+    Bakery(BakeryError),
+    Pool(PoolError),
+    Fibonacci(FibonacciError),
+    Join(JoinError),
+    Task(TaskError),
+    Cli(CliError),
+    QueryResult(QueryResultError),
+    JsonPath(JsonPathError),
+    Schema(SchemaError),
+    Validation(ValidationError),
+    Format(FormatError),
+    Patch(PatchError),
+    Bookmark(BookmarkError),
+    Url(UrlError),
+    Resolve(ResolveError),
+    LinkCheck(DeadLinks),
+    Other(Box<dyn Error + Send + Sync>),
+    Context {
+        message: String,
+        source: Box<AppError>,
+    },
+}
+
+#[derive(Debug)]
+pub struct AppError {
+    kind: ErrorKind,
+    backtrace: Backtrace,
+}
+
+impl AppError {
+    pub fn new(kind: ErrorKind) -> Self {
+        AppError {
+            kind,
+            backtrace: Backtrace::capture(),
+        }
+    }
+
+    // Any other error type, e.g. one only a single lesson knows about
+    pub fn other<E>(error: E) -> Self
+    where
+        E: Into<Box<dyn Error + Send + Sync>>,
+    {
+        AppError::new(ErrorKind::Other(error.into()))
+    }
+
+    pub fn context(self, message: impl Into<String>) -> Self {
+        AppError {
+            kind: ErrorKind::Context {
+                message: message.into(),
+                source: Box::new(self),
+            },
+            // The wrapped error already carries the backtrace of where things went wrong
+            backtrace: Backtrace::disabled(),
+        }
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    // The innermost AppError, skipping all context layers
+    pub fn root(&self) -> &AppError {
+        match &self.kind {
+            ErrorKind::Context { source, .. } => source.root(),
+            _ => self,
+        }
+    }
+
+    pub fn backtrace(&self) -> &Backtrace {
+        &self.root().backtrace
+    }
+
+    pub fn report(&self) -> Report<'_> {
+        Report {
+            error: self,
+            backtrace: true,
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Subsystem variants are transparent: they print the wrapped error as is
+        match &self.kind {
+            ErrorKind::Io(e) => write!(f, "{}", e),
+            ErrorKind::ParseInt(e) => write!(f, "{}", e),
+            ErrorKind::Json(e) => write!(f, "{}", e),
+            ErrorKind::Role(e) => write!(f, "{}", e),
+            ErrorKind::Template(e) => write!(f, "{}", e),
+            ErrorKind::RateLimit(e) => write!(f, "{}", e),
+            ErrorKind::Decode(e) => write!(f, "{}", e),
+            ErrorKind::Bakery(e) => write!(f, "{}", e),
+            ErrorKind::Pool(e) => write!(f, "{}", e),
+            ErrorKind::Fibonacci(e) => write!(f, "{}", e),
+            ErrorKind::Join(e) => write!(f, "{}", e),
+            ErrorKind::Task(e) => write!(f, "{}", e),
+            ErrorKind::Cli(e) => write!(f, "{}", e),
+            ErrorKind::QueryResult(e) => write!(f, "{}", e),
+            ErrorKind::JsonPath(e) => write!(f, "{}", e),
+            ErrorKind::Schema(e) => write!(f, "{}", e),
+            ErrorKind::Validation(e) => write!(f, "{}", e),
+            ErrorKind::Format(e) => write!(f, "{}", e),
+            ErrorKind::Patch(e) => write!(f, "{}", e),
+            ErrorKind::Bookmark(e) => write!(f, "{}", e),
+            ErrorKind::Url(e) => write!(f, "{}", e),
+            ErrorKind::Resolve(e) => write!(f, "{}", e),
+            ErrorKind::LinkCheck(e) => write!(f, "{}", e),
+            ErrorKind::Other(e) => write!(f, "{}", e),
+            ErrorKind::Context { message, .. } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for AppError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match &self.kind {
+            ErrorKind::Io(e) => e.source(),
+            ErrorKind::ParseInt(e) => e.source(),
+            ErrorKind::Json(e) => e.source(),
+            ErrorKind::Role(e) => e.source(),
+            ErrorKind::Template(e) => e.source(),
+            ErrorKind::RateLimit(e) => e.source(),
+            ErrorKind::Decode(e) => e.source(),
+            ErrorKind::Bakery(e) => e.source(),
+            ErrorKind::Pool(e) => e.source(),
+            ErrorKind::Fibonacci(e) => e.source(),
+            ErrorKind::Join(e) => e.source(),
+            ErrorKind::Task(e) => e.source(),
+            ErrorKind::Cli(e) => e.source(),
+            ErrorKind::QueryResult(e) => e.source(),
+            ErrorKind::JsonPath(e) => e.source(),
+            ErrorKind::Schema(e) => e.source(),
+            ErrorKind::Validation(e) => e.source(),
+            ErrorKind::Format(e) => e.source(),
+            ErrorKind::Patch(e) => e.source(),
+            ErrorKind::Bookmark(e) => e.source(),
+            ErrorKind::Url(e) => e.source(),
+            ErrorKind::Resolve(e) => e.source(),
+            ErrorKind::LinkCheck(e) => e.source(),
+            ErrorKind::Other(e) => e.source(),
+            ErrorKind::Context { source, .. } => Some(source.as_ref()),
+        }
+    }
+}
+
+macro_rules! impl_from {
+    ($($error:ty => $variant:ident),* $(,)?) => {
+        $(
+            impl From<$error> for AppError {
+                fn from(error: $error) -> Self {
+                    AppError::new(ErrorKind::$variant(error))
+                }
+            }
+        )*
+    };
+}
+
+impl_from!(
+    io::Error => Io,
+    ParseIntError => ParseInt,
+    serde_json::Error => Json,
+    ParseRoleError => Role,
+    TemplateError => Template,
+    RateLimitError => RateLimit,
+    DecodeError => Decode,
+    BakeryError => Bakery,
+    PoolError => Pool,
+    FibonacciError => Fibonacci,
+    JoinError => Join,
+    TaskError => Task,
+    CliError => Cli,
+    QueryResultError => QueryResult,
+    JsonPathError => JsonPath,
+    SchemaError => Schema,
+    ValidationError => Validation,
+    FormatError => Format,
+    PatchError => Patch,
+    BookmarkError => Bookmark,
+    UrlError => Url,
+    ResolveError => Resolve,
+    DeadLinks => LinkCheck,
+);
+
+// Adds .context() and .with_context() to any Result whose error converts into AppError
+pub trait Context<T> {
+    fn context(self, message: impl Into<String>) -> Result<T, AppError>;
+
+    // The message is only built when there actually is an error (lazily evaluated, like unwrap_or_else)
+    fn with_context<F>(self, message: F) -> Result<T, AppError>
+    where
+        F: FnOnce() -> String;
+}
+
+impl<T, E> Context<T> for Result<T, E>
+where
+    E: Into<AppError>,
+{
+    fn context(self, message: impl Into<String>) -> Result<T, AppError> {
+        self.map_err(|e| e.into().context(message))
+    }
+
+    fn with_context<F>(self, message: F) -> Result<T, AppError>
+    where
+        F: FnOnce() -> String,
+    {
+        self.map_err(|e| e.into().context(message()))
+    }
+}
+
+// Prints an error followed by every error in its source() chain, and the backtrace if one was captured
+pub struct Report<'a> {
+    error: &'a AppError,
+    backtrace: bool,
+}
+
+impl Report<'_> {
+    // For errors that are expected and handled, where the backtrace would only be noise
+    pub fn without_backtrace(mut self) -> Self {
+        self.backtrace = false;
+        self
+    }
+}
+
+impl fmt::Display for Report<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Error: {}", self.error)?;
+
+        let mut source = self.error.source();
+        if source.is_some() {
+            write!(f, "\n\nCaused by:")?;
+        }
+        let mut depth = 0;
+        while let Some(error) = source {
+            write!(f, "\n{:>5}: {}", depth, error)?;
+            source = error.source();
+            depth += 1;
+        }
+
+        let backtrace = self.error.backtrace();
+        if self.backtrace && backtrace.status() == BacktraceStatus::Captured {
+            write!(f, "\n\nBacktrace:\n{}", backtrace)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<i32, AppError> {
+        text.parse::<i32>()
+            .with_context(|| format!("could not parse \"{text}\""))
+    }
+
+    #[test]
+    fn conversions_pick_the_subsystem_variant() {
+        let error: AppError = "x".parse::<u8>().unwrap_err().into();
+        assert!(matches!(error.kind(), ErrorKind::ParseInt(_)));
+        assert_eq!(error.to_string(), "invalid digit found in string");
+
+        let error = AppError::from(io::Error::new(io::ErrorKind::NotFound, "no such file"));
+        assert!(matches!(error.kind(), ErrorKind::Io(_)));
+        assert_eq!(error.to_string(), "no such file");
+
+        let error = AppError::other("something else");
+        assert!(matches!(error.kind(), ErrorKind::Other(_)));
+        assert_eq!(error.to_string(), "something else");
+    }
+
+    #[test]
+    fn context_wraps_and_root_unwraps() {
+        let error = parse("ten").context("reading the config").unwrap_err();
+        assert_eq!(error.to_string(), "reading the config");
+        assert!(matches!(error.kind(), ErrorKind::Context { .. }));
+        assert!(matches!(error.root().kind(), ErrorKind::ParseInt(_)));
+
+        let chain: Vec<String> = std::iter::successors(error.source(), |&e| e.source())
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(
+            chain,
+            ["could not parse \"ten\"", "invalid digit found in string"]
+        );
+    }
+
+    #[test]
+    fn with_context_is_lazy() {
+        let ok: Result<i32, ParseIntError> = Ok(1);
+        let value = ok
+            .with_context(|| panic!("only built for an error"))
+            .unwrap();
+        assert_eq!(value, 1);
+        assert_eq!(parse("12").unwrap(), 12);
+    }
+
+    #[test]
+    fn report_lists_the_whole_chain() {
+        let error = parse("ten").context("reading the config").unwrap_err();
+        assert_eq!(
+            error.report().without_backtrace().to_string(),
+            "Error: reading the config\n\nCaused by:\n    0: could not parse \"ten\"\n    1: invalid digit found in string"
+        );
+        let error = AppError::other("alone");
+        assert_eq!(
+            error.report().without_backtrace().to_string(),
+            "Error: alone"
+        );
+    }
+
+    #[test]
+    fn the_backtrace_is_the_one_of_the_original_error() {
+        let error = AppError::other("inner");
+        let status = error.backtrace().status();
+        let error = error.context("outer");
+        assert_eq!(error.backtrace().status(), status);
+        assert_eq!(error.backtrace.status(), BacktraceStatus::Disabled);
+    }
+}
@@ -0,0 +1,334 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    time::{Duration, Instant},
+};
+
+/*
+ * Five ways to compute the n-th Fibonacci number, from hopeless to fast.
+ *
+ * - naive: the textbook recursion. It computes the same values over and over, so it takes exponential time.
+ * - memoized: the same recursion, but every value is remembered the first time it is computed. Linear time.
+ * - iterative: walks up from 0 and 1 keeping only the last two values. Linear time, constant memory.
+ * - matrix: [[1, 1], [1, 0]]^n = [[F(n+1), F(n)], [F(n), F(n-1)]], and a matrix power only needs log(n) multiplications.
+ * - big: like iterative, but on a BigUint that never overflows.
+ *
+ * F(93) is the largest Fibonacci number that fits in a u64. Instead of wrapping around (or panicking in debug builds),
+ * every u64 version returns FibonacciError::Overflow past that point. Only big() keeps going.
+ */
+
+pub const MAX_U64_N: u32 = 93;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FibonacciError {
+    Overflow { n: u32 },
+}
+
+impl fmt::Display for FibonacciError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FibonacciError::Overflow { n } => write!(
+                f,
+                "fibonacci({}) does not fit in a u64, the largest is fibonacci({})",
+                n, MAX_U64_N
+            ),
+        }
+    }
+}
+
+impl Error for FibonacciError {}
+
+pub fn naive(n: u32) -> Result<u64, FibonacciError> {
+    // Checked up front: finding the overflow the slow way would take exponential time
+    if n > MAX_U64_N {
+        return Err(FibonacciError::Overflow { n });
+    }
+    match n {
+        0 => Ok(0),
+        1 => Ok(1),
+        _ => naive(n - 1)?
+            .checked_add(naive(n - 2)?)
+            .ok_or(FibonacciError::Overflow { n }),
+    }
+}
+
+pub fn memoized(n: u32) -> Result<u64, FibonacciError> {
+    // Checked up front: the recursion is n levels deep, so a huge n would overflow the stack before the u64
+    if n > MAX_U64_N {
+        return Err(FibonacciError::Overflow { n });
+    }
+
+    fn fib(n: u32, memo: &mut HashMap<u32, u64>) -> u64 {
+        if n < 2 {
+            return n as u64;
+        }
+        if let Some(&value) = memo.get(&n) {
+            return value;
+        }
+        let value = fib(n - 1, memo) + fib(n - 2, memo);
+        memo.insert(n, value);
+        value
+    }
+
+    Ok(fib(n, &mut HashMap::new()))
+}
+
+pub fn iterative(n: u32) -> Result<u64, FibonacciError> {
+    if n == 0 {
+        return Ok(0);
+    }
+
+    // Stop at F(n) itself: taking one more step would compute F(n+1), which overflows one step earlier
+    let (mut previous, mut current) = (0u64, 1u64);
+    for _ in 1..n {
+        let next = previous
+            .checked_add(current)
+            .ok_or(FibonacciError::Overflow { n })?;
+        previous = current;
+        current = next;
+    }
+    Ok(current)
+}
+
+type Matrix = [[u64; 2]; 2];
+
+fn multiply(a: &Matrix, b: &Matrix) -> Option<Matrix> {
+    let cell = |row: usize, column: usize| {
+        a[row][0]
+            .checked_mul(b[0][column])?
+            .checked_add(a[row][1].checked_mul(b[1][column])?)
+    };
+    Some([[cell(0, 0)?, cell(0, 1)?], [cell(1, 0)?, cell(1, 1)?]])
+}
+
+pub fn matrix(n: u32) -> Result<u64, FibonacciError> {
+    if n == 0 {
+        return Ok(0);
+    }
+
+    // M^(n-1) has F(n) in its top left corner. M^n would also hold F(n+1), the same trap as in iterative()
+    let mut result: Matrix = [[1, 0], [0, 1]];
+    let mut base: Matrix = [[1, 1], [1, 0]];
+    let mut exponent = n - 1;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = multiply(&result, &base).ok_or(FibonacciError::Overflow { n })?;
+        }
+        exponent >>= 1;
+        // Squaring once more than needed could overflow even though the answer fits
+        if exponent > 0 {
+            base = multiply(&base, &base).ok_or(FibonacciError::Overflow { n })?;
+        }
+    }
+    Ok(result[0][0])
+}
+
+pub fn big(n: u32) -> BigUint {
+    let (mut current, mut next) = (BigUint::from(0), BigUint::from(1));
+    for _ in 0..n {
+        let after = &current + &next;
+        current = std::mem::replace(&mut next, after);
+    }
+    current
+}
+
+/*
+ * Just enough of an arbitrary precision unsigned integer for Fibonacci: addition and printing.
+ * The digits are stored in base 10^9, least significant first, so Display does not need any division.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigUint {
+    limbs: Vec<u32>,
+}
+
+impl BigUint {
+    const BASE: u32 = 1_000_000_000;
+
+    pub fn digits(&self) -> usize {
+        let last = self.limbs.last().copied().unwrap_or(0);
+        (self.limbs.len() - 1) * 9 + last.to_string().len()
+    }
+}
+
+impl From<u64> for BigUint {
+    fn from(mut value: u64) -> Self {
+        let mut limbs = vec![(value % Self::BASE as u64) as u32];
+        value /= Self::BASE as u64;
+        while value > 0 {
+            limbs.push((value % Self::BASE as u64) as u32);
+            value /= Self::BASE as u64;
+        }
+        BigUint { limbs }
+    }
+}
+
+impl std::ops::Add for &BigUint {
+    type Output = BigUint;
+
+    fn add(self, other: &BigUint) -> BigUint {
+        let mut limbs = Vec::with_capacity(self.limbs.len().max(other.limbs.len()) + 1);
+        let mut carry = 0;
+        for i in 0..self.limbs.len().max(other.limbs.len()) {
+            let sum = self.limbs.get(i).copied().unwrap_or(0)
+                + other.limbs.get(i).copied().unwrap_or(0)
+                + carry; // At most 2 * (10^9 - 1) + 1, well within a u32
+            limbs.push(sum % BigUint::BASE);
+            carry = sum / BigUint::BASE;
+        }
+        if carry > 0 {
+            limbs.push(carry);
+        }
+        BigUint { limbs }
+    }
+}
+
+impl fmt::Display for BigUint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut limbs = self.limbs.iter().rev();
+        if let Some(first) = limbs.next() {
+            write!(f, "{}", first)?;
+        }
+        for limb in limbs {
+            write!(f, "{:09}", limb)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Naive,
+    Memoized,
+    Iterative,
+    Matrix,
+    Big,
+}
+
+impl Method {
+    pub const ALL: [Method; 5] = [
+        Method::Naive,
+        Method::Memoized,
+        Method::Iterative,
+        Method::Matrix,
+        Method::Big,
+    ];
+
+    // Past this the naive version takes seconds and the benchmark skips it
+    pub const NAIVE_LIMIT: u32 = 32;
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Method::Naive => "naive",
+            Method::Memoized => "memoized",
+            Method::Iterative => "iterative",
+            Method::Matrix => "matrix",
+            Method::Big => "big",
+        }
+    }
+
+    pub fn run(self, n: u32) -> Result<BigUint, FibonacciError> {
+        match self {
+            Method::Naive => naive(n).map(BigUint::from),
+            Method::Memoized => memoized(n).map(BigUint::from),
+            Method::Iterative => iterative(n).map(BigUint::from),
+            Method::Matrix => matrix(n).map(BigUint::from),
+            Method::Big => Ok(big(n)),
+        }
+    }
+}
+
+pub struct Timing {
+    pub method: Method,
+    pub n: u32,
+    pub elapsed: Duration,
+    pub result: Result<BigUint, FibonacciError>,
+}
+
+/*
+ * Runs every method for every n and keeps the fastest of `runs` attempts,
+ * which filters out most of the noise from the OS scheduling other work in between.
+ * The naive method is left out above NAIVE_LIMIT, so there is no Timing for it there.
+ */
+pub fn benchmark(ns: impl IntoIterator<Item = u32>, runs: u32) -> Vec<Timing> {
+    let mut timings = Vec::new();
+    for n in ns {
+        for method in Method::ALL {
+            if method == Method::Naive && n > Method::NAIVE_LIMIT {
+                continue;
+            }
+
+            let mut fastest = Duration::MAX;
+            let mut result = Err(FibonacciError::Overflow { n });
+            for _ in 0..runs.max(1) {
+                let started_at = Instant::now();
+                result = method.run(n);
+                fastest = fastest.min(started_at.elapsed());
+            }
+            timings.push(Timing {
+                method,
+                n,
+                elapsed: fastest,
+                result,
+            });
+        }
+    }
+    timings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const F93: u64 = 12_200_160_415_121_876_738;
+
+    #[test]
+    fn every_method_agrees() {
+        let ns = (0..=Method::NAIVE_LIMIT).chain([50, 92, MAX_U64_N, MAX_U64_N + 1, 200]);
+        for timing in benchmark(ns, 1) {
+            let expected = big(timing.n);
+            match &timing.result {
+                Ok(answer) => assert_eq!(
+                    answer,
+                    &expected,
+                    "{} of {}",
+                    timing.method.name(),
+                    timing.n
+                ),
+                Err(e) => assert!(timing.n > MAX_U64_N, "{}: {e}", timing.method.name()),
+            }
+        }
+    }
+
+    #[test]
+    fn the_largest_u64_and_past_it() {
+        for method in [memoized, iterative, matrix] {
+            assert_eq!(method(MAX_U64_N), Ok(F93));
+            assert_eq!(
+                method(MAX_U64_N + 1),
+                Err(FibonacciError::Overflow { n: MAX_U64_N + 1 })
+            );
+        }
+        assert_eq!(big(MAX_U64_N), BigUint::from(F93));
+    }
+
+    #[test]
+    fn naive_rejects_large_n_without_computing() {
+        assert_eq!(naive(20), Ok(6765));
+        assert_eq!(naive(1_000), Err(FibonacciError::Overflow { n: 1_000 }));
+        assert_eq!(
+            memoized(u32::MAX),
+            Err(FibonacciError::Overflow { n: u32::MAX })
+        );
+    }
+
+    #[test]
+    fn big_numbers_print_every_digit() {
+        assert_eq!(big(0).to_string(), "0");
+        assert_eq!(BigUint::from(1_000_000_000).to_string(), "1000000000");
+        let f100 = big(100);
+        assert_eq!(f100.to_string(), "354224848179261915075");
+        assert_eq!(f100.digits(), 21);
+        assert_eq!(big(10_000).digits(), 2090);
+    }
+}
@@ -0,0 +1,791 @@
+use crate::binary::{ByteCursor, DecodeError, Endian};
+use crate::json_schema::pointer_token;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Number, Value};
+use std::{error::Error, fmt, path::Path};
+
+/*
+ * The same data as JSON, CSV or a compact binary encoding.
+ *
+ * Every format converts to and from serde_json::Value, so anything that derives Serialize / Deserialize
+ * (Ap, Config, ChatCompletionMessage, Bookmark, ...) can be written in any of them: encode() turns the data into
+ * a Value and the Value into bytes, decode() goes the other way.
+ *
+ * CSV is a table, so it only holds a list of records (objects), one row each with a column per field:
+ * - null and missing fields are empty cells
+ * - numbers, bools, arrays and objects are written as JSON text, e.g. {"v6":"hex"}
+ * - strings are written as they are, unless they would read back as something else ("42", "true", "")
+ *   in which case they are written as a JSON string literal, e.g. "42" with its quotes
+ * Reading a cell back: empty is null, valid JSON is that value, anything else is a string.
+ *
+ * Binary is a tagged encoding of the Value tree behind a 4 byte magic number: a tag byte per value, LEB128
+ * variable length integers for counts, lengths and whole numbers, little-endian f64 for floats. Nothing is lost.
+ * Reading stops at arrays and objects nested more than 128 deep, the limit serde_json has for JSON text,
+ * so a small crafted file cannot recurse until the stack overflows.
+ *
+ * Whatever cannot survive a conversion is not refused but reported as a Loss, with the JSON pointer of the
+ * value it happened to, so the caller can decide whether that is acceptable.
+ */
+
+const MAGIC: &[u8; 4] = b"RBV1";
+
+const NULL: u8 = 0;
+const FALSE: u8 = 1;
+const TRUE: u8 = 2;
+const UNSIGNED: u8 = 3;
+const NEGATIVE: u8 = 4; // Stored as -(n + 1), so i64::MIN fits as well
+const FLOAT: u8 = 5;
+const STRING: u8 = 6;
+const ARRAY: u8 = 7;
+const OBJECT: u8 = 8;
+
+const MAX_DEPTH: usize = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Csv,
+    Binary,
+}
+
+#[derive(Debug)]
+pub enum FormatError {
+    UnknownFormat(String),
+    NotRecords {
+        pointer: String,
+        found: &'static str,
+    },
+    Csv {
+        line: usize,
+        message: String,
+    },
+    BadMagic,
+    Binary {
+        position: usize,
+        message: String,
+    },
+    Truncated(DecodeError),
+    Json(serde_json::Error),
+    // The data does not fit the requested type, or the other way round
+    Data(serde_json::Error),
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::UnknownFormat(file) => write!(
+                f,
+                "Cannot tell the format of {}, expected a .json, .csv or .bin file",
+                file
+            ),
+            FormatError::NotRecords { pointer, found } => write!(
+                f,
+                "CSV holds a list of objects, \"{}\" is {}",
+                pointer, found
+            ),
+            FormatError::Csv { line, message } => {
+                write!(f, "Invalid CSV on line {}: {}", line, message)
+            }
+            FormatError::BadMagic => write!(f, "Not a binary file of this program"),
+            FormatError::Binary { position, message } => {
+                write!(f, "Invalid binary data at byte {}: {}", position, message)
+            }
+            FormatError::Truncated(_) => write!(f, "The binary data is cut short"),
+            FormatError::Json(_) => write!(f, "Invalid JSON"),
+            FormatError::Data(_) => write!(f, "The data does not have the expected shape"),
+        }
+    }
+}
+
+impl Error for FormatError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FormatError::Truncated(source) => Some(source),
+            FormatError::Json(source) | FormatError::Data(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<DecodeError> for FormatError {
+    fn from(error: DecodeError) -> Self {
+        FormatError::Truncated(error)
+    }
+}
+
+// Something that will not read back the way it was written
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loss {
+    pub pointer: String,
+    pub reason: String,
+}
+
+impl fmt::Display for Loss {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"{}\": {}", self.pointer, self.reason)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Encoded {
+    pub bytes: Vec<u8>,
+    pub losses: Vec<Loss>,
+}
+
+impl Format {
+    pub const ALL: [Format; 3] = [Format::Json, Format::Csv, Format::Binary];
+
+    // By file extension: .json, .csv or .bin
+    pub fn from_path(path: &Path) -> Option<Format> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "json" => Some(Format::Json),
+            "csv" => Some(Format::Csv),
+            "bin" => Some(Format::Binary),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Format::Json => "JSON",
+            Format::Csv => "CSV",
+            Format::Binary => "binary",
+        }
+    }
+
+    pub fn encode<T: Serialize + ?Sized>(self, data: &T) -> Result<Encoded, FormatError> {
+        let value = serde_json::to_value(data).map_err(FormatError::Data)?;
+        self.encode_value(&value)
+    }
+
+    pub fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T, FormatError> {
+        serde_json::from_value(self.decode_value(bytes)?).map_err(FormatError::Data)
+    }
+
+    pub fn encode_value(self, value: &Value) -> Result<Encoded, FormatError> {
+        match self {
+            Format::Json => Ok(Encoded {
+                bytes: serde_json::to_vec_pretty(value).map_err(FormatError::Json)?,
+                losses: Vec::new(),
+            }),
+            Format::Csv => encode_csv(value),
+            Format::Binary => {
+                let mut bytes = MAGIC.to_vec();
+                encode_binary(value, &mut bytes);
+                Ok(Encoded {
+                    bytes,
+                    losses: Vec::new(),
+                })
+            }
+        }
+    }
+
+    pub fn decode_value(self, bytes: &[u8]) -> Result<Value, FormatError> {
+        match self {
+            Format::Json => serde_json::from_slice(bytes).map_err(FormatError::Json),
+            Format::Csv => decode_csv(bytes),
+            Format::Binary => decode_binary(bytes),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+// What kind of JSON value, for error messages. Shared with query_result.rs and json_patch.rs
+pub fn kind_of(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a bool",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+fn encode_csv(value: &Value) -> Result<Encoded, FormatError> {
+    let mut losses = Vec::new();
+    let records: Vec<&Map<String, Value>> = match value {
+        Value::Array(items) => items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| {
+                item.as_object().ok_or_else(|| FormatError::NotRecords {
+                    pointer: format!("/{index}"),
+                    found: kind_of(item),
+                })
+            })
+            .collect::<Result<_, _>>()?,
+        Value::Object(record) => {
+            losses.push(Loss {
+                pointer: String::new(),
+                reason: "a single object is written as a one row table, it reads back as a list"
+                    .to_string(),
+            });
+            vec![record]
+        }
+        other => {
+            return Err(FormatError::NotRecords {
+                pointer: String::new(),
+                found: kind_of(other),
+            })
+        }
+    };
+
+    // Every field of every record gets a column, in the order they first show up
+    let mut columns: Vec<&str> = Vec::new();
+    for record in &records {
+        for name in record.keys() {
+            if !columns.contains(&name.as_str()) {
+                columns.push(name);
+            }
+        }
+    }
+
+    let mut text = String::new();
+    if columns.is_empty() {
+        if !records.is_empty() {
+            losses.push(Loss {
+                pointer: String::new(),
+                reason:
+                    "objects without fields leave nothing to write, reads back as an empty list"
+                        .to_string(),
+            });
+        }
+        return Ok(Encoded {
+            bytes: Vec::new(),
+            losses,
+        });
+    }
+    write_csv_line(&mut text, columns.iter().map(|name| name.to_string()));
+    for (index, record) in records.iter().enumerate() {
+        let cells = columns.iter().map(|&name| match record.get(name) {
+            Some(value) => csv_cell(value),
+            None => {
+                losses.push(Loss {
+                    pointer: format!("/{index}/{}", pointer_token(name)),
+                    reason: "missing, reads back as null".to_string(),
+                });
+                String::new()
+            }
+        });
+        write_csv_line(&mut text, cells.collect::<Vec<_>>());
+    }
+
+    Ok(Encoded {
+        bytes: text.into_bytes(),
+        losses,
+    })
+}
+
+fn csv_cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        // A string that would read back as another value keeps its JSON quotes
+        Value::String(text) if text.is_empty() || serde_json::from_str::<Value>(text).is_ok() => {
+            value.to_string()
+        }
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn write_csv_line(text: &mut String, cells: impl IntoIterator<Item = String>) {
+    let quoted: Vec<String> = cells
+        .into_iter()
+        .map(|cell| {
+            if cell.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", cell.replace('"', "\"\""))
+            } else {
+                cell
+            }
+        })
+        .collect();
+    text.push_str(&quoted.join(","));
+    text.push('\n');
+}
+
+fn decode_csv(bytes: &[u8]) -> Result<Value, FormatError> {
+    let text = std::str::from_utf8(bytes).map_err(|e| FormatError::Csv {
+        line: 1 + bytes[..e.valid_up_to()]
+            .iter()
+            .filter(|&&b| b == b'\n')
+            .count(),
+        message: "not valid UTF-8".to_string(),
+    })?;
+    let mut lines = parse_csv(text)?.into_iter();
+    let Some((_, header)) = lines.next() else {
+        return Ok(Value::Array(Vec::new()));
+    };
+    for (index, name) in header.iter().enumerate() {
+        if header[..index].contains(name) {
+            return Err(FormatError::Csv {
+                line: 1,
+                message: format!("the column {name:?} appears twice"),
+            });
+        }
+    }
+
+    let mut records = Vec::new();
+    for (line, cells) in lines {
+        if cells.len() != header.len() {
+            return Err(FormatError::Csv {
+                line,
+                message: format!("expected {} fields, found {}", header.len(), cells.len()),
+            });
+        }
+        let record: Map<String, Value> = header
+            .iter()
+            .cloned()
+            .zip(cells.into_iter().map(|cell| match cell.as_str() {
+                "" => Value::Null,
+                text => serde_json::from_str(text).unwrap_or(Value::String(cell)),
+            }))
+            .collect();
+        records.push(Value::Object(record));
+    }
+    Ok(Value::Array(records))
+}
+
+// The fields of every line, with the line number each one starts on
+fn parse_csv(text: &str) -> Result<Vec<(usize, Vec<String>)>, FormatError> {
+    let mut lines = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut line = 1;
+    let mut start = 1;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if field.is_empty() => {
+                // A quoted field runs until a quote that is not doubled
+                loop {
+                    match chars.next() {
+                        Some('"') if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            field.push('"');
+                        }
+                        Some('"') => break,
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            field.push(c);
+                        }
+                        None => {
+                            return Err(FormatError::Csv {
+                                line: start,
+                                message: "a quoted field is never closed".to_string(),
+                            })
+                        }
+                    }
+                }
+                if !matches!(chars.peek(), None | Some(',' | '\n' | '\r')) {
+                    return Err(FormatError::Csv {
+                        line,
+                        message: "text after the closing quote of a field".to_string(),
+                    });
+                }
+            }
+            ',' => fields.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                fields.push(std::mem::take(&mut field));
+                lines.push((start, std::mem::take(&mut fields)));
+                line += 1;
+                start = line;
+            }
+            c => field.push(c),
+        }
+    }
+    // The last line may or may not end with a newline
+    if !field.is_empty() || !fields.is_empty() {
+        fields.push(field);
+        lines.push((start, fields));
+    }
+    Ok(lines)
+}
+
+fn write_varint(mut n: u64, bytes: &mut Vec<u8>) {
+    while n >= 0x80 {
+        bytes.push((n as u8 & 0x7f) | 0x80);
+        n >>= 7;
+    }
+    bytes.push(n as u8);
+}
+
+fn write_text(text: &str, bytes: &mut Vec<u8>) {
+    write_varint(text.len() as u64, bytes);
+    bytes.extend_from_slice(text.as_bytes());
+}
+
+fn encode_binary(value: &Value, bytes: &mut Vec<u8>) {
+    match value {
+        Value::Null => bytes.push(NULL),
+        Value::Bool(false) => bytes.push(FALSE),
+        Value::Bool(true) => bytes.push(TRUE),
+        Value::Number(number) => {
+            if let Some(n) = number.as_u64() {
+                bytes.push(UNSIGNED);
+                write_varint(n, bytes);
+            } else if let Some(n) = number.as_i64() {
+                bytes.push(NEGATIVE);
+                write_varint(!(n as u64), bytes); // !n == -(n + 1) in two's complement
+            } else {
+                bytes.push(FLOAT);
+                let n = number
+                    .as_f64()
+                    .expect("a JSON number is an integer or a float");
+                bytes.extend_from_slice(&n.to_le_bytes());
+            }
+        }
+        Value::String(text) => {
+            bytes.push(STRING);
+            write_text(text, bytes);
+        }
+        Value::Array(items) => {
+            bytes.push(ARRAY);
+            write_varint(items.len() as u64, bytes);
+            for item in items {
+                encode_binary(item, bytes);
+            }
+        }
+        Value::Object(object) => {
+            bytes.push(OBJECT);
+            write_varint(object.len() as u64, bytes);
+            for (name, value) in object {
+                write_text(name, bytes);
+                encode_binary(value, bytes);
+            }
+        }
+    }
+}
+
+fn decode_binary(bytes: &[u8]) -> Result<Value, FormatError> {
+    let mut cursor = ByteCursor::new(bytes, Endian::Little);
+    if cursor.read_bytes(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+        return Err(FormatError::BadMagic);
+    }
+    let value = read_value(&mut cursor, 0)?;
+    if cursor.remaining() > 0 {
+        return Err(FormatError::Binary {
+            position: cursor.position(),
+            message: format!("{} bytes after the end of the data", cursor.remaining()),
+        });
+    }
+    Ok(value)
+}
+
+fn read_varint(cursor: &mut ByteCursor) -> Result<u64, FormatError> {
+    let start = cursor.position();
+    let too_long = || FormatError::Binary {
+        position: start,
+        message: "a number longer than 64 bits".to_string(),
+    };
+    let mut n = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte: u8 = cursor.read()?;
+        let bits = u64::from(byte & 0x7f);
+        // The 10th byte only has room for the top bit, anything above it would be shifted out
+        if bits << shift >> shift != bits {
+            return Err(too_long());
+        }
+        n |= bits << shift;
+        if byte & 0x80 == 0 {
+            return Ok(n);
+        }
+    }
+    Err(too_long())
+}
+
+// A length that must still fit in what is left, so a corrupt length cannot make us allocate gigabytes
+fn read_length(cursor: &mut ByteCursor) -> Result<usize, FormatError> {
+    let position = cursor.position();
+    let length = read_varint(cursor)?;
+    if length > cursor.remaining() as u64 {
+        return Err(FormatError::Binary {
+            position,
+            message: format!(
+                "a length of {length}, but only {} bytes left",
+                cursor.remaining()
+            ),
+        });
+    }
+    Ok(length as usize)
+}
+
+fn read_text(cursor: &mut ByteCursor) -> Result<String, FormatError> {
+    let length = read_length(cursor)?;
+    let position = cursor.position();
+    let bytes = cursor.read_bytes(length)?;
+    String::from_utf8(bytes.to_vec()).map_err(|_| FormatError::Binary {
+        position,
+        message: "a string that is not valid UTF-8".to_string(),
+    })
+}
+
+// `depth` counts the arrays and objects around the value
+fn read_value(cursor: &mut ByteCursor, depth: usize) -> Result<Value, FormatError> {
+    let position = cursor.position();
+    let tag: u8 = cursor.read()?;
+    if matches!(tag, ARRAY | OBJECT) && depth == MAX_DEPTH {
+        return Err(FormatError::Binary {
+            position,
+            message: format!("arrays and objects nested more than {MAX_DEPTH} deep"),
+        });
+    }
+    Ok(match tag {
+        NULL => Value::Null,
+        FALSE => Value::Bool(false),
+        TRUE => Value::Bool(true),
+        UNSIGNED => Value::from(read_varint(cursor)?),
+        // !n of a negative n is at most i64::MAX, a larger payload was not written by us
+        NEGATIVE => match read_varint(cursor)? {
+            inverted if inverted > i64::MAX as u64 => {
+                return Err(FormatError::Binary {
+                    position,
+                    message: format!("a negative number with a payload of {inverted}"),
+                })
+            }
+            inverted => Value::from(!(inverted as i64)),
+        },
+        FLOAT => {
+            let n: f64 = cursor.read()?;
+            Number::from_f64(n)
+                .map(Value::Number)
+                .ok_or_else(|| FormatError::Binary {
+                    position,
+                    message: format!("{n} is not a JSON number"),
+                })?
+        }
+        STRING => Value::String(read_text(cursor)?),
+        ARRAY => {
+            let length = read_length(cursor)?;
+            let items = (0..length)
+                .map(|_| read_value(cursor, depth + 1))
+                .collect::<Result<_, _>>()?;
+            Value::Array(items)
+        }
+        OBJECT => {
+            let length = read_length(cursor)?;
+            let mut object = Map::new();
+            for _ in 0..length {
+                let name = read_text(cursor)?;
+                object.insert(name, read_value(cursor, depth + 1)?);
+            }
+            Value::Object(object)
+        }
+        tag => {
+            return Err(FormatError::Binary {
+                position,
+                message: format!("unknown tag {tag}"),
+            })
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Record {
+        id: i64,
+        name: String,
+        score: Option<f64>,
+        tags: Vec<String>,
+    }
+
+    fn records() -> Vec<Record> {
+        vec![
+            Record {
+                id: i64::MIN,
+                name: "comma, \"quote\"\nnewline".to_string(),
+                score: Some(-0.5),
+                tags: vec!["a".to_string()],
+            },
+            Record {
+                id: 42,
+                name: "42".to_string(),
+                score: None,
+                tags: Vec::new(),
+            },
+            Record {
+                id: 0,
+                name: String::new(),
+                score: Some(1e300),
+                tags: vec!["true".to_string(), "".to_string()],
+            },
+        ]
+    }
+
+    #[test]
+    fn records_round_trip_through_every_format() {
+        for format in Format::ALL {
+            let encoded = format.encode(&records()).unwrap();
+            assert!(encoded.losses.is_empty(), "{format}: {:?}", encoded.losses);
+            let decoded: Vec<Record> = format.decode(&encoded.bytes).unwrap();
+            assert_eq!(decoded, records(), "{format}");
+        }
+    }
+
+    #[test]
+    fn binary_keeps_every_value_exactly() {
+        let value = json!({
+            "numbers": [0, 127, 128, u64::MAX, -1, i64::MIN, 0.1, -2.5e-300],
+            "nested": {"": [null, true, false, {}, []]},
+            "text": "bücher 🦀",
+        });
+        let encoded = Format::Binary.encode_value(&value).unwrap();
+        assert!(encoded.bytes.starts_with(MAGIC));
+        assert_eq!(Format::Binary.decode_value(&encoded.bytes).unwrap(), value);
+    }
+
+    #[test]
+    fn csv_reports_what_does_not_survive() {
+        let encoded = Format::Csv
+            .encode_value(&json!([{"a": 1, "b": 2}, {"a": 3}]))
+            .unwrap();
+        assert_eq!(encoded.bytes, b"a,b\n1,2\n3,\n");
+        assert_eq!(
+            encoded.losses,
+            [Loss {
+                pointer: "/1/b".to_string(),
+                reason: "missing, reads back as null".to_string()
+            }]
+        );
+
+        let single = Format::Csv.encode_value(&json!({"a": 1})).unwrap();
+        assert_eq!(single.losses[0].pointer, "");
+        let empty = Format::Csv.encode_value(&json!([{}, {}])).unwrap();
+        assert!(empty.bytes.is_empty() && empty.losses.len() == 1);
+
+        for not_records in [json!(1), json!([1]), json!([{"a": 1}, "b"])] {
+            assert!(matches!(
+                Format::Csv.encode_value(&not_records),
+                Err(FormatError::NotRecords { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn invalid_csv_says_which_line() {
+        let line = |text: &str| match Format::Csv.decode_value(text.as_bytes()) {
+            Err(FormatError::Csv { line, .. }) => line,
+            other => panic!("{text:?} gave {other:?}"),
+        };
+        assert_eq!(line("a,b\n1,2\n3\n"), 3);
+        assert_eq!(line("a,a\n"), 1);
+        assert_eq!(line("a\n\"x\ny\n"), 2);
+        assert_eq!(line("a\n\"x\"y\n"), 2);
+        assert_eq!(
+            Format::Csv.decode_value(b"a\r\n\"x\ny\"\r\n").unwrap(),
+            json!([{"a": "x\ny"}])
+        );
+    }
+
+    #[test]
+    fn invalid_binary_is_rejected() {
+        assert!(matches!(
+            Format::Binary.decode_value(b"JSON"),
+            Err(FormatError::BadMagic)
+        ));
+        let mut bytes = Format::Binary.encode_value(&json!([1, 2])).unwrap().bytes;
+        assert!(matches!(
+            Format::Binary.decode_value(&bytes[..bytes.len() - 1]),
+            Err(FormatError::Truncated(_))
+        ));
+        bytes.push(NULL);
+        assert!(matches!(
+            Format::Binary.decode_value(&bytes),
+            Err(FormatError::Binary { .. })
+        ));
+        let huge_length = [MAGIC.as_slice(), &[STRING, 0xFF, 0xFF, 0x03]].concat();
+        assert!(Format::Binary.decode_value(&huge_length).is_err());
+    }
+
+    #[test]
+    fn varints_must_fit_in_64_bits() {
+        let decode =
+            |payload: &[u8]| Format::Binary.decode_value(&[MAGIC.as_slice(), payload].concat());
+        let max = [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01];
+        assert_eq!(
+            decode(&[&[UNSIGNED], &max[..]].concat()).unwrap(),
+            json!(u64::MAX)
+        );
+        // Bits above the 64th in the 10th byte, and an 11th byte
+        let too_big = [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x02];
+        assert!(matches!(
+            decode(&[&[UNSIGNED], &too_big[..]].concat()),
+            Err(FormatError::Binary { position, .. }) if position == MAGIC.len() + 1
+        ));
+        let too_long = [
+            0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00,
+        ];
+        assert!(decode(&[&[UNSIGNED], &too_long[..]].concat()).is_err());
+    }
+
+    #[test]
+    fn negative_payloads_above_i64_max_are_rejected() {
+        let decode =
+            |payload: &[u8]| Format::Binary.decode_value(&[MAGIC.as_slice(), payload].concat());
+        // i64::MAX as a varint: !i64::MIN
+        let i64_max = [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x7F];
+        assert_eq!(
+            decode(&[&[NEGATIVE], &i64_max[..]].concat()).unwrap(),
+            json!(i64::MIN)
+        );
+        assert_eq!(decode(&[NEGATIVE, 0]).unwrap(), json!(-1));
+        // 2^63 would wrap around to a positive number
+        let above = [0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01];
+        assert!(matches!(
+            decode(&[&[NEGATIVE], &above[..]].concat()),
+            Err(FormatError::Binary { position, .. }) if position == MAGIC.len()
+        ));
+    }
+
+    #[test]
+    fn kinds_of_values_read_as_prose() {
+        assert_eq!(kind_of(&json!(null)), "null");
+        assert_eq!(kind_of(&json!([1])), "an array");
+        assert_eq!(kind_of(&json!({})), "an object");
+        assert_eq!(kind_of(&json!(1.5)), "a number");
+    }
+
+    #[test]
+    fn binary_nesting_is_limited() {
+        let nested = |depth: usize| {
+            let mut bytes = MAGIC.to_vec();
+            for _ in 0..depth {
+                bytes.extend_from_slice(&[ARRAY, 1]);
+            }
+            bytes.push(NULL);
+            bytes
+        };
+        assert!(Format::Binary.decode_value(&nested(MAX_DEPTH)).is_ok());
+        let err = Format::Binary
+            .decode_value(&nested(MAX_DEPTH + 1))
+            .unwrap_err();
+        assert!(
+            matches!(err, FormatError::Binary { position, .. } if position == MAGIC.len() + 2 * MAX_DEPTH)
+        );
+        // Deep enough to overflow the stack without the limit
+        assert!(Format::Binary.decode_value(&nested(1_000_000)).is_err());
+    }
+
+    #[test]
+    fn formats_by_extension() {
+        assert_eq!(Format::from_path(Path::new("a/b.JSON")), Some(Format::Json));
+        assert_eq!(Format::from_path(Path::new("b.bin")), Some(Format::Binary));
+        assert_eq!(Format::from_path(Path::new("b.txt")), None);
+        assert_eq!(Format::from_path(Path::new("csv")), None);
+    }
+}
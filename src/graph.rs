@@ -0,0 +1,204 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    rc::{Rc, Weak},
+};
+
+/*
+ * A tree (or graph) of nodes shared through Rc<RefCell<Node>>.
+ *
+ * Rc gives every node multiple owners, RefCell lets us mutate a node through any of them.
+ * Children are strong references: a parent keeps its children alive.
+ * Parents are Weak references: a child can reach its parent with upgrade(), but does not keep it alive.
+ * If parents were strong as well, parent and child would own each other, their strong counts would never reach 0,
+ * and both would leak. find_cycles() looks for exactly that kind of loop among the strong references.
+ */
+
+pub type NodeRef = Rc<RefCell<Node>>;
+
+#[derive(Debug)]
+pub struct Node {
+    pub name: String,
+    pub parent: Weak<RefCell<Node>>,
+    pub children: Vec<NodeRef>,
+}
+
+impl Node {
+    pub fn new(name: &str) -> NodeRef {
+        Rc::new(RefCell::new(Node {
+            name: name.to_string(),
+            parent: Weak::new(),
+            children: Vec::new(),
+        }))
+    }
+}
+
+// The tree way: the parent owns the child, the child only points back weakly
+pub fn add_child(parent: &NodeRef, child: &NodeRef) {
+    child.borrow_mut().parent = Rc::downgrade(parent);
+    parent.borrow_mut().children.push(Rc::clone(child));
+}
+
+// A plain strong edge without any parent bookkeeping. This is how cycles sneak in
+pub fn add_edge(from: &NodeRef, to: &NodeRef) {
+    from.borrow_mut().children.push(Rc::clone(to));
+}
+
+pub fn counts(node: &NodeRef) -> String {
+    format!(
+        "{}: strong = {}, weak = {}",
+        node.borrow().name,
+        Rc::strong_count(node),
+        Rc::weak_count(node)
+    )
+}
+
+// Follows the Weak parent links. upgrade() returns None once the parent has been dropped
+pub fn path_to_root(node: &NodeRef) -> Vec<String> {
+    let mut path = vec![node.borrow().name.clone()];
+    let mut current = node.borrow().parent.upgrade();
+    while let Some(parent) = current {
+        path.push(parent.borrow().name.clone());
+        current = parent.borrow().parent.upgrade();
+    }
+    path
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Visit {
+    InProgress,
+    Done,
+}
+
+/*
+ * Depth-first search over the strong (children) references, starting from every root.
+ * Reaching a node that is still on the current path means we walked in a circle: that is a reference cycle.
+ * Each cycle is returned as the names along it, e.g. ["a", "b", "c", "a"].
+ */
+pub fn find_cycles<'a>(roots: impl IntoIterator<Item = &'a NodeRef>) -> Vec<Vec<String>> {
+    let mut visits: HashMap<*const RefCell<Node>, Visit> = HashMap::new();
+    let mut path: Vec<NodeRef> = Vec::new();
+    let mut cycles = Vec::new();
+
+    for root in roots {
+        visit(root, &mut visits, &mut path, &mut cycles);
+    }
+    cycles
+}
+
+fn visit(
+    node: &NodeRef,
+    visits: &mut HashMap<*const RefCell<Node>, Visit>,
+    path: &mut Vec<NodeRef>,
+    cycles: &mut Vec<Vec<String>>,
+) {
+    // The address of the RefCell identifies a node, names don't have to be unique
+    let id = Rc::as_ptr(node);
+    match visits.get(&id) {
+        Some(Visit::Done) => return,
+        Some(Visit::InProgress) => {
+            let start = path
+                .iter()
+                .position(|other| Rc::ptr_eq(other, node))
+                .expect("an in-progress node is on the path");
+            let mut cycle: Vec<String> = path[start..]
+                .iter()
+                .map(|other| other.borrow().name.clone())
+                .collect();
+            cycle.push(node.borrow().name.clone());
+            cycles.push(cycle);
+            return;
+        }
+        None => {}
+    }
+
+    visits.insert(id, Visit::InProgress);
+    path.push(Rc::clone(node));
+    // Clone the children first, so no borrow is held while recursing into a node that might be this one again
+    let children = node.borrow().children.clone();
+    for child in &children {
+        visit(child, visits, path, cycles);
+    }
+    path.pop();
+    visits.insert(id, Visit::Done);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Strong cycles never drop on their own, so tests cut them once done
+    fn cut(nodes: &[&NodeRef]) {
+        for node in nodes {
+            node.borrow_mut().children.clear();
+        }
+    }
+
+    #[test]
+    fn children_are_owned_and_parents_are_weak() {
+        let root = Node::new("root");
+        let child = Node::new("child");
+        let leaf = Node::new("leaf");
+        add_child(&root, &child);
+        add_child(&child, &leaf);
+
+        assert_eq!(counts(&child), "child: strong = 2, weak = 1");
+        assert_eq!(path_to_root(&leaf), ["leaf", "child", "root"]);
+        assert!(find_cycles([&root]).is_empty());
+
+        // Nothing else owns root, so dropping it ends the path there
+        drop(root);
+        assert_eq!(counts(&child), "child: strong = 1, weak = 1");
+        assert_eq!(path_to_root(&leaf), ["leaf", "child"]);
+    }
+
+    #[test]
+    fn finds_a_cycle_of_strong_edges() {
+        let a = Node::new("a");
+        let b = Node::new("b");
+        let c = Node::new("c");
+        add_child(&a, &b);
+        add_child(&b, &c);
+        add_edge(&c, &a);
+
+        assert_eq!(find_cycles([&a]), [["a", "b", "c", "a"]]);
+        // Found once even when the search starts inside it
+        assert_eq!(find_cycles([&b, &a]), [["b", "c", "a", "b"]]);
+        cut(&[&a, &b, &c]);
+    }
+
+    #[test]
+    fn a_node_pointing_at_itself_is_a_cycle() {
+        let a = Node::new("a");
+        add_edge(&a, &a);
+        assert_eq!(find_cycles([&a]), [["a", "a"]]);
+        cut(&[&a]);
+    }
+
+    #[test]
+    fn a_shared_child_is_not_a_cycle() {
+        // a -> b -> d and a -> c -> d: d has two owners, but no path leads back
+        let a = Node::new("a");
+        let b = Node::new("b");
+        let c = Node::new("c");
+        let d = Node::new("d");
+        add_edge(&a, &b);
+        add_edge(&a, &c);
+        add_edge(&b, &d);
+        add_edge(&c, &d);
+        assert!(find_cycles([&a, &d]).is_empty());
+        assert_eq!(Rc::strong_count(&d), 3);
+    }
+
+    #[test]
+    fn nodes_are_told_apart_by_address_not_name() {
+        let first = Node::new("same");
+        let second = Node::new("same");
+        add_edge(&first, &second);
+        assert!(find_cycles([&first]).is_empty());
+
+        add_edge(&second, &first);
+        assert_eq!(find_cycles([&first]), [["same", "same", "same"]]);
+        cut(&[&first, &second]);
+    }
+}
@@ -0,0 +1,490 @@
+use crate::formats::kind_of;
+use crate::json_schema::pointer_token;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{error::Error, fmt};
+
+/*
+ * Structural diffs of two JSON documents, and the two standard ways of writing one down.
+ *
+ * JSON Patch (RFC 6902) is a list of operations, each addressing a value by its JSON pointer (RFC 6901):
+ *   [{"op": "replace", "path": "/port", "value": 8443}, {"op": "remove", "path": "/aps/1"}]
+ * diff() produces add, remove and replace operations. apply() understands all six, move, copy and test included,
+ * and applies a patch as a whole: if one operation fails the document is left as it was.
+ *
+ * JSON Merge Patch (RFC 7386) is a document shaped like the target: objects are merged member by member,
+ * null removes a member, anything else replaces the value. Simpler, but it cannot set a value to null
+ * or change a single array element.
+ *
+ * Arrays are diffed by position after skipping their common start and end, so inserting or removing
+ * a few elements does not replace everything behind them.
+ */
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Operation {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    Replace { path: String, value: Value },
+    Move { from: String, path: String },
+    Copy { from: String, path: String },
+    Test { path: String, value: Value },
+}
+
+impl Operation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operation::Add { .. } => "add",
+            Operation::Remove { .. } => "remove",
+            Operation::Replace { .. } => "replace",
+            Operation::Move { .. } => "move",
+            Operation::Copy { .. } => "copy",
+            Operation::Test { .. } => "test",
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        match self {
+            Operation::Add { path, .. }
+            | Operation::Remove { path }
+            | Operation::Replace { path, .. }
+            | Operation::Move { path, .. }
+            | Operation::Copy { path, .. }
+            | Operation::Test { path, .. } => path,
+        }
+    }
+}
+
+// One line per operation, e.g. replace /port: 8443
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::Add { path, value }
+            | Operation::Replace { path, value }
+            | Operation::Test { path, value } => write!(f, "{} {}: {}", self.name(), path, value),
+            Operation::Remove { path } => write!(f, "remove {}", path),
+            Operation::Move { from, path } | Operation::Copy { from, path } => {
+                write!(f, "{} {} to {}", self.name(), from, path)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatchError {
+    pub operation: usize, // Index in the patch
+    pub op: &'static str,
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Patch operation {} ({} \"{}\") failed: {}",
+            self.operation, self.op, self.path, self.message
+        )
+    }
+}
+
+impl Error for PatchError {}
+
+// The operations that turn `from` into `to`
+pub fn diff(from: &Value, to: &Value) -> Vec<Operation> {
+    let mut operations = Vec::new();
+    diff_at(from, to, &mut String::new(), &mut operations);
+    operations
+}
+
+fn diff_at(from: &Value, to: &Value, path: &mut String, operations: &mut Vec<Operation>) {
+    match (from, to) {
+        _ if from == to => {}
+        (Value::Object(from), Value::Object(to)) => {
+            for (name, old) in from {
+                let length = path.len();
+                path.push('/');
+                path.push_str(&pointer_token(name));
+                match to.get(name) {
+                    Some(new) => diff_at(old, new, path, operations),
+                    None => operations.push(Operation::Remove { path: path.clone() }),
+                }
+                path.truncate(length);
+            }
+            for (name, new) in to.iter().filter(|(name, _)| !from.contains_key(*name)) {
+                operations.push(Operation::Add {
+                    path: format!("{path}/{}", pointer_token(name)),
+                    value: new.clone(),
+                });
+            }
+        }
+        (Value::Array(from), Value::Array(to)) => diff_arrays(from, to, path, operations),
+        _ => operations.push(Operation::Replace {
+            path: path.clone(),
+            value: to.clone(),
+        }),
+    }
+}
+
+fn diff_arrays(from: &[Value], to: &[Value], path: &mut String, operations: &mut Vec<Operation>) {
+    let start = from.iter().zip(to).take_while(|(a, b)| a == b).count();
+    let end = from[start..]
+        .iter()
+        .rev()
+        .zip(to[start..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (from, to) = (&from[start..from.len() - end], &to[start..to.len() - end]);
+    let paired = from.len().min(to.len());
+
+    for (offset, (old, new)) in from.iter().zip(to).enumerate() {
+        let length = path.len();
+        path.push_str(&format!("/{}", start + offset));
+        diff_at(old, new, path, operations);
+        path.truncate(length);
+    }
+    // Highest index first, so every removal leaves the indexes of the ones still to come alone
+    for index in (start + paired..start + from.len()).rev() {
+        operations.push(Operation::Remove {
+            path: format!("{path}/{index}"),
+        });
+    }
+    for (offset, new) in to[paired..].iter().enumerate() {
+        operations.push(Operation::Add {
+            path: format!("{path}/{}", start + paired + offset),
+            value: new.clone(),
+        });
+    }
+}
+
+// All or nothing: when an operation fails, `document` is left untouched
+pub fn apply(document: &mut Value, patch: &[Operation]) -> Result<(), PatchError> {
+    let mut patched = document.clone();
+    for (index, operation) in patch.iter().enumerate() {
+        apply_one(&mut patched, operation).map_err(|message| PatchError {
+            operation: index,
+            op: operation.name(),
+            path: operation.path().to_string(),
+            message,
+        })?;
+    }
+    *document = patched;
+    Ok(())
+}
+
+fn apply_one(document: &mut Value, operation: &Operation) -> Result<(), String> {
+    match operation {
+        Operation::Add { path, value } => add(document, path, value.clone()),
+        Operation::Remove { path } => remove(document, path).map(|_| ()),
+        Operation::Replace { path, value } => {
+            *lookup_mut(document, path)? = value.clone();
+            Ok(())
+        }
+        Operation::Move { from, path } => {
+            if path.starts_with(&format!("{from}/")) {
+                return Err(format!("cannot move {from} into one of its own children"));
+            }
+            let value = remove(document, from)?;
+            add(document, path, value)
+        }
+        Operation::Copy { from, path } => {
+            let value = lookup_mut(document, from)?.clone();
+            add(document, path, value)
+        }
+        Operation::Test { path, value } => match lookup_mut(document, path)? {
+            found if json_equal(found, value) => Ok(()),
+            found => Err(format!("expected {value}, found {found}")),
+        },
+    }
+}
+
+// Like ==, except that numbers are equal when their values are (RFC 6902 4.6), so 1 and 1.0 are
+pub fn json_equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => match (a.as_i64(), b.as_i64()) {
+            (Some(a), Some(b)) => a == b,
+            _ => match (a.as_u64(), b.as_u64()) {
+                (Some(a), Some(b)) => a == b,
+                _ => a.as_f64() == b.as_f64(),
+            },
+        },
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| json_equal(a, b))
+        }
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(name, a)| b.get(name).is_some_and(|b| json_equal(a, b)))
+        }
+        _ => a == b,
+    }
+}
+
+// "/a~1b/0" -> ["a/b", "0"], the root "" has no tokens
+pub fn parse_pointer(pointer: &str) -> Result<Vec<String>, String> {
+    if pointer.is_empty() {
+        return Ok(Vec::new());
+    }
+    let Some(rest) = pointer.strip_prefix('/') else {
+        return Err(format!(
+            "{pointer:?} is not a JSON pointer, it must start with /"
+        ));
+    };
+    Ok(rest
+        .split('/')
+        .map(|token| token.replace("~1", "/").replace("~0", "~"))
+        .collect())
+}
+
+fn array_index(token: &str, length: usize, allow_end: bool) -> Result<usize, String> {
+    // No signs and no leading zeros, "-" is the position after the last element
+    let index = match token {
+        "-" if allow_end => length,
+        "0" => 0,
+        _ if token.starts_with('0') || !token.bytes().all(|b| b.is_ascii_digit()) => {
+            return Err(format!("{token:?} is not an array index"))
+        }
+        _ => token
+            .parse()
+            .map_err(|_| format!("{token:?} is not an array index"))?,
+    };
+    // Adding may append right after the last element, everything else needs an existing one
+    let in_bounds = if allow_end {
+        index <= length
+    } else {
+        index < length
+    };
+    if !in_bounds {
+        return Err(format!(
+            "index {index} is out of bounds for {length} elements"
+        ));
+    }
+    Ok(index)
+}
+
+fn lookup_mut<'a>(document: &'a mut Value, pointer: &str) -> Result<&'a mut Value, String> {
+    let mut current = document;
+    for token in parse_pointer(pointer)? {
+        current = match current {
+            Value::Object(object) => object
+                .get_mut(&token)
+                .ok_or_else(|| format!("there is no member {token:?}"))?,
+            Value::Array(items) => {
+                let index = array_index(&token, items.len(), false)?;
+                &mut items[index]
+            }
+            _ => return Err(format!("cannot look up {token:?} in {}", kind_of(current))),
+        };
+    }
+    Ok(current)
+}
+
+// The container the last token of `pointer` lives in, and that token
+fn parent_mut<'a>(
+    document: &'a mut Value,
+    pointer: &str,
+) -> Result<(&'a mut Value, String), String> {
+    let mut tokens = parse_pointer(pointer)?;
+    let last = tokens.pop().ok_or("the document itself has no parent")?;
+    let parent_pointer: String = tokens
+        .iter()
+        .map(|token| format!("/{}", pointer_token(token)))
+        .collect();
+    Ok((lookup_mut(document, &parent_pointer)?, last))
+}
+
+fn add(document: &mut Value, path: &str, value: Value) -> Result<(), String> {
+    if path.is_empty() {
+        *document = value;
+        return Ok(());
+    }
+    match parent_mut(document, path)? {
+        (Value::Object(object), name) => {
+            object.insert(name, value);
+        }
+        (Value::Array(items), token) => {
+            let index = array_index(&token, items.len(), true)?;
+            items.insert(index, value);
+        }
+        (parent, _) => return Err(format!("cannot add to {}", kind_of(parent))),
+    }
+    Ok(())
+}
+
+fn remove(document: &mut Value, path: &str) -> Result<Value, String> {
+    match parent_mut(document, path)? {
+        (Value::Object(object), name) => object
+            .remove(&name)
+            .ok_or_else(|| format!("there is no member {name:?}")),
+        (Value::Array(items), token) => {
+            let index = array_index(&token, items.len(), false)?;
+            Ok(items.remove(index))
+        }
+        (parent, _) => Err(format!("cannot remove from {}", kind_of(parent))),
+    }
+}
+
+// RFC 7386: objects merge, null removes, everything else replaces
+pub fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    let Value::Object(target) = target else {
+        unreachable!("the target was just made an object")
+    };
+    for (name, value) in patch {
+        if value.is_null() {
+            target.remove(name);
+        } else {
+            merge_patch(target.entry(name.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn patch(operations: Value) -> Vec<Operation> {
+        serde_json::from_value(operations).unwrap()
+    }
+
+    #[test]
+    fn diff_then_apply_gives_the_target() {
+        let pairs = [
+            (
+                json!({"a": 1, "b": [1, 2, 3]}),
+                json!({"a": 2, "c": null, "b": [1, 3]}),
+            ),
+            (json!([1, 2, 3, 4, 5]), json!([0, 1, 2, 9, 4, 5, 6, 7])),
+            (
+                json!({"x/y": {"~": [true]}}),
+                json!({"x/y": {"~": [false, {}]}}),
+            ),
+            (
+                json!({"nested": {"deep": [1, {"k": "v"}]}}),
+                json!({"nested": {"deep": []}}),
+            ),
+            (json!(1), json!("one")),
+            (json!({}), json!({})),
+        ];
+        for (from, to) in pairs {
+            let operations = diff(&from, &to);
+            let mut document = from.clone();
+            apply(&mut document, &operations).unwrap();
+            assert_eq!(document, to, "{from} -> {to} via {operations:?}");
+        }
+    }
+
+    #[test]
+    fn diff_keeps_common_array_ends() {
+        let operations = diff(&json!([1, 2, 3, 4]), &json!([1, 2, 9, 3, 4]));
+        assert_eq!(
+            operations,
+            vec![Operation::Add {
+                path: "/2".to_string(),
+                value: json!(9)
+            }]
+        );
+        assert!(diff(&json!({"a": [1]}), &json!({"a": [1]})).is_empty());
+    }
+
+    #[test]
+    fn move_and_copy() {
+        let mut document = json!({"a": {"b": 1}, "list": [1, 2]});
+        let operations = patch(json!([
+            {"op": "move", "from": "/a/b", "path": "/c"},
+            {"op": "copy", "from": "/list", "path": "/a/list"},
+            {"op": "move", "from": "/list/0", "path": "/list/-"},
+        ]));
+        apply(&mut document, &operations).unwrap();
+        assert_eq!(
+            document,
+            json!({"a": {"list": [1, 2]}, "c": 1, "list": [2, 1]})
+        );
+
+        let into_child = patch(json!([{"op": "move", "from": "/a", "path": "/a/b"}]));
+        assert!(apply(&mut document, &into_child).is_err());
+    }
+
+    #[test]
+    fn test_compares_numbers_by_value() {
+        let mut document = json!({"a": 1, "b": [2.0, {"c": 3}]});
+        let operations = patch(json!([
+            {"op": "test", "path": "/a", "value": 1.0},
+            {"op": "test", "path": "/b", "value": [2, {"c": 3.0}]},
+        ]));
+        assert_eq!(apply(&mut document, &operations), Ok(()));
+
+        let operations = patch(json!([{"op": "test", "path": "/a", "value": "1"}]));
+        assert!(apply(&mut document, &operations).is_err());
+    }
+
+    #[test]
+    fn a_failing_operation_leaves_the_document_unchanged() {
+        let original = json!({"a": 1, "list": [1]});
+        let mut document = original.clone();
+        let operations = patch(json!([
+            {"op": "replace", "path": "/a", "value": 2},
+            {"op": "add", "path": "/list/-", "value": 2},
+            {"op": "remove", "path": "/missing"},
+        ]));
+        let err = apply(&mut document, &operations).unwrap_err();
+        assert_eq!(
+            (err.operation, err.op, err.path.as_str()),
+            (2, "remove", "/missing")
+        );
+        assert_eq!(document, original);
+
+        for bad in [
+            json!({"op": "add", "path": "/list/01", "value": 0}),
+            json!({"op": "add", "path": "/list/5", "value": 0}),
+            json!({"op": "replace", "path": "a", "value": 0}),
+            json!({"op": "add", "path": "/a/b", "value": 0}),
+        ] {
+            assert!(apply(&mut document, &patch(json!([bad]))).is_err());
+        }
+        assert_eq!(document, original);
+    }
+
+    #[test]
+    fn merge_patch_follows_rfc_7386() {
+        // The example of RFC 7386 section 3
+        let mut target = json!({
+            "title": "Goodbye!",
+            "author": {"givenName": "John", "familyName": "Doe"},
+            "tags": ["example", "sample"],
+            "content": "This will be unchanged"
+        });
+        merge_patch(
+            &mut target,
+            &json!({
+                "title": "Hello!",
+                "phoneNumber": "+01-123-456-7890",
+                "author": {"familyName": null},
+                "tags": ["example"]
+            }),
+        );
+        assert_eq!(
+            target,
+            json!({
+                "title": "Hello!",
+                "author": {"givenName": "John"},
+                "tags": ["example"],
+                "content": "This will be unchanged",
+                "phoneNumber": "+01-123-456-7890"
+            })
+        );
+
+        let mut target = json!([1]);
+        merge_patch(&mut target, &json!({"a": {"b": null, "c": 1}}));
+        assert_eq!(target, json!({"a": {"c": 1}}));
+        merge_patch(&mut target, &json!("text"));
+        assert_eq!(target, json!("text"));
+    }
+}
@@ -0,0 +1,837 @@
+use serde_json::Value;
+use std::{error::Error, fmt};
+
+/*
+ * A JSONPath query engine over serde_json::Value, following the syntax of RFC 9535 closely enough for everyday use.
+ *
+ *   $                    the root
+ *   .name  ['name']      a child by name
+ *   .*  [*]              every child (object values or array elements)
+ *   ..name  ..*  ..[0]   the same, but on the node and all of its descendants
+ *   [0]  [-1]  [0,2]     array elements, negative indices count from the end, a comma selects several
+ *   [1:3]  [::-1]        array slices: start (inclusive), end (exclusive), step
+ *   [?(@.price < 10)]    children for which the filter holds
+ *
+ * Filters compare @ (the child being tested) or $ (the root) paths with literals or each other:
+ * ==, !=, <, <=, >, >=, combined with &&, || and !. A path on its own tests that it exists: [?(@.isbn)].
+ * A path used in a comparison must select at most one value; when it selects nothing, only == against
+ * another path selecting nothing is true.
+ *
+ * Every match comes with its normalized path, e.g. $['store']['book'][0], so callers can tell where it was found.
+ *
+ * The parser recurses into !, parentheses and filters, so it stops at 128 levels of them instead of
+ * overflowing the stack on something like "$[?(!!!!...".
+ */
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonPathError {
+    pub position: usize, // Character offset into the expression
+    pub message: String,
+}
+
+impl fmt::Display for JsonPathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid JSON path at character {}: {}",
+            self.position, self.message
+        )
+    }
+}
+
+impl Error for JsonPathError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Child(Vec<Selector>),
+    Descendant(Vec<Selector>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Selector {
+    Name(String),
+    Wildcard,
+    Index(i64),
+    Slice {
+        start: Option<i64>,
+        end: Option<i64>,
+        step: Option<i64>,
+    },
+    Filter(Expression),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Literal(Value),
+    Current(Vec<Segment>), // @...
+    Root(Vec<Segment>),    // $...
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expression {
+    Or(Box<Expression>, Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+    Compare(Operand, Comparison, Operand),
+    Exists(Operand),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonPath {
+    segments: Vec<Segment>,
+}
+
+// One step of a normalized path
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Step {
+    Key(String),
+    Index(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match<'a> {
+    pub path: String, // Normalized, e.g. $['store']['book'][0]
+    pub value: &'a Value,
+}
+
+impl JsonPath {
+    pub fn parse(expression: &str) -> Result<JsonPath, JsonPathError> {
+        let mut parser = Parser {
+            chars: expression.chars().collect(),
+            position: 0,
+            depth: 0,
+        };
+        parser.skip_whitespace();
+        parser.expect('$')?;
+        let segments = parser.segments()?;
+        parser.skip_whitespace();
+        if parser.position < parser.chars.len() {
+            return Err(parser.error("unexpected character"));
+        }
+        Ok(JsonPath { segments })
+    }
+
+    pub fn query<'a>(&self, root: &'a Value) -> Vec<Match<'a>> {
+        select(&self.segments, root, root, Vec::new())
+            .into_iter()
+            .map(|(steps, value)| Match {
+                path: normalized(&steps),
+                value,
+            })
+            .collect()
+    }
+
+    pub fn values<'a>(&self, root: &'a Value) -> Vec<&'a Value> {
+        select(&self.segments, root, root, Vec::new())
+            .into_iter()
+            .map(|(_, value)| value)
+            .collect()
+    }
+}
+
+// Parses and runs in one go
+pub fn query<'a>(expression: &str, root: &'a Value) -> Result<Vec<Match<'a>>, JsonPathError> {
+    Ok(JsonPath::parse(expression)?.query(root))
+}
+
+fn normalized(steps: &[Step]) -> String {
+    let mut path = String::from("$");
+    for step in steps {
+        match step {
+            Step::Key(key) => {
+                path.push_str("['");
+                path.push_str(&key.replace('\\', "\\\\").replace('\'', "\\'"));
+                path.push_str("']");
+            }
+            Step::Index(index) => path.push_str(&format!("[{index}]")),
+        }
+    }
+    path
+}
+
+/*
+ * Evaluation. Each segment maps the current list of nodes to a new one, in document order.
+ * The steps leading to every node are carried along for the normalized paths.
+ */
+
+type Node<'a> = (Vec<Step>, &'a Value);
+
+fn select<'a>(
+    segments: &[Segment],
+    current: &'a Value,
+    root: &'a Value,
+    steps: Vec<Step>,
+) -> Vec<Node<'a>> {
+    let mut nodes = vec![(steps, current)];
+    for segment in segments {
+        let mut next = Vec::new();
+        for node in nodes {
+            match segment {
+                Segment::Child(selectors) => apply(selectors, &node, root, &mut next),
+                Segment::Descendant(selectors) => {
+                    for descendant in descendants(node) {
+                        apply(selectors, &descendant, root, &mut next);
+                    }
+                }
+            }
+        }
+        nodes = next;
+    }
+    nodes
+}
+
+// The node itself and everything below it, parents before children
+fn descendants(node: Node<'_>) -> Vec<Node<'_>> {
+    let mut all = Vec::new();
+    let mut stack = vec![node];
+    while let Some((steps, value)) = stack.pop() {
+        // Pushed in reverse so they pop in document order
+        for child in children(&steps, value).into_iter().rev() {
+            stack.push(child);
+        }
+        all.push((steps, value));
+    }
+    all
+}
+
+fn children<'a>(steps: &[Step], value: &'a Value) -> Vec<Node<'a>> {
+    let child = |step: Step| {
+        let mut steps = steps.to_vec();
+        steps.push(step);
+        steps
+    };
+    match value {
+        Value::Object(object) => object
+            .iter()
+            .map(|(key, value)| (child(Step::Key(key.clone())), value))
+            .collect(),
+        Value::Array(array) => array
+            .iter()
+            .enumerate()
+            .map(|(index, value)| (child(Step::Index(index)), value))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn apply<'a>(selectors: &[Selector], node: &Node<'a>, root: &'a Value, out: &mut Vec<Node<'a>>) {
+    let (steps, value) = node;
+    let with = |step: Step| {
+        let mut steps = steps.clone();
+        steps.push(step);
+        steps
+    };
+
+    for selector in selectors {
+        match (selector, value) {
+            (Selector::Name(name), Value::Object(object)) => {
+                if let Some(child) = object.get(name) {
+                    out.push((with(Step::Key(name.clone())), child));
+                }
+            }
+            (Selector::Wildcard, _) => out.extend(children(steps, value)),
+            (Selector::Index(index), Value::Array(array)) => {
+                let resolved = if *index < 0 {
+                    array.len() as i64 + index
+                } else {
+                    *index
+                };
+                if let Some(child) = usize::try_from(resolved).ok().and_then(|i| array.get(i)) {
+                    out.push((with(Step::Index(resolved as usize)), child));
+                }
+            }
+            (Selector::Slice { start, end, step }, Value::Array(array)) => {
+                for index in slice_indices(array.len(), *start, *end, *step) {
+                    out.push((with(Step::Index(index)), &array[index]));
+                }
+            }
+            (Selector::Filter(expression), _) => {
+                for child in children(steps, value) {
+                    if evaluate(expression, child.1, root) {
+                        out.push(child);
+                    }
+                }
+            }
+            _ => {} // e.g. a name on an array: selects nothing
+        }
+    }
+}
+
+// Python style slicing, as specified by RFC 9535
+fn slice_indices(
+    len: usize,
+    start: Option<i64>,
+    end: Option<i64>,
+    step: Option<i64>,
+) -> Vec<usize> {
+    let len = len as i64;
+    let step = step.unwrap_or(1);
+    let normalize = |i: i64| if i < 0 { len + i } else { i };
+
+    let mut indices = Vec::new();
+    if step > 0 {
+        let start = start.map_or(0, normalize).clamp(0, len);
+        let end = end.map_or(len, normalize).clamp(0, len);
+        let mut i = start;
+        while i < end {
+            indices.push(i as usize);
+            let Some(next) = i.checked_add(step) else {
+                break;
+            };
+            i = next;
+        }
+    } else if step < 0 {
+        let start = start.map_or(len - 1, normalize).clamp(-1, len - 1);
+        let end = end.map_or(-1, normalize).clamp(-1, len - 1);
+        let mut i = start;
+        while i > end {
+            indices.push(i as usize);
+            let Some(next) = i.checked_add(step) else {
+                break;
+            };
+            i = next;
+        }
+    }
+    indices // A step of 0 selects nothing
+}
+
+fn evaluate(expression: &Expression, current: &Value, root: &Value) -> bool {
+    match expression {
+        Expression::Or(left, right) => {
+            evaluate(left, current, root) || evaluate(right, current, root)
+        }
+        Expression::And(left, right) => {
+            evaluate(left, current, root) && evaluate(right, current, root)
+        }
+        Expression::Not(inner) => !evaluate(inner, current, root),
+        Expression::Exists(operand) => !resolve(operand, current, root).is_empty(),
+        Expression::Compare(left, comparison, right) => {
+            let left = singular(resolve(left, current, root));
+            let right = singular(resolve(right, current, root));
+            match comparison {
+                Comparison::Equal => equal(left, right),
+                Comparison::NotEqual => !equal(left, right),
+                Comparison::Less => less(left, right),
+                Comparison::LessOrEqual => less(left, right) || equal(left, right),
+                Comparison::Greater => less(right, left),
+                Comparison::GreaterOrEqual => less(right, left) || equal(left, right),
+            }
+        }
+    }
+}
+
+fn resolve<'a>(operand: &'a Operand, current: &'a Value, root: &'a Value) -> Vec<&'a Value> {
+    let nodes = match operand {
+        Operand::Literal(value) => return vec![value],
+        Operand::Current(segments) => select(segments, current, root, Vec::new()),
+        Operand::Root(segments) => select(segments, root, root, Vec::new()),
+    };
+    nodes.into_iter().map(|(_, value)| value).collect()
+}
+
+// A comparison needs a single value. Selecting several is treated like selecting nothing
+fn singular(values: Vec<&Value>) -> Option<&Value> {
+    match values.as_slice() {
+        [value] => Some(value),
+        _ => None,
+    }
+}
+
+fn equal(left: Option<&Value>, right: Option<&Value>) -> bool {
+    match (left, right) {
+        (None, None) => true,
+        // 1 and 1.0 are equal numbers, even though serde_json stores them differently
+        (Some(Value::Number(a)), Some(Value::Number(b))) => a.as_f64() == b.as_f64(),
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn less(left: Option<&Value>, right: Option<&Value>) -> bool {
+    match (left, right) {
+        (Some(Value::Number(a)), Some(Value::Number(b))) => a.as_f64() < b.as_f64(),
+        (Some(Value::String(a)), Some(Value::String(b))) => a < b,
+        _ => false,
+    }
+}
+
+/*
+ * A hand written recursive descent parser. Each method consumes what it recognizes and leaves
+ * `position` on the first character it does not understand.
+ */
+const MAX_DEPTH: usize = 128;
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+    depth: usize, // How many !, ( and filters the parser is inside of
+}
+
+impl Parser {
+    fn error(&self, message: &str) -> JsonPathError {
+        JsonPathError {
+            position: self.position,
+            message: message.to_string(),
+        }
+    }
+
+    // Every recursion into a nested expression goes through here
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, JsonPathError>,
+    ) -> Result<T, JsonPathError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error(&format!("nested more than {MAX_DEPTH} deep")));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn peek_str(&self, expected: &str) -> bool {
+        expected
+            .chars()
+            .enumerate()
+            .all(|(i, c)| self.chars.get(self.position + i) == Some(&c))
+    }
+
+    fn eat(&mut self, expected: &str) -> bool {
+        if self.peek_str(expected) {
+            self.position += expected.chars().count();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), JsonPathError> {
+        if self.peek() == Some(expected) {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{expected}'")))
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.position += 1;
+        }
+    }
+
+    fn segments(&mut self) -> Result<Vec<Segment>, JsonPathError> {
+        let mut segments = Vec::new();
+        loop {
+            if self.eat("..") {
+                let selectors = match self.peek() {
+                    Some('[') => self.bracket()?,
+                    Some('*') => {
+                        self.position += 1;
+                        vec![Selector::Wildcard]
+                    }
+                    _ => vec![Selector::Name(self.name()?)],
+                };
+                segments.push(Segment::Descendant(selectors));
+            } else if self.eat(".") {
+                if self.eat("*") {
+                    segments.push(Segment::Child(vec![Selector::Wildcard]));
+                } else {
+                    segments.push(Segment::Child(vec![Selector::Name(self.name()?)]));
+                }
+            } else if self.peek() == Some('[') {
+                segments.push(Segment::Child(self.bracket()?));
+            } else {
+                return Ok(segments);
+            }
+        }
+    }
+
+    // A member name in dot notation: letters, digits, _ and anything non-ASCII, not starting with a digit
+    fn name(&mut self) -> Result<String, JsonPathError> {
+        let start = self.position;
+        while let Some(c) = self.peek() {
+            let valid = c.is_alphabetic()
+                || c == '_'
+                || !c.is_ascii()
+                || (c.is_ascii_digit() && self.position > start);
+            if !valid {
+                break;
+            }
+            self.position += 1;
+        }
+        if self.position == start {
+            return Err(self.error("expected a member name"));
+        }
+        Ok(self.chars[start..self.position].iter().collect())
+    }
+
+    fn bracket(&mut self) -> Result<Vec<Selector>, JsonPathError> {
+        self.expect('[')?;
+        let mut selectors = Vec::new();
+        loop {
+            self.skip_whitespace();
+            selectors.push(self.selector()?);
+            self.skip_whitespace();
+            if self.eat("]") {
+                return Ok(selectors);
+            }
+            self.expect(',')
+                .map_err(|_| self.error("expected ',' or ']'"))?;
+        }
+    }
+
+    fn selector(&mut self) -> Result<Selector, JsonPathError> {
+        match self.peek() {
+            Some('*') => {
+                self.position += 1;
+                Ok(Selector::Wildcard)
+            }
+            Some('\'' | '"') => Ok(Selector::Name(self.string()?)),
+            Some('?') => {
+                self.position += 1;
+                self.skip_whitespace();
+                Ok(Selector::Filter(self.nested(Self::or)?))
+            }
+            Some(c) if c == ':' || c == '-' || c.is_ascii_digit() => self.index_or_slice(),
+            _ => Err(self.error("expected a selector")),
+        }
+    }
+
+    fn index_or_slice(&mut self) -> Result<Selector, JsonPathError> {
+        let start = self.integer()?;
+        self.skip_whitespace();
+        if !self.eat(":") {
+            return start
+                .map(Selector::Index)
+                .ok_or_else(|| self.error("expected an index"));
+        }
+        self.skip_whitespace();
+        let end = self.integer()?;
+        self.skip_whitespace();
+        let step = if self.eat(":") {
+            self.skip_whitespace();
+            self.integer()?
+        } else {
+            None
+        };
+        Ok(Selector::Slice { start, end, step })
+    }
+
+    // None if there is no integer here at all
+    fn integer(&mut self) -> Result<Option<i64>, JsonPathError> {
+        let start = self.position;
+        self.eat("-");
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.position += 1;
+        }
+        let text: String = self.chars[start..self.position].iter().collect();
+        match text.as_str() {
+            "" => Ok(None),
+            _ => text.parse().map(Some).map_err(|_| JsonPathError {
+                position: start,
+                message: format!("{text:?} is not a valid integer"),
+            }),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, JsonPathError> {
+        let quote = self.peek().ok_or_else(|| self.error("expected a string"))?;
+        self.position += 1;
+        let mut text = String::new();
+        loop {
+            match self.peek() {
+                None => return Err(self.error("unterminated string")),
+                Some(c) if c == quote => {
+                    self.position += 1;
+                    return Ok(text);
+                }
+                Some('\\') => {
+                    self.position += 1;
+                    let escaped = match self.peek() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some(c @ ('\\' | '/' | '\'' | '"')) => c,
+                        _ => return Err(self.error("unknown escape sequence")),
+                    };
+                    text.push(escaped);
+                    self.position += 1;
+                }
+                Some(c) => {
+                    text.push(c);
+                    self.position += 1;
+                }
+            }
+        }
+    }
+
+    // Precedence from loose to tight: || then && then ! then comparisons
+    fn or(&mut self) -> Result<Expression, JsonPathError> {
+        let mut left = self.and()?;
+        while self.eat("||") {
+            self.skip_whitespace();
+            left = Expression::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expression, JsonPathError> {
+        let mut left = self.not()?;
+        while self.eat("&&") {
+            self.skip_whitespace();
+            left = Expression::And(Box::new(left), Box::new(self.not()?));
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<Expression, JsonPathError> {
+        // "!=" is a comparison, only a lone "!" negates
+        if self.peek() == Some('!') && !self.peek_str("!=") {
+            self.position += 1;
+            self.skip_whitespace();
+            return Ok(Expression::Not(Box::new(self.nested(Self::not)?)));
+        }
+        let expression = if self.eat("(") {
+            self.skip_whitespace();
+            let inner = self.nested(Self::or)?;
+            self.expect(')')?;
+            inner
+        } else {
+            self.comparison()?
+        };
+        self.skip_whitespace();
+        Ok(expression)
+    }
+
+    fn comparison(&mut self) -> Result<Expression, JsonPathError> {
+        let start = self.position;
+        let left = self.operand()?;
+        self.skip_whitespace();
+        let comparison = [
+            ("==", Comparison::Equal),
+            ("!=", Comparison::NotEqual),
+            ("<=", Comparison::LessOrEqual),
+            (">=", Comparison::GreaterOrEqual),
+            ("<", Comparison::Less),
+            (">", Comparison::Greater),
+        ]
+        .into_iter()
+        .find(|(symbol, _)| self.eat(symbol));
+
+        match (comparison, left) {
+            (Some((_, comparison)), left) => {
+                self.skip_whitespace();
+                let right = self.operand()?;
+                Ok(Expression::Compare(left, comparison, right))
+            }
+            (None, Operand::Literal(_)) => Err(JsonPathError {
+                position: start,
+                message: "a literal on its own is not a test, compare it with something"
+                    .to_string(),
+            }),
+            (None, path) => Ok(Expression::Exists(path)),
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, JsonPathError> {
+        match self.peek() {
+            Some('@') => {
+                self.position += 1;
+                Ok(Operand::Current(self.segments()?))
+            }
+            Some('$') => {
+                self.position += 1;
+                Ok(Operand::Root(self.segments()?))
+            }
+            Some('\'' | '"') => Ok(Operand::Literal(Value::String(self.string()?))),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            _ => {
+                for (word, value) in [
+                    ("true", Value::Bool(true)),
+                    ("false", Value::Bool(false)),
+                    ("null", Value::Null),
+                ] {
+                    if self.eat(word) {
+                        return Ok(Operand::Literal(value));
+                    }
+                }
+                Err(self.error("expected @, $ or a literal"))
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Operand, JsonPathError> {
+        let start = self.position;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+        {
+            self.position += 1;
+        }
+        let text: String = self.chars[start..self.position].iter().collect();
+        // serde_json already knows how to read a JSON number
+        match serde_json::from_str::<Value>(&text) {
+            Ok(number @ Value::Number(_)) => Ok(Operand::Literal(number)),
+            _ => Err(JsonPathError {
+                position: start,
+                message: format!("{text:?} is not a valid number"),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn store() -> Value {
+        json!({"store": {
+            "book": [
+                {"category": "reference", "author": "Rees", "price": 8.95},
+                {"category": "fiction", "author": "Waugh", "price": 12.99},
+                {"category": "fiction", "author": "Melville", "price": 8.99, "isbn": "0-553"},
+                {"category": "fiction", "author": "Tolkien", "price": 22.99, "isbn": "0-395"}
+            ],
+            "bicycle": {"color": "red", "price": 399}
+        }})
+    }
+
+    fn paths(expression: &str, root: &Value) -> Vec<String> {
+        query(expression, root)
+            .unwrap()
+            .into_iter()
+            .map(|found| found.path)
+            .collect()
+    }
+
+    fn values(expression: &str, root: &Value) -> Vec<Value> {
+        JsonPath::parse(expression)
+            .unwrap()
+            .values(root)
+            .into_iter()
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn names_indices_and_wildcards() {
+        let store = store();
+        assert_eq!(values("$.store.bicycle['color']", &store), [json!("red")]);
+        assert_eq!(paths("$.store.book[-1]", &store), ["$['store']['book'][3]"]);
+        assert_eq!(
+            values("$.store.book[0,2].author", &store),
+            [json!("Rees"), json!("Melville")]
+        );
+        assert_eq!(paths("$.store.*", &store).len(), 2);
+        assert!(paths("$.store.book[4]", &store).is_empty());
+        assert!(paths("$.store.book.name", &store).is_empty());
+    }
+
+    #[test]
+    fn slices_follow_python() {
+        let numbers = json!([0, 1, 2, 3, 4, 5]);
+        let slice = |expression| values(expression, &numbers);
+        assert_eq!(slice("$[1:3]"), [json!(1), json!(2)]);
+        assert_eq!(slice("$[:2]"), [json!(0), json!(1)]);
+        assert_eq!(slice("$[-2:]"), [json!(4), json!(5)]);
+        assert_eq!(slice("$[::2]"), [json!(0), json!(2), json!(4)]);
+        assert_eq!(slice("$[::-1]").len(), 6);
+        assert_eq!(slice("$[::-1]")[0], json!(5));
+        assert_eq!(slice("$[4:1:-2]"), [json!(4), json!(2)]);
+        assert!(slice("$[3:1]").is_empty());
+        assert!(slice("$[::0]").is_empty());
+        assert_eq!(slice("$[1:100:9223372036854775807]"), [json!(1)]);
+        assert_eq!(slice("$[::-9223372036854775808]"), [json!(5)]);
+    }
+
+    #[test]
+    fn descendants_in_document_order() {
+        let store = store();
+        assert_eq!(values("$..author", &store).len(), 4);
+        assert_eq!(
+            values("$..price", &store),
+            [
+                json!(399),
+                json!(8.95),
+                json!(12.99),
+                json!(8.99),
+                json!(22.99)
+            ]
+        );
+        assert_eq!(paths("$..book[0]", &store), ["$['store']['book'][0]"]);
+        assert_eq!(
+            paths("$..*", &json!({"a": [1, {"b": 2}]})),
+            ["$['a']", "$['a'][0]", "$['a'][1]", "$['a'][1]['b']"]
+        );
+    }
+
+    #[test]
+    fn filters() {
+        let store = store();
+        let authors = |filter: &str| -> Vec<Value> {
+            values(&format!("$.store.book[?({filter})].author"), &store)
+        };
+        assert_eq!(authors("@.isbn"), [json!("Melville"), json!("Tolkien")]);
+        assert_eq!(authors("@.price < 9"), [json!("Rees"), json!("Melville")]);
+        assert_eq!(
+            authors("@.category == 'fiction' && !(@.price > 20)"),
+            [json!("Waugh"), json!("Melville")]
+        );
+        assert_eq!(
+            authors("@.price == 8.95 || @.author == \"Tolkien\""),
+            [json!("Rees"), json!("Tolkien")]
+        );
+        assert_eq!(authors("@.price < $.store.bicycle.price").len(), 4);
+        assert_eq!(authors("@.missing == $.nothing").len(), 4);
+        assert!(authors("@.price != @.price").is_empty());
+        assert_eq!(values("$[?(@ == 1)]", &json!([1.0, 2, "1"])), [json!(1.0)]);
+        assert_eq!(paths("$..[?(@.color)]", &store), ["$['store']['bicycle']"]);
+    }
+
+    #[test]
+    fn errors_say_where() {
+        for (expression, position) in [
+            ("store", 0),
+            ("$.store[", 8),
+            ("$['unterminated", 15),
+            ("$[?(@.price <)]", 13),
+            ("$[?(1)]", 4),
+            ("$.a b", 4),
+        ] {
+            let err = JsonPath::parse(expression).unwrap_err();
+            assert_eq!(err.position, position, "{expression}: {err}");
+        }
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let nots = |n: usize| format!("$[?({}@.a)]", "!".repeat(n));
+        let parens = |n: usize| format!("$[?({}@.a{})]", "(".repeat(n), ")".repeat(n));
+        let filters = |n: usize| format!("${}", "[?(@".repeat(n)) + &")]".repeat(n);
+        // The filter and its parentheses are two levels already
+        assert!(JsonPath::parse(&nots(MAX_DEPTH - 2)).is_ok());
+        assert!(JsonPath::parse(&nots(MAX_DEPTH - 1)).is_err());
+        assert!(JsonPath::parse(&parens(MAX_DEPTH - 2)).is_ok());
+        assert!(JsonPath::parse(&filters(MAX_DEPTH / 2)).is_ok());
+        for expression in [nots(100_000), parens(100_000), filters(100_000)] {
+            let err = JsonPath::parse(&expression).unwrap_err();
+            assert!(err.message.contains("nested"), "{err}");
+        }
+    }
+}
@@ -0,0 +1,617 @@
+use crate::json_patch::json_equal;
+use regex_lite::Regex;
+use serde_json::{Map, Value};
+use std::{collections::BTreeMap, error::Error, fmt};
+
+/*
+ * A validator for a practical subset of JSON Schema:
+ *
+ *   type                    "null", "boolean", "object", "array", "number", "string", "integer", or a list of them
+ *   required, properties    object members, additionalProperties: false rejects members not listed in properties
+ *   items                   one schema every array element must match, minItems / maxItems
+ *   enum                    the value must equal one of the listed values
+ *   minimum, maximum        inclusive bounds for numbers, exclusiveMinimum / exclusiveMaximum exclusive ones
+ *   minLength, maxLength    string length in characters
+ *   pattern                 a regular expression the string must contain a match of (use ^ and $ to match it whole)
+ *
+ * Other keywords ($schema, title, description, ...) are ignored, like the specification asks for unknown ones.
+ *
+ * A schema is compiled once, so malformed schemas and invalid patterns are reported before any document is checked.
+ * validate() does not stop at the first problem: it returns every violation, each with the JSON pointer
+ * (RFC 6901) of the offending value, e.g. /0/name. The document root is the empty pointer "".
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JsonType {
+    Null,
+    Boolean,
+    Object,
+    Array,
+    Number,
+    String,
+    Integer,
+}
+
+impl JsonType {
+    fn parse(name: &str) -> Option<JsonType> {
+        Some(match name {
+            "null" => JsonType::Null,
+            "boolean" => JsonType::Boolean,
+            "object" => JsonType::Object,
+            "array" => JsonType::Array,
+            "number" => JsonType::Number,
+            "string" => JsonType::String,
+            "integer" => JsonType::Integer,
+            _ => return None,
+        })
+    }
+
+    pub fn of(value: &Value) -> JsonType {
+        match value {
+            Value::Null => JsonType::Null,
+            Value::Bool(_) => JsonType::Boolean,
+            Value::Object(_) => JsonType::Object,
+            Value::Array(_) => JsonType::Array,
+            Value::String(_) => JsonType::String,
+            Value::Number(n) if n.is_f64() => JsonType::Number,
+            Value::Number(_) => JsonType::Integer,
+        }
+    }
+
+    fn matches(self, value: &Value) -> bool {
+        match (self, value) {
+            (JsonType::Number, Value::Number(_)) => true,
+            // 1.0 is an integer as far as JSON Schema is concerned
+            (JsonType::Integer, Value::Number(n)) => n.as_f64().is_some_and(|n| n.fract() == 0.0),
+            (expected, value) => expected == JsonType::of(value),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            JsonType::Null => "null",
+            JsonType::Boolean => "boolean",
+            JsonType::Object => "object",
+            JsonType::Array => "array",
+            JsonType::Number => "number",
+            JsonType::String => "string",
+            JsonType::Integer => "integer",
+        }
+    }
+}
+
+impl fmt::Display for JsonType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+// The schema itself is malformed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaError {
+    pub pointer: String, // Where in the schema
+    pub message: String,
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid schema at \"{}\": {}",
+            self.pointer, self.message
+        )
+    }
+}
+
+impl Error for SchemaError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ViolationKind {
+    WrongType {
+        expected: Vec<JsonType>,
+        found: JsonType,
+    },
+    MissingProperty(String),
+    UnexpectedProperty(String),
+    NotInEnum(Vec<Value>),
+    BelowMinimum {
+        minimum: f64,
+        exclusive: bool,
+    },
+    AboveMaximum {
+        maximum: f64,
+        exclusive: bool,
+    },
+    TooShort {
+        min_length: usize,
+    },
+    TooLong {
+        max_length: usize,
+    },
+    TooFewItems {
+        min_items: usize,
+    },
+    TooManyItems {
+        max_items: usize,
+    },
+    PatternMismatch {
+        pattern: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub pointer: String, // Where in the document
+    pub kind: ViolationKind,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"{}\": ", self.pointer)?;
+        match &self.kind {
+            ViolationKind::WrongType { expected, found } => {
+                let expected: Vec<&str> = expected.iter().map(|t| t.as_str()).collect();
+                write!(f, "expected {}, found {}", expected.join(" or "), found)
+            }
+            ViolationKind::MissingProperty(name) => write!(f, "missing required property {name:?}"),
+            ViolationKind::UnexpectedProperty(name) => write!(f, "unexpected property {name:?}"),
+            ViolationKind::NotInEnum(allowed) => {
+                let allowed: Vec<String> = allowed.iter().map(Value::to_string).collect();
+                write!(f, "must be one of {}", allowed.join(", "))
+            }
+            ViolationKind::BelowMinimum { minimum, exclusive } => {
+                let or_equal = if *exclusive { "" } else { " or equal to" };
+                write!(f, "must be greater than{} {}", or_equal, minimum)
+            }
+            ViolationKind::AboveMaximum { maximum, exclusive } => {
+                let or_equal = if *exclusive { "" } else { " or equal to" };
+                write!(f, "must be less than{} {}", or_equal, maximum)
+            }
+            ViolationKind::TooShort { min_length } => {
+                write!(f, "must be at least {min_length} characters long")
+            }
+            ViolationKind::TooLong { max_length } => {
+                write!(f, "must be at most {max_length} characters long")
+            }
+            ViolationKind::TooFewItems { min_items } => {
+                write!(f, "must have at least {min_items} items")
+            }
+            ViolationKind::TooManyItems { max_items } => {
+                write!(f, "must have at most {max_items} items")
+            }
+            ViolationKind::PatternMismatch { pattern } => write!(f, "must match {pattern:?}"),
+        }
+    }
+}
+
+// Every violation of a document, returned by Schema::check()
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationError {
+    pub violations: Vec<Violation>,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The document does not match its schema ({} violations)",
+            self.violations.len()
+        )?;
+        for violation in &self.violations {
+            write!(f, "\n  {}", violation)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ValidationError {}
+
+#[derive(Debug, Clone, Default)]
+struct Bound {
+    value: f64,
+    exclusive: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Schema {
+    types: Option<Vec<JsonType>>,
+    required: Vec<String>,
+    properties: BTreeMap<String, Schema>,
+    additional_properties: bool,
+    items: Option<Box<Schema>>,
+    enum_values: Option<Vec<Value>>,
+    minimums: Vec<Bound>, // minimum and exclusiveMinimum may both be given, every one is checked
+    maximums: Vec<Bound>,
+    min_length: Option<usize>,
+    max_length: Option<usize>,
+    min_items: Option<usize>,
+    max_items: Option<usize>,
+    pattern: Option<Regex>,
+}
+
+// "/" and "~" are the only characters a JSON pointer escapes
+pub fn pointer_token(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+impl Schema {
+    pub fn compile(schema: &Value) -> Result<Schema, SchemaError> {
+        compile(schema, "")
+    }
+
+    pub fn validate(&self, document: &Value) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        self.validate_at(document, &mut String::new(), &mut violations);
+        violations
+    }
+
+    pub fn check(&self, document: &Value) -> Result<(), ValidationError> {
+        let violations = self.validate(document);
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationError { violations })
+        }
+    }
+
+    // `pointer` grows and shrinks while walking the document, instead of allocating one per value
+    fn validate_at(&self, value: &Value, pointer: &mut String, violations: &mut Vec<Violation>) {
+        let mut report = |kind| {
+            violations.push(Violation {
+                pointer: pointer.clone(),
+                kind,
+            })
+        };
+
+        if let Some(types) = &self.types {
+            if !types.iter().any(|t| t.matches(value)) {
+                report(ViolationKind::WrongType {
+                    expected: types.clone(),
+                    found: JsonType::of(value),
+                });
+                return; // Every other keyword would only repeat the same mistake
+            }
+        }
+        if let Some(allowed) = &self.enum_values {
+            // Numbers by value, so 1 is in [1.0]
+            if !allowed.iter().any(|allowed| json_equal(allowed, value)) {
+                report(ViolationKind::NotInEnum(allowed.clone()));
+            }
+        }
+
+        match value {
+            Value::Number(number) => {
+                let number = number.as_f64().unwrap_or(f64::NAN);
+                for &Bound { value, exclusive } in &self.minimums {
+                    if number < value || (exclusive && number == value) {
+                        report(ViolationKind::BelowMinimum {
+                            minimum: value,
+                            exclusive,
+                        });
+                    }
+                }
+                for &Bound { value, exclusive } in &self.maximums {
+                    if number > value || (exclusive && number == value) {
+                        report(ViolationKind::AboveMaximum {
+                            maximum: value,
+                            exclusive,
+                        });
+                    }
+                }
+            }
+            Value::String(text) => {
+                let length = text.chars().count();
+                if let Some(min_length) = self.min_length.filter(|min| length < *min) {
+                    report(ViolationKind::TooShort { min_length });
+                }
+                if let Some(max_length) = self.max_length.filter(|max| length > *max) {
+                    report(ViolationKind::TooLong { max_length });
+                }
+                if let Some(pattern) = self.pattern.as_ref().filter(|p| !p.is_match(text)) {
+                    report(ViolationKind::PatternMismatch {
+                        pattern: pattern.as_str().to_string(),
+                    });
+                }
+            }
+            Value::Array(items) => {
+                if let Some(min_items) = self.min_items.filter(|min| items.len() < *min) {
+                    report(ViolationKind::TooFewItems { min_items });
+                }
+                if let Some(max_items) = self.max_items.filter(|max| items.len() > *max) {
+                    report(ViolationKind::TooManyItems { max_items });
+                }
+                if let Some(schema) = &self.items {
+                    for (index, item) in items.iter().enumerate() {
+                        let length = pointer.len();
+                        pointer.push_str(&format!("/{index}"));
+                        schema.validate_at(item, pointer, violations);
+                        pointer.truncate(length);
+                    }
+                }
+            }
+            Value::Object(object) => self.validate_object(object, pointer, violations),
+            Value::Null | Value::Bool(_) => {}
+        }
+    }
+
+    fn validate_object(
+        &self,
+        object: &Map<String, Value>,
+        pointer: &mut String,
+        violations: &mut Vec<Violation>,
+    ) {
+        for name in &self.required {
+            if !object.contains_key(name) {
+                violations.push(Violation {
+                    pointer: pointer.clone(),
+                    kind: ViolationKind::MissingProperty(name.clone()),
+                });
+            }
+        }
+        for (name, value) in object {
+            let length = pointer.len();
+            pointer.push('/');
+            pointer.push_str(&pointer_token(name));
+            match self.properties.get(name) {
+                Some(schema) => schema.validate_at(value, pointer, violations),
+                None if !self.additional_properties => violations.push(Violation {
+                    pointer: pointer.clone(),
+                    kind: ViolationKind::UnexpectedProperty(name.clone()),
+                }),
+                None => {}
+            }
+            pointer.truncate(length);
+        }
+    }
+}
+
+fn compile(schema: &Value, pointer: &str) -> Result<Schema, SchemaError> {
+    let error = |keyword: &str, message: &str| SchemaError {
+        pointer: format!("{pointer}/{keyword}"),
+        message: message.to_string(),
+    };
+    let Value::Object(keywords) = schema else {
+        return Err(SchemaError {
+            pointer: pointer.to_string(),
+            message: "a schema must be an object".to_string(),
+        });
+    };
+    let count = |keyword: &str| -> Result<Option<usize>, SchemaError> {
+        keywords
+            .get(keyword)
+            .map(|value| {
+                value
+                    .as_u64()
+                    .map(|n| n as usize)
+                    .ok_or_else(|| error(keyword, "must be a non-negative integer"))
+            })
+            .transpose()
+    };
+    let bounds = |inclusive: &str, exclusive: &str| -> Result<Vec<Bound>, SchemaError> {
+        let number = |keyword: &str| {
+            keywords
+                .get(keyword)
+                .map(|value| {
+                    value
+                        .as_f64()
+                        .ok_or_else(|| error(keyword, "must be a number"))
+                })
+                .transpose()
+        };
+        let inclusive = number(inclusive)?.map(|value| Bound {
+            value,
+            exclusive: false,
+        });
+        let exclusive = number(exclusive)?.map(|value| Bound {
+            value,
+            exclusive: true,
+        });
+        Ok(inclusive.into_iter().chain(exclusive).collect())
+    };
+
+    let mut compiled = Schema {
+        additional_properties: true,
+        min_length: count("minLength")?,
+        max_length: count("maxLength")?,
+        min_items: count("minItems")?,
+        max_items: count("maxItems")?,
+        minimums: bounds("minimum", "exclusiveMinimum")?,
+        maximums: bounds("maximum", "exclusiveMaximum")?,
+        ..Schema::default()
+    };
+
+    if let Some(types) = keywords.get("type") {
+        let names = match types {
+            Value::String(name) => Some(vec![name.as_str()]),
+            Value::Array(names) => names.iter().map(Value::as_str).collect(),
+            _ => None,
+        };
+        let parsed: Option<Vec<JsonType>> =
+            names.and_then(|names| names.into_iter().map(JsonType::parse).collect());
+        compiled.types = Some(
+            parsed
+                .filter(|types| !types.is_empty())
+                .ok_or_else(|| error("type", "must be a type name or a list of them"))?,
+        );
+    }
+    if let Some(required) = keywords.get("required") {
+        compiled.required = required
+            .as_array()
+            .and_then(|names| {
+                names
+                    .iter()
+                    .map(|name| name.as_str().map(str::to_string))
+                    .collect()
+            })
+            .ok_or_else(|| error("required", "must be a list of property names"))?;
+    }
+    if let Some(properties) = keywords.get("properties") {
+        let properties = properties
+            .as_object()
+            .ok_or_else(|| error("properties", "must be an object"))?;
+        for (name, schema) in properties {
+            let at = format!("{pointer}/properties/{}", pointer_token(name));
+            compiled
+                .properties
+                .insert(name.clone(), compile(schema, &at)?);
+        }
+    }
+    if let Some(additional) = keywords.get("additionalProperties") {
+        compiled.additional_properties = additional
+            .as_bool()
+            .ok_or_else(|| error("additionalProperties", "only true or false is supported"))?;
+    }
+    if let Some(items) = keywords.get("items") {
+        compiled.items = Some(Box::new(compile(items, &format!("{pointer}/items"))?));
+    }
+    if let Some(allowed) = keywords.get("enum") {
+        compiled.enum_values = Some(
+            allowed
+                .as_array()
+                .cloned()
+                .ok_or_else(|| error("enum", "must be a list of values"))?,
+        );
+    }
+    if let Some(pattern) = keywords.get("pattern") {
+        let pattern = pattern
+            .as_str()
+            .ok_or_else(|| error("pattern", "must be a string"))?;
+        compiled.pattern = Some(Regex::new(pattern).map_err(|e| error("pattern", &e.to_string()))?);
+    }
+
+    Ok(compiled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn kinds(schema: Value, document: Value) -> Vec<ViolationKind> {
+        let schema = Schema::compile(&schema).unwrap();
+        schema
+            .validate(&document)
+            .into_iter()
+            .map(|violation| violation.kind)
+            .collect()
+    }
+
+    #[test]
+    fn inclusive_and_exclusive_bounds_are_both_checked() {
+        let schema =
+            json!({"minimum": 5, "exclusiveMinimum": 0, "maximum": 10, "exclusiveMaximum": 20});
+        assert_eq!(kinds(schema.clone(), json!(7)), vec![]);
+        assert_eq!(
+            kinds(schema.clone(), json!(3)),
+            vec![ViolationKind::BelowMinimum {
+                minimum: 5.0,
+                exclusive: false
+            }]
+        );
+        assert_eq!(
+            kinds(schema, json!(15)),
+            vec![ViolationKind::AboveMaximum {
+                maximum: 10.0,
+                exclusive: false
+            }]
+        );
+
+        let schema = json!({"minimum": 0, "exclusiveMinimum": 5});
+        assert_eq!(kinds(schema.clone(), json!(6)), vec![]);
+        assert_eq!(
+            kinds(schema, json!(5)),
+            vec![ViolationKind::BelowMinimum {
+                minimum: 5.0,
+                exclusive: true
+            }]
+        );
+    }
+
+    #[test]
+    fn type_lists_and_integers() {
+        let schema = json!({"type": ["string", "integer"]});
+        assert_eq!(kinds(schema.clone(), json!("a")), vec![]);
+        assert_eq!(kinds(schema.clone(), json!(1.0)), vec![]);
+        assert_eq!(
+            kinds(schema, json!(1.5)),
+            vec![ViolationKind::WrongType {
+                expected: vec![JsonType::String, JsonType::Integer],
+                found: JsonType::Number
+            }]
+        );
+    }
+
+    #[test]
+    fn enum_compares_numbers_by_value() {
+        let schema = json!({"enum": [1.0, [2], {"a": 3.0}, "x"]});
+        for allowed in [json!(1), json!([2.0]), json!({"a": 3}), json!("x")] {
+            assert_eq!(kinds(schema.clone(), allowed.clone()), vec![], "{allowed}");
+        }
+        assert_eq!(
+            kinds(schema, json!(1.5)),
+            vec![ViolationKind::NotInEnum(vec![
+                json!(1.0),
+                json!([2]),
+                json!({"a": 3.0}),
+                json!("x")
+            ])]
+        );
+    }
+
+    #[test]
+    fn malformed_type_keywords_are_schema_errors() {
+        for types in [json!(["string", 1]), json!([]), json!("text"), json!(true)] {
+            let err = Schema::compile(&json!({"properties": {"a": {"type": types}}})).unwrap_err();
+            assert_eq!(err.pointer, "/properties/a/type", "{err}");
+        }
+    }
+
+    #[test]
+    fn every_violation_is_reported_with_its_pointer() {
+        let schema = Schema::compile(&json!({
+            "type": "object",
+            "required": ["name", "tags"],
+            "additionalProperties": false,
+            "properties": {
+                "name": {"type": "string", "minLength": 2, "pattern": "^[a-z]+$"},
+                "tags": {"type": "array", "maxItems": 2, "items": {"enum": ["a", "b"]}},
+                "a/b": {"type": "null"}
+            }
+        }))
+        .unwrap();
+        let violations = schema
+            .validate(&json!({"name": "X", "tags": ["a", "c", "b"], "a/b": 1, "extra": true}));
+        let pointers: Vec<&str> = violations.iter().map(|v| v.pointer.as_str()).collect();
+        assert_eq!(
+            pointers,
+            ["/a~1b", "/extra", "/name", "/name", "/tags", "/tags/1"]
+        );
+        assert!(
+            matches!(violations[1].kind, ViolationKind::UnexpectedProperty(ref name) if name == "extra")
+        );
+
+        let err = schema.check(&json!({})).unwrap_err();
+        assert_eq!(
+            err.violations
+                .iter()
+                .map(|v| v.kind.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                ViolationKind::MissingProperty("name".to_string()),
+                ViolationKind::MissingProperty("tags".to_string())
+            ]
+        );
+        assert!(schema.check(&json!({"name": "ok", "tags": []})).is_ok());
+    }
+
+    #[test]
+    fn malformed_schemas_are_rejected_when_compiled() {
+        assert!(Schema::compile(&json!([])).is_err());
+        assert!(Schema::compile(&json!({"minLength": -1})).is_err());
+        assert!(Schema::compile(&json!({"minimum": "1"})).is_err());
+        assert!(Schema::compile(&json!({"pattern": "("})).is_err());
+        assert!(Schema::compile(&json!({"items": {"type": "thing"}})).is_err());
+        // Unknown keywords are ignored
+        assert!(Schema::compile(&json!({"title": "T", "$schema": "x"})).is_ok());
+    }
+}
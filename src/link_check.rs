@@ -0,0 +1,626 @@
+use crate::resolver::{self, ResolveError, Resolver};
+use crate::supervisor::CancellationToken;
+use crate::url::{Url, UrlError};
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt, io,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::Semaphore,
+    task::JoinSet,
+};
+
+/*
+ * Checks whether links are still alive, all of them at once.
+ *
+ * Links are taken as written, so a link that is not a valid URL, like the javascript: bookmarks some browsers export,
+ * is reported dead instead of stopping the whole check.
+ *
+ * Every link is its own task: resolve the host, connect to the first address that accepts, and with Probe::Head
+ * ask an http:// server for the headers of the page. https:// links only get the TCP connect, speaking TLS is
+ * beyond this crate. A link is dead when any step fails, the server answers 400 or above, or the whole check
+ * takes longer than the timeout.
+ *
+ * Many links often point at the same host, and hammering it with all of them at once is rude. Each host gets a
+ * semaphore with `per_host` permits, a task waits for a permit before its timeout starts.
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Probe {
+    Connect, // Alive once the TCP connection is accepted
+    Head,    // Also needs a successful answer to an HTTP HEAD request
+}
+
+#[derive(Debug)]
+pub enum LinkError {
+    InvalidUrl(UrlError),
+    Resolve(ResolveError),
+    UnknownPort(String), // The scheme has no default port and the URL names none
+    Connect {
+        address: SocketAddr,
+        source: io::Error,
+    },
+    Request(io::Error),
+    BadResponse(String),
+    Status(u16),
+    Timeout(Duration),
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkError::InvalidUrl(_) => write!(f, "Not a URL that can be checked"),
+            LinkError::Resolve(_) => write!(f, "Failed to resolve the host"),
+            LinkError::UnknownPort(scheme) => {
+                write!(f, "No port given, and {} has no default port", scheme)
+            }
+            LinkError::Connect { address, .. } => write!(f, "Failed to connect to {}", address),
+            LinkError::Request(_) => write!(f, "The HEAD request failed"),
+            LinkError::BadResponse(line) => write!(f, "Not an HTTP response: {:?}", line),
+            LinkError::Status(status) => write!(f, "HTTP status {}", status),
+            LinkError::Timeout(after) => write!(f, "No answer within {:?}", after),
+        }
+    }
+}
+
+impl Error for LinkError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LinkError::InvalidUrl(source) => Some(source),
+            LinkError::Resolve(source) => Some(source),
+            LinkError::Connect { source, .. } | LinkError::Request(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<ResolveError> for LinkError {
+    fn from(error: ResolveError) -> Self {
+        LinkError::Resolve(error)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Alive {
+    pub address: SocketAddr, // The address that answered
+    pub status: Option<u16>, // Only with Probe::Head on http:// links
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for Alive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.address)?;
+        if let Some(status) = self.status {
+            write!(f, ", HTTP {}", status)?;
+        }
+        write!(f, " in {:.0?}", self.elapsed)
+    }
+}
+
+#[derive(Debug)]
+pub struct Checked {
+    pub name: String,
+    pub url: String, // Normalized when it is a valid URL, as written otherwise
+    pub result: Result<Alive, LinkError>,
+}
+
+impl Checked {
+    pub fn is_alive(&self) -> bool {
+        self.result.is_ok()
+    }
+
+    fn write_outcome(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.result {
+            Ok(alive) => write!(f, "{}", alive),
+            Err(error) => {
+                // A report has one line per link, so the causes go on the same line
+                write!(f, "{}", error)?;
+                let mut source = error.source();
+                while let Some(cause) = source {
+                    write!(f, ": {}", cause)?;
+                    source = cause.source();
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Checked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}: ", self.name, self.url)?;
+        self.write_outcome(f)
+    }
+}
+
+// The links in the order they were given
+#[derive(Debug)]
+pub struct Report {
+    pub links: Vec<Checked>,
+    pub elapsed: Duration,
+}
+
+impl Report {
+    pub fn dead(&self) -> impl Iterator<Item = &Checked> {
+        self.links.iter().filter(|checked| !checked.is_alive())
+    }
+
+    // Fails when any link is dead, so a command can exit with an error after printing the report
+    pub fn check(&self) -> Result<(), DeadLinks> {
+        match self.dead().count() {
+            0 => Ok(()),
+            dead => Err(DeadLinks {
+                dead,
+                total: self.links.len(),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name_width = self.links.iter().map(|checked| checked.name.len()).max();
+        let url_width = self.links.iter().map(|checked| checked.url.len()).max();
+        for checked in &self.links {
+            let state = if checked.is_alive() { "ok" } else { "DEAD" };
+            write!(
+                f,
+                "{:<4} {:<3$} {:<4$} ",
+                state,
+                checked.name,
+                checked.url,
+                name_width.unwrap_or_default(),
+                url_width.unwrap_or_default()
+            )?;
+            checked.write_outcome(f)?;
+            writeln!(f)?;
+        }
+        write!(
+            f,
+            "{} links checked in {:.0?}, {} dead",
+            self.links.len(),
+            self.elapsed,
+            self.dead().count()
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeadLinks {
+    pub dead: usize,
+    pub total: usize,
+}
+
+impl fmt::Display for DeadLinks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} of {} links are dead", self.dead, self.total)
+    }
+}
+
+impl Error for DeadLinks {}
+
+pub struct LinkChecker<R> {
+    resolver: Arc<R>,
+    timeout: Duration,
+    per_host: usize,
+    probe: Probe,
+}
+
+impl<R: Resolver + Send + Sync + 'static> LinkChecker<R> {
+    pub fn new(resolver: R) -> Self {
+        LinkChecker {
+            resolver: Arc::new(resolver),
+            timeout: Duration::from_secs(5),
+            per_host: 2,
+            probe: Probe::Head,
+        }
+    }
+
+    // For one link: resolving, connecting and the HEAD request together
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn per_host(mut self, per_host: usize) -> Self {
+        self.per_host = per_host.max(1);
+        self
+    }
+
+    pub fn probe(mut self, probe: Probe) -> Self {
+        self.probe = probe;
+        self
+    }
+
+    // Names and URLs, e.g. ("Bing", "www.bing.com")
+    pub async fn check<N, U>(&self, links: impl IntoIterator<Item = (N, U)>) -> Report
+    where
+        N: Into<String>,
+        U: AsRef<str>,
+    {
+        let started_at = Instant::now();
+        let mut hosts: HashMap<String, Arc<Semaphore>> = HashMap::new();
+        let tasks: Vec<_> = links
+            .into_iter()
+            .map(|(name, url)| {
+                let name = name.into();
+                let url = match Url::parse(url.as_ref()) {
+                    Ok(url) => url,
+                    Err(e) => {
+                        let checked = Checked {
+                            name,
+                            url: url.as_ref().to_string(),
+                            result: Err(LinkError::InvalidUrl(e)),
+                        };
+                        return tokio::spawn(async move { checked });
+                    }
+                };
+                let permits = Arc::clone(
+                    hosts
+                        .entry(url.host().to_string())
+                        .or_insert_with(|| Arc::new(Semaphore::new(self.per_host))),
+                );
+                let resolver = Arc::clone(&self.resolver);
+                let (timeout, probe) = (self.timeout, self.probe);
+                tokio::spawn(async move {
+                    let _permit = permits
+                        .acquire_owned()
+                        .await
+                        .expect("semaphore never closed");
+                    let started_at = Instant::now();
+                    let result = tokio::time::timeout(timeout, probe_link(&*resolver, &url, probe))
+                        .await
+                        .unwrap_or(Err(LinkError::Timeout(timeout)))
+                        .map(|(address, status)| Alive {
+                            address,
+                            status,
+                            elapsed: started_at.elapsed(),
+                        });
+                    Checked {
+                        name,
+                        url: url.to_string(),
+                        result,
+                    }
+                })
+            })
+            .collect();
+
+        let mut checked = Vec::with_capacity(tasks.len());
+        for task in tasks {
+            checked.push(task.await.expect("link check panicked"));
+        }
+        Report {
+            links: checked,
+            elapsed: started_at.elapsed(),
+        }
+    }
+}
+
+// The address that accepted the connection, and the HTTP status if one was asked for
+async fn probe_link<R: Resolver>(
+    resolver: &R,
+    url: &Url,
+    probe: Probe,
+) -> Result<(SocketAddr, Option<u16>), LinkError> {
+    let port = url
+        .port_or_default()
+        .ok_or_else(|| LinkError::UnknownPort(url.scheme().to_string()))?;
+    let mut failure = None;
+    // A host with several addresses is alive if any of them answers, like browsers try them in turn
+    for ip in resolver::resolve_host(resolver, url.host()).await? {
+        let address = SocketAddr::new(ip, port);
+        match TcpStream::connect(address).await {
+            Ok(stream) if probe == Probe::Head && url.scheme() == "http" => {
+                return Ok((address, Some(head(stream, url).await?)));
+            }
+            Ok(_) => return Ok((address, None)),
+            Err(source) => failure = Some(LinkError::Connect { address, source }),
+        }
+    }
+    Err(failure.unwrap_or_else(|| ResolveError::NotFound(url.host().to_string()).into()))
+}
+
+// Sends a HEAD request and reads the status of the answer, the headers are not needed
+async fn head(mut stream: TcpStream, url: &Url) -> Result<u16, LinkError> {
+    let request = format!(
+        "HEAD {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: rust-basic\r\nConnection: close\r\n\r\n",
+        url.request_target(),
+        url.authority()
+    );
+    stream
+        .write_all(request.as_bytes())
+        .await
+        .map_err(LinkError::Request)?;
+    let mut status_line = String::new();
+    BufReader::new(stream)
+        .read_line(&mut status_line)
+        .await
+        .map_err(LinkError::Request)?;
+
+    // "HTTP/1.1 200 OK"
+    let mut parts = status_line.split_whitespace();
+    let status = match (parts.next(), parts.next().map(str::parse::<u16>)) {
+        (Some(version), Some(Ok(status))) if version.starts_with("HTTP/") => status,
+        _ => return Err(LinkError::BadResponse(status_line.trim_end().to_string())),
+    };
+    match status {
+        400.. => Err(LinkError::Status(status)),
+        _ => Ok(status),
+    }
+}
+
+/*
+ * An HTTP server that answers every request with a status and no body, for lessons and tests that must not depend
+ * on the network. Each page has a status and a delay before the answer. Unknown paths are 404 Not Found.
+ * It counts how many connections were open at the same time, which shows whether a client keeps its limits.
+ * A client hanging up while its page is delayed closes the connection on this side too.
+ */
+pub struct StubHttpServer {
+    listener: TcpListener,
+    pages: HashMap<String, (u16, Duration)>,
+    peak: Arc<AtomicUsize>,
+}
+
+impl StubHttpServer {
+    // Port 0 lets the OS pick a free port, local_addr() tells which one
+    pub async fn bind(addr: impl ToSocketAddrs) -> io::Result<StubHttpServer> {
+        Ok(StubHttpServer {
+            listener: TcpListener::bind(addr).await?,
+            pages: HashMap::new(),
+            peak: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+
+    pub fn page(mut self, path: &str, status: u16, delay: Duration) -> Self {
+        self.pages.insert(path.to_string(), (status, delay));
+        self
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    // The most connections open at once so far, and can still be read while the server runs
+    pub fn peak_connections(&self) -> Arc<AtomicUsize> {
+        Arc::clone(&self.peak)
+    }
+
+    // Returns the number of requests answered. Connections still open at shutdown are dropped
+    pub async fn run(self, shutdown: CancellationToken) -> io::Result<usize> {
+        let pages = Arc::new(self.pages);
+        let open = Arc::new(AtomicUsize::new(0));
+        let mut connections = JoinSet::new();
+        let mut answered = 0;
+        loop {
+            let (stream, _) = tokio::select! {
+                accepted = self.listener.accept() => accepted?,
+                Some(served) = connections.join_next(), if !connections.is_empty() => {
+                    if let Ok(Ok(())) = served {
+                        answered += 1;
+                    }
+                    continue;
+                }
+                _ = shutdown.cancelled() => {
+                    connections.shutdown().await;
+                    return Ok(answered);
+                }
+            };
+            let now_open = open.fetch_add(1, Ordering::Relaxed) + 1;
+            self.peak.fetch_max(now_open, Ordering::Relaxed);
+            let (pages, open) = (Arc::clone(&pages), Arc::clone(&open));
+            connections.spawn(async move {
+                let served = answer(stream, &pages).await;
+                open.fetch_sub(1, Ordering::Relaxed);
+                served
+            });
+        }
+    }
+}
+
+async fn answer(stream: TcpStream, pages: &HashMap<String, (u16, Duration)>) -> io::Result<()> {
+    let mut stream = BufReader::new(stream);
+    let mut request_line = String::new();
+    if stream.read_line(&mut request_line).await? == 0 {
+        // Connected and hung up without asking anything, like Probe::Connect does
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    // The headers end with an empty line
+    let mut header = String::new();
+    while stream.read_line(&mut header).await? > 2 {
+        header.clear();
+    }
+
+    // "HEAD /path HTTP/1.1"
+    let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+    let (status, delay) = pages.get(path).copied().unwrap_or((404, Duration::ZERO));
+    // A client that gives up while the page is slow closes the connection, no need to keep it open any longer
+    tokio::select! {
+        _ = tokio::time::sleep(delay) => {}
+        _ = stream.read_u8() => return Err(io::ErrorKind::ConnectionAborted.into()),
+    }
+    let reason = match status {
+        200 => "OK",
+        301 => "Moved Permanently",
+        404 => "Not Found",
+        500 => "Internal Server Error",
+        _ => "Unknown",
+    };
+    let response =
+        format!("HTTP/1.1 {status} {reason}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+    stream.get_mut().write_all(response.as_bytes()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::HostsFile;
+    use tokio::task::JoinHandle;
+
+    struct Site {
+        port: u16,
+        peak: Arc<AtomicUsize>,
+        shutdown: CancellationToken,
+        server: JoinHandle<io::Result<usize>>,
+    }
+
+    impl Site {
+        async fn start(pages: &[(&str, u16, Duration)]) -> Site {
+            let server = StubHttpServer::bind("127.0.0.1:0").await.unwrap();
+            let server = pages.iter().fold(server, |server, &(path, status, delay)| {
+                server.page(path, status, delay)
+            });
+            let port = server.local_addr().unwrap().port();
+            let peak = server.peak_connections();
+            let shutdown = CancellationToken::new();
+            let server = tokio::spawn(server.run(shutdown.clone()));
+            Site {
+                port,
+                peak,
+                shutdown,
+                server,
+            }
+        }
+
+        fn link(&self, path: &str) -> (String, String) {
+            let url = format!("http://site.test:{}{}", self.port, path);
+            (path.to_string(), url)
+        }
+
+        async fn stop(self) -> usize {
+            self.shutdown.cancel();
+            self.server.await.unwrap().unwrap()
+        }
+    }
+
+    fn checker() -> LinkChecker<HostsFile> {
+        LinkChecker::new(HostsFile::parse("127.0.0.1 site.test"))
+            .timeout(Duration::from_millis(300))
+    }
+
+    #[tokio::test]
+    async fn a_page_that_answers_is_alive() {
+        let site = Site::start(&[("/", 200, Duration::ZERO), ("/old", 301, Duration::ZERO)]).await;
+        let report = checker().check([site.link("/"), site.link("/old")]).await;
+        let statuses: Vec<_> = report
+            .links
+            .iter()
+            .map(|checked| checked.result.as_ref().unwrap().status)
+            .collect();
+        assert_eq!(statuses, [Some(200), Some(301)]);
+        assert!(report.check().is_ok());
+        assert_eq!(site.stop().await, 2);
+    }
+
+    #[tokio::test]
+    async fn error_statuses_are_dead() {
+        let site = Site::start(&[("/broken", 500, Duration::ZERO)]).await;
+        let report = checker()
+            .check([site.link("/missing"), site.link("/broken")])
+            .await;
+        let statuses: Vec<_> = report
+            .links
+            .iter()
+            .map(|checked| match checked.result {
+                Err(LinkError::Status(status)) => status,
+                _ => panic!("expected an error status, got {checked}"),
+            })
+            .collect();
+        assert_eq!(statuses, [404, 500]);
+        assert_eq!(report.check(), Err(DeadLinks { dead: 2, total: 2 }));
+        site.stop().await;
+    }
+
+    #[tokio::test]
+    async fn a_slow_page_times_out() {
+        let site = Site::start(&[("/slow", 200, Duration::from_secs(5))]).await;
+        let report = checker().check([site.link("/slow")]).await;
+        assert!(matches!(
+            report.links[0].result,
+            Err(LinkError::Timeout(after)) if after == Duration::from_millis(300)
+        ));
+        site.stop().await;
+    }
+
+    #[tokio::test]
+    async fn a_closed_port_is_refused() {
+        let closed = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let report = checker()
+            .check([("closed", format!("http://{closed}/"))])
+            .await;
+        assert!(matches!(
+            &report.links[0].result,
+            Err(LinkError::Connect { address, .. }) if *address == closed
+        ));
+    }
+
+    #[tokio::test]
+    async fn an_unknown_host_does_not_resolve() {
+        let report = checker().check([("gone", "http://gone.test/")]).await;
+        assert!(matches!(
+            report.links[0].result,
+            Err(LinkError::Resolve(ResolveError::NotFound(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn an_invalid_url_is_dead_and_the_rest_is_still_checked() {
+        let site = Site::start(&[("/", 200, Duration::ZERO)]).await;
+        let (name, url) = site.link("/");
+        let links = [
+            ("script".to_string(), "javascript:alert(1)".to_string()),
+            (name, url),
+        ];
+        let report = checker().check(links).await;
+        assert!(matches!(
+            report.links[0].result,
+            Err(LinkError::InvalidUrl(_))
+        ));
+        assert_eq!(report.links[0].url, "javascript:alert(1)");
+        assert!(report.links[1].is_alive());
+        site.stop().await;
+    }
+
+    #[tokio::test]
+    async fn connect_only_ignores_the_status() {
+        let site = Site::start(&[]).await;
+        let report = checker()
+            .probe(Probe::Connect)
+            .check([site.link("/missing")])
+            .await;
+        assert_eq!(report.links[0].result.as_ref().unwrap().status, None);
+        site.stop().await;
+    }
+
+    #[tokio::test]
+    async fn connections_per_host_are_limited() {
+        let pages: Vec<_> = (0..6).map(|n| format!("/{n}")).collect();
+        let site = Site::start(
+            &pages
+                .iter()
+                .map(|path| (path.as_str(), 200, Duration::from_millis(50)))
+                .collect::<Vec<_>>(),
+        )
+        .await;
+        let links: Vec<_> = pages.iter().map(|path| site.link(path)).collect();
+        let report = checker().per_host(2).check(links).await;
+        assert_eq!(report.dead().count(), 0);
+        assert_eq!(site.peak.load(Ordering::Relaxed), 2);
+        // The links come back in the order they were given
+        let names: Vec<_> = report.links.iter().map(|checked| &checked.name).collect();
+        assert_eq!(names, pages.iter().collect::<Vec<_>>());
+        site.stop().await;
+    }
+}
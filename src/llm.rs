@@ -0,0 +1,85 @@
+/*
+ * Building blocks shared by the LLM flavoured examples of the OOP lesson (ChatCompletionMessage and friends).
+ *
+ * The struct and the Token trait are declared here rather than inside oop() so that other modules can implement
+ * Token for their own types and account them together (see usage.rs).
+ */
+pub mod media;
+pub mod rate_limit;
+pub mod role;
+pub mod template;
+pub mod usage;
+
+pub use rate_limit::{RateLimiter, RateLimits};
+pub use role::{validate_transitions, Role};
+pub use usage::{Budget, LLMEcosystem, UsageType};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatCompletionMessage {
+    pub role: Role, // A closed set of roles instead of a free-form String. See src/llm/role.rs
+    pub content: String,
+    pub last_response: Option<String>,
+}
+
+impl ChatCompletionMessage {
+    // The first parameter of any method must be a reference to the instance associated with the method call
+    pub fn show_input_tokens(&self) {
+        // &self - Immutable ref to the inst; &mut self Mutable one
+        // Only the printed label is uppercased, the stored role stays untouched
+        println!("{}: {}", self.role.as_str().to_uppercase(), &self.content);
+    }
+
+    // By default fields and methods are accessible only to the module they belong to. Use pub to make them public
+    pub fn show_response(&mut self) {
+        let response = format!(
+            "{} seems to wanna know {}. There are some advices...",
+            self.role, self.content
+        );
+        println!("{}", response);
+        self.last_response = Some(response);
+    }
+}
+
+// Polymorphism provides ability for objects of different types through a common interface.
+// Rust supports polymorphism with traits. Traits allow us to associate a set of methods with a struct type.
+pub trait Token {
+    fn compute_tokens(&self) -> i32;
+
+    // Which kind of application consumed the tokens. Used by the accounting in usage.rs
+    fn usage_type(&self) -> UsageType;
+
+    // Traits can have implemented methods.
+    fn use_multi_trait_fns(&self) {
+        let a = self.compute_tokens();
+        let b = self.compute_tokens();
+
+        println!("If you ask 2 times, you will consume {} tokens", a + b);
+    }
+
+    // Cost in USD. By default every token is billed at the rate of its usage type; implementors can override it.
+    fn cost(&self) -> f64 {
+        self.compute_tokens() as f64 * self.usage_type().usd_per_1k_tokens() / 1000.0
+    }
+}
+
+impl Token for ChatCompletionMessage {
+    // Declared first and assigned in the match, to show late initialization
+    #[allow(clippy::needless_late_init)]
+    fn compute_tokens(&self) -> i32 {
+        let input_tokens: i32 = self.content.len() as i32;
+
+        let output_tokens: i32;
+        match &self.last_response {
+            Some(response) => output_tokens = response.len() as i32,
+            None => output_tokens = 0,
+        }
+
+        input_tokens + output_tokens
+    }
+
+    fn usage_type(&self) -> UsageType {
+        UsageType::Chat
+    }
+}
@@ -0,0 +1,135 @@
+use super::{Token, UsageType};
+use std::time::Duration;
+
+/*
+ * Token implementors other than chat messages. Each one counts tokens with its own rule,
+ * which is exactly why LLMEcosystem stores them as Box<dyn Token>.
+ *
+ * Counts saturate at i32::MAX instead of overflowing, whatever the size of the input.
+ */
+
+fn saturate(n: impl TryInto<i32>) -> i32 {
+    n.try_into().unwrap_or(i32::MAX)
+}
+
+pub struct ImageGeneration {
+    pub prompt: String,
+    pub width: u32,
+    pub height: u32,
+    pub count: u32,
+}
+
+impl ImageGeneration {
+    // Images are billed per 512x512 tile plus a fixed base per image
+    const TOKENS_PER_TILE: i32 = 170;
+    const BASE_TOKENS: i32 = 85;
+    const USD_PER_IMAGE: f64 = 0.02;
+
+    fn tiles(&self) -> i32 {
+        let tiles = u64::from(self.width.div_ceil(512)) * u64::from(self.height.div_ceil(512));
+        saturate(tiles)
+    }
+}
+
+impl Token for ImageGeneration {
+    fn compute_tokens(&self) -> i32 {
+        let per_image = self
+            .tiles()
+            .saturating_mul(Self::TOKENS_PER_TILE)
+            .saturating_add(Self::BASE_TOKENS);
+        saturate(self.prompt.len()).saturating_add(per_image.saturating_mul(saturate(self.count)))
+    }
+
+    fn usage_type(&self) -> UsageType {
+        UsageType::Image
+    }
+
+    // Unlike text, images are sold per generated picture, scaled by the tiles it covers
+    fn cost(&self) -> f64 {
+        Self::USD_PER_IMAGE * self.tiles() as f64 * self.count as f64
+    }
+}
+
+pub struct AudioTranscription {
+    pub duration: Duration,
+}
+
+impl AudioTranscription {
+    const TOKENS_PER_SECOND: f64 = 25.0;
+    const USD_PER_MINUTE: f64 = 0.006;
+}
+
+impl Token for AudioTranscription {
+    fn compute_tokens(&self) -> i32 {
+        (self.duration.as_secs_f64() * Self::TOKENS_PER_SECOND).ceil() as i32
+    }
+
+    fn usage_type(&self) -> UsageType {
+        UsageType::Audio
+    }
+
+    // Audio is billed per started minute no matter how many tokens the transcript has
+    fn cost(&self) -> f64 {
+        (self.duration.as_secs_f64() / 60.0).ceil() * Self::USD_PER_MINUTE
+    }
+}
+
+pub struct Embedding {
+    pub inputs: Vec<String>,
+}
+
+impl Token for Embedding {
+    // Roughly 4 characters per token, at least one token per input
+    fn compute_tokens(&self) -> i32 {
+        self.inputs
+            .iter()
+            .map(|input| saturate(input.len().div_ceil(4)).max(1))
+            .fold(0, i32::saturating_add)
+    }
+
+    fn usage_type(&self) -> UsageType {
+        UsageType::Embedding
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(width: u32, height: u32, count: u32) -> ImageGeneration {
+        ImageGeneration {
+            prompt: "a crab".to_string(),
+            width,
+            height,
+            count,
+        }
+    }
+
+    #[test]
+    fn images_count_tiles_of_512_pixels() {
+        assert_eq!(image(512, 512, 1).compute_tokens(), 6 + 85 + 170);
+        assert_eq!(image(513, 1024, 2).compute_tokens(), 6 + 2 * (85 + 4 * 170));
+        assert!((image(1024, 1024, 3).cost() - 0.24).abs() < 1e-9);
+    }
+
+    #[test]
+    fn huge_images_saturate_instead_of_overflowing() {
+        assert_eq!(image(u32::MAX, u32::MAX, 1).tiles(), i32::MAX);
+        assert_eq!(image(u32::MAX, u32::MAX, 1).compute_tokens(), i32::MAX);
+        assert_eq!(image(512, 512, u32::MAX).compute_tokens(), i32::MAX);
+    }
+
+    #[test]
+    fn audio_and_embeddings() {
+        let audio = AudioTranscription {
+            duration: Duration::from_millis(61_500),
+        };
+        assert_eq!(audio.compute_tokens(), 1538);
+        assert!((audio.cost() - 0.012).abs() < 1e-9);
+
+        let embedding = Embedding {
+            inputs: vec![String::new(), "abcde".to_string()],
+        };
+        assert_eq!(embedding.compute_tokens(), 1 + 2);
+    }
+}
@@ -0,0 +1,334 @@
+use super::Token;
+use std::{collections::HashMap, error::Error, fmt, sync::Mutex};
+use tokio::time::{self, Duration, Instant};
+
+/*
+ * Token bucket rate limiting for outgoing messages.
+ *
+ * Every (user, model) pair owns two buckets: one holding tokens-per-minute, one holding requests-per-minute.
+ * A bucket starts full and refills continuously at capacity / 60s. Sending a message takes compute_tokens() from
+ * the first bucket and 1 from the second, and only succeeds when both can pay.
+ *
+ * try_acquire() rejects straight away, acquire() queues the caller by sleeping on tokio time until the buckets refilled.
+ * A limit of 0 per minute never refills, so a model with one is rejected like a message that can never fit.
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimits {
+    pub tokens_per_minute: u32,
+    pub requests_per_minute: u32,
+    pub context_window: u32, // The biggest single message the model accepts
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RateLimitError {
+    // Can never succeed, not even after waiting
+    ZeroLimit { model: String },
+    ContextWindowExceeded { tokens: u32, context_window: u32 },
+    RequestTooLarge { tokens: u32, tokens_per_minute: u32 },
+    // Can succeed once the bucket refilled
+    TokensPerMinute { retry_after: Duration },
+    RequestsPerMinute { retry_after: Duration },
+}
+
+impl RateLimitError {
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            RateLimitError::TokensPerMinute { retry_after }
+            | RateLimitError::RequestsPerMinute { retry_after } => Some(*retry_after),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for RateLimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RateLimitError::ZeroLimit { model } => {
+                write!(f, "model {} allows 0 tokens or requests per minute", model)
+            }
+            RateLimitError::ContextWindowExceeded {
+                tokens,
+                context_window,
+            } => write!(
+                f,
+                "message of {} tokens exceeds the context window of {} tokens",
+                tokens, context_window
+            ),
+            RateLimitError::RequestTooLarge {
+                tokens,
+                tokens_per_minute,
+            } => write!(
+                f,
+                "message of {} tokens can never fit in {} tokens per minute",
+                tokens, tokens_per_minute
+            ),
+            RateLimitError::TokensPerMinute { retry_after } => {
+                write!(
+                    f,
+                    "tokens per minute exhausted, retry after {:?}",
+                    retry_after
+                )
+            }
+            RateLimitError::RequestsPerMinute { retry_after } => write!(
+                f,
+                "requests per minute exhausted, retry after {:?}",
+                retry_after
+            ),
+        }
+    }
+}
+
+impl Error for RateLimitError {}
+
+struct TokenBucket {
+    capacity: f64,
+    available: f64,
+    refill_per_sec: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    fn per_minute(capacity: u32, now: Instant) -> Self {
+        TokenBucket {
+            capacity: capacity as f64,
+            available: capacity as f64,
+            refill_per_sec: capacity as f64 / 60.0,
+            updated_at: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.available = (self.available + elapsed * self.refill_per_sec).min(self.capacity);
+        self.updated_at = now;
+    }
+
+    // How long until `amount` is available. Zero means it can be taken right now
+    fn wait_for(&self, amount: f64) -> Duration {
+        if self.available >= amount {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64((amount - self.available) / self.refill_per_sec)
+    }
+}
+
+struct Buckets {
+    tokens: TokenBucket,
+    requests: TokenBucket,
+}
+
+pub struct RateLimiter {
+    default_limits: RateLimits,
+    model_limits: HashMap<String, RateLimits>,
+    // A std Mutex is enough: it is never held across an .await
+    buckets: Mutex<HashMap<(String, String), Buckets>>,
+}
+
+impl RateLimiter {
+    pub fn new(default_limits: RateLimits) -> Self {
+        RateLimiter {
+            default_limits,
+            model_limits: HashMap::new(),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_model_limits(mut self, model: &str, limits: RateLimits) -> Self {
+        self.model_limits.insert(model.to_string(), limits);
+        self
+    }
+
+    pub fn limits_for(&self, model: &str) -> RateLimits {
+        self.model_limits
+            .get(model)
+            .copied()
+            .unwrap_or(self.default_limits)
+    }
+
+    // Takes the message's tokens and one request from the (user, model) buckets or rejects without waiting
+    pub fn try_acquire(
+        &self,
+        user: &str,
+        model: &str,
+        message: &dyn Token,
+    ) -> Result<(), RateLimitError> {
+        let limits = self.limits_for(model);
+        if limits.tokens_per_minute == 0 || limits.requests_per_minute == 0 {
+            return Err(RateLimitError::ZeroLimit {
+                model: model.to_string(),
+            });
+        }
+        let tokens = message.compute_tokens().max(0) as u32;
+        if tokens > limits.context_window {
+            return Err(RateLimitError::ContextWindowExceeded {
+                tokens,
+                context_window: limits.context_window,
+            });
+        }
+        if tokens > limits.tokens_per_minute {
+            return Err(RateLimitError::RequestTooLarge {
+                tokens,
+                tokens_per_minute: limits.tokens_per_minute,
+            });
+        }
+
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().expect("rate limiter lock poisoned");
+        let buckets = buckets
+            .entry((user.to_string(), model.to_string()))
+            .or_insert_with(|| Buckets {
+                tokens: TokenBucket::per_minute(limits.tokens_per_minute, now),
+                requests: TokenBucket::per_minute(limits.requests_per_minute, now),
+            });
+        buckets.tokens.refill(now);
+        buckets.requests.refill(now);
+
+        // Check both buckets before taking from either, so a rejected request costs nothing
+        let request_wait = buckets.requests.wait_for(1.0);
+        if !request_wait.is_zero() {
+            return Err(RateLimitError::RequestsPerMinute {
+                retry_after: request_wait,
+            });
+        }
+        let token_wait = buckets.tokens.wait_for(tokens as f64);
+        if !token_wait.is_zero() {
+            return Err(RateLimitError::TokensPerMinute {
+                retry_after: token_wait,
+            });
+        }
+
+        buckets.requests.available -= 1.0;
+        buckets.tokens.available -= tokens as f64;
+        Ok(())
+    }
+
+    // Like try_acquire(), but queues the caller until the buckets refilled. Returns how long it waited
+    pub async fn acquire(
+        &self,
+        user: &str,
+        model: &str,
+        message: &dyn Token,
+    ) -> Result<Duration, RateLimitError> {
+        let started_at = Instant::now();
+        loop {
+            match self.try_acquire(user, model, message) {
+                Ok(()) => return Ok(started_at.elapsed()),
+                Err(e) => match e.retry_after() {
+                    Some(retry_after) => time::sleep(retry_after).await,
+                    None => return Err(e),
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::{ChatCompletionMessage, Role};
+
+    fn message(tokens: usize) -> ChatCompletionMessage {
+        ChatCompletionMessage {
+            role: Role::User,
+            content: "x".repeat(tokens),
+            last_response: None,
+        }
+    }
+
+    fn limits(tokens_per_minute: u32, requests_per_minute: u32) -> RateLimits {
+        RateLimits {
+            tokens_per_minute,
+            requests_per_minute,
+            context_window: 100,
+        }
+    }
+
+    #[test]
+    fn bucket_refills_at_capacity_per_minute_up_to_capacity() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::per_minute(60, start);
+        bucket.available = 0.0;
+
+        bucket.refill(start + Duration::from_secs(30));
+        assert_eq!(bucket.available, 30.0);
+        assert_eq!(bucket.wait_for(40.0), Duration::from_secs(10));
+        assert_eq!(bucket.wait_for(30.0), Duration::ZERO);
+
+        bucket.refill(start + Duration::from_secs(600));
+        assert_eq!(bucket.available, 60.0);
+    }
+
+    #[test]
+    fn try_acquire_rejects_once_requests_are_used_up() {
+        let limiter = RateLimiter::new(limits(1000, 3));
+        for _ in 0..3 {
+            assert_eq!(limiter.try_acquire("ann", "m", &message(10)), Ok(()));
+        }
+        let err = limiter.try_acquire("ann", "m", &message(10)).unwrap_err();
+        assert!(matches!(err, RateLimitError::RequestsPerMinute { .. }));
+        // 3 requests per minute refill one every 20 seconds
+        let retry_after = err.retry_after().unwrap();
+        assert!(retry_after > Duration::from_secs(19) && retry_after <= Duration::from_secs(20));
+
+        // Every (user, model) pair has buckets of its own
+        assert_eq!(limiter.try_acquire("bob", "m", &message(10)), Ok(()));
+        assert_eq!(limiter.try_acquire("ann", "other", &message(10)), Ok(()));
+    }
+
+    #[test]
+    fn try_acquire_rejects_once_tokens_are_used_up_without_taking_a_request() {
+        let limiter = RateLimiter::new(limits(100, 2));
+        assert_eq!(limiter.try_acquire("ann", "m", &message(80)), Ok(()));
+        let err = limiter.try_acquire("ann", "m", &message(30)).unwrap_err();
+        assert!(matches!(err, RateLimitError::TokensPerMinute { .. }));
+
+        // The rejected message did not cost a request, so one is still left
+        assert_eq!(limiter.try_acquire("ann", "m", &message(20)), Ok(()));
+        assert!(matches!(
+            limiter.try_acquire("ann", "m", &message(0)),
+            Err(RateLimitError::RequestsPerMinute { .. })
+        ));
+    }
+
+    #[test]
+    fn try_acquire_rejects_messages_that_can_never_fit() {
+        let limiter = RateLimiter::new(limits(50, 10));
+        assert_eq!(
+            limiter.try_acquire("ann", "m", &message(101)),
+            Err(RateLimitError::ContextWindowExceeded {
+                tokens: 101,
+                context_window: 100
+            })
+        );
+        let err = limiter.try_acquire("ann", "m", &message(60)).unwrap_err();
+        assert_eq!(
+            err,
+            RateLimitError::RequestTooLarge {
+                tokens: 60,
+                tokens_per_minute: 50
+            }
+        );
+        assert_eq!(err.retry_after(), None);
+    }
+
+    #[test]
+    fn try_acquire_rejects_zero_limits() {
+        let limiter = RateLimiter::new(limits(1000, 0)).with_model_limits("muted", limits(0, 10));
+        for model in ["default", "muted"] {
+            assert_eq!(
+                limiter.try_acquire("ann", model, &message(0)),
+                Err(RateLimitError::ZeroLimit {
+                    model: model.to_string()
+                })
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn acquire_does_not_wait_for_a_limit_that_never_refills() {
+        let limiter = RateLimiter::new(limits(1000, 0));
+        let err = limiter.acquire("ann", "m", &message(1)).await.unwrap_err();
+        assert!(matches!(err, RateLimitError::ZeroLimit { .. }));
+    }
+}
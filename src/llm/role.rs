@@ -0,0 +1,238 @@
+use serde::{Deserialize, Serialize};
+use std::{error::Error, fmt, str::FromStr};
+
+/*
+ * Role of the author of a ChatCompletionMessage.
+ *
+ * A closed enum instead of a free-form String means a typo like "devloper" is rejected when parsing
+ * instead of silently becoming a new kind of author. Serialization uses the lowercase wire names
+ * ("system", "developer", ...) while deserialization goes through FromStr so it accepts any casing.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase", try_from = "String")]
+pub enum Role {
+    System,
+    Developer,
+    User,
+    Assistant,
+    Tool,
+}
+
+impl Role {
+    pub const ALL: [Role; 5] = [
+        Role::System,
+        Role::Developer,
+        Role::User,
+        Role::Assistant,
+        Role::Tool,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::System => "system",
+            Role::Developer => "developer",
+            Role::User => "user",
+            Role::Assistant => "assistant",
+            Role::Tool => "tool",
+        }
+    }
+
+    /*
+     * Whether a message with this role may follow a message with role `prev` (None = first message).
+     *
+     * - system / developer instructions only appear at the head of a conversation
+     * - user may speak after the instructions or after the assistant answered
+     * - assistant answers a user or consumes a tool result
+     * - tool results only answer an assistant tool call, one after another when it made several calls at once
+     */
+    pub fn can_follow(&self, prev: Option<Role>) -> bool {
+        matches!(
+            (prev, self),
+            (None, Role::System | Role::Developer | Role::User)
+                | (
+                    Some(Role::System | Role::Developer),
+                    Role::System | Role::Developer
+                )
+                | (
+                    Some(Role::System | Role::Developer | Role::Assistant),
+                    Role::User
+                )
+                | (Some(Role::User | Role::Tool), Role::Assistant)
+                | (Some(Role::Assistant | Role::Tool), Role::Tool)
+        )
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseRoleError(String);
+
+impl fmt::Display for ParseRoleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let allowed: Vec<&str> = Role::ALL.iter().map(Role::as_str).collect();
+        write!(
+            f,
+            "unknown role \"{}\" (expected one of: {})",
+            self.0,
+            allowed.join(", ")
+        )
+    }
+}
+
+impl Error for ParseRoleError {}
+
+impl FromStr for Role {
+    type Err = ParseRoleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized = s.trim().to_lowercase();
+        Role::ALL
+            .into_iter()
+            .find(|role| role.as_str() == normalized)
+            .ok_or_else(|| ParseRoleError(s.to_string()))
+    }
+}
+
+impl TryFrom<String> for Role {
+    type Error = ParseRoleError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoleTransitionError {
+    pub index: usize,
+    pub from: Option<Role>,
+    pub to: Role,
+}
+
+impl fmt::Display for RoleTransitionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.from {
+            Some(from) => write!(
+                f,
+                "message #{}: {} cannot follow {}",
+                self.index, self.to, from
+            ),
+            None => write!(
+                f,
+                "message #{}: a conversation cannot start with {}",
+                self.index, self.to
+            ),
+        }
+    }
+}
+
+impl Error for RoleTransitionError {}
+
+// Checks every consecutive pair of roles and reports the first transition that is not allowed
+pub fn validate_transitions<I>(roles: I) -> Result<(), RoleTransitionError>
+where
+    I: IntoIterator<Item = Role>,
+{
+    let mut prev = None;
+    for (index, role) in roles.into_iter().enumerate() {
+        if !role.can_follow(prev) {
+            return Err(RoleTransitionError {
+                index,
+                from: prev,
+                to: role,
+            });
+        }
+        prev = Some(role);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Role::*;
+
+    #[test]
+    fn parses_any_casing() {
+        assert_eq!("assistant".parse(), Ok(Assistant));
+        assert_eq!(" Developer ".parse(), Ok(Developer));
+        assert_eq!("TOOL".parse(), Ok(Tool));
+        for role in Role::ALL {
+            assert_eq!(role.to_string().parse(), Ok(role));
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_roles() {
+        let error = "devloper".parse::<Role>().unwrap_err();
+        assert_eq!(error, ParseRoleError("devloper".to_string()));
+        assert_eq!(
+            error.to_string(),
+            "unknown role \"devloper\" (expected one of: system, developer, user, assistant, tool)"
+        );
+        assert!("".parse::<Role>().is_err());
+    }
+
+    #[test]
+    fn serde_uses_the_lowercase_names() {
+        assert_eq!(serde_json::to_string(&Developer).unwrap(), "\"developer\"");
+        for role in Role::ALL {
+            let json = serde_json::to_string(&role).unwrap();
+            assert_eq!(serde_json::from_str::<Role>(&json).unwrap(), role);
+        }
+        assert_eq!(serde_json::from_str::<Role>("\"User\"").unwrap(), User);
+        assert!(serde_json::from_str::<Role>("\"robot\"").is_err());
+    }
+
+    #[test]
+    fn valid_conversations() {
+        let conversations: [&[Role]; 5] = [
+            &[],
+            &[User, Assistant],
+            &[System, Developer, User, Assistant, User, Assistant],
+            &[User, Assistant, Tool, Assistant],
+            // Parallel tool calls: one result per call
+            &[
+                Developer, User, Assistant, Tool, Tool, Tool, Assistant, User,
+            ],
+        ];
+        for roles in conversations {
+            assert_eq!(
+                validate_transitions(roles.iter().copied()),
+                Ok(()),
+                "{roles:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_conversations_report_the_first_bad_transition() {
+        let cases: [(&[Role], usize, Option<Role>, Role); 6] = [
+            (&[Assistant], 0, None, Assistant),
+            (&[Tool], 0, None, Tool),
+            (&[User, User], 1, Some(User), User),
+            (&[User, Assistant, System], 2, Some(Assistant), System),
+            (&[User, Tool], 1, Some(User), Tool),
+            (&[User, Assistant, Tool, User], 3, Some(Tool), User),
+        ];
+        for (roles, index, from, to) in cases {
+            assert_eq!(
+                validate_transitions(roles.iter().copied()),
+                Err(RoleTransitionError { index, from, to }),
+                "{roles:?}"
+            );
+        }
+        let error = validate_transitions([User, User]).unwrap_err();
+        assert_eq!(error.to_string(), "message #1: user cannot follow user");
+        let error = validate_transitions([Tool]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "message #0: a conversation cannot start with tool"
+        );
+    }
+}
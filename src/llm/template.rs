@@ -0,0 +1,517 @@
+use super::{ChatCompletionMessage, Role};
+use serde_json::Value;
+use std::{error::Error, fmt};
+
+/*
+ * A tiny mustache-like template engine to build prompts from data instead of concatenating strings.
+ *
+ * {{name}} / {{user.name}}            - insert a variable, dots walk into nested objects
+ * {{#each items}} {{this}} {{/each}}  - repeat the body for every element of an array, {{@index}} is the position
+ * {{#if flag}} .. {{else}} .. {{/if}} - falsy values are null, false, 0, "", [] and {}. A missing variable is falsy too
+ *
+ * Variables are looked up in the innermost {{#each}} element first, then outwards up to the root context.
+ * Rendering a variable that cannot be found is an error rather than an empty string, so typos don't go unnoticed.
+ *
+ * Blocks nest at most MAX_DEPTH deep. Parsing keeps its own stack, but rendering recurses into every block.
+ */
+
+const MAX_DEPTH: usize = 128;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateError {
+    UnclosedTag { position: usize },
+    EmptyTag { position: usize },
+    UnknownBlock { name: String, position: usize },
+    UnexpectedTag { tag: String, position: usize },
+    UnclosedBlock { name: String, position: usize },
+    MissingVariable { path: String, position: usize },
+    NotAnArray { path: String, position: usize },
+    TooDeep { position: usize },
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::UnclosedTag { position } => {
+                write!(
+                    f,
+                    "tag opened at byte {} is never closed with }}}}",
+                    position
+                )
+            }
+            TemplateError::EmptyTag { position } => write!(f, "empty tag at byte {}", position),
+            TemplateError::UnknownBlock { name, position } => {
+                write!(f, "unknown block #{} at byte {}", name, position)
+            }
+            TemplateError::UnexpectedTag { tag, position } => {
+                write!(f, "unexpected {{{{{}}}}} at byte {}", tag, position)
+            }
+            TemplateError::UnclosedBlock { name, position } => {
+                write!(
+                    f,
+                    "block #{} opened at byte {} is never closed",
+                    name, position
+                )
+            }
+            TemplateError::MissingVariable { path, position } => {
+                write!(f, "missing variable \"{}\" at byte {}", path, position)
+            }
+            TemplateError::NotAnArray { path, position } => write!(
+                f,
+                "#each expects \"{}\" to be an array (byte {})",
+                path, position
+            ),
+            TemplateError::TooDeep { position } => write!(
+                f,
+                "block at byte {} is nested more than {} deep",
+                position, MAX_DEPTH
+            ),
+        }
+    }
+}
+
+impl Error for TemplateError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Text(String),
+    Variable {
+        path: String,
+        position: usize,
+    },
+    Each {
+        path: String,
+        position: usize,
+        body: Vec<Node>,
+    },
+    If {
+        path: String,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+}
+
+// A block that has been opened but not closed yet while parsing
+enum Frame {
+    Root,
+    Each {
+        path: String,
+        position: usize,
+    },
+    If {
+        path: String,
+        position: usize,
+        then: Option<Vec<Node>>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self, TemplateError> {
+        let mut stack: Vec<(Frame, Vec<Node>)> = vec![(Frame::Root, Vec::new())];
+        let mut rest = source;
+        let mut offset = 0;
+
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                push_node(&mut stack, Node::Text(rest[..start].to_string()));
+            }
+            let position = offset + start;
+            let after_open = &rest[start + 2..];
+            let end = after_open
+                .find("}}")
+                .ok_or(TemplateError::UnclosedTag { position })?;
+            let tag = after_open[..end].trim();
+            if tag.is_empty() {
+                return Err(TemplateError::EmptyTag { position });
+            }
+
+            if let Some(block) = tag.strip_prefix('#') {
+                let (name, path) = block.split_once(char::is_whitespace).unwrap_or((block, ""));
+                let path = path.trim().to_string();
+                let frame = match name {
+                    "each" => Frame::Each { path, position },
+                    "if" => Frame::If {
+                        path,
+                        position,
+                        then: None,
+                    },
+                    _ => {
+                        return Err(TemplateError::UnknownBlock {
+                            name: name.to_string(),
+                            position,
+                        })
+                    }
+                };
+                // The root frame is not a block
+                if stack.len() > MAX_DEPTH {
+                    return Err(TemplateError::TooDeep { position });
+                }
+                stack.push((frame, Vec::new()));
+            } else if tag == "else" {
+                match stack.last_mut() {
+                    Some((
+                        Frame::If {
+                            then: then @ None, ..
+                        },
+                        nodes,
+                    )) => {
+                        *then = Some(std::mem::take(nodes));
+                    }
+                    _ => return Err(unexpected(tag, position)),
+                }
+            } else if let Some(name) = tag.strip_prefix('/') {
+                let node = match (stack.pop(), name.trim()) {
+                    (Some((Frame::Each { path, position }, body)), "each") => Node::Each {
+                        path,
+                        position,
+                        body,
+                    },
+                    (Some((Frame::If { path, then, .. }, nodes)), "if") => match then {
+                        Some(then) => Node::If {
+                            path,
+                            then,
+                            otherwise: nodes,
+                        },
+                        None => Node::If {
+                            path,
+                            then: nodes,
+                            otherwise: Vec::new(),
+                        },
+                    },
+                    _ => return Err(unexpected(tag, position)),
+                };
+                push_node(&mut stack, node);
+            } else {
+                push_node(
+                    &mut stack,
+                    Node::Variable {
+                        path: tag.to_string(),
+                        position,
+                    },
+                );
+            }
+
+            let consumed = start + 2 + end + 2;
+            rest = &rest[consumed..];
+            offset += consumed;
+        }
+        if !rest.is_empty() {
+            push_node(&mut stack, Node::Text(rest.to_string()));
+        }
+
+        match stack.pop() {
+            Some((Frame::Root, nodes)) if stack.is_empty() => Ok(Template { nodes }),
+            Some((Frame::Each { position, .. }, _)) => Err(TemplateError::UnclosedBlock {
+                name: "each".to_string(),
+                position,
+            }),
+            Some((Frame::If { position, .. }, _)) => Err(TemplateError::UnclosedBlock {
+                name: "if".to_string(),
+                position,
+            }),
+            _ => unreachable!("the root frame is only popped at the end"),
+        }
+    }
+
+    pub fn render(&self, context: &Value) -> Result<String, TemplateError> {
+        let mut out = String::new();
+        let mut scopes = vec![Scope {
+            value: context,
+            index: None,
+        }];
+        render_nodes(&self.nodes, &mut scopes, &mut out)?;
+        Ok(out)
+    }
+}
+
+impl ChatCompletionMessage {
+    pub fn from_template(
+        role: Role,
+        template: &Template,
+        context: &Value,
+    ) -> Result<Self, TemplateError> {
+        Ok(ChatCompletionMessage {
+            role,
+            content: template.render(context)?,
+            last_response: None,
+        })
+    }
+}
+
+fn unexpected(tag: &str, position: usize) -> TemplateError {
+    TemplateError::UnexpectedTag {
+        tag: tag.to_string(),
+        position,
+    }
+}
+
+fn push_node(stack: &mut [(Frame, Vec<Node>)], node: Node) {
+    if let Some((_, nodes)) = stack.last_mut() {
+        nodes.push(node);
+    }
+}
+
+struct Scope<'a> {
+    value: &'a Value,
+    index: Option<usize>, // Position within the enclosing #each
+}
+
+fn render_nodes<'a>(
+    nodes: &'a [Node],
+    scopes: &mut Vec<Scope<'a>>,
+    out: &mut String,
+) -> Result<(), TemplateError> {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Variable { path, position } => {
+                if path == "@index" {
+                    match scopes.last().and_then(|scope| scope.index) {
+                        Some(index) => out.push_str(&index.to_string()),
+                        None => return Err(missing(path, *position)),
+                    }
+                    continue;
+                }
+                match lookup(scopes, path) {
+                    Some(Value::String(s)) => out.push_str(s),
+                    Some(Value::Null) => {}
+                    Some(value) => out.push_str(&value.to_string()),
+                    None => return Err(missing(path, *position)),
+                }
+            }
+            Node::Each {
+                path,
+                position,
+                body,
+            } => {
+                let items = match lookup(scopes, path) {
+                    Some(Value::Array(items)) => items,
+                    Some(_) => {
+                        return Err(TemplateError::NotAnArray {
+                            path: path.clone(),
+                            position: *position,
+                        })
+                    }
+                    None => return Err(missing(path, *position)),
+                };
+                for (index, item) in items.iter().enumerate() {
+                    scopes.push(Scope {
+                        value: item,
+                        index: Some(index),
+                    });
+                    let rendered = render_nodes(body, scopes, out);
+                    scopes.pop();
+                    rendered?;
+                }
+            }
+            Node::If {
+                path,
+                then,
+                otherwise,
+            } => {
+                let truthy = if path == "@index" {
+                    scopes
+                        .last()
+                        .and_then(|scope| scope.index)
+                        .is_some_and(|i| i > 0)
+                } else {
+                    lookup(scopes, path).is_some_and(is_truthy)
+                };
+                let branch = if truthy { then } else { otherwise };
+                render_nodes(branch, scopes, out)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn missing(path: &str, position: usize) -> TemplateError {
+    TemplateError::MissingVariable {
+        path: path.to_string(),
+        position,
+    }
+}
+
+// "this" (or ".") is the current scope, otherwise the first segment is searched from the innermost scope outwards
+fn lookup<'a>(scopes: &[Scope<'a>], path: &str) -> Option<&'a Value> {
+    if path == "." {
+        return scopes.last().map(|scope| scope.value);
+    }
+
+    let mut segments = path.split('.');
+    let first = segments.next()?;
+    let mut value = if first == "this" {
+        scopes.last()?.value
+    } else {
+        scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.value.get(first))?
+    };
+
+    for segment in segments {
+        value = match value {
+            Value::Array(items) => items.get(segment.parse::<usize>().ok()?)?,
+            _ => value.get(segment)?,
+        };
+    }
+    Some(value)
+}
+
+fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64().is_some_and(|n| n != 0.0),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(map) => !map.is_empty(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn render(source: &str, context: Value) -> Result<String, TemplateError> {
+        Template::parse(source)?.render(&context)
+    }
+
+    #[test]
+    fn variables_walk_into_nested_objects_and_arrays() {
+        let context =
+            json!({"user": {"name": "Ada", "langs": ["en", "fr"]}, "age": 36, "none": null});
+        assert_eq!(
+            render(
+                "{{ user.name }} ({{age}}) speaks {{user.langs.1}}{{none}}",
+                context
+            )
+            .unwrap(),
+            "Ada (36) speaks fr"
+        );
+    }
+
+    #[test]
+    fn each_repeats_with_index_and_sees_outer_scopes() {
+        let context = json!({"sep": ", ", "items": [{"n": "a"}, {"n": "b"}, {"n": "c"}]});
+        assert_eq!(
+            render(
+                "{{#each items}}{{#if @index}}{{sep}}{{/if}}{{@index}}={{n}}{{/each}}",
+                context
+            )
+            .unwrap(),
+            "0=a, 1=b, 2=c"
+        );
+        assert_eq!(
+            render(
+                "{{#each xs}}[{{this}}{{.}}]{{/each}}",
+                json!({"xs": [1, "x"]})
+            )
+            .unwrap(),
+            "[11][xx]"
+        );
+    }
+
+    #[test]
+    fn if_follows_the_falsy_rules() {
+        let template = Template::parse("{{#if v}}yes{{else}}no{{/if}}").unwrap();
+        for falsy in [
+            json!(null),
+            json!(false),
+            json!(0),
+            json!(""),
+            json!([]),
+            json!({}),
+        ] {
+            assert_eq!(template.render(&json!({ "v": falsy })).unwrap(), "no");
+        }
+        for truthy in [
+            json!(true),
+            json!(0.5),
+            json!("x"),
+            json!([0]),
+            json!({"a": 0}),
+        ] {
+            assert_eq!(template.render(&json!({ "v": truthy })).unwrap(), "yes");
+        }
+        assert_eq!(template.render(&json!({})).unwrap(), "no");
+        assert_eq!(render("{{#if v}}yes{{/if}}", json!({})).unwrap(), "");
+    }
+
+    #[test]
+    fn parse_errors_carry_the_position() {
+        let cases = [
+            ("ab {{name", TemplateError::UnclosedTag { position: 3 }),
+            ("{{ }}", TemplateError::EmptyTag { position: 0 }),
+            (
+                "x{{#with a}}{{/with}}",
+                TemplateError::UnknownBlock {
+                    name: "with".to_string(),
+                    position: 1,
+                },
+            ),
+            ("{{else}}", unexpected("else", 0)),
+            ("{{#each a}}{{else}}{{/each}}", unexpected("else", 11)),
+            ("{{#if a}}{{/each}}", unexpected("/each", 9)),
+            (
+                "{{#if a}}{{#each b}}{{/each}}",
+                TemplateError::UnclosedBlock {
+                    name: "if".to_string(),
+                    position: 0,
+                },
+            ),
+        ];
+        for (source, error) in cases {
+            assert_eq!(Template::parse(source), Err(error), "{source}");
+        }
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let nested = |depth: usize| "{{#if a}}".repeat(depth) + &"{{/if}}".repeat(depth);
+        let template = Template::parse(&nested(MAX_DEPTH)).unwrap();
+        assert_eq!(template.render(&json!({"a": true})).unwrap(), "");
+        assert_eq!(
+            Template::parse(&nested(MAX_DEPTH + 1)),
+            Err(TemplateError::TooDeep {
+                position: MAX_DEPTH * "{{#if a}}".len()
+            })
+        );
+        // Deep enough to overflow the stack while rendering without the limit
+        assert!(Template::parse(&nested(1_000_000)).is_err());
+    }
+
+    #[test]
+    fn render_errors_on_missing_variables_and_non_arrays() {
+        assert_eq!(
+            render("hi {{nmae}}", json!({"name": "Ada"})),
+            Err(missing("nmae", 3))
+        );
+        assert_eq!(render("{{@index}}", json!({})), Err(missing("@index", 0)));
+        assert_eq!(
+            render("{{#each name}}{{/each}}", json!({"name": "Ada"})),
+            Err(TemplateError::NotAnArray {
+                path: "name".to_string(),
+                position: 0
+            })
+        );
+    }
+
+    #[test]
+    fn from_template_builds_a_message() {
+        let template = Template::parse("Summarize {{topic}}").unwrap();
+        let message =
+            ChatCompletionMessage::from_template(Role::User, &template, &json!({"topic": "Rust"}))
+                .unwrap();
+        assert_eq!(message.role, Role::User);
+        assert_eq!(message.content, "Summarize Rust");
+        assert!(message.last_response.is_none());
+    }
+}
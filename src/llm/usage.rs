@@ -0,0 +1,474 @@
+use super::Token;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/*
+ * Usage accounting for LLMEcosystem.
+ *
+ * Every Box<dyn Token> that goes through the ecosystem is recorded together with the user who consumed it and when.
+ * The records can then be aggregated per user, per usage type or per time window,
+ * and optional per-user budgets raise alerts once a rolling window gets close to, or over, its limit.
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum UsageType {
+    Chat,
+    Image,
+    Audio,
+    Embedding,
+}
+
+impl UsageType {
+    pub fn usd_per_1k_tokens(&self) -> f64 {
+        match self {
+            UsageType::Chat => 0.002,
+            UsageType::Image => 0.01,
+            UsageType::Audio => 0.006,
+            UsageType::Embedding => 0.0001,
+        }
+    }
+}
+
+impl fmt::Display for UsageType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            UsageType::Chat => "chat",
+            UsageType::Image => "image",
+            UsageType::Audio => "audio",
+            UsageType::Embedding => "embedding",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Usage {
+    pub requests: u32,
+    pub tokens: i64,
+    pub cost: f64,
+}
+
+impl Usage {
+    fn add(&mut self, tokens: i32, cost: f64) {
+        self.requests += 1;
+        self.tokens += tokens as i64;
+        self.cost += cost;
+    }
+}
+
+impl fmt::Display for Usage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} requests, {} tokens, ${:.4}",
+            self.requests, self.tokens, self.cost
+        )
+    }
+}
+
+pub struct UsageRecord {
+    pub user: String,
+    pub item: Box<dyn Token>,
+    pub tokens: i32, // compute_tokens() and cost() are evaluated once when recorded
+    pub cost: f64,
+    pub at: SystemTime,
+}
+
+// A limit on tokens and/or cost over a rolling window, e.g. 10k tokens per hour
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Budget {
+    pub window: Duration,
+    pub max_tokens: Option<i64>,
+    pub max_cost: Option<f64>,
+}
+
+impl Budget {
+    pub fn per(window: Duration) -> Self {
+        Budget {
+            window,
+            max_tokens: None,
+            max_cost: None,
+        }
+    }
+
+    pub fn max_tokens(mut self, max_tokens: i64) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    pub fn max_cost(mut self, max_cost: f64) -> Self {
+        self.max_cost = Some(max_cost);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertLevel {
+    Warning, // WARNING_RATIO of the limit has been used
+    Exceeded,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BudgetMetric {
+    Tokens,
+    Cost,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetAlert {
+    pub user: String,
+    pub level: AlertLevel,
+    pub metric: BudgetMetric,
+    pub used: f64,
+    pub limit: f64,
+    pub at: SystemTime,
+}
+
+impl fmt::Display for BudgetAlert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = match self.level {
+            AlertLevel::Warning => "WARNING",
+            AlertLevel::Exceeded => "EXCEEDED",
+        };
+        match self.metric {
+            BudgetMetric::Tokens => write!(
+                f,
+                "[{}] {} used {} of {} tokens",
+                level, self.user, self.used, self.limit
+            ),
+            BudgetMetric::Cost => write!(
+                f,
+                "[{}] {} spent ${:.4} of ${:.4}",
+                level, self.user, self.used, self.limit
+            ),
+        }
+    }
+}
+
+const WARNING_RATIO: f64 = 0.8;
+
+#[derive(Default)]
+pub struct LLMEcosystem {
+    records: Vec<UsageRecord>, // ChatCompletionMessage is one of the LLM applications. There are plenty of others like image, audio and embeddings
+    budgets: HashMap<String, Budget>,
+    alerts: Vec<BudgetAlert>,
+}
+
+impl LLMEcosystem {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_budget(&mut self, user: &str, budget: Budget) {
+        self.budgets.insert(user.to_string(), budget);
+    }
+
+    pub fn record(&mut self, user: &str, item: Box<dyn Token>) -> Vec<BudgetAlert> {
+        self.record_at(user, item, SystemTime::now())
+    }
+
+    // Same as record() with an explicit timestamp, which makes windows reproducible in examples
+    pub fn record_at(
+        &mut self,
+        user: &str,
+        item: Box<dyn Token>,
+        at: SystemTime,
+    ) -> Vec<BudgetAlert> {
+        let tokens = item.compute_tokens();
+        let cost = item.cost();
+
+        let alerts = match self.budgets.get(user) {
+            Some(budget) => {
+                let from = at.checked_sub(budget.window).unwrap_or(UNIX_EPOCH);
+                let before = self.usage_between(Some(user), from, at);
+                let mut alerts = Vec::new();
+                if let Some(limit) = budget.max_tokens {
+                    let used = (before.tokens as f64, (before.tokens + tokens as i64) as f64);
+                    alerts.extend(check_limit(
+                        user,
+                        BudgetMetric::Tokens,
+                        used,
+                        limit as f64,
+                        at,
+                    ));
+                }
+                if let Some(limit) = budget.max_cost {
+                    let used = (before.cost, before.cost + cost);
+                    alerts.extend(check_limit(user, BudgetMetric::Cost, used, limit, at));
+                }
+                alerts
+            }
+            None => Vec::new(),
+        };
+
+        self.records.push(UsageRecord {
+            user: user.to_string(),
+            item,
+            tokens,
+            cost,
+            at,
+        });
+        self.alerts.extend(alerts.iter().cloned());
+
+        alerts
+    }
+
+    pub fn records(&self) -> &[UsageRecord] {
+        &self.records
+    }
+
+    pub fn alerts(&self) -> &[BudgetAlert] {
+        &self.alerts
+    }
+
+    pub fn usage_by_user(&self) -> BTreeMap<&str, Usage> {
+        let mut usage: BTreeMap<&str, Usage> = BTreeMap::new();
+        for record in &self.records {
+            usage
+                .entry(record.user.as_str())
+                .or_default()
+                .add(record.tokens, record.cost);
+        }
+        usage
+    }
+
+    pub fn usage_by_type(&self) -> BTreeMap<UsageType, Usage> {
+        let mut usage: BTreeMap<UsageType, Usage> = BTreeMap::new();
+        for record in &self.records {
+            usage
+                .entry(record.item.usage_type())
+                .or_default()
+                .add(record.tokens, record.cost);
+        }
+        usage
+    }
+
+    // Usage recorded in (from, to], optionally restricted to a single user
+    pub fn usage_between(&self, user: Option<&str>, from: SystemTime, to: SystemTime) -> Usage {
+        let mut usage = Usage::default();
+        for record in &self.records {
+            if user.is_some_and(|user| user != record.user) {
+                continue;
+            }
+            if record.at > from && record.at <= to {
+                usage.add(record.tokens, record.cost);
+            }
+        }
+        usage
+    }
+
+    // Buckets all records into fixed windows keyed by the window start in seconds since the UNIX epoch
+    pub fn usage_per_window(&self, window: Duration) -> BTreeMap<u64, Usage> {
+        let window_secs = window.as_secs().max(1);
+        let mut usage: BTreeMap<u64, Usage> = BTreeMap::new();
+        for record in &self.records {
+            let secs = record
+                .at
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            usage
+                .entry(secs - secs % window_secs)
+                .or_default()
+                .add(record.tokens, record.cost);
+        }
+        usage
+    }
+}
+
+// Only the record that crosses a threshold raises an alert, so a user over budget is not reported on every request
+fn check_limit(
+    user: &str,
+    metric: BudgetMetric,
+    (before, after): (f64, f64),
+    limit: f64,
+    at: SystemTime,
+) -> Option<BudgetAlert> {
+    let warning = limit * WARNING_RATIO;
+    let level = if before <= limit && after > limit {
+        AlertLevel::Exceeded
+    } else if before < warning && after >= warning && after <= limit {
+        AlertLevel::Warning
+    } else {
+        return None;
+    };
+
+    Some(BudgetAlert {
+        user: user.to_string(),
+        level,
+        metric,
+        used: after,
+        limit,
+        at,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fixed(i32, UsageType);
+
+    impl Token for Fixed {
+        fn compute_tokens(&self) -> i32 {
+            self.0
+        }
+
+        fn usage_type(&self) -> UsageType {
+            self.1
+        }
+    }
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn chat(tokens: i32) -> Box<dyn Token> {
+        Box::new(Fixed(tokens, UsageType::Chat))
+    }
+
+    fn levels(alerts: &[BudgetAlert]) -> Vec<(AlertLevel, BudgetMetric)> {
+        alerts
+            .iter()
+            .map(|alert| (alert.level, alert.metric))
+            .collect()
+    }
+
+    #[test]
+    fn aggregates_per_user_and_per_type() {
+        let mut ecosystem = LLMEcosystem::new();
+        ecosystem.record_at("ada", chat(1000), at(10));
+        ecosystem.record_at("ada", Box::new(Fixed(500, UsageType::Image)), at(20));
+        ecosystem.record_at("bob", chat(3000), at(30));
+
+        let by_user = ecosystem.usage_by_user();
+        assert_eq!(by_user.keys().copied().collect::<Vec<_>>(), ["ada", "bob"]);
+        assert_eq!((by_user["ada"].requests, by_user["ada"].tokens), (2, 1500));
+        assert!((by_user["ada"].cost - (0.002 + 0.005)).abs() < 1e-9);
+        assert_eq!((by_user["bob"].requests, by_user["bob"].tokens), (1, 3000));
+
+        let by_type = ecosystem.usage_by_type();
+        assert_eq!(by_type[&UsageType::Chat].tokens, 4000);
+        assert_eq!(by_type[&UsageType::Image].tokens, 500);
+        assert!(!by_type.contains_key(&UsageType::Audio));
+        assert_eq!(ecosystem.records().len(), 3);
+    }
+
+    #[test]
+    fn windows_exclude_the_start_and_include_the_end() {
+        let mut ecosystem = LLMEcosystem::new();
+        ecosystem.record_at("ada", chat(1), at(10));
+        ecosystem.record_at("ada", chat(10), at(20));
+        ecosystem.record_at("bob", chat(100), at(20));
+        ecosystem.record_at("ada", chat(1000), at(30));
+
+        assert_eq!(ecosystem.usage_between(None, at(10), at(20)).tokens, 110);
+        assert_eq!(
+            ecosystem.usage_between(Some("ada"), at(10), at(20)).tokens,
+            10
+        );
+        assert_eq!(
+            ecosystem.usage_between(Some("ada"), at(9), at(30)).tokens,
+            1011
+        );
+        assert_eq!(
+            ecosystem.usage_between(Some("ada"), at(30), at(40)),
+            Usage::default()
+        );
+    }
+
+    #[test]
+    fn warns_at_80_percent_and_exceeds_once() {
+        let mut ecosystem = LLMEcosystem::new();
+        ecosystem.set_budget(
+            "ada",
+            Budget::per(Duration::from_secs(3600)).max_tokens(1000),
+        );
+
+        assert!(ecosystem.record_at("ada", chat(500), at(1)).is_empty());
+        assert!(ecosystem.record_at("ada", chat(299), at(2)).is_empty());
+        let warning = ecosystem.record_at("ada", chat(1), at(3));
+        assert_eq!(
+            levels(&warning),
+            [(AlertLevel::Warning, BudgetMetric::Tokens)]
+        );
+        assert_eq!((warning[0].used, warning[0].limit), (800.0, 1000.0));
+        assert_eq!(
+            warning[0].to_string(),
+            "[WARNING] ada used 800 of 1000 tokens"
+        );
+
+        // Reaching the limit exactly is not over it
+        assert!(ecosystem.record_at("ada", chat(200), at(4)).is_empty());
+        let exceeded = ecosystem.record_at("ada", chat(1), at(5));
+        assert_eq!(
+            levels(&exceeded),
+            [(AlertLevel::Exceeded, BudgetMetric::Tokens)]
+        );
+        assert!(ecosystem.record_at("ada", chat(500), at(6)).is_empty());
+
+        assert_eq!(ecosystem.alerts().len(), 2);
+        // Users without a budget never raise alerts
+        assert!(ecosystem
+            .record_at("bob", chat(1_000_000), at(7))
+            .is_empty());
+    }
+
+    #[test]
+    fn jumping_over_the_limit_only_exceeds() {
+        let mut ecosystem = LLMEcosystem::new();
+        ecosystem.set_budget(
+            "ada",
+            Budget::per(Duration::from_secs(60))
+                .max_tokens(1000)
+                .max_cost(0.01),
+        );
+        // 6000 chat tokens cost $0.012: over both limits at once
+        let alerts = ecosystem.record_at("ada", chat(6000), at(100));
+        assert_eq!(
+            levels(&alerts),
+            [
+                (AlertLevel::Exceeded, BudgetMetric::Tokens),
+                (AlertLevel::Exceeded, BudgetMetric::Cost)
+            ]
+        );
+        assert_eq!(
+            alerts[1].to_string(),
+            "[EXCEEDED] ada spent $0.0120 of $0.0100"
+        );
+    }
+
+    #[test]
+    fn the_budget_window_rolls() {
+        let mut ecosystem = LLMEcosystem::new();
+        ecosystem.set_budget("ada", Budget::per(Duration::from_secs(60)).max_tokens(100));
+        assert_eq!(ecosystem.record_at("ada", chat(150), at(100)).len(), 1);
+        // 60 seconds later the first record has left the window
+        let alerts = ecosystem.record_at("ada", chat(150), at(160));
+        assert_eq!(
+            levels(&alerts),
+            [(AlertLevel::Exceeded, BudgetMetric::Tokens)]
+        );
+        assert!(ecosystem.record_at("ada", chat(1), at(161)).is_empty());
+    }
+
+    #[test]
+    fn buckets_records_into_fixed_windows() {
+        let mut ecosystem = LLMEcosystem::new();
+        for (secs, tokens) in [(0, 1), (59, 2), (60, 4), (185, 8)] {
+            ecosystem.record_at("ada", chat(tokens), at(secs));
+        }
+        let windows: Vec<(u64, i64)> = ecosystem
+            .usage_per_window(Duration::from_secs(60))
+            .into_iter()
+            .map(|(start, usage)| (start, usage.tokens))
+            .collect();
+        assert_eq!(windows, [(0, 3), (60, 4), (180, 8)]);
+
+        // A window shorter than a second counts as one second
+        assert_eq!(ecosystem.usage_per_window(Duration::ZERO).len(), 4);
+    }
+}
@@ -1,17 +1,139 @@
+mod bakery;
+mod binary;
+mod bookmarks;
+mod cache;
+mod chat;
+mod cli;
+mod commands;
+mod error;
+mod fibonacci;
+mod formats;
+mod graph;
+mod json_patch;
+mod json_path;
+mod json_schema;
+mod link_check;
+mod llm;
+mod network;
+mod query_result;
+mod resolver;
+mod supervisor;
+mod tattle_tell;
+mod timeline;
+mod url;
+mod worker_pool;
+
+use bakery::{BakeryError, Clock, FakeClock, Inventory, Pie, SystemClock};
+use binary::{ByteCursor, Endian};
+use bookmarks::{Bookmark, BookmarkStore};
+use cache::{Cache, Capacity, Entry};
+use chat::{ChatClient, ChatServer};
+use cli::{Args, Command};
+use error::{AppError, Context, ErrorKind};
+use graph::Node;
+use json_path::JsonPath;
+use link_check::{LinkChecker, Probe, StubHttpServer};
+use llm::media::{AudioTranscription, Embedding, ImageGeneration};
+use llm::template::Template;
+use llm::{Budget, ChatCompletionMessage, LLMEcosystem, RateLimiter, RateLimits, Role, Token};
+use query_result::QueryResult;
 use rand::Rng;
+use resolver::{
+    CachingResolver, DnsResolver, Fallback, HostsFile, ResolveError, Resolver, StubDnsServer,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::fmt;
+use std::collections::HashMap;
 use std::io::Write;
-use std::time::Duration;
-use std::{cmp::Ordering, error::Error, fmt::Display};
-use std::{
-    collections::HashMap,
-    net::{IpAddr, Ipv4Addr},
-};
-use std::{io, ops::Deref, rc::Rc, sync::Arc, thread};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicU32, Ordering as AtomicOrdering};
+use std::time::{Duration, Instant, SystemTime};
+use std::{cmp::Ordering, error::Error};
+use std::{io, rc::Rc, sync::Arc, thread};
 use std::{str, vec};
+use supervisor::{CancellationToken, JobError, RetryPolicy, Supervisor};
+use tattle_tell::TattleTell;
+use timeline::Timeline;
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
+use url::Url;
+use worker_pool::WorkerPool;
+
+fn main() -> ExitCode {
+    let result = Args::parse(std::env::args().skip(1))
+        .map_err(AppError::from)
+        .and_then(run);
+
+    // Every lesson returns Result<(), AppError>. A failing one prints the whole chain of causes. See src/error.rs
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        // A typo in a flag needs the usage, not a backtrace
+        Err(e) if matches!(e.root().kind(), ErrorKind::Cli(_)) => {
+            eprintln!("{}\n{}", e.report().without_backtrace(), cli::USAGE);
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            eprintln!("{}", e.report());
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: Args) -> Result<(), AppError> {
+    if args.help {
+        println!("{}", cli::USAGE);
+        return Ok(());
+    }
+
+    match args.command {
+        Command::Menu => menu(args.worker_threads),
+        Command::Bookmarks { file, action } => commands::bookmarks(&file, action),
+        Command::Check { file } => {
+            let links = match file {
+                Some(file) => commands::bookmark_links(&file)?,
+                None => {
+                    let mut links: Vec<_> = fav_websites()?
+                        .into_iter()
+                        .map(|(name, url)| (name.to_string(), url.to_string()))
+                        .collect();
+                    links.sort_by(|(a, _), (b, _)| a.cmp(b));
+                    links
+                }
+            };
+            runtime(args.worker_threads)?.block_on(commands::check(
+                links,
+                args.timeout,
+                args.per_host,
+            ))
+        }
+        Command::Convert { input, output } => {
+            commands::convert(&input, &output, args.schema.as_deref())
+        }
+        Command::Diff { from, to } => commands::diff(&from, &to, args.schema.as_deref()),
+        Command::Patch { file, patch } => commands::patch(&file, &patch, args.schema.as_deref()),
+        Command::Query { expression, file } => {
+            commands::query(&expression, &file, args.schema.as_deref())
+        }
+        Command::Validate { schema, file } => commands::validate(&schema, &file),
+    }
+}
+
+fn runtime(worker_threads: Option<usize>) -> Result<tokio::runtime::Runtime, AppError> {
+    /*
+     * The whole program shares one Tokio runtime instead of every async lesson building its own with #[tokio::main].
+     * Lessons run inside runtime.block_on(), so any of them can be an async fn and .await, spawn tasks, use tokio::time, ...
+     * Without --worker-threads, Tokio starts one worker thread per CPU core (or TOKIO_WORKER_THREADS if set).
+     */
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    builder.enable_all().thread_name("lesson-worker");
+    if let Some(threads) = worker_threads {
+        builder.worker_threads(threads);
+    }
+    builder.build().context("Failed to start the Tokio runtime")
+}
+
+fn menu(worker_threads: Option<usize>) -> Result<(), AppError> {
+    let runtime = runtime(worker_threads)?;
 
-fn main() {
     // mut means mutable. If not specified, the variable is immutable. But mut is something different from const, which would be elaborated later
     let mut option = String::new();
     cli_out_options();
@@ -20,8 +142,24 @@ fn main() {
         .read_line(&mut option)
         .expect("Failed to read line");
 
+    let started_at = Instant::now();
     // str.trim() would remove leading and trailing whitespace
-    match option.trim() {
+    let result = runtime.block_on(lesson(option.trim()));
+
+    // Tasks still alive here were spawned by the lesson and never finished, they are dropped with the runtime
+    let metrics = runtime.metrics();
+    println!(
+        "[Runtime] {} workers, {} alive tasks, global queue depth {}, lesson took {:.2?}",
+        metrics.num_workers(),
+        metrics.num_alive_tasks(),
+        metrics.global_queue_depth(),
+        started_at.elapsed()
+    );
+    result
+}
+
+async fn lesson(option: &str) -> Result<(), AppError> {
+    match option {
         "1" => cmp_num(true),
         "2" => const_mut_shadowing(),
         "3" => control_flow(),
@@ -32,9 +170,14 @@ fn main() {
         "8" => text(),
         "9" => oop(),
         "10" => smart_pointers(),
-        "11" => tokio_async_programming(),
+        "11" => tokio_async_programming().await,
         "12" => serde(),
         "13" => hash_map(),
+        "14" => fibonacci_benchmark(),
+        "15" => tcp_chat().await,
+        "16" => dns_resolver().await,
+        "17" => link_checker().await,
+        "18" => lru_cache(),
         _ => {
             // default
             let mut rng = rand::rng();
@@ -43,6 +186,7 @@ fn main() {
                 "Undefined but show a random number for you: {}",
                 secret_number
             );
+            Ok(())
         }
     }
 }
@@ -66,6 +210,11 @@ fn cli_out_options() {
         "Tokio Asynchronous Programming",
         "Serde",
         "Hashmap",
+        "Fibonacci Benchmark",
+        "TCP Chat Server",
+        "DNS Resolver",
+        "Link Health Checker",
+        "LRU/TTL Cache",
     ];
     for (id, option) in menu_optrions.iter().enumerate() {
         println!("{}", &format!("{:>2}: {}", id + 1, option)); // :>2 indicates right alignment, and 2 sets the width to 2 characters
@@ -82,46 +231,52 @@ fn cli_out_options() {
     io::stdout().flush().expect("Failed to flush stdout"); // 強制立即輸出緩衝區
 }
 
-fn cmp_num(looping: bool) {
+fn cmp_num(looping: bool) -> Result<(), AppError> {
     if looping {
         // The loop keyword creates an infinite loop.
         loop {
             // Quitting after a good score
-            if execute() {
+            if execute()? {
                 break;
             }
         }
     } else {
-        execute();
+        execute()?;
     }
 
-    fn execute() -> bool {
+    return Ok(());
+
+    fn execute() -> Result<bool, AppError> {
         let mut score: String = String::new();
         print!("Enter score: ");
-        io::stdout().flush().expect("Failed to flush stdout"); // 強制立即輸出緩衝區
+        io::stdout().flush().context("Failed to flush stdout")?; // 強制立即輸出緩衝區
 
         io::stdin()
             .read_line(&mut score)
-            .expect("Failed to read line");
+            .context("Failed to read line")?;
 
         // The way to convert a string to a number. But wait, doesn’t the program already have a variable named score?
         // Rust allows us to shadow the previous value of guess with a new one. Shadowing lets us reuse the score variable
-        let score: u64 = score.trim().parse().expect("Failed to parse");
+        // with_context() only builds the message if parsing failed, and ? hands the error to main() instead of panicking
+        let score: u64 = score
+            .trim()
+            .parse()
+            .with_context(|| format!("\"{}\" is not a valid score", score.trim()))?;
 
         match score.cmp(&60) {
             Ordering::Less => println!("Failed"),
             Ordering::Greater => {
                 println!("Good");
-                return true;
+                return Ok(true);
             }
             Ordering::Equal => println!("Safe"),
         }
 
-        false // Most functions return the last expression implicitly. Be sure not to use a semicolon after the return keyword.
+        Ok(false) // Most functions return the last expression implicitly. Be sure not to use a semicolon after the return keyword.
     }
 }
 
-fn const_mut_shadowing() {
+fn const_mut_shadowing() -> Result<(), AppError> {
     /*
      * const variables is initialize at compile time, while mutable variables is initialize at runtime
      * const variables cannot be changed forever, while mutable variables can be changed by mut
@@ -163,9 +318,11 @@ fn const_mut_shadowing() {
     let spaces = "   "; // Bound to a string literal (&str). Note that value not can be mutable since the key concept of shadowing is to reuse the variable name
     let spaces = spaces.len();
     println!("The value of spaces is: {spaces}");
+
+    Ok(())
 }
 
-fn control_flow() {
+fn control_flow() -> Result<(), AppError> {
     // if in let
     let condition = true;
     let number = if condition { 5 } else { 6 };
@@ -242,9 +399,11 @@ fn control_flow() {
         print!("{x} ");
     }
     println!();
+
+    Ok(())
 }
 
-fn closures() {
+fn closures() -> Result<(), AppError> {
     /*
      * Closures in Rust are anonymous functions that can capture variables from their surrounding environment
      * That is, by defining a closure with lambdas, to represent some parameters are passed into a function
@@ -268,28 +427,17 @@ fn closures() {
     // Ignore Parameters (|_|). The closure |_, message| takes two parameters, but only message is used.
     let print_message = |_, message| println!("Message: {}", message);
     print_message(42, "Hello!");
-}
 
-fn enum_struct() {
-    // Enumerations allow you to create a new type that can have a value of several tagged elements
-    enum V6Format {
-        Bin,
-        Hex,
-    }
+    Ok(())
+}
 
-    enum IpAddrKind {
-        V4,
-        V6(V6Format),
-        None, // None represents the absensce of a kind
-    }
-
-    // Define a struct that holds various data, including an IpAddrKind.
-    struct Ap {
-        id: i32,
-        name: String,
-        kind: IpAddrKind,
-        address: String,
-    }
+// The lessons deliberately demonstrate patterns clippy would flag in production code, so those lints are silenced
+// per lesson: here taking &String to show the conversion to &str
+#[allow(clippy::ptr_arg, clippy::single_char_add_str)]
+fn enum_struct() -> Result<(), AppError> {
+    // Enumerations allow you to create a new type that can have a value of several tagged elements.
+    // V6Format, IpAddrKind and the Ap struct that holds one are defined in network.rs, so other code can save and load them.
+    use network::{Ap, IpAddrKind, V6Format};
 
     let aps = vec![
         Ap {
@@ -318,6 +466,25 @@ fn enum_struct() {
         },
     ];
 
+    // Saved as JSON, and checked against the schema before anyone loads the file again
+    let saved = serde_json::to_value(&aps).context("Failed to serialize the access points")?;
+    match Ap::list_schema().check(&saved) {
+        Ok(()) => println!(
+            "{} access points saved, the file matches its schema",
+            aps.len()
+        ),
+        Err(e) => println!("{e}"),
+    }
+    // A hand edited file with several mistakes, every one of them is reported
+    let edited = json!([
+        { "id": 0, "name": "ac-03", "kind": "v5", "address": "10.0.0.1" },
+        { "id": 6, "name": "AC-04", "kind": { "v6": "hex" }, "address": "fe80::/64", "vlan": 7 },
+        { "name": "AC-05", "kind": "v4", "address": 10 }
+    ]);
+    if let Err(e) = Ap::list_schema().check(&edited) {
+        println!("{e}");
+    }
+
     for ap in aps {
         match ap.kind {
             IpAddrKind::V4 => println!(
@@ -349,9 +516,17 @@ fn enum_struct() {
         }
         bin
     }
+
+    Ok(())
 }
 
-fn generic_type() {
+// Builds vectors step by step and unwraps literals to show the semantics
+#[allow(
+    clippy::useless_vec,
+    clippy::vec_init_then_push,
+    clippy::unnecessary_literal_unwrap
+)]
+fn generic_type() -> Result<(), AppError> {
     /*
      * Generic types allow us to partially define a struct or enum,
      * enabling a compiler to create a fully defined version at compile-time based off our code usage.
@@ -399,10 +574,8 @@ fn generic_type() {
     // === End of parsing a String ===
 
     // --- Optional Configuration or Parameters ---
-    struct Config {
-        port: Option<u16>, // Some API fields may be optional
-        host: String,
-    }
+    // Config { port: Option<u16>, host: String } is defined in network.rs, some API fields may be optional
+    use network::Config;
 
     let config = Config {
         port: Some(8080),
@@ -414,6 +587,21 @@ fn generic_type() {
     } else {
         println!("Using default port.");
     }
+
+    // Loading a config file: the schema says what is wrong with it before serde tries to make a Config of it
+    for text in [
+        r#"{"host": "example.com", "port": null}"#,
+        r#"{"host": "", "port": 70000, "debug": true}"#,
+    ] {
+        let document: Value = serde_json::from_str(text).context("Invalid config JSON")?;
+        match Config::schema().check(&document) {
+            Ok(()) => {
+                let loaded: Config = serde_json::from_value(document).context("Invalid config")?;
+                println!("Loaded {:?}", loaded);
+            }
+            Err(e) => println!("{e}"),
+        }
+    }
     // === End of Optional Configuration or Parameters ===
 
     // -- Returning Early from a Function
@@ -547,9 +735,13 @@ fn generic_type() {
             expensive_computation()
         );
     }
+
+    Ok(())
 }
 
-fn ownership_and_borrowing() {
+// Placeholder names like foo, and assignments made only to show moves and borrows
+#[allow(clippy::disallowed_names, clippy::ptr_arg, unused_assignments)]
+fn ownership_and_borrowing() -> Result<(), AppError> {
     #[derive(Copy, Clone)] // Make struct Foo implement copy trait for later demo of deference
     struct Foo {
         x: i32,
@@ -700,9 +892,11 @@ fn ownership_and_borrowing() {
         // };
         // println!("Excerpt: {}", excerpt.part);
     }
+
+    Ok(())
 }
 
-fn text() {
+fn text() -> Result<(), AppError> {
     /*
      * String literals are always Unicode and its type are &'static str where
      * "'static" meaning the string data is created at compile time and will be available till the end of our program (it never drops)
@@ -718,37 +912,16 @@ fn text() {
     println!("{}", s.to_uppercase());
     println!("{}", &["hello", " ", "world", "!"].concat()); // hello world!. The temporary String lives until the end of the println!
     println!("{}", ["a", "b", "c"].join(",")); // a,b,c. The & takes a reference to this temporary String
-}
 
-fn oop() {
-    struct ChatCompletionMessage {
-        role: String,
-        content: String,
-        last_response: Option<String>,
-    }
+    Ok(())
+}
 
+fn oop() -> Result<(), AppError> {
     // Rust supports the concept of an object that is a struct associated with some functions (also known as methods).
-    impl ChatCompletionMessage {
-        // The first parameter of any method must be a reference to the instance associated with the method call
-        fn show_input_tokens(&mut self) {
-            // &self - Immutable ref to the inst; &mut self Mutable one
-            self.role = self.role.to_uppercase();
-            println!("{}: {}", &self.role, &self.content);
-        }
-
-        // By default fields and methods are accessible only to the module they belong to. Use pub to make them public
-        pub fn show_response(&mut self) {
-            let response = format!(
-                "{} seems to wanna know {}. There are some advices...",
-                self.role, self.content
-            );
-            println!("{}", response);
-            self.last_response = Some(response);
-        }
-    }
+    // ChatCompletionMessage and its methods are declared in src/llm.rs so other modules can use them too.
 
     let mut new_chat = ChatCompletionMessage {
-        role: "devloper".to_string(),
+        role: Role::Developer,
         content: "Explain OOP in Rust".to_string(),
         last_response: None,
     };
@@ -756,34 +929,60 @@ fn oop() {
     new_chat.show_input_tokens();
     new_chat.show_response();
 
-    // Polymorphism provides ability for objects of different types through a common interface.
-    // Rust supports polymorphism with traits. Traits allow us to associate a set of methods with a struct type.
-    trait Token {
-        fn compute_tokens(&self) -> i32;
-
-        // Traits can have implemented methods.
-        fn use_multi_trait_fns(&self) {
-            let a = self.compute_tokens();
-            let b = self.compute_tokens();
-
-            println!("If you ask 2 times, you will consume {} tokens", a + b);
+    // Role implements FromStr, so parse() turns user input into a Role and rejects anything outside the enum
+    for input in ["Assistant", "devloper"] {
+        match input.parse::<Role>() {
+            Ok(role) => println!("Parsed role: {role}"),
+            Err(e) => println!("Rejected role: {e}"),
         }
     }
 
-    impl Token for ChatCompletionMessage {
-        fn compute_tokens(&self) -> i32 {
-            let input_tokens: i32 = self.content.len() as i32;
-
-            let output_tokens: i32;
-            match &self.last_response {
-                Some(response) => output_tokens = response.len() as i32,
-                None => output_tokens = 0,
-            }
+    // Serialization uses the lowercase wire names
+    println!(
+        "Serialized role: {}",
+        serde_json::to_string(&new_chat.role)?
+    );
 
-            input_tokens + output_tokens
+    // Roles must appear in a sensible order within a conversation, e.g. a tool result only answers an assistant
+    let conversations = [
+        vec![
+            Role::System,
+            Role::User,
+            Role::Assistant,
+            Role::Tool,
+            Role::Assistant,
+        ],
+        vec![Role::User, Role::Tool],
+    ];
+    for roles in conversations {
+        match llm::validate_transitions(roles) {
+            Ok(()) => println!("Conversation order is valid"),
+            Err(e) => println!("Invalid conversation order: {e}"),
         }
     }
 
+    // Message content can also be rendered from a template and a serde_json::Value context. See src/llm/template.rs
+    let prompt = Template::parse(
+        "Hi, I'm {{user.name}}. Compare {{#each languages}}{{#if @index}} and {{/if}}{{this}}{{/each}}\
+         {{#if user.beginner}} in simple terms{{else}} in depth{{/if}}.",
+    )
+    .context("Failed to parse the prompt template")?;
+    let context = json!({
+        "user": { "name": "Tyler", "beginner": true },
+        "languages": ["Golang", "Rust"],
+    });
+    match ChatCompletionMessage::from_template(Role::User, &prompt, &context) {
+        Ok(message) => message.show_input_tokens(),
+        Err(e) => println!("Template error: {e}"),
+    }
+    // A variable missing from the context is an error instead of silently rendering nothing
+    if let Err(e) = prompt.render(&json!({ "languages": [] })) {
+        println!("Template error: {e}");
+    }
+
+    // Polymorphism provides ability for objects of different types through a common interface.
+    // Rust supports polymorphism with traits. The Token trait and its impl for ChatCompletionMessage are in src/llm.rs as well.
+
     println!(
         "Total comsumed tokens in this conversation: {}",
         new_chat.compute_tokens()
@@ -873,29 +1072,97 @@ fn oop() {
      * Box is a struct known as a smart pointer that JUST holds the pointer to our data on the heap.
      * Box is often used as a way to store a reference to something in a struct that must know the size of its fillds.
      */
-    struct LLMEcosystem {
-        types: Vec<Box<dyn Token>>, // ChatCompletionMessage is one of the LLM applications. There are plenty of others like image, audio and assistant
-    }
-    let total_tokens_by_user = LLMEcosystem {
-        types: vec![
-            Box::new(ChatCompletionMessage {
-                role: "devloper".to_string(),
-                content: "Golang vs. Rust".to_string(),
-                last_response: None,
+    let mut ecosystem = LLMEcosystem::new();
+    ecosystem.record(
+        "tyler",
+        Box::new(ChatCompletionMessage {
+            role: Role::Developer,
+            content: "Golang vs. Rust".to_string(),
+            last_response: None,
+        }),
+    );
+    ecosystem.record(
+        "tyler",
+        Box::new(ChatCompletionMessage {
+            role: Role::Developer,
+            content: "Rust Learning Map".to_string(),
+            last_response: None,
+        }),
+    );
+    for (idx, record) in ecosystem.records().iter().enumerate() {
+        println!("[Box pointer]{}: {}", idx, record.item.compute_tokens());
+    }
+
+    /*
+     * Because the ecosystem only knows about Box<dyn Token>, any other application can be accounted as well.
+     * Each type counts and prices its tokens differently, the ecosystem just aggregates the results.
+     * Budgets limit a user over a rolling window and raise an alert when the limit is getting close or exceeded.
+     */
+    ecosystem.set_budget(
+        "alice",
+        Budget::per(Duration::from_secs(60 * 60)).max_tokens(4000),
+    );
+    ecosystem.set_budget(
+        "bob",
+        Budget::per(Duration::from_secs(60 * 60)).max_cost(1.0),
+    );
+    let usages: Vec<(&str, Box<dyn Token>)> = vec![
+        (
+            "alice",
+            Box::new(ImageGeneration {
+                prompt: "A crab writing Rust".to_string(),
+                width: 1024,
+                height: 1024,
+                count: 2,
             }),
-            Box::new(ChatCompletionMessage {
-                role: "devloper".to_string(),
-                content: "Rust Learning Map".to_string(),
-                last_response: None,
+        ),
+        (
+            "alice",
+            Box::new(AudioTranscription {
+                duration: Duration::from_secs(90),
             }),
-        ],
-    };
-    for (idx, data) in total_tokens_by_user.types.iter().enumerate() {
-        println!("[Box pointer]{}: {}", idx, data.compute_tokens());
+        ),
+        (
+            "bob",
+            Box::new(Embedding {
+                inputs: vec!["ownership".to_string(), "borrowing".to_string()],
+            }),
+        ),
+        (
+            "alice",
+            Box::new(ImageGeneration {
+                prompt: "The same crab, but bigger".to_string(),
+                width: 512,
+                height: 512,
+                count: 1,
+            }),
+        ),
+    ];
+    for (user, item) in usages {
+        for alert in ecosystem.record(user, item) {
+            println!("{alert}");
+        }
+    }
+
+    for (user, usage) in ecosystem.usage_by_user() {
+        println!("[Usage by user] {:>5}: {}", user, usage);
+    }
+    for (usage_type, usage) in ecosystem.usage_by_type() {
+        println!("[Usage by type] {:>9}: {}", usage_type, usage);
     }
+    for (window_start, usage) in ecosystem.usage_per_window(Duration::from_secs(60)) {
+        println!("[Usage by minute] {}: {}", window_start, usage);
+    }
+    println!(
+        "{} budget alert(s) raised in total",
+        ecosystem.alerts().len()
+    );
+
+    Ok(())
 }
 
-fn smart_pointers() {
+#[allow(clippy::disallowed_names, clippy::useless_vec)]
+fn smart_pointers() -> Result<(), AppError> {
     /*
      * Reference can be converted into a more primitive type called a raw pointer.
      * *const T - A raw pointer to data of type T that should never change.
@@ -914,36 +1181,75 @@ fn smart_pointers() {
      * Typically smart pointers implement Deref, DerefMut, and Drop traits to
      * specify the logic of what should happen when the structure is dereferenced with * and . operators.
      */
-    struct TattleTell<T> {
-        value: T,
-    }
-    impl<T> Deref for TattleTell<T> {
-        type Target = T; // Represents get T after dereferencing
-        fn deref(&self) -> &T {
-            println!("{} was used!", std::any::type_name::<T>());
-            &self.value
+    // TattleTell (src/tattle_tell.rs) implements all three of them to keep track of who accesses the value it wraps.
+    let foo = TattleTell::new("foo", "secret message").loud();
+    println!("{}", foo.len()); // TattleTell doesn't define a len() method. Rust attempts to deref automatically.
+
+    // Wrapping a shared configuration object reveals how often, when and from where it is read or written
+    {
+        let mut config =
+            TattleTell::new("config", HashMap::from([("retries", 3)])).report_on_drop();
+        for _ in 0..2 {
+            println!("retries: {:?}", config.get("retries"));
         }
+        config.insert("timeout_secs", 30); // insert() needs &mut, so this goes through DerefMut
+        println!(
+            "{} reads, {} writes so far",
+            config.reads(),
+            config.writes()
+        );
+        // Only the most recent accesses are kept one by one, older ones just count towards the report
+        if let Some(last) = config.accesses().last() {
+            println!("last access: {:?} at {}", last.kind, last.location);
+        }
+
+        // *** config is dropped here and Drop prints its report ***
     }
-    let foo = TattleTell {
-        value: "secret message",
-    };
-    println!("{}", foo.len()); // TattleTell doesn't define a len() method. Rust attempts to deref automatically.
 
     /*
      * Use unsafe when absolutely necessary and only after exhausting all safe alternatives.
      * The Rust philosophy encourages minimal use of unsafe, ensuring memory safety and concurrency guarantees.
      */
+    //
+    // A classic temptation is reinterpreting bytes through a raw pointer: (&a as *const u8 as *const f32) and then
+    // dereferencing it in an unsafe block. Rust cannot verify that assumption, and it is in fact undefined behavior
+    // because a [u8; 4] is only 1-byte aligned while an f32 needs 4. The result would also differ between little and big endian machines.
+    //
+    // The safe alternative copies the bytes instead: f32::from_le_bytes / from_be_bytes. See src/binary.rs
     let a: [u8; 4] = [0, 1, 2, 3];
-    let pointer_a = &a as *const u8 as usize;
-    let pointer_b = pointer_a as *const f32;
-    let b = unsafe {
-        // This is unsafe because we are telling the compiler
-        // to assume our pointer is a valid f32 and
-        // dereference it's value into the variable b.
-        // Rust has no way to verify this assumption is true.
-        *pointer_b
-    };
+    let b = f32::from_le_bytes(a);
     println!("I swear this is a pie! {}", b);
+    println!(
+        "The same bytes read as big endian: {:?}",
+        binary::from_be_bytes::<f32>(&a)
+    );
+
+    // ByteCursor reads a sequence of values with bounds checks instead of running off the end of the buffer
+    let packet: [u8; 11] = [0x01, 0x00, 0x2a, 0xdb, 0x0f, 0x49, 0x40, 0, 0, 0, 7];
+    let mut cursor = ByteCursor::new(&packet, Endian::Little);
+    let version: u8 = cursor.read().context("Failed to read the version")?;
+    let length: u16 = cursor
+        .read_with(Endian::Big)
+        .context("Failed to read the length")?;
+    let pi: f32 = cursor.read().context("Failed to read pi")?;
+    println!(
+        "version: {version}, length: {length}, pi: {pi} (cursor at byte {})",
+        cursor.position()
+    );
+    match cursor.read::<u64>() {
+        Ok(value) => println!("trailer: {value}"),
+        Err(e) => println!("Error: {e}"),
+    }
+    cursor.set_endian(Endian::Big);
+    cursor.skip(2)?;
+    println!("trailer as big endian u16: {:?}", cursor.read::<u16>());
+
+    // The f32 starts at byte 3, which is not 4-byte aligned. Copying the bytes does not care about that
+    println!(
+        "Misaligned f32: {:?}, this machine is {:?} endian",
+        binary::from_le_bytes::<f32>(&packet[3..7]),
+        Endian::NATIVE
+    );
 
     /*
      * The standard library has a universal trait std::error::Error for describing errors.
@@ -951,38 +1257,106 @@ fn smart_pointers() {
      * Using a smart pointer Box we can use the type Box<dyn std::error::Error> as a common type for returning errors
      * because it allows us to propagate up an error on the heap and interact with it at a high level without having to know a specific type.
      */
-    struct Pie;
-
-    #[derive(Debug)]
-    struct NotFreshError;
+    //
+    // Pie and its NotFreshError live in src/bakery.rs. NotFreshError implements Display and the (empty) Error trait,
+    // which is all it takes for a type to be usable as an error.
+    const DAY: u64 = 24 * 60 * 60;
 
-    impl Display for NotFreshError {
-        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            write!(f, "This pie is not fresh")
-        }
+    // Return a type capable of describing almost any kind of error that might occur in our program
+    // so long as the error's data structure implements Rust's common Error trait
+    fn eat_pie(clock: &dyn Clock) -> Result<(), Box<dyn Error>> {
+        let heap_pie = Box::new(Pie {
+            name: "apple".to_string(),
+            baked_at: clock.now() - Duration::from_secs(3 * DAY),
+            shelf_life: Duration::from_secs(2 * DAY),
+        });
+        heap_pie.eat(clock)?; // The ? operator propagates the error if one occurs. It expands to an early return Err(From::from(err))
+        Ok(())
     }
 
-    impl Error for NotFreshError {}
+    match eat_pie(&SystemClock) {
+        Ok(()) => println!("Yummy!"),
+        Err(e) => println!("Error: {}", e),
+    }
 
-    impl Pie {
-        fn eat(&self) -> Result<(), Box<dyn Error>> {
-            Err(Box::new(NotFreshError))
+    /*
+     * Box<dyn Error> forgets which part of the program failed and what we were trying to do at that moment.
+     * The program-wide AppError (src/error.rs) has a variant per subsystem, context() messages wrapping the original error,
+     * and walks the chain of causes through Error::source(). Every lesson returns Result<(), AppError>.
+     */
+    fn count_slices(input: &str) -> Result<u8, AppError> {
+        input
+            .parse::<u8>()
+            .with_context(|| format!("Failed to count the slices in \"{input}\""))
+    }
+
+    fn serve_breakfast(slices: &str) -> Result<(), AppError> {
+        let slices = count_slices(slices).context("Breakfast is cancelled")?;
+        println!("Serving {slices} slices");
+        let stale_pie = Pie {
+            name: "pumpkin".to_string(),
+            baked_at: SystemTime::now() - Duration::from_secs(2 * DAY),
+            shelf_life: Duration::from_secs(DAY),
+        };
+        // NotFreshError becomes a BakeryError through From, which AppError has a variant for
+        stale_pie
+            .eat(&SystemClock)
+            .map_err(BakeryError::from)
+            .context("Breakfast is cancelled")
+    }
+
+    for slices in ["8", "eight"] {
+        if let Err(e) = serve_breakfast(slices) {
+            println!("{}", e.report().without_backtrace());
+            if let ErrorKind::ParseInt(_) = e.root().kind() {
+                println!("The root cause is a ParseIntError");
+            }
         }
     }
 
-    // Return a type capable of describing almost any kind of error that might occur in our program
-    // so long as the error's data structure implements Rust's common Error trait
-    fn eat_pie() -> Result<(), Box<dyn Error>> {
-        let heap_pie = Box::new(Pie);
-        heap_pie.eat()?; // The ? operator propagates the error if one occurs. It expands to an early return Err(From::from(err))
-        Ok(())
+    /*
+     * Whether a pie is fresh depends on the current time. The bakery inventory (src/bakery.rs) asks a Clock for it
+     * instead of calling SystemTime::now() directly, so a FakeClock can fast-forward a few days in an instant.
+     */
+    let clock = FakeClock::new(SystemTime::now());
+    let mut inventory = Inventory::new(clock.clone()); // Both clones share the same time
+    inventory.bake("apple", Duration::from_secs(2 * DAY));
+    inventory.bake("cherry", Duration::from_secs(DAY));
+    clock.advance(Duration::from_secs(DAY / 2));
+    inventory.bake("apple", Duration::from_secs(3 * DAY));
+    print!(
+        "{} pies in stock:\n{}",
+        inventory.total(),
+        inventory.report()
+    );
+
+    for (pie, remaining) in inventory.expiring_within(Duration::from_secs(2 * DAY)) {
+        println!(
+            "{} pie expires in {} hours",
+            pie.name,
+            remaining.as_secs() / 3600
+        );
     }
 
-    match eat_pie() {
-        Ok(()) => println!("Yummy!"),
-        Err(e) => println!("Error: {}", e),
+    clock.advance(Duration::from_secs(DAY));
+    for name in ["cherry", "apple", "cherry"] {
+        match inventory
+            .eat(name)
+            .with_context(|| format!("Failed to serve a {name} pie"))
+        {
+            Ok(pie) => println!("Yummy! The {} pie was still fresh", pie.name),
+            Err(e) => println!("{}", e.report().without_backtrace()),
+        }
     }
 
+    clock.advance(Duration::from_secs(3 * DAY));
+    let expired = inventory.remove_expired();
+    println!(
+        "Threw away {} expired pie(s), {} apple pie(s) left",
+        expired.len(),
+        inventory.count("apple")
+    );
+
     /*
      * Rc (Reference Counted) is a smart pointer that moves data from the stack onto the heap. Manage data lifecycle with reference counts.
      * Copying the Rc pointer does not copy the data itself, it increases the reference count.
@@ -991,20 +1365,90 @@ fn smart_pointers() {
      *
      * Time to use: Needing multiple ownership within a single thread
      */
-    impl Pie {
-        fn fresh_eat(&self) {
-            println!("tastes better on the heap!")
-        }
+    let fresh_eat = |pie: &Pie| match pie.eat(&SystemClock) {
+        Ok(()) => println!("{} pie tastes better on the heap!", pie.name),
+        Err(e) => println!("{e}"),
+    };
+
+    let heap_pie = Rc::new(Pie {
+        name: "cherry".to_string(),
+        baked_at: SystemTime::now(),
+        shelf_life: Duration::from_secs(DAY),
+    });
+    let weak_pie = Rc::downgrade(&heap_pie); // A Weak pointer does not keep the Pie alive
+    {
+        let heap_pie2 = heap_pie.clone();
+        let heap_pie3 = heap_pie2.clone();
+        println!(
+            "strong count after two clones: {}",
+            Rc::strong_count(&heap_pie)
+        );
+
+        fresh_eat(&heap_pie3);
+        fresh_eat(&heap_pie2);
+        fresh_eat(&heap_pie);
     }
+    println!(
+        "strong count after the clones went out of scope: {}",
+        Rc::strong_count(&heap_pie)
+    );
+    drop(heap_pie);
+    println!(
+        "All reference count smart pointers are dropped now. The heap data Pie finally deallocates. Weak upgrade: {:?}",
+        weak_pie.upgrade().map(|pie| pie.name.clone())
+    );
 
-    let heap_pie = Rc::new(Pie);
-    let heap_pie2 = heap_pie.clone();
-    let heap_pie3 = heap_pie2.clone();
+    /*
+     * Rc<RefCell<T>> is the usual way to build trees and graphs: Rc shares a node, RefCell allows mutating it.
+     * Children are owned through Rc, while the link back to the parent is a Weak pointer (src/graph.rs).
+     * Watch the counts: each child link adds to the strong count, each parent link only to the weak count.
+     */
+    let root = Node::new("root");
+    println!("[Graph] {}", graph::counts(&root));
+    let branch = Node::new("branch");
+    graph::add_child(&root, &branch);
+    println!(
+        "[Graph] {} | {}",
+        graph::counts(&root),
+        graph::counts(&branch)
+    );
+    {
+        let leaf = Node::new("leaf");
+        graph::add_child(&branch, &leaf);
+        println!(
+            "[Graph] {} | {}",
+            graph::counts(&branch),
+            graph::counts(&leaf)
+        );
+        println!(
+            "[Graph] path to root: {}",
+            graph::path_to_root(&leaf).join(" -> ")
+        );
+    }
+    // The leaf binding is gone, but the leaf lives on because branch still owns it
+    println!("[Graph] {}", graph::counts(&branch.borrow().children[0]));
+    println!(
+        "[Graph] cycles in the tree: {:?}",
+        graph::find_cycles([&root])
+    );
 
-    heap_pie3.fresh_eat();
-    heap_pie2.fresh_eat();
-    heap_pie.fresh_eat();
-    println!("All reference count smart pointers are dropped now. The heap data Pie finally deallocates.");
+    // A strong edge pointing back up closes a loop: root -> branch -> leaf -> root. None of them could ever be freed
+    let leaf = branch.borrow().children[0].clone();
+    graph::add_edge(&leaf, &root);
+    println!("[Graph] {}", graph::counts(&root));
+    for cycle in graph::find_cycles([&root]) {
+        println!(
+            "[Graph] leak detected, reference cycle: {}",
+            cycle.join(" -> ")
+        );
+    }
+    // Breaking one strong link of the cycle lets the counts drop to 0 again
+    leaf.borrow_mut().children.clear();
+    println!(
+        "[Graph] after breaking the cycle: {} | cycles: {:?}",
+        graph::counts(&root),
+        graph::find_cycles([&root])
+    );
 
     /*
      * Arc (Atomically Reference Counted): Arc serves a similar purpose to Rc but is designed to be thread-safe.
@@ -1023,10 +1467,49 @@ fn smart_pointers() {
             a, b, foo
         );
     }
+
+    /*
+     * Arc alone only gives shared *read* access. To mutate shared state from several threads,
+     * wrap it: Arc<Mutex<T>> allows one thread at a time, Arc<RwLock<T>> allows many readers or one writer.
+     * WorkerPool (src/worker_pool.rs) shares its job queue through an Arc<Mutex<..>> and its results through an Arc<RwLock<..>>.
+     */
+    let threads = thread::available_parallelism().map_or(2, |n| n.get().min(4));
+    let pool = WorkerPool::new(threads);
+    for n in 1..=8u64 {
+        pool.submit(move || {
+            thread::sleep(Duration::from_millis(10 * n));
+            (1..=n).product::<u64>()
+        })?;
+    }
+    pool.submit(|| panic!("this job fails on purpose"))?;
+    let results = pool.results();
+    let workers = pool.threads();
+
+    // shutdown() still runs the queued jobs before joining the workers
+    let jobs_per_worker = pool.shutdown();
+    println!("[Worker pool] {workers} workers ran {jobs_per_worker:?} jobs");
+    let results = results.read().expect("no worker is running anymore");
+    let mut ids: Vec<&usize> = results.keys().collect();
+    ids.sort();
+    for id in ids {
+        match &results[id] {
+            Ok(factorial) => println!("[Worker pool] job #{id}: {factorial}"),
+            Err(e) => println!("[Worker pool] job #{id}: {e}"),
+        }
+    }
+
+    // The same read-heavy workload (9 reads per write) on a Mutex, a RwLock and an atomic counter
+    for result in worker_pool::contention_benchmark(threads, 200_000, 9) {
+        println!(
+            "[Contention] {:>6}: {:>10.2?} (counter = {})",
+            result.name, result.elapsed, result.final_value
+        );
+    }
+
+    Ok(())
 }
 
-#[tokio::main]
-async fn tokio_async_programming() {
+async fn tokio_async_programming() -> Result<(), AppError> {
     /*
      * Tokio is able to concurrently run many tasks on a few threads by repeatedly swapping the currently running task on each thread
      * However, this kind of swapping can only happen at .await points, so code that spends a long time without reaching an .await
@@ -1034,46 +1517,455 @@ async fn tokio_async_programming() {
      * To combat this, Tokio provides two kinds of threads: Core threads and blocking threads.
      *
      * The core threads are where all asynchronous code runs, and Tokio will by default spawn one for each CPU core.
-     * You can use the environment variable TOKIO_WORKER_THREADS to override the default value.
+     * Run the program with --worker-threads N to override the default value. See run().
      *
      * The blocking threads are spawned on demand, can be used to run blocking code that would otherwise block other tasks from running
      * and are kept alive when not used for a certain amount of time which can be configured with thread_keep_alive.
      */
 
+    /*
+     * spawn_blocking(..).await.unwrap() would wait forever and re-panic if the job panicked.
+     * The Supervisor (src/supervisor.rs) runs the job on a blocking thread as well, but adds a deadline,
+     * cancellation, retries, and turns panics into a TaskError.
+     */
+    let supervisor = Supervisor::new();
+
     // This is running on a core thread.
-    let blocking_task = tokio::task::spawn_blocking(|| {
-        // This is running on a blocking thread. Blocking here is ok.
-        // Simulate a blocking operation with thread::sleep
-        thread::sleep(Duration::from_secs(1));
-        println!("Blocking operation: 1000 ms have elapsed");
+    let answer = supervisor
+        .task("sleepy", |_| {
+            // This is running on a blocking thread. Blocking here is ok.
+            // Simulate a blocking operation with thread::sleep
+            thread::sleep(Duration::from_secs(1));
+            println!("Blocking operation: 1000 ms have elapsed");
+
+            Ok(42)
+        })
+        .deadline(Duration::from_secs(2))
+        .run()
+        .await?;
+    println!("The blocking task answered {answer}");
+
+    // The naive recursive fibonacci is a good stand-in for a CPU intensive task. See src/fibonacci.rs for faster ones
+    let n = 40;
+    let value = supervisor
+        .task("fibonacci", move |_| Ok(fibonacci::naive(n)?))
+        .deadline(Duration::from_secs(30))
+        .run()
+        .await?;
+    println!("Fibonacci({}) = {}", n, value);
+
+    // The ways a blocking task can go wrong, all supervised at the same time
+    let flaky_attempts = Arc::new(AtomicU32::new(0));
+    let attempts = Arc::clone(&flaky_attempts);
+    let (stuck, panicky, flaky) = tokio::join!(
+        supervisor
+            .task(
+                "stuck",
+                |token: &CancellationToken| -> Result<(), JobError> {
+                    // Never finishes on its own, but checks its token, so the thread stops soon after the deadline
+                    loop {
+                        token.check()?;
+                        thread::sleep(Duration::from_millis(10));
+                    }
+                }
+            )
+            .deadline(Duration::from_millis(200))
+            .retry(RetryPolicy::attempts(2).retry_timeouts())
+            .run(),
+        supervisor
+            .task("panicky", |_| -> Result<(), JobError> {
+                panic!("index out of bounds")
+            })
+            .run(),
+        supervisor
+            .task("flaky", move |_| {
+                let attempt = attempts.fetch_add(1, AtomicOrdering::SeqCst) + 1;
+                if attempt < 3 {
+                    return Err(format!("connection reset on attempt {attempt}").into());
+                }
+                Ok(attempt)
+            })
+            .retry(RetryPolicy::attempts(5).backoff(Duration::from_millis(50), 2.0))
+            .run(),
+    );
+    for error in [stuck.err(), panicky.err()].into_iter().flatten() {
+        println!("[Supervisor] {} gave up: {error}", error.task());
+    }
+    println!("[Supervisor] flaky succeeded on attempt {}", flaky?);
+
+    // cancel_all() stops running tasks (cooperatively) and refuses to start new ones
+    let (cancelled, ()) = tokio::join!(
+        supervisor
+            .task(
+                "long report",
+                |token: &CancellationToken| -> Result<(), JobError> {
+                    for _ in 0..500 {
+                        token.check()?;
+                        thread::sleep(Duration::from_millis(10));
+                    }
+                    Ok(())
+                }
+            )
+            .run(),
+        async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            supervisor.cancel_all();
+        }
+    );
+    if let Err(e) = cancelled {
+        println!("[Supervisor] {e}");
+    }
+    if let Err(e) = supervisor.task("too late", |_| Ok(())).run().await {
+        println!("[Supervisor] {e}");
+    }
 
-        42
-    });
+    let report = supervisor.report();
+    println!(
+        "[Supervisor] {} of {} tasks did not succeed:",
+        report.failed().count(),
+        report.outcomes.len()
+    );
+    print!("{report}");
+
+    /*
+     * tokio::time also makes a nice rate limiter: a token bucket that refills over time.
+     * Before a ChatCompletionMessage is "sent", its compute_tokens() is taken from a tokens-per-minute bucket
+     * and one request from a requests-per-minute bucket of the (user, model) pair. See src/llm/rate_limit.rs
+     */
+    let limiter = RateLimiter::new(RateLimits {
+        tokens_per_minute: 1200,
+        requests_per_minute: 3,
+        context_window: 100,
+    })
+    .with_model_limits(
+        "lite",
+        RateLimits {
+            tokens_per_minute: 60,
+            requests_per_minute: 600,
+            context_window: 50,
+        },
+    );
+    let message = ChatCompletionMessage {
+        role: Role::User,
+        content: "Explain token buckets".to_string(),
+        last_response: None,
+    };
+
+    // try_acquire rejects as soon as a limit is hit
+    for attempt in 1..=4 {
+        match limiter.try_acquire("tyler", "default", &message) {
+            Ok(()) => println!("[Rate limit] attempt {attempt}: sent"),
+            Err(e) => println!("[Rate limit] attempt {attempt}: rejected, {e}"),
+        }
+    }
+
+    // acquire queues the request instead, sleeping until the bucket refilled (60 tokens per minute = 1 token per second)
+    for attempt in 1..=3 {
+        match limiter.acquire("tyler", "lite", &message).await {
+            Ok(waited) => {
+                println!("[Rate limit] lite attempt {attempt}: sent after waiting {waited:.1?}")
+            }
+            Err(e) => println!("[Rate limit] lite attempt {attempt}: rejected, {e}"),
+        }
+    }
+
+    // Some requests can never be sent no matter how long we wait
+    let huge_message = ChatCompletionMessage {
+        role: Role::User,
+        content: "Rust ".repeat(30),
+        last_response: None,
+    };
+    if let Err(e) = limiter.acquire("tyler", "default", &huge_message).await {
+        println!("[Rate limit] huge message: {e}");
+    }
+
+    /*
+     * So far everything was awaited one thing at a time. The lessons below run many tasks at once,
+     * and each one prints a timeline (src/timeline.rs) showing how the tasks interleave.
+     */
+    spawn_many_tasks().await?;
+    join_and_try_join().await?;
+    select_with_timeouts().await?;
+    channels().await?;
+    pipeline_with_backpressure().await?;
 
-    // We can wait for the blocking task like this:
-    // If the blocking task panics, the unwrap below will propagate the panic.
-    blocking_task.await.unwrap();
+    Ok(())
+}
 
-    // An fn for simulating a CPU intensive task
-    fn fibonacci(n: i8) -> i32 {
-        match n {
-            0 => 0,
-            1 => 1,
-            _ => fibonacci(n - 1) + fibonacci(n - 2),
+// tokio::spawn starts a task right away and hands back a JoinHandle to wait for its result
+async fn spawn_many_tasks() -> Result<(), AppError> {
+    println!("\n[Spawn] 10 tasks sleeping at the same time");
+    let timeline = Timeline::new();
+    let handles: Vec<_> = (0..10u64)
+        .map(|id| {
+            let timeline = timeline.clone();
+            tokio::spawn(async move {
+                let task = format!("task {id}");
+                let nap = Duration::from_millis(100 + (id * 37) % 200);
+                timeline.record(&task, format!("sleeping {nap:?}"));
+                tokio::time::sleep(nap).await;
+                timeline.record(&task, "done");
+                nap
+            })
+        })
+        .collect();
+
+    let mut slept = Duration::ZERO;
+    for handle in handles {
+        // A JoinHandle resolves to Err(JoinError) if the task panicked or was aborted
+        slept += handle.await?;
+    }
+    print!("{timeline}");
+    println!(
+        "[Spawn] {:?} of sleeping took {:?} of wall time",
+        slept,
+        timeline.elapsed()
+    );
+    Ok(())
+}
+
+async fn join_and_try_join() -> Result<(), AppError> {
+    println!("\n[Join] join! waits for every future, try_join! stops at the first error");
+    let timeline = Timeline::new();
+
+    async fn lookup(
+        timeline: &Timeline,
+        what: &str,
+        millis: u64,
+        fail: bool,
+    ) -> Result<String, AppError> {
+        timeline.record(what, "started");
+        tokio::time::sleep(Duration::from_millis(millis)).await;
+        if fail {
+            timeline.record(what, "failed");
+            return Err(AppError::other(format!("{what} is unavailable")));
         }
+        timeline.record(what, "finished");
+        Ok(format!("{what} data"))
+    }
+
+    // The futures run concurrently on the current task, no spawn needed. Both finish after ~150ms, not 250ms
+    let (user, orders) = tokio::join!(
+        lookup(&timeline, "user", 100, false),
+        lookup(&timeline, "orders", 150, false)
+    );
+    timeline.record("join!", format!("got {} and {}", user?, orders?));
+
+    // try_join! drops the other futures as soon as one fails, so "slow" never finishes
+    let result = tokio::try_join!(
+        lookup(&timeline, "slow", 300, false),
+        lookup(&timeline, "broken", 50, true)
+    );
+    if let Err(e) = result {
+        timeline.record("try_join!", format!("gave up: {e}"));
     }
+    print!("{timeline}");
+    Ok(())
+}
+
+async fn select_with_timeouts() -> Result<(), AppError> {
+    println!("\n[Select] racing futures against ticks and deadlines");
+    let timeline = Timeline::new();
 
-    let n: i8 = 40;
-    let blocking_task2 = tokio::task::spawn_blocking(move || {
-        let result = fibonacci(n);
-        println!("Fibonacci({}) = {}", n, result);
-        result
+    // tokio::time::timeout is the shorthand for racing a single future against a sleep
+    let slow_call = tokio::time::sleep(Duration::from_millis(500));
+    match tokio::time::timeout(Duration::from_millis(100), slow_call).await {
+        Ok(()) => timeline.record("timeout", "slow call finished"),
+        Err(elapsed) => timeline.record("timeout", format!("slow call cancelled, {elapsed}")),
+    }
+
+    let (tx, mut rx) = mpsc::channel::<u32>(8);
+    let sender_timeline = timeline.clone();
+    let sender = tokio::spawn(async move {
+        for n in 1..=3 {
+            tokio::time::sleep(Duration::from_millis(60)).await;
+            sender_timeline.record("sender", format!("sending {n}"));
+            tx.send(n).await.map_err(AppError::other)?;
+        }
+        // Goes quiet for longer than the receiver is willing to wait
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        tx.send(4).await.map_err(AppError::other)
+    });
+
+    // select! polls every branch and runs the first one that is ready, the others are dropped for this round
+    let deadline = tokio::time::sleep(Duration::from_millis(500));
+    tokio::pin!(deadline); // Polled again on every loop iteration, so it must not move (or restart)
+    let mut ticks = tokio::time::interval(Duration::from_millis(150));
+    loop {
+        tokio::select! {
+            message = rx.recv() => match message {
+                Some(n) => timeline.record("select!", format!("received {n}")),
+                None => {
+                    timeline.record("select!", "sender hung up");
+                    break;
+                }
+            },
+            _ = ticks.tick() => timeline.record("select!", "tick"),
+            _ = &mut deadline => {
+                timeline.record("select!", "deadline reached, giving up");
+                break;
+            }
+        }
+    }
+
+    // Nobody is listening anymore, so stop the sender instead of letting it sleep on
+    sender.abort();
+    if let Err(e) = sender.await {
+        timeline.record("sender", format!("cancelled: {}", e.is_cancelled()));
+    }
+    print!("{timeline}");
+    Ok(())
+}
+
+async fn channels() -> Result<(), AppError> {
+    // mpsc: many producers, one consumer. Every clone of the sender is another producer
+    println!("\n[Channels] mpsc");
+    let timeline = Timeline::new();
+    let (tx, mut rx) = mpsc::channel(16);
+    let workers: Vec<_> = (1..=3u64)
+        .map(|worker| {
+            let tx = tx.clone();
+            let timeline = timeline.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(20 * (4 - worker))).await;
+                timeline.record(&format!("worker {worker}"), "reporting in");
+                tx.send(worker).await.map_err(AppError::other)
+            })
+        })
+        .collect();
+    drop(tx); // Otherwise recv() would wait forever for this last sender
+    while let Some(worker) = rx.recv().await {
+        timeline.record("main", format!("heard from worker {worker}"));
+    }
+    for worker in workers {
+        worker.await??;
+    }
+    print!("{timeline}");
+
+    // broadcast: every subscriber gets every message. One that falls too far behind is told how many it missed
+    println!("[Channels] broadcast");
+    let timeline = Timeline::new();
+    let (tx, mut fast) = broadcast::channel(2);
+    let mut slow = tx.subscribe();
+    let fast_timeline = timeline.clone();
+    let fast = tokio::spawn(async move {
+        while let Ok(n) = fast.recv().await {
+            fast_timeline.record("fast", format!("got {n}"));
+        }
+    });
+    let slow_timeline = timeline.clone();
+    let slow = tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        loop {
+            match slow.recv().await {
+                Ok(n) => slow_timeline.record("slow", format!("got {n}")),
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    slow_timeline.record("slow", format!("lagged, missed {missed}"))
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+    for n in 1..=4 {
+        timeline.record("publisher", format!("sending {n}"));
+        tx.send(n).map_err(AppError::other)?;
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    drop(tx);
+    fast.await?;
+    slow.await?;
+    print!("{timeline}");
+
+    // watch: only the latest value is kept, a busy receiver skips the values it was too slow to see
+    println!("[Channels] watch");
+    let timeline = Timeline::new();
+    let (tx, mut rx) = watch::channel("v1".to_string());
+    let watcher_timeline = timeline.clone();
+    let watcher = tokio::spawn(async move {
+        while rx.changed().await.is_ok() {
+            let config = rx.borrow_and_update().clone();
+            watcher_timeline.record("watcher", format!("config is now {config}"));
+            tokio::time::sleep(Duration::from_millis(50)).await; // Busy applying it
+        }
+        watcher_timeline.record("watcher", "publisher gone, stopping");
+    });
+    for version in ["v2", "v3", "v4"] {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        timeline.record("publisher", format!("publishing {version}"));
+        tx.send(version.to_string()).map_err(AppError::other)?;
+    }
+    drop(tx);
+    watcher.await?;
+    print!("{timeline}");
+
+    // oneshot: exactly one value, typically the reply to a request
+    println!("[Channels] oneshot");
+    let timeline = Timeline::new();
+    let (reply_tx, reply_rx) = oneshot::channel();
+    let responder_timeline = timeline.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        responder_timeline.record("responder", "answering");
+        reply_tx.send(42) // Err only if the requester stopped waiting
+    });
+    timeline.record("requester", "waiting for the answer");
+    let answer = reply_rx.await.map_err(AppError::other)?;
+    timeline.record("requester", format!("the answer is {answer}"));
+    print!("{timeline}");
+    Ok(())
+}
+
+async fn pipeline_with_backpressure() -> Result<(), AppError> {
+    /*
+     * producer -> squarer -> consumer, connected by bounded channels of capacity 2.
+     * The consumer is the slowest stage. Once a channel is full, send().await suspends the stage in front of it
+     * until there is room again, so the slow consumer ends up pacing the whole pipeline
+     * instead of an unbounded queue quietly growing in memory.
+     */
+    println!("\n[Pipeline] a fast producer, a slow consumer and bounded channels in between");
+    let timeline = Timeline::new();
+    let (raw_tx, mut raw_rx) = mpsc::channel::<u64>(2);
+    let (squared_tx, mut squared_rx) = mpsc::channel::<u64>(2);
+
+    let producer_timeline = timeline.clone();
+    let producer = tokio::spawn(async move {
+        for n in 1..=6 {
+            let started_at = tokio::time::Instant::now();
+            raw_tx.send(n).await.map_err(AppError::other)?;
+            let waited = started_at.elapsed();
+            if waited > Duration::from_millis(5) {
+                producer_timeline.record("producer", format!("sent {n}, blocked for {waited:.0?}"));
+            } else {
+                producer_timeline.record("producer", format!("sent {n}"));
+            }
+        }
+        Ok::<(), AppError>(())
     });
 
-    blocking_task2.await.unwrap();
+    let squarer_timeline = timeline.clone();
+    let squarer = tokio::spawn(async move {
+        while let Some(n) = raw_rx.recv().await {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            squared_tx.send(n * n).await.map_err(AppError::other)?;
+            squarer_timeline.record("squarer", format!("{n} -> {}", n * n));
+        }
+        Ok::<(), AppError>(())
+    });
+
+    let mut total = 0;
+    while let Some(square) = squared_rx.recv().await {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        total += square;
+        timeline.record("consumer", format!("consumed {square}"));
+    }
+    producer.await??;
+    squarer.await??;
+    print!("{timeline}");
+    println!("[Pipeline] total = {total}");
+    Ok(())
 }
 
-fn serde() {
+fn serde() -> Result<(), AppError> {
     let scalar_res = json!(null);
     let table_res: Vec<i32> = Vec::new();
 
@@ -1085,40 +1977,248 @@ fn serde() {
     if let Value::Null = inst["scalar"] {
         println!("scalar is null");
     }
-}
 
-fn hash_map() {
     /*
-     * Where vectors store value by an integer index, HashMap store values by keys.
-     * Keys can be boolean, integer, string, or any type that implements the Eq and Hash traits.
-     *
-     * HashMap is growable with recommended initial with a certain capacity by using:
-     * 1. HashMap::with_capacity(uint)
-     * 2. HashMap::new()
+     * inst["scalar"] digs out one field we know by name. A JSON path (src/json_path.rs) describes a whole search instead:
+     * every author, every price at any depth, the books cheaper than 10, ...
+     * The same engine backs the `query` command: rust-basic query '$..price' store.json
+     */
+    let store = json!({
+        "store": {
+            "book": [
+                {"category": "reference", "author": "Nigel Rees", "title": "Sayings of the Century", "price": 8.95},
+                {"category": "fiction", "author": "Evelyn Waugh", "title": "Sword of Honour", "price": 12.99},
+                {"category": "fiction", "author": "Herman Melville", "title": "Moby Dick", "isbn": "0-553-21311-3", "price": 8.99},
+                {"category": "fiction", "author": "J. R. R. Tolkien", "title": "The Lord of the Rings", "isbn": "0-395-19395-8", "price": 22.99},
+            ],
+            "bicycle": {"color": "red", "price": 399},
+        },
+    });
+    for expression in [
+        "$.store.book[*].author",
+        "$..price",
+        "$.store.book[-1:].title",
+        "$.store.book[::2].title",
+        "$.store.book[?(@.price < 10)].title",
+        "$..book[?(@.isbn && @.price > 10)].title",
+        "$.store.book[?(@.category != 'fiction' || @.price > $.store.bicycle.price)].title",
+    ] {
+        let path = JsonPath::parse(expression)?;
+        let values: Vec<Value> = path.values(&store).into_iter().cloned().collect();
+        println!("[JSON path] {expression} -> {}", Value::Array(values));
+    }
+    // Every match also knows where it was found
+    for found in json_path::query("$..book[0,2].category", &store)? {
+        println!("[JSON path] {} = {}", found.path, found.value);
+    }
+    if let Err(e) = JsonPath::parse("$.store.book[?(@.price <)]") {
+        println!("[JSON path] {e}");
+    }
+
+    /*
+     * That {"scalar", "table"} shape is what our query tools answer with. QueryResult (src/query_result.rs) is its typed form:
+     * from_value() checks the shape and the type of every column, rows_as() turns the rows into our own structs.
      */
+    let empty = QueryResult::from_value(inst)?;
+    println!(
+        "[Query] scalar: {:?}, rows: {}",
+        empty.scalar,
+        empty.table.len()
+    );
 
-    enum Url {
-        Domain(String),
-        IP(IpAddr),
+    let count = QueryResult::from_value(json!({"scalar": 3, "table": []}))?;
+    if let Some(count) = count.scalar_as::<u64>()? {
+        println!("[Query] scalar result: {count}");
     }
 
-    impl fmt::Display for Url {
-        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            match self {
-                Url::Domain(domain) => write!(f, "{}", domain),
-                Url::IP(ip) => write!(f, "{}", ip),
-            }
+    // Integer and float scores mix into a float column, a missing or null score makes it nullable
+    let players = QueryResult::from_value(json!({
+        "scalar": null,
+        "table": [
+            {"id": 1, "name": "Ferris", "score": 9.5},
+            {"id": 2, "name": "Corro", "score": 7},
+            {"id": 3, "name": "Tyler", "score": null},
+            {"id": 4, "name": "Rusty"},
+        ],
+    }))?;
+    print!("{players}");
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Player {
+        id: u32,
+        name: String,
+        score: Option<f64>,
+    }
+
+    let typed: Vec<Player> = players.rows_as()?;
+    println!("[Query] typed rows: {:?}", typed);
+
+    // And back: typed rows -> QueryResult -> Value, which is the same document we started from
+    let round_trip = QueryResult::from_rows(&typed)?;
+    let id_column = round_trip.column("id").map(|column| column.column_type);
+    println!(
+        "[Query] round trip has {} rows, id column is {:?}, rows equal: {}",
+        round_trip.table.len(),
+        id_column,
+        round_trip.rows_as::<Player>()? == typed
+    );
+    println!("[Query] as JSON: {}", round_trip.to_value());
+
+    // Results that do not fit are errors that say where, instead of a panic or a silently wrong value
+    let mismatches = [
+        json!({"table": [{"id": 1}, {"id": "two"}]}),
+        json!({"table": [{"id": 1}, 2]}),
+        json!({"table": {"id": 1}}),
+        json!({"tabel": [{"id": 1}]}),
+        json!({
+            "columns": [{"name": "id", "type": "integer"}],
+            "table": [{"id": 1}, {"id": null}],
+        }),
+        json!({
+            "columns": [{"name": "id", "type": "integer"}],
+            "table": [{"id": 1, "name": "Ferris"}],
+        }),
+    ];
+    for value in mismatches {
+        if let Err(e) = QueryResult::from_value(value) {
+            println!("[Query] rejected: {e}");
         }
     }
+    let wrong_type = QueryResult::from_value(json!({"table": [{"id": -1, "name": "Nobody"}]}))?;
+    if let Err(e) = wrong_type.rows_as::<Player>() {
+        println!(
+            "[Query] rejected: {e}: {}",
+            e.source()
+                .map_or(String::new(), |source| source.to_string())
+        );
+    }
+
+    // --- Other formats ---
+    // Anything serde can turn into a Value can also be written as CSV or binary, see src/formats.rs
+    let aps = vec![
+        network::Ap {
+            id: 1,
+            name: String::from("AC-01"),
+            kind: network::IpAddrKind::V4,
+            address: "127.0.0.1".to_string(),
+        },
+        network::Ap {
+            id: 2,
+            name: String::from("ACX-01"),
+            kind: network::IpAddrKind::V6(network::V6Format::Hex),
+            address: "2377:3b49:3ef1:63ab:b003:8a2e:5b4c:3d02".to_string(),
+        },
+    ];
+    round_trips("access points", &aps)?;
+    let config = network::Config {
+        port: None,
+        host: String::from("localhost"),
+    };
+    round_trips("config", &config)?;
+    let conversation = vec![
+        ChatCompletionMessage {
+            role: Role::User,
+            content: String::from("How many legs does a crab have, \"roughly\"?"),
+            last_response: Some(String::from("10")),
+        },
+        ChatCompletionMessage {
+            role: Role::Assistant,
+            content: String::from("Ten,\nincluding the claws."),
+            last_response: None,
+        },
+    ];
+    round_trips("conversation", &conversation)?;
+    let encoded = formats::Format::Csv.encode(&conversation)?;
+    print!("{}", String::from_utf8_lossy(&encoded.bytes));
+    // === End of Other formats ===
+
+    // --- Diff and patch ---
+    // Two versions of a config: what changed, as a JSON Patch (RFC 6902) and as a JSON Merge Patch (RFC 7386)
+    let v1 = json!({
+        "host": "localhost",
+        "port": 8080,
+        "aps": ["AC-01", "ACX-01", "AC-02"],
+        "tls": { "enabled": false },
+    });
+    let v2 = json!({
+        "host": "localhost",
+        "port": 8443,
+        "aps": ["AC-00", "AC-01", "ACX-01", "AC-02"],
+        "tls": { "enabled": true, "cert": "/etc/ssl/server.pem" },
+    });
+    let operations = json_patch::diff(&v1, &v2);
+    for operation in &operations {
+        println!("[Patch] {operation}");
+    }
+    let mut patched = v1.clone();
+    json_patch::apply(&mut patched, &operations)?;
+    println!("[Patch] v1 + patch == v2: {}", patched == v2);
+
+    // A patch written by hand, with a test guarding it: applied all or nothing
+    let hand_written: Vec<json_patch::Operation> = serde_json::from_value(json!([
+        { "op": "copy", "from": "/aps/0", "path": "/aps/-" },
+        { "op": "test", "path": "/port", "value": 8080 },
+    ]))
+    .context("Invalid patch")?;
+    if let Err(e) = json_patch::apply(&mut patched, &hand_written) {
+        println!("[Patch] {e}, aps still {}", patched["aps"]);
+    }
+
+    let mut merged = v1.clone();
+    json_patch::merge_patch(
+        &mut merged,
+        &json!({ "port": 8443, "tls": { "enabled": true }, "aps": null }),
+    );
+    println!("[Patch] merge patched: {merged}");
+    // === End of Diff and patch ===
+
+    Ok(())
+}
+
+// Writes `data` in every format and reads it back, reporting the size and whether anything was lost
+fn round_trips<T>(label: &str, data: &T) -> Result<(), AppError>
+where
+    T: Serialize + DeserializeOwned + PartialEq,
+{
+    for format in formats::Format::ALL {
+        let encoded = format.encode(data)?;
+        for loss in &encoded.losses {
+            println!("[Formats] {label} as {format}: lossy {loss}");
+        }
+        match format.decode::<T>(&encoded.bytes) {
+            Ok(decoded) => println!(
+                "[Formats] {label} as {format:<6} {:>4} bytes, reads back equal: {}",
+                encoded.bytes.len(),
+                decoded == *data
+            ),
+            Err(e) => println!("[Formats] {label} as {format:<6} does not read back: {e}"),
+        }
+    }
+    Ok(())
+}
 
+// Also what the check command checks without a bookmarks file
+fn fav_websites() -> Result<HashMap<&'static str, Url>, AppError> {
+    // Url (src/url.rs) parses and normalizes, so the keys and values below hold checked addresses
     let mut fav_websites = HashMap::new();
 
-    fav_websites.insert(
-        "Google",
-        Url::IP(IpAddr::V4(Ipv4Addr::new(142, 251, 33, 100))),
-    );
-    fav_websites.insert("ChatGPT", Url::Domain(String::from("chat.openai.com")));
-    fav_websites.insert("Bing", Url::Domain(String::from("www.bing.com")));
+    fav_websites.insert("Google", Url::parse("142.251.33.100")?);
+    fav_websites.insert("ChatGPT", Url::parse("chat.openai.com")?);
+    fav_websites.insert("Bing", Url::parse("https://WWW.Bing.com:443")?);
+    Ok(fav_websites)
+}
+
+fn hash_map() -> Result<(), AppError> {
+    /*
+     * Where vectors store value by an integer index, HashMap store values by keys.
+     * Keys can be boolean, integer, string, or any type that implements the Eq and Hash traits.
+     *
+     * HashMap is growable with recommended initial with a certain capacity by using:
+     * 1. HashMap::with_capacity(uint)
+     * 2. HashMap::new()
+     */
+
+    let fav_websites = fav_websites()?;
 
     // Takes a reference and returns Option<&V>
     match fav_websites.get(&"Google") {
@@ -1133,4 +2233,592 @@ fn hash_map() {
     for (k, v) in &fav_websites {
         println!("[Iteration] {:>7} -> {}", k, v);
     }
+
+    // Different spellings of the same address are one key once normalized
+    let mut visits: HashMap<Url, u32> = HashMap::new();
+    for spelling in [
+        "chat.openai.com",
+        "https://chat.openai.com/",
+        "HTTPS://Chat.OpenAI.com:443/./",
+        "https://chat.openai.com/c/../",
+        "http://chat.openai.com",
+    ] {
+        *visits.entry(spelling.parse()?).or_insert(0) += 1;
+    }
+    let mut counted: Vec<(&Url, &u32)> = visits.iter().collect();
+    counted.sort_by_key(|(url, _)| url.to_string());
+    for (url, count) in counted {
+        println!("[Url] {url} visited {count} times");
+    }
+
+    let url = Url::parse("HTTP://Bücher.Example:8080/a/./b/../%7euser/ä dir?q=rust lang#Top")?;
+    println!(
+        "[Url] {url}\n      scheme {}, host {} ({}), port {:?}, path {}, query {:?}, fragment {:?}",
+        url.scheme(),
+        url.host(),
+        url.unicode_host(),
+        url.port_or_default(),
+        url.path(),
+        url.query(),
+        url.fragment()
+    );
+    for idn in ["https://日本語.jp/", "MÜNCHEN.de/?next=https://example.com"] {
+        let url = Url::parse(idn)?;
+        println!("[Url] {idn} -> {url} ({})", url.unicode_host());
+    }
+    for ip in ["http://[::1]:8080/", "10.0.0.1:22"] {
+        let url = Url::parse(ip)?;
+        if let url::Host::Ipv4(_) | url::Host::Ipv6(_) = url.host() {
+            println!("[Url] {url} has the IP host {}", url.host());
+        }
+    }
+    for invalid in [
+        "",
+        "ht tp://x",
+        "https://user:pw@example.com",
+        "https://-bad-.com",
+        "https://example.com:99999",
+        "https://[::1",
+    ] {
+        if let Err(e) = Url::parse(invalid) {
+            println!("[Url] {invalid:?}: {e}");
+        }
+    }
+
+    // --- Bookmark manager ---
+    // The same map grown into a bookmark store with tags and folders, see src/bookmarks.rs
+    let mut store = BookmarkStore::default();
+    for (name, url) in &fav_websites {
+        store.add(Bookmark::new(*name, url.to_string()).tagged("search"))?;
+    }
+    store.add(
+        Bookmark::new("Rust", "https://www.rust-lang.org")
+            .tagged("lang")
+            .in_folder("Dev")
+            .added_at(1_431_648_000),
+    )?;
+    store.add(Bookmark::new("Tokio", "https://tokio.rs").in_folder("Dev/Async"))?;
+    store.untag("ChatGPT", "search")?;
+    store.move_to("ChatGPT", "AI")?;
+    store.tag("ChatGPT", "llm")?;
+    store.rename("Tokio", "Tokio docs")?;
+    if let Err(e) = store.add(Bookmark::new("Bing", "https://www.bing.com")) {
+        println!("[Bookmarks] {e}");
+    }
+
+    // Unlike iterating over the HashMap above, listing always gives the same order: by folder, then name
+    for bookmark in store.list() {
+        println!("[Bookmarks] {bookmark}");
+    }
+    let found: Vec<&str> = store
+        .search("search")
+        .iter()
+        .map(|b| b.name.as_str())
+        .collect();
+    println!("[Bookmarks] search \"search\": {}", found.join(", "));
+    println!("[Bookmarks] folders: {:?}", store.folders());
+
+    // Netscape HTML is what browsers export, importing it gives back the same bookmarks
+    let html = store.to_html();
+    print!("{html}");
+    let mut imported = BookmarkStore::default();
+    let summary = imported.import_html(&html)?;
+    println!(
+        "[Bookmarks] imported {} from HTML, same as the original: {}",
+        summary.added,
+        imported.list() == store.list()
+    );
+
+    // Saved to a file and opened again, as the bookmarks command does
+    let path = std::env::temp_dir().join("rust-basic-bookmarks.json");
+    store.save(&path)?;
+    let mut reopened = BookmarkStore::open(&path)?;
+    let removed = reopened.remove("Google")?;
+    println!(
+        "[Bookmarks] reopened {} bookmarks from {}, removed {}, {} left, Rust is {:?}",
+        store.len(),
+        path.display(),
+        removed.name,
+        reopened.len(),
+        reopened.get("Rust").map(|b| &b.url)
+    );
+    let json_summary = reopened.import_json(&store.to_json())?;
+    println!(
+        "[Bookmarks] importing the saved JSON again: {} added, {} replaced, empty: {}",
+        json_summary.added,
+        json_summary.replaced,
+        reopened.is_empty()
+    );
+    std::fs::remove_file(&path).context("Failed to clean up the bookmark file")?;
+
+    let bookmarks: Vec<Bookmark> = store.list().into_iter().cloned().collect();
+    round_trips("bookmarks", &bookmarks)?;
+    // === End of Bookmark manager ===
+
+    Ok(())
+}
+
+fn lru_cache() -> Result<(), AppError> {
+    /*
+     * A HashMap keeps everything it was given. A cache (src/cache.rs) is a HashMap with a capacity: when full, it evicts
+     * the entry used least recently (LRU), and entries can expire after a time to live (TTL).
+     *
+     * The workload: 20,000 lookups of 1,000 user profiles, each one expensive to load. 90% of the lookups go to 50 "hot"
+     * users, and halfway through a different 50 become hot, like the popular pages of a site changing over the day.
+     */
+    use rand::{rngs::StdRng, SeedableRng};
+    let mut rng = StdRng::seed_from_u64(2024); // The same workload on every run
+    let lookups: Vec<u32> = (0..20_000)
+        .map(|i| {
+            let hot = if i < 10_000 { 0 } else { 500 };
+            if rng.random_bool(0.9) {
+                hot + rng.random_range(0..50)
+            } else {
+                rng.random_range(0..1000)
+            }
+        })
+        .collect();
+    let load = |user: u32| format!("profile of user {user}");
+    let report = |strategy: &str, loads: usize, entries: usize, elapsed: Duration| {
+        let hit_rate = 100.0 * (lookups.len() - loads) as f64 / lookups.len() as f64;
+        println!(
+            "[Cache] {strategy:<26} {hit_rate:>5.1}% hits {loads:>5} loads {entries:>5} entries held, {elapsed:.2?}"
+        );
+    };
+
+    // Remembers every profile ever loaded: the best hit rate, paid for with memory that only grows
+    let started_at = Instant::now();
+    let mut map: HashMap<u32, String> = HashMap::new();
+    let mut loads = 0;
+    for &user in &lookups {
+        map.entry(user).or_insert_with(|| {
+            loads += 1;
+            load(user)
+        });
+    }
+    report("HashMap, unbounded", loads, map.len(), started_at.elapsed());
+
+    // Capped at 100 profiles: without knowing which entry is stale, a full map can only refuse newcomers
+    let started_at = Instant::now();
+    let mut map: HashMap<u32, String> = HashMap::new();
+    let mut loads = 0;
+    for &user in &lookups {
+        if !map.contains_key(&user) {
+            loads += 1;
+            if map.len() < 100 {
+                map.insert(user, load(user));
+            }
+        }
+    }
+    report(
+        "HashMap, first 100 kept",
+        loads,
+        map.len(),
+        started_at.elapsed(),
+    );
+
+    // Also 100 profiles, but the least recently used make room: after the shift the new hot users move in
+    let started_at = Instant::now();
+    let mut cache = Cache::new(Capacity::Entries(100));
+    let mut loads = 0;
+    for &user in &lookups {
+        cache.entry(user).or_insert_with(|| {
+            loads += 1;
+            load(user)
+        });
+    }
+    report(
+        "Cache, LRU of 100",
+        loads,
+        cache.len(),
+        started_at.elapsed(),
+    );
+    println!("[Cache] LRU of 100: {}", cache.stats());
+
+    // TTL: a FakeClock (src/bakery.rs) lets minutes pass instantly
+    let clock = FakeClock::new(SystemTime::UNIX_EPOCH);
+    let mut sessions =
+        Cache::with_clock(Capacity::Entries(10), clock.clone()).ttl(Duration::from_secs(30));
+    sessions.insert("alice", "token-a");
+    sessions.insert_with_ttl("bob", "token-b", Some(Duration::from_secs(300)));
+    sessions.insert("carol", "token-c");
+    clock.advance(Duration::from_secs(20));
+    println!("[TTL] after 20s: alice {:?}", sessions.get("alice"));
+    clock.advance(Duration::from_secs(15));
+    let alice = sessions.get("alice").copied();
+    println!(
+        "[TTL] after 35s: alice {:?}, bob {:?} (5 minutes to live)",
+        alice,
+        sessions.peek("bob")
+    );
+    println!(
+        "[TTL] purged {} expired, {} left: {}",
+        sessions.purge_expired(),
+        sessions.len(),
+        sessions.stats()
+    );
+
+    // Capacity by weight: at most 64 bytes of pages, however many pages that is
+    let mut pages: Cache<&str, String> = Cache::new(Capacity::Weight {
+        max: 64,
+        weigher: |_, page: &String| page.len(),
+    });
+    for (name, size) in [("home", 20), ("about", 10), ("blog", 30), ("contact", 15)] {
+        pages.insert(name, "x".repeat(size));
+        let held: Vec<_> = pages
+            .iter()
+            .map(|(name, page)| format!("{name}={}", page.len()))
+            .collect();
+        println!(
+            "[Weight] + {name:<7} {size:>2} bytes -> {} of 64 bytes, most recent first: {}",
+            pages.weight(),
+            held.join(" ")
+        );
+    }
+
+    // The entry API works like HashMap's, here counting words while only the 3 most recent are kept
+    let mut counts = Cache::new(Capacity::Entries(3));
+    for word in "the cat saw the dog and the dog saw the cat".split_whitespace() {
+        counts
+            .entry(word)
+            .and_modify(|count| *count += 1)
+            .or_insert(1);
+    }
+    let held: Vec<_> = counts
+        .iter()
+        .map(|(word, count)| format!("{word}={count}"))
+        .collect();
+    println!(
+        "[Entry] counts of the 3 most recent words: {}",
+        held.join(" ")
+    );
+    if let Entry::Occupied(entry) = counts.entry("the") {
+        println!("[Entry] removed \"the\", counted {} times", entry.remove());
+    }
+    counts.remove("cat");
+    counts.remove("saw");
+    println!(
+        "[Entry] after removing the rest: empty {}, {}",
+        counts.is_empty(),
+        counts.stats()
+    );
+    Ok(())
+}
+
+fn fibonacci_benchmark() -> Result<(), AppError> {
+    /*
+     * The same numbers computed five ways, see src/fibonacci.rs.
+     * Each time is the fastest of a few runs. Past n = 93 the u64 versions report an overflow instead of a wrong number,
+     * and the naive one is not even tried past Method::NAIVE_LIMIT because it would take ages.
+     */
+    let ns = [10, 20, 30, 32, 50, 90, 93, 94, 1_000, 10_000];
+    let timings = fibonacci::benchmark(ns, 5);
+
+    print!("{:>6}", "n");
+    for method in fibonacci::Method::ALL {
+        print!(" {:>12}", method.name());
+    }
+    println!();
+    for n in ns {
+        print!("{:>6}", n);
+        for method in fibonacci::Method::ALL {
+            match timings.iter().find(|t| t.n == n && t.method == method) {
+                Some(timing) if timing.result.is_ok() => print!(" {:>12.2?}", timing.elapsed),
+                Some(_) => print!(" {:>12}", "overflow"),
+                None => print!(" {:>12}", "skipped"),
+            }
+        }
+        println!();
+    }
+
+    let huge = fibonacci::big(10_000);
+    let digits = huge.to_string();
+    println!(
+        "fibonacci(10000) has {} digits: {}...{}",
+        huge.digits(),
+        &digits[..10],
+        &digits[digits.len() - 10..]
+    );
+    println!(
+        "fibonacci({}) = {}",
+        fibonacci::MAX_U64_N,
+        fibonacci::matrix(fibonacci::MAX_U64_N)?
+    );
+
+    // Overflow is an error, not a panic
+    if let Err(e) = fibonacci::iterative(fibonacci::MAX_U64_N + 1) {
+        println!("{e}");
+    }
+    Ok(())
+}
+
+async fn tcp_chat() -> Result<(), AppError> {
+    /*
+     * A chat server and its clients talking over real TCP sockets on the loopback interface (src/chat.rs).
+     * Binding to port 0 lets the OS pick a free port, so the lesson never collides with anything else running.
+     * The server runs as its own task, every client is just a TcpStream in this one.
+     */
+    let server = ChatServer::bind("127.0.0.1:0")
+        .await
+        .context("Failed to start the chat server")?;
+    let addr = server.local_addr()?;
+    println!("[Chat] server listening on {addr}");
+    let shutdown = CancellationToken::new();
+    let server = tokio::spawn(server.run(shutdown.clone()));
+
+    // Prints what every client received since the last call, after they all went quiet for a moment
+    async fn show(timeline: &Timeline, clients: &mut [&mut ChatClient]) -> Result<(), AppError> {
+        for client in clients.iter_mut() {
+            for line in client.drain(Duration::from_millis(50)).await? {
+                timeline.record(client.name(), format!("< {line}"));
+            }
+        }
+        Ok(())
+    }
+
+    let timeline = Timeline::new();
+    let (mut alice, welcome) = ChatClient::connect(addr, "alice").await?;
+    timeline.record("alice", format!("< {welcome}"));
+    let (mut bob, welcome) = ChatClient::connect(addr, "bob").await?;
+    timeline.record("bob", format!("< {welcome}"));
+    show(&timeline, &mut [&mut alice, &mut bob]).await?;
+
+    async fn say(timeline: &Timeline, client: &mut ChatClient, line: &str) -> Result<(), AppError> {
+        timeline.record(client.name(), format!("> {line}"));
+        Ok(client.send(line).await?)
+    }
+
+    say(&timeline, &mut alice, "/echo is this thing on?").await?;
+    say(&timeline, &mut alice, "hi bob!").await?;
+    show(&timeline, &mut [&mut alice, &mut bob]).await?;
+    say(&timeline, &mut bob, "hey alice").await?;
+    show(&timeline, &mut [&mut alice, &mut bob]).await?;
+
+    // Ten clients at once: each one joins, says hello and quits, all handled concurrently by the server
+    let visitors: Vec<_> = (1..=10)
+        .map(|n| {
+            tokio::spawn(async move {
+                let (mut visitor, _) = ChatClient::connect(addr, &format!("visitor{n}")).await?;
+                visitor.send("hello everyone").await?;
+                visitor.quit().await
+            })
+        })
+        .collect();
+    for visitor in visitors {
+        visitor.await??;
+    }
+    let lines = alice.drain(Duration::from_millis(50)).await?;
+    let greetings = lines
+        .iter()
+        .filter(|line| line.ends_with("hello everyone"))
+        .count();
+    timeline.record(
+        "alice",
+        format!(
+            "< {} lines from 10 visitors, {} of them greetings",
+            lines.len(),
+            greetings
+        ),
+    );
+    bob.drain(Duration::from_millis(50)).await?;
+
+    timeline.record("bob", "> /quit");
+    for line in bob.quit().await? {
+        timeline.record("bob", format!("< {line}"));
+    }
+    show(&timeline, &mut [&mut alice]).await?;
+
+    // Shutting down tells every connected client before the connections close
+    timeline.record("server", "shutting down");
+    shutdown.cancel();
+    let stats = server.await??;
+    show(&timeline, &mut [&mut alice]).await?;
+
+    print!("{timeline}");
+    println!("[Chat] {stats}");
+    Ok(())
+}
+
+async fn dns_resolver() -> Result<(), AppError> {
+    /*
+     * Resolving the favorite websites of the hashmap lesson to IP addresses (src/resolver.rs).
+     * The DNS server is a stub on the loopback interface that only knows a few names, so the lesson works offline
+     * and shows exactly how many questions reach it. Point DnsResolver at 1.1.1.1:53 and the same code asks the internet.
+     */
+    let v4 = |ip: &str| -> std::net::IpAddr { ip.parse().expect("literal IP address") };
+    let server = StubDnsServer::bind("127.0.0.1:0")
+        .await
+        .context("Failed to start the stub DNS server")?
+        .record("chat.openai.com", v4("104.18.33.45"), 300)
+        .record("chat.openai.com", v4("2606:4700:4400::6812:212d"), 300)
+        .record("www.bing.com", v4("13.107.21.200"), 1);
+    let addr = server.local_addr()?;
+    let queries = server.queries();
+    let shutdown = CancellationToken::new();
+    let server = tokio::spawn(server.run(shutdown.clone()));
+    println!("[DNS] stub server listening on {addr}");
+
+    // Hosts file first, like the C library does, then DNS. Answers of both are cached for their TTL
+    let hosts = HostsFile::parse(
+        "127.0.0.1  localhost\n\
+         ::1        localhost   # IPv6 as well\n\
+         10.0.0.7   intranet.example intranet\n",
+    );
+    println!("[DNS] hosts file with {} names", hosts.len());
+    let resolver = CachingResolver::new(Fallback::new(
+        hosts,
+        DnsResolver::new(addr)
+            .timeout(Duration::from_millis(500))
+            .attempts(2),
+    ));
+
+    let fav_websites = [
+        ("Google", Url::parse("142.251.33.100")?),
+        ("ChatGPT", Url::parse("chat.openai.com")?),
+        ("Bing", Url::parse("https://WWW.Bing.com:443")?),
+        ("Intranet", Url::parse("http://intranet/wiki")?),
+    ];
+    for round in 1..=2 {
+        for (name, url) in &fav_websites {
+            let addresses = resolver::resolve_host(&resolver, url.host()).await?;
+            println!(
+                "[DNS] round {round}: {name:<8} {:<16} -> {addresses:?}",
+                url.host()
+            );
+        }
+        println!("[DNS] round {round}: cache {}", resolver.stats());
+    }
+
+    // Bing's answer only lives for a second: once it is gone, the cache asks the server again
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let bing = resolver.resolve("www.bing.com").await?;
+    let chatgpt = resolver.resolve("CHAT.openai.com.").await?;
+    println!(
+        "[DNS] after 1.1s: www.bing.com asked again, ttl {:?}",
+        bing.ttl
+    );
+    println!(
+        "[DNS] after 1.1s: chat.openai.com still cached, {}s left",
+        chatgpt.ttl.as_secs()
+    );
+    println!(
+        "[DNS] cache {}, the stub server answered {} questions (A and AAAA per lookup)",
+        resolver.stats(),
+        queries.load(AtomicOrdering::Relaxed)
+    );
+
+    // What can go wrong: a name nobody knows, a name that is not one, a server that never answers
+    match resolver.resolve("nowhere.invalid").await {
+        Err(e @ ResolveError::NotFound(_)) => println!("[DNS] {e}"),
+        other => println!("[DNS] unexpected: {other:?}"),
+    }
+    if let Err(e) = resolver.resolve("bad..name").await {
+        println!("[DNS] {e}");
+    }
+    let silent = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let unanswered = DnsResolver::new(silent.local_addr()?)
+        .timeout(Duration::from_millis(100))
+        .attempts(2);
+    if let Err(e) = unanswered.resolve("www.bing.com").await {
+        println!("[DNS] {e}");
+    }
+
+    shutdown.cancel();
+    server.await??;
+
+    // The real hosts file of this machine, if it has one
+    match HostsFile::system().await {
+        Ok(hosts) => match hosts.resolve("localhost").await {
+            Ok(resolved) => println!("[DNS] /etc/hosts: localhost -> {:?}", resolved.addresses),
+            Err(e) => println!("[DNS] /etc/hosts: {e}"),
+        },
+        Err(e) => println!(
+            "[DNS] /etc/hosts: {}",
+            AppError::from(e).context("Failed to read the hosts file")
+        ),
+    }
+    Ok(())
+}
+
+async fn link_checker() -> Result<(), AppError> {
+    /*
+     * Checking links concurrently (src/link_check.rs), against a stub web server on the loopback interface.
+     * The server knows a few pages, some slow or broken, and counts how many connections it had open at once.
+     * The hosts file maps site.test to it, so links look like real ones without leaving the machine.
+     */
+    let server = StubHttpServer::bind("127.0.0.1:0")
+        .await
+        .context("Failed to start the stub web server")?
+        .page("/", 200, Duration::from_millis(50))
+        .page("/old", 301, Duration::ZERO)
+        .page("/broken", 500, Duration::ZERO)
+        .page("/slow", 200, Duration::from_secs(2));
+    let server = (1..=8).fold(server, |server, n| {
+        server.page(&format!("/articles/{n}"), 200, Duration::from_millis(100))
+    });
+    let port = server.local_addr()?.port();
+    let peak = server.peak_connections();
+    let shutdown = CancellationToken::new();
+    let server = tokio::spawn(server.run(shutdown.clone()));
+    println!("[Links] stub web server listening on port {port}");
+
+    // Nothing listens on a port that was just released, so connecting to it is refused
+    let closed = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await?
+        .local_addr()?;
+
+    let hosts = HostsFile::parse("127.0.0.1 site.test");
+    let checker = LinkChecker::new(hosts)
+        .timeout(Duration::from_millis(500))
+        .per_host(2)
+        .probe(Probe::Head);
+    let site = |path: &str| format!("http://site.test:{port}{path}");
+    let mut links = vec![
+        ("Home".to_string(), site("/")),
+        ("Moved".to_string(), site("/old")),
+        ("Broken".to_string(), site("/broken")),
+        ("Missing".to_string(), site("/missing")),
+        ("Slow".to_string(), site("/slow")),
+        ("Gone".to_string(), "http://gone.test/".to_string()),
+        ("Closed".to_string(), format!("http://{closed}/")),
+        ("Script".to_string(), "javascript:void(0)".to_string()),
+    ];
+    for n in 1..=8 {
+        links.push((format!("Article {n}"), site(&format!("/articles/{n}"))));
+    }
+    let report = checker.check(links).await;
+    println!("{report}");
+    for dead in report.dead() {
+        println!("[Links] dead: {dead}");
+    }
+    if let Err(e) = report.check() {
+        println!("[Links] {e}");
+    }
+
+    // The same 8 articles, each taking the server 100ms, with more and more connections allowed per host
+    for per_host in [1, 2, 8] {
+        let checker = LinkChecker::new(HostsFile::parse("127.0.0.1 site.test")).per_host(per_host);
+        let articles = (1..=8).map(|n| (format!("Article {n}"), site(&format!("/articles/{n}"))));
+        peak.store(0, AtomicOrdering::Relaxed);
+        let report = checker.check(articles).await;
+        println!(
+            "[Links] 8 articles, {per_host} per host: {:.0?}, {} dead, at most {} connections open at once",
+            report.elapsed,
+            report.dead().count(),
+            peak.load(AtomicOrdering::Relaxed)
+        );
+    }
+
+    // Probe::Connect only asks whether the port is open, so a missing page does not make the link dead
+    let connect_only =
+        LinkChecker::new(HostsFile::parse("127.0.0.1 site.test")).probe(Probe::Connect);
+    let report = connect_only.check([("Missing", site("/missing"))]).await;
+    for checked in &report.links {
+        println!("[Links] connect only: {checked}");
+    }
+
+    shutdown.cancel();
+    let answered = server.await??;
+    println!("[Links] the stub web server answered {answered} requests");
+    Ok(())
 }
@@ -0,0 +1,106 @@
+use crate::json_schema::Schema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/*
+ * The access point records and the server config of the enum and generic type lessons.
+ * They live here instead of inside the lessons so they can be saved, loaded and checked against a schema.
+ *
+ * In JSON an access point looks like {"id": 2, "name": "ACX-01", "kind": {"v6": "hex"}, "address": "2377:..."},
+ * a config like {"host": "localhost", "port": 8080}, where the port may be null.
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum V6Format {
+    Bin,
+    Hex,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IpAddrKind {
+    V4,
+    V6(V6Format),
+    None, // None represents the absensce of a kind
+}
+
+// Define a struct that holds various data, including an IpAddrKind.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ap {
+    pub id: i32,
+    pub name: String,
+    pub kind: IpAddrKind,
+    pub address: String,
+}
+
+impl Ap {
+    // A JSON array of access points
+    pub fn list_schema() -> Schema {
+        Schema::compile(&json!({
+            "type": "array",
+            "items": {
+                "type": "object",
+                "required": ["id", "name", "kind", "address"],
+                "properties": {
+                    "id": { "type": "integer", "minimum": 1 },
+                    "name": { "type": "string", "minLength": 1, "maxLength": 32, "pattern": "^[A-Z]+-[0-9]+( --[a-z]+)?$" },
+                    "kind": { "enum": ["v4", "none", { "v6": "hex" }, { "v6": "bin" }] },
+                    "address": { "type": "string", "pattern": "^[0-9A-Fa-f.:]+$" }
+                },
+                "additionalProperties": false
+            }
+        }))
+        .expect("the access point schema is valid")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Config {
+    pub port: Option<u16>, // Some API fields may be optional
+    pub host: String,
+}
+
+impl Config {
+    pub fn schema() -> Schema {
+        Schema::compile(&json!({
+            "type": "object",
+            "required": ["host"],
+            "properties": {
+                "host": { "type": "string", "minLength": 1 },
+                "port": { "type": ["integer", "null"], "minimum": 1, "maximum": 65535 }
+            },
+            "additionalProperties": false
+        }))
+        .expect("the config schema is valid")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn access_point_names_match_the_whole_pattern() {
+        let schema = Ap::list_schema();
+        let ap = |name: &str| json!([{"id": 1, "name": name, "kind": "v4", "address": "10.0.0.1"}]);
+        assert!(schema.check(&ap("AC-01")).is_ok());
+        assert!(schema.check(&ap("ACX-01")).is_ok());
+        assert!(schema.check(&ap("AC-02 --beta")).is_ok());
+        for name in ["AC-01 extra", "AC-01x", "AC-01 --", "ac-01", "AC-"] {
+            assert!(schema.check(&ap(name)).is_err(), "{name}");
+        }
+    }
+
+    #[test]
+    fn config_port_may_be_null() {
+        let schema = Config::schema();
+        assert!(schema
+            .check(&json!({"host": "localhost", "port": null}))
+            .is_ok());
+        assert!(schema
+            .check(&json!({"host": "localhost", "port": 8080}))
+            .is_ok());
+        assert!(schema.check(&json!({"port": 8080})).is_err());
+    }
+}
@@ -0,0 +1,552 @@
+use crate::formats::kind_of;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{collections::BTreeMap, error::Error, fmt};
+
+/*
+ * The shape our query tools answer with: {"scalar": ..., "table": [{column: value, ...}, ...]}.
+ * A query either produces a single value (scalar) or rows (table), the other part is null or empty.
+ *
+ * json!() and Value are fine to build such a result, but every reader has to check every field by hand.
+ * QueryResult is the typed form:
+ *
+ * - from_value() checks the shape and the column types, and says exactly where a value does not fit.
+ *   Top-level keys other than scalar, columns and table are rejected, so a typo is not mistaken for an empty table
+ * - to_value() goes back to the dynamic form
+ * - rows_as::<T>() / scalar_as::<T>() deserialize into the caller's own structs, from_rows() serializes them
+ *
+ * Columns are typed. A result can declare them in "columns", otherwise they are inferred from the rows:
+ * every non-null value of a column must have the same type, except that integers may appear in a float column.
+ * A column that is null in every row is typed "any".
+ */
+
+pub type Row = Map<String, Value>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColumnType {
+    Bool,
+    Integer,
+    Float,
+    Text,
+    Array,
+    Object,
+    Any, // Only ever null so far, or declared to hold anything
+}
+
+impl ColumnType {
+    // None for null, which fits any nullable column
+    pub fn of(value: &Value) -> Option<ColumnType> {
+        match value {
+            Value::Null => None,
+            Value::Bool(_) => Some(ColumnType::Bool),
+            Value::Number(n) if n.is_f64() => Some(ColumnType::Float),
+            Value::Number(_) => Some(ColumnType::Integer),
+            Value::String(_) => Some(ColumnType::Text),
+            Value::Array(_) => Some(ColumnType::Array),
+            Value::Object(_) => Some(ColumnType::Object),
+        }
+    }
+
+    pub fn accepts(self, found: ColumnType) -> bool {
+        self == found
+            || self == ColumnType::Any
+            || (self == ColumnType::Float && found == ColumnType::Integer)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ColumnType::Bool => "bool",
+            ColumnType::Integer => "integer",
+            ColumnType::Float => "float",
+            ColumnType::Text => "text",
+            ColumnType::Array => "array",
+            ColumnType::Object => "object",
+            ColumnType::Any => "any",
+        }
+    }
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Column {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub column_type: ColumnType,
+    #[serde(default)]
+    pub nullable: bool, // Null or missing in at least one row
+}
+
+// Deserializing goes through from_value, so serde_json::from_str runs the same checks
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(try_from = "Value")]
+pub struct QueryResult {
+    pub scalar: Option<Value>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub columns: Vec<Column>,
+    pub table: Vec<Row>,
+}
+
+#[derive(Debug)]
+pub enum QueryResultError {
+    // `path` is where in the document, e.g. "table[2]" or "table[0].score"
+    WrongShape {
+        path: String,
+        expected: &'static str,
+        found: &'static str,
+    },
+    TypeMismatch {
+        path: String,
+        expected: ColumnType,
+        found: ColumnType,
+    },
+    NotNullable {
+        path: String,
+    },
+    UnknownColumn {
+        path: String,
+    },
+    UnknownField(String),
+    // A row (or the scalar) that does not deserialize into the caller's type
+    Deserialize {
+        path: String,
+        source: serde_json::Error,
+    },
+    Serialize(serde_json::Error),
+}
+
+impl fmt::Display for QueryResultError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryResultError::WrongShape {
+                path,
+                expected,
+                found,
+            } => write!(f, "{} should be {}, found {}", path, expected, found),
+            QueryResultError::TypeMismatch {
+                path,
+                expected,
+                found,
+            } => write!(f, "{} should be {}, found {}", path, expected, found),
+            QueryResultError::NotNullable { path } => {
+                write!(f, "{} is null, but the column is not nullable", path)
+            }
+            QueryResultError::UnknownColumn { path } => {
+                write!(f, "{} is not one of the declared columns", path)
+            }
+            QueryResultError::UnknownField(name) => write!(
+                f,
+                "{:?} is not a field of a query result, expected scalar, columns or table",
+                name
+            ),
+            QueryResultError::Deserialize { path, .. } => {
+                write!(f, "{} does not match the requested type", path)
+            }
+            QueryResultError::Serialize(_) => write!(f, "Failed to serialize the rows"),
+        }
+    }
+}
+
+impl Error for QueryResultError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            QueryResultError::Deserialize { source, .. } | QueryResultError::Serialize(source) => {
+                Some(source)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<Value> for QueryResult {
+    type Error = QueryResultError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        QueryResult::from_value(value)
+    }
+}
+
+impl QueryResult {
+    pub fn from_value(value: Value) -> Result<QueryResult, QueryResultError> {
+        let shape_error = |path: &str, expected, found: &Value| QueryResultError::WrongShape {
+            path: path.to_string(),
+            expected,
+            found: kind_of(found),
+        };
+
+        let Value::Object(mut object) = value else {
+            return Err(shape_error("the result", "an object", &value));
+        };
+        let scalar = match object.remove("scalar") {
+            None | Some(Value::Null) => None,
+            Some(scalar) => Some(scalar),
+        };
+        let table = match object.remove("table") {
+            None | Some(Value::Null) => Vec::new(),
+            Some(Value::Array(rows)) => rows
+                .into_iter()
+                .enumerate()
+                .map(|(index, row)| match row {
+                    Value::Object(row) => Ok(row),
+                    other => Err(shape_error(&format!("table[{index}]"), "an object", &other)),
+                })
+                .collect::<Result<_, _>>()?,
+            Some(other) => return Err(shape_error("table", "an array", &other)),
+        };
+        let columns = match object.remove("columns") {
+            None | Some(Value::Null) => Vec::new(),
+            Some(columns) => {
+                serde_json::from_value(columns).map_err(|source| QueryResultError::Deserialize {
+                    path: "columns".to_string(),
+                    source,
+                })?
+            }
+        };
+        if let Some(name) = object.keys().next() {
+            return Err(QueryResultError::UnknownField(name.clone()));
+        }
+
+        let mut result = QueryResult {
+            scalar,
+            columns,
+            table,
+        };
+        if result.columns.is_empty() {
+            result.columns = result.infer_columns()?;
+        } else {
+            result.check_columns()?;
+        }
+        Ok(result)
+    }
+
+    pub fn to_value(&self) -> Value {
+        serde_json::to_value(self).expect("a QueryResult only holds JSON values")
+    }
+
+    // Sorted by name, the order of serde_json's Map
+    pub fn infer_columns(&self) -> Result<Vec<Column>, QueryResultError> {
+        // The type stays None until the first non-null value of the column shows up
+        let mut columns: BTreeMap<&str, (Option<ColumnType>, bool)> = BTreeMap::new();
+        for (index, row) in self.table.iter().enumerate() {
+            for (name, value) in row {
+                // A column first seen after row 0 was missing from the rows before, so it is nullable
+                let (column_type, nullable) = columns.entry(name).or_insert((None, index > 0));
+                match (*column_type, ColumnType::of(value)) {
+                    (_, None) => *nullable = true,
+                    (None, Some(found)) => *column_type = Some(found),
+                    // Widen: 1 and 2.5 make a float column
+                    (Some(ColumnType::Integer), Some(ColumnType::Float)) => {
+                        *column_type = Some(ColumnType::Float)
+                    }
+                    (Some(expected), Some(found)) if expected.accepts(found) => {}
+                    (Some(expected), Some(found)) => {
+                        return Err(QueryResultError::TypeMismatch {
+                            path: format!("table[{index}].{name}"),
+                            expected,
+                            found,
+                        })
+                    }
+                }
+            }
+        }
+
+        Ok(columns
+            .into_iter()
+            .map(|(name, (column_type, nullable))| Column {
+                name: name.to_string(),
+                column_type: column_type.unwrap_or(ColumnType::Any),
+                // Missing from a later row counts as null too
+                nullable: nullable || self.table.iter().any(|row| !row.contains_key(name)),
+            })
+            .collect())
+    }
+
+    // Every value must fit its declared column
+    pub fn check_columns(&self) -> Result<(), QueryResultError> {
+        for (index, row) in self.table.iter().enumerate() {
+            for (name, value) in row {
+                let path = format!("table[{index}].{name}");
+                let Some(column) = self.column(name) else {
+                    return Err(QueryResultError::UnknownColumn { path });
+                };
+                match ColumnType::of(value) {
+                    None if !column.nullable => return Err(QueryResultError::NotNullable { path }),
+                    None => {}
+                    Some(found) if !column.column_type.accepts(found) => {
+                        return Err(QueryResultError::TypeMismatch {
+                            path,
+                            expected: column.column_type,
+                            found,
+                        })
+                    }
+                    Some(_) => {}
+                }
+            }
+            for column in &self.columns {
+                if !column.nullable && !row.contains_key(&column.name) {
+                    return Err(QueryResultError::NotNullable {
+                        path: format!("table[{index}].{}", column.name),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn column(&self, name: &str) -> Option<&Column> {
+        self.columns.iter().find(|column| column.name == name)
+    }
+
+    pub fn rows_as<T: DeserializeOwned>(&self) -> Result<Vec<T>, QueryResultError> {
+        self.table
+            .iter()
+            .enumerate()
+            .map(|(index, row)| {
+                serde_json::from_value(Value::Object(row.clone())).map_err(|source| {
+                    QueryResultError::Deserialize {
+                        path: format!("table[{index}]"),
+                        source,
+                    }
+                })
+            })
+            .collect()
+    }
+
+    // None when the query did not produce a scalar
+    pub fn scalar_as<T: DeserializeOwned>(&self) -> Result<Option<T>, QueryResultError> {
+        self.scalar
+            .as_ref()
+            .map(|scalar| {
+                T::deserialize(scalar).map_err(|source| QueryResultError::Deserialize {
+                    path: "scalar".to_string(),
+                    source,
+                })
+            })
+            .transpose()
+    }
+
+    pub fn from_rows<T: Serialize>(rows: &[T]) -> Result<QueryResult, QueryResultError> {
+        let table = rows
+            .iter()
+            .enumerate()
+            .map(|(index, row)| match serde_json::to_value(row) {
+                Ok(Value::Object(row)) => Ok(row),
+                Ok(other) => Err(QueryResultError::WrongShape {
+                    path: format!("table[{index}]"),
+                    expected: "an object",
+                    found: kind_of(&other),
+                }),
+                Err(e) => Err(QueryResultError::Serialize(e)),
+            })
+            .collect::<Result<_, _>>()?;
+        let mut result = QueryResult {
+            table,
+            ..QueryResult::default()
+        };
+        result.columns = result.infer_columns()?;
+        Ok(result)
+    }
+}
+
+// A plain text table, or the scalar
+impl fmt::Display for QueryResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(scalar) = &self.scalar {
+            writeln!(f, "{}", scalar)?;
+        }
+        if self.columns.is_empty() {
+            return Ok(());
+        }
+
+        let cells: Vec<Vec<String>> = self
+            .table
+            .iter()
+            .map(|row| {
+                self.columns
+                    .iter()
+                    .map(|column| match row.get(&column.name) {
+                        None | Some(Value::Null) => "-".to_string(),
+                        Some(Value::String(text)) => text.clone(),
+                        Some(value) => value.to_string(),
+                    })
+                    .collect()
+            })
+            .collect();
+        let headers: Vec<String> = self
+            .columns
+            .iter()
+            .map(|column| {
+                let optional = if column.nullable { "?" } else { "" };
+                format!("{} ({}{})", column.name, column.column_type, optional)
+            })
+            .collect();
+        let widths: Vec<usize> = headers
+            .iter()
+            .enumerate()
+            .map(|(i, header)| {
+                cells
+                    .iter()
+                    .map(|row| row[i].len())
+                    .chain([header.len()])
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        for line in [&headers].into_iter().chain(&cells) {
+            let padded: Vec<String> = line
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect();
+            writeln!(f, "| {} |", padded.join(" | "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn column(name: &str, column_type: ColumnType, nullable: bool) -> Column {
+        Column {
+            name: name.to_string(),
+            column_type,
+            nullable,
+        }
+    }
+
+    #[test]
+    fn scalar_and_empty_results() {
+        let result = QueryResult::from_value(json!({"scalar": 3, "table": []})).unwrap();
+        assert_eq!(result.scalar_as::<u64>().unwrap(), Some(3));
+        assert!(result.table.is_empty() && result.columns.is_empty());
+
+        let result = QueryResult::from_value(json!({"scalar": null, "table": null})).unwrap();
+        assert_eq!(result, QueryResult::default());
+        assert_eq!(result.scalar_as::<u64>().unwrap(), None);
+    }
+
+    #[test]
+    fn unknown_top_level_keys_are_rejected() {
+        let err = QueryResult::from_value(json!({"tabel": [{"id": 1}]})).unwrap_err();
+        assert!(matches!(err, QueryResultError::UnknownField(ref name) if name == "tabel"));
+        assert!(QueryResult::from_value(json!({"scalar": 1, "count": 1})).is_err());
+    }
+
+    #[test]
+    fn serde_deserialization_runs_the_same_checks() {
+        let parse = serde_json::from_str::<QueryResult>;
+        let error = parse(r#"{"tabel": []}"#).unwrap_err();
+        assert!(
+            error.to_string().contains("\"tabel\" is not a field"),
+            "{error}"
+        );
+        assert!(parse(r#"{"table": [1]}"#).is_err());
+        assert!(parse(
+            r#"{"columns": [{"name": "id", "type": "integer"}], "table": [{"id": "one"}]}"#
+        )
+        .is_err());
+        assert!(
+            parse(r#"{"columns": [{"name": "id", "type": "integer"}], "table": [{}]}"#).is_err()
+        );
+
+        let result = parse(r#"{"table": [{"id": 1}, {"id": null}]}"#).unwrap();
+        assert_eq!(result.columns, [column("id", ColumnType::Integer, true)]);
+        assert_eq!(parse(&result.to_value().to_string()).unwrap(), result);
+    }
+
+    #[test]
+    fn columns_are_inferred_from_the_rows() {
+        let result = QueryResult::from_value(json!({"table": [
+            {"id": 1, "score": 9.5, "tag": null},
+            {"id": 2, "score": 7},
+            {"id": 3, "score": 1, "note": "late"},
+        ]}))
+        .unwrap();
+        assert_eq!(
+            result.columns,
+            vec![
+                column("id", ColumnType::Integer, false),
+                column("note", ColumnType::Text, true),
+                column("score", ColumnType::Float, false),
+                column("tag", ColumnType::Any, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn mismatches_say_where() {
+        let path = |value| match QueryResult::from_value(value).unwrap_err() {
+            QueryResultError::WrongShape { path, .. }
+            | QueryResultError::TypeMismatch { path, .. }
+            | QueryResultError::NotNullable { path }
+            | QueryResultError::UnknownColumn { path }
+            | QueryResultError::Deserialize { path, .. } => path,
+            other => panic!("unexpected {other}"),
+        };
+        assert_eq!(path(json!([])), "the result");
+        assert_eq!(path(json!({"table": {"id": 1}})), "table");
+        assert_eq!(path(json!({"table": [{"id": 1}, 2]})), "table[1]");
+        assert_eq!(
+            path(json!({"table": [{"id": 1}, {"id": "two"}]})),
+            "table[1].id"
+        );
+        let columns = json!([{"name": "id", "type": "integer"}]);
+        assert_eq!(
+            path(json!({"columns": columns, "table": [{"id": null}]})),
+            "table[0].id"
+        );
+        assert_eq!(
+            path(json!({"columns": columns, "table": [{"name": "x"}]})),
+            "table[0].name"
+        );
+        assert_eq!(
+            path(json!({"columns": columns, "table": [{}]})),
+            "table[0].id"
+        );
+        assert_eq!(path(json!({"columns": [{"name": "id"}]})), "columns");
+    }
+
+    #[test]
+    fn typed_rows_round_trip() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Player {
+            id: u32,
+            name: String,
+            score: Option<f64>,
+        }
+        let players = vec![
+            Player {
+                id: 1,
+                name: "Ferris".to_string(),
+                score: Some(9.5),
+            },
+            Player {
+                id: 2,
+                name: "Corro".to_string(),
+                score: None,
+            },
+        ];
+        let result = QueryResult::from_rows(&players).unwrap();
+        assert!(result.column("score").unwrap().nullable);
+        assert_eq!(result.rows_as::<Player>().unwrap(), players);
+
+        let again = QueryResult::from_value(result.to_value()).unwrap();
+        assert_eq!(again, result);
+
+        let negative =
+            QueryResult::from_value(json!({"table": [{"id": -1, "name": "x"}]})).unwrap();
+        assert!(matches!(
+            negative.rows_as::<Player>(),
+            Err(QueryResultError::Deserialize { ref path, .. }) if path == "table[0]"
+        ));
+    }
+}
@@ -0,0 +1,225 @@
+/*
+ * Turning host names into IP addresses, the way the favorite websites of the hashmap lesson need it:
+ * Google is stored as an IP address, the others as domains only a resolver can turn into one.
+ *
+ * Resolver is the extension point, with four implementations that can be combined:
+ * - HostsFile (hosts.rs) answers from an /etc/hosts style file
+ * - DnsResolver (dns.rs) asks a DNS server over UDP, any server: StubDnsServer is a tiny one for the lessons
+ * - CachingResolver (cache.rs) wraps another resolver and remembers answers for as long as their TTL allows
+ * - SystemResolver asks the operating system, the same way every other program on the machine resolves names
+ * and Fallback asks a second resolver whatever the first one does not know, like hosts file first, then DNS.
+ *
+ * Resolvers are async, so a lookup waiting on the network does not hold up anything else on the runtime.
+ */
+pub mod cache;
+pub mod dns;
+pub mod hosts;
+
+pub use cache::CachingResolver;
+pub use dns::{DnsResolver, StubDnsServer};
+pub use hosts::HostsFile;
+
+use crate::url::Host;
+use std::{error::Error, fmt, future::Future, io, net::IpAddr, time::Duration};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resolved {
+    pub addresses: Vec<IpAddr>,
+    pub ttl: Duration, // How long the answer may be reused
+}
+
+#[derive(Debug)]
+pub enum ResolveError {
+    InvalidName { name: String, reason: String },
+    NotFound(String),
+    Timeout { name: String, after: Duration },
+    ServerFailure { name: String, code: u8 }, // The DNS response code, e.g. 2 for SERVFAIL
+    Malformed(String),                        // A DNS response we cannot make sense of
+    Io(io::Error),
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResolveError::InvalidName { name, reason } => {
+                write!(f, "{:?} is not a valid host name: {}", name, reason)
+            }
+            ResolveError::NotFound(name) => write!(f, "{} does not resolve to any address", name),
+            ResolveError::Timeout { name, after } => {
+                write!(f, "No answer for {} after {:?}", name, after)
+            }
+            ResolveError::ServerFailure { name, code } => {
+                write!(
+                    f,
+                    "The DNS server failed to resolve {} (code {})",
+                    name, code
+                )
+            }
+            ResolveError::Malformed(reason) => write!(f, "Malformed DNS response: {}", reason),
+            ResolveError::Io(_) => write!(f, "Failed to talk to the DNS server"),
+        }
+    }
+}
+
+impl Error for ResolveError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ResolveError::Io(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ResolveError {
+    fn from(error: io::Error) -> Self {
+        ResolveError::Io(error)
+    }
+}
+
+pub trait Resolver {
+    // `name` is a host name like "www.bing.com", case and a trailing dot do not matter
+    fn resolve(&self, name: &str) -> impl Future<Output = Result<Resolved, ResolveError>> + Send;
+}
+
+// Lowercase without the trailing dot, the form every resolver looks names up in
+pub fn normalize_name(name: &str) -> Result<String, ResolveError> {
+    let invalid = |reason: &str| ResolveError::InvalidName {
+        name: name.to_string(),
+        reason: reason.to_string(),
+    };
+    let normalized = name.strip_suffix('.').unwrap_or(name).to_ascii_lowercase();
+    if normalized.is_empty() || normalized.len() > 253 {
+        return Err(invalid("must be 1 to 253 characters long"));
+    }
+    if !normalized.is_ascii() {
+        return Err(invalid(
+            "internationalized names must be in punycode, see Url",
+        ));
+    }
+    if normalized
+        .split('.')
+        .any(|label| label.is_empty() || label.len() > 63)
+    {
+        return Err(invalid("every label must be 1 to 63 characters long"));
+    }
+    Ok(normalized)
+}
+
+// Asks `second` whatever `first` does not know. Any other error of `first` is returned as it is
+pub struct Fallback<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A, B> Fallback<A, B> {
+    pub fn new(first: A, second: B) -> Self {
+        Fallback { first, second }
+    }
+}
+
+impl<A: Resolver + Sync, B: Resolver + Sync> Resolver for Fallback<A, B> {
+    async fn resolve(&self, name: &str) -> Result<Resolved, ResolveError> {
+        match self.first.resolve(name).await {
+            Err(ResolveError::NotFound(_)) => self.second.resolve(name).await,
+            resolved => resolved,
+        }
+    }
+}
+
+// getaddrinfo() on a blocking thread: /etc/hosts, then the DNS servers of /etc/resolv.conf.
+// It does not tell the TTL, so its answers have a TTL of zero and CachingResolver does not keep them
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    async fn resolve(&self, name: &str) -> Result<Resolved, ResolveError> {
+        let name = normalize_name(name)?;
+        let mut addresses = Vec::new();
+        for address in tokio::net::lookup_host((name.as_str(), 0)).await? {
+            if !addresses.contains(&address.ip()) {
+                addresses.push(address.ip());
+            }
+        }
+        if addresses.is_empty() {
+            return Err(ResolveError::NotFound(name));
+        }
+        Ok(Resolved {
+            addresses,
+            ttl: Duration::ZERO,
+        })
+    }
+}
+
+// The addresses of a URL host: IP addresses as they are, domains through `resolver`
+pub async fn resolve_host<R: Resolver>(
+    resolver: &R,
+    host: &Host,
+) -> Result<Vec<IpAddr>, ResolveError> {
+    match host {
+        Host::Ipv4(ip) => Ok(vec![IpAddr::V4(*ip)]),
+        Host::Ipv6(ip) => Ok(vec![IpAddr::V6(*ip)]),
+        Host::Domain(domain) => Ok(resolver.resolve(domain).await?.addresses),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_normalized_and_checked() {
+        assert_eq!(normalize_name("WWW.Bing.com.").unwrap(), "www.bing.com");
+        let long_label = format!("{}.com", "a".repeat(64));
+        for invalid in ["", ".", "a..b", "bücher.de", long_label.as_str()] {
+            assert!(
+                matches!(
+                    normalize_name(invalid),
+                    Err(ResolveError::InvalidName { .. })
+                ),
+                "{invalid:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn fallback_only_asks_the_second_resolver_what_the_first_does_not_know() {
+        let first = HostsFile::parse("10.0.0.1 both.test first.test");
+        let second = HostsFile::parse("10.0.0.2 both.test second.test");
+        let resolver = Fallback::new(first, second);
+        assert_eq!(
+            first_address(&resolver, "both.test").await,
+            Some("10.0.0.1".parse().unwrap())
+        );
+        assert_eq!(
+            first_address(&resolver, "second.test").await,
+            Some("10.0.0.2".parse().unwrap())
+        );
+        assert_eq!(first_address(&resolver, "neither.test").await, None);
+        // Errors other than NotFound are not passed on
+        assert!(matches!(
+            resolver.resolve("a..b").await,
+            Err(ResolveError::InvalidName { .. })
+        ));
+    }
+
+    async fn first_address<R: Resolver>(resolver: &R, name: &str) -> Option<IpAddr> {
+        resolver
+            .resolve(name)
+            .await
+            .ok()?
+            .addresses
+            .first()
+            .copied()
+    }
+
+    #[tokio::test]
+    async fn ip_hosts_need_no_resolver() {
+        let nobody = HostsFile::default();
+        let host = Host::Ipv4("192.0.2.1".parse().unwrap());
+        assert_eq!(
+            resolve_host(&nobody, &host).await.unwrap(),
+            ["192.0.2.1".parse::<IpAddr>().unwrap()]
+        );
+        let domain = Host::Domain("example.test".to_string());
+        assert!(resolve_host(&nobody, &domain).await.is_err());
+    }
+}
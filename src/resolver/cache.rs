@@ -0,0 +1,180 @@
+use super::{normalize_name, ResolveError, Resolved, Resolver};
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Instant,
+};
+
+/*
+ * Remembers the answers of another resolver until their TTL runs out.
+ * A cached answer is returned with the TTL it has left, so a cache in front of this one does not keep it longer.
+ * Failures are not cached: a name that did not resolve is asked again next time.
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize, // Including expired ones not looked up since
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} hits, {} misses, {} entries",
+            self.hits, self.misses, self.entries
+        )
+    }
+}
+
+pub struct CachingResolver<R> {
+    inner: R,
+    // Only held while looking up or storing, never across an await
+    entries: Mutex<HashMap<String, (Resolved, Instant)>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<R> CachingResolver<R> {
+    pub fn new(inner: R) -> Self {
+        CachingResolver {
+            inner,
+            entries: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.entries.lock().expect("resolver cache poisoned").len(),
+        }
+    }
+
+    fn cached(&self, name: &str) -> Option<Resolved> {
+        let entries = self.entries.lock().expect("resolver cache poisoned");
+        let (resolved, expires) = entries.get(name)?;
+        let ttl = expires.checked_duration_since(Instant::now())?;
+        Some(Resolved {
+            addresses: resolved.addresses.clone(),
+            ttl,
+        })
+    }
+}
+
+impl<R: Resolver + Sync> Resolver for CachingResolver<R> {
+    async fn resolve(&self, name: &str) -> Result<Resolved, ResolveError> {
+        let name = normalize_name(name)?;
+        if let Some(resolved) = self.cached(&name) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(resolved);
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let resolved = self.inner.resolve(&name).await?;
+        if !resolved.ttl.is_zero() {
+            let expires = Instant::now() + resolved.ttl;
+            self.entries
+                .lock()
+                .expect("resolver cache poisoned")
+                .insert(name, (resolved.clone(), expires));
+        }
+        Ok(resolved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::{DnsResolver, StubDnsServer};
+    use crate::supervisor::CancellationToken;
+    use std::{
+        net::{IpAddr, SocketAddr},
+        sync::{atomic::AtomicUsize, Arc},
+        time::Duration,
+    };
+
+    async fn stub(
+        records: &[(&str, &str, u32)],
+    ) -> (SocketAddr, Arc<AtomicUsize>, CancellationToken) {
+        let mut server = StubDnsServer::bind("127.0.0.1:0").await.unwrap();
+        for &(name, address, ttl) in records {
+            server = server.record(name, address.parse::<IpAddr>().unwrap(), ttl);
+        }
+        server.start()
+    }
+
+    #[tokio::test]
+    async fn answers_are_reused_with_the_ttl_they_have_left() {
+        let (addr, queries, shutdown) = stub(&[("www.bing.com", "13.107.21.200", 300)]).await;
+        let resolver = CachingResolver::new(DnsResolver::new(addr));
+
+        let first = resolver.resolve("www.bing.com").await.unwrap();
+        assert_eq!(first.ttl, Duration::from_secs(300));
+        let asked = queries.load(Ordering::Relaxed);
+
+        let second = resolver.resolve("WWW.Bing.com.").await.unwrap();
+        assert_eq!(second.addresses, first.addresses);
+        assert!(second.ttl < first.ttl && second.ttl > Duration::from_secs(299));
+        assert_eq!(queries.load(Ordering::Relaxed), asked);
+        assert_eq!(
+            resolver.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 1,
+                entries: 1
+            }
+        );
+        shutdown.cancel();
+    }
+
+    #[tokio::test]
+    async fn answers_are_asked_again_once_their_ttl_ran_out() {
+        let (addr, queries, shutdown) = stub(&[("short.test", "10.0.0.1", 1)]).await;
+        let resolver = CachingResolver::new(DnsResolver::new(addr));
+
+        resolver.resolve("short.test").await.unwrap();
+        resolver.resolve("short.test").await.unwrap();
+        let asked = queries.load(Ordering::Relaxed);
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        resolver.resolve("short.test").await.unwrap();
+
+        assert!(queries.load(Ordering::Relaxed) > asked);
+        let stats = resolver.stats();
+        assert_eq!((stats.hits, stats.misses), (1, 2));
+        shutdown.cancel();
+    }
+
+    #[tokio::test]
+    async fn zero_ttls_and_failures_are_not_cached() {
+        let (addr, _, shutdown) = stub(&[("zero.test", "10.0.0.2", 0)]).await;
+        let resolver = CachingResolver::new(DnsResolver::new(addr));
+
+        for _ in 0..2 {
+            assert_eq!(
+                resolver.resolve("zero.test").await.unwrap().ttl,
+                Duration::ZERO
+            );
+            assert!(matches!(
+                resolver.resolve("missing.test").await,
+                Err(ResolveError::NotFound(_))
+            ));
+        }
+        assert_eq!(
+            resolver.stats(),
+            CacheStats {
+                hits: 0,
+                misses: 4,
+                entries: 0
+            }
+        );
+        shutdown.cancel();
+    }
+}
@@ -0,0 +1,610 @@
+use super::{normalize_name, ResolveError, Resolved, Resolver};
+use crate::binary::{ByteCursor, DecodeError, Endian};
+use crate::supervisor::CancellationToken;
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{
+    net::{ToSocketAddrs, UdpSocket},
+    time::Instant,
+};
+
+/*
+ * A DNS client over UDP (RFC 1035), and a stub server to point it at.
+ *
+ * A lookup sends two questions at once, one for IPv4 (A) and one for IPv6 (AAAA) addresses, and takes every
+ * address record of the answers. The TTL of the result is the smallest TTL among them. A question that gets
+ * no answer within the timeout is sent again, up to `attempts` times in total.
+ *
+ * Not supported: truncated answers (the server would want us to ask again over TCP), and following CNAME
+ * records ourselves. Recursive servers answer with the addresses behind a CNAME anyway.
+ *
+ * Every message is a 12 byte header (id, flags, the number of questions and records), the questions, then
+ * the records. Names are length-prefixed labels: www.bing.com is 3 www 4 bing 3 com 0. Servers may shorten
+ * a name they already wrote by pointing back at it (two bytes starting with 0b11), which the parser follows.
+ * All numbers are big-endian.
+ */
+
+const A: u16 = 1;
+const AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+
+const QR: u16 = 0x8000; // Set in responses
+const TC: u16 = 0x0200; // Truncated
+const RD: u16 = 0x0100; // Recursion desired
+const RA: u16 = 0x0080; // Recursion available
+const NXDOMAIN: u16 = 3;
+
+impl From<DecodeError> for ResolveError {
+    fn from(_: DecodeError) -> Self {
+        ResolveError::Malformed("the message is cut short".to_string())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Record {
+    name: String,
+    record_type: u16,
+    ttl: u32,
+    data: Vec<u8>,
+}
+
+// The parts of a message both sides need, the authority and additional sections are skipped
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Message {
+    id: u16,
+    flags: u16,
+    questions: Vec<(String, u16)>, // Name and record type
+    answers: Vec<Record>,
+}
+
+impl Message {
+    // Names are written in full, without pointers
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(512);
+        for n in [
+            self.id,
+            self.flags,
+            self.questions.len() as u16,
+            self.answers.len() as u16,
+            0,
+            0,
+        ] {
+            bytes.extend_from_slice(&n.to_be_bytes());
+        }
+        for (name, record_type) in &self.questions {
+            write_name(name, &mut bytes);
+            bytes.extend_from_slice(&record_type.to_be_bytes());
+            bytes.extend_from_slice(&CLASS_IN.to_be_bytes());
+        }
+        for record in &self.answers {
+            write_name(&record.name, &mut bytes);
+            bytes.extend_from_slice(&record.record_type.to_be_bytes());
+            bytes.extend_from_slice(&CLASS_IN.to_be_bytes());
+            bytes.extend_from_slice(&record.ttl.to_be_bytes());
+            bytes.extend_from_slice(&(record.data.len() as u16).to_be_bytes());
+            bytes.extend_from_slice(&record.data);
+        }
+        bytes
+    }
+
+    fn parse(message: &[u8]) -> Result<Message, ResolveError> {
+        let mut cursor = ByteCursor::new(message, Endian::Big);
+        let id: u16 = cursor.read()?;
+        let flags: u16 = cursor.read()?;
+        let question_count: u16 = cursor.read()?;
+        let answer_count: u16 = cursor.read()?;
+        cursor.skip(4)?; // Authority and additional record counts
+
+        let mut questions = Vec::new();
+        for _ in 0..question_count {
+            let name = read_name(message, &mut cursor)?;
+            let record_type: u16 = cursor.read()?;
+            cursor.skip(2)?; // Class
+            questions.push((name, record_type));
+        }
+        let mut answers = Vec::new();
+        for _ in 0..answer_count {
+            let name = read_name(message, &mut cursor)?;
+            let record_type: u16 = cursor.read()?;
+            cursor.skip(2)?;
+            let ttl: u32 = cursor.read()?;
+            let length: u16 = cursor.read()?;
+            let data = cursor.read_bytes(length as usize)?.to_vec();
+            answers.push(Record {
+                name,
+                record_type,
+                ttl,
+                data,
+            });
+        }
+        Ok(Message {
+            id,
+            flags,
+            questions,
+            answers,
+        })
+    }
+}
+
+fn write_name(name: &str, bytes: &mut Vec<u8>) {
+    for label in name.split('.').filter(|label| !label.is_empty()) {
+        bytes.push(label.len() as u8);
+        bytes.extend_from_slice(label.as_bytes());
+    }
+    bytes.push(0);
+}
+
+// Reads the name at the cursor, following pointers into the rest of `message`
+fn read_name(message: &[u8], cursor: &mut ByteCursor) -> Result<String, ResolveError> {
+    let malformed = |reason: &str| ResolveError::Malformed(reason.to_string());
+    let mut labels = Vec::new();
+    let mut position = cursor.position();
+    let mut end = None; // Where the name ends in the message, i.e. after the first pointer
+    let mut jumps = 0;
+
+    loop {
+        let length = *message
+            .get(position)
+            .ok_or_else(|| malformed("a name runs past the end"))?;
+        match length & 0xC0 {
+            0x00 if length == 0 => {
+                position += 1;
+                break;
+            }
+            0x00 => {
+                let label = message
+                    .get(position + 1..position + 1 + length as usize)
+                    .ok_or_else(|| malformed("a label runs past the end"))?;
+                labels.push(String::from_utf8_lossy(label).to_ascii_lowercase());
+                position += 1 + length as usize;
+            }
+            0xC0 => {
+                let low = *message
+                    .get(position + 1)
+                    .ok_or_else(|| malformed("a pointer runs past the end"))?;
+                end.get_or_insert(position + 2);
+                jumps += 1;
+                if jumps > 32 {
+                    return Err(malformed("a name pointer loop"));
+                }
+                position = ((length as usize & 0x3F) << 8) | low as usize;
+            }
+            _ => return Err(malformed("an unknown label type")),
+        }
+    }
+    cursor.skip(end.unwrap_or(position) - cursor.position())?;
+    Ok(labels.join("."))
+}
+
+fn address(record: &Record) -> Option<IpAddr> {
+    match (record.record_type, record.data.len()) {
+        (A, 4) => Some(IpAddr::V4(Ipv4Addr::from(
+            <[u8; 4]>::try_from(record.data.as_slice()).ok()?,
+        ))),
+        (AAAA, 16) => Some(IpAddr::V6(Ipv6Addr::from(
+            <[u8; 16]>::try_from(record.data.as_slice()).ok()?,
+        ))),
+        _ => None,
+    }
+}
+
+pub struct DnsResolver {
+    server: SocketAddr,
+    timeout: Duration,
+    attempts: u32,
+}
+
+impl DnsResolver {
+    pub fn new(server: SocketAddr) -> Self {
+        DnsResolver {
+            server,
+            timeout: Duration::from_secs(2),
+            attempts: 2,
+        }
+    }
+
+    // How long to wait for each attempt
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn attempts(mut self, attempts: u32) -> Self {
+        self.attempts = attempts.max(1);
+        self
+    }
+
+    // The addresses and TTLs of one record type, empty if the name does not exist
+    async fn query(
+        &self,
+        name: &str,
+        record_type: u16,
+    ) -> Result<Vec<(IpAddr, u32)>, ResolveError> {
+        let id: u16 = rand::random();
+        let request = Message {
+            id,
+            flags: RD,
+            questions: vec![(name.to_string(), record_type)],
+            answers: Vec::new(),
+        }
+        .encode();
+        let local = match self.server {
+            SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+            SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+        };
+        let socket = UdpSocket::bind(local).await?;
+        socket.connect(self.server).await?;
+        let mut buffer = [0u8; 512]; // The most a DNS server sends over UDP without EDNS
+
+        for _ in 0..self.attempts {
+            socket.send(&request).await?;
+            let deadline = Instant::now() + self.timeout;
+            while let Ok(received) =
+                tokio::time::timeout_at(deadline, socket.recv(&mut buffer)).await
+            {
+                // A late answer to an earlier attempt has the same id, anything else is not for us
+                let Ok(response) = Message::parse(&buffer[..received?]) else {
+                    continue;
+                };
+                if response.id != id || response.flags & QR == 0 {
+                    continue;
+                }
+                if response.flags & TC != 0 {
+                    return Err(ResolveError::Malformed(
+                        "the answer is truncated, and asking again over TCP is not supported"
+                            .to_string(),
+                    ));
+                }
+                return match response.flags & 0x000F {
+                    0 => Ok(response
+                        .answers
+                        .iter()
+                        .filter_map(|record| Some((address(record)?, record.ttl)))
+                        .collect()),
+                    NXDOMAIN => Ok(Vec::new()),
+                    code => Err(ResolveError::ServerFailure {
+                        name: name.to_string(),
+                        code: code as u8,
+                    }),
+                };
+            }
+        }
+        Err(ResolveError::Timeout {
+            name: name.to_string(),
+            after: self.timeout * self.attempts,
+        })
+    }
+}
+
+impl Resolver for DnsResolver {
+    async fn resolve(&self, name: &str) -> Result<Resolved, ResolveError> {
+        let name = normalize_name(name)?;
+        let (v4, v6) = tokio::join!(self.query(&name, A), self.query(&name, AAAA));
+        // Middleboxes that drop or fail AAAA queries are common, so one family answering is enough.
+        // A failure only counts when the other family has no addresses to offer either
+        let records = match (v4, v6) {
+            (Ok(mut v4), Ok(v6)) => {
+                v4.extend(v6);
+                v4
+            }
+            (Ok(records), Err(e)) | (Err(e), Ok(records)) if records.is_empty() => return Err(e),
+            (Ok(records), Err(_)) | (Err(_), Ok(records)) => records,
+            (Err(e), Err(_)) => return Err(e),
+        };
+
+        let ttl = records.iter().map(|&(_, ttl)| ttl).min();
+        match ttl {
+            Some(ttl) => Ok(Resolved {
+                addresses: records.into_iter().map(|(address, _)| address).collect(),
+                ttl: Duration::from_secs(ttl.into()),
+            }),
+            None => Err(ResolveError::NotFound(name)),
+        }
+    }
+}
+
+/*
+ * A DNS server that only knows the records it was given, for lessons and tests that must not depend on
+ * the network. Unknown names are answered with NXDOMAIN, packets that are not a DNS query are ignored.
+ */
+pub struct StubDnsServer {
+    socket: UdpSocket,
+    records: HashMap<String, Vec<(IpAddr, u32)>>,
+    queries: Arc<AtomicUsize>,
+}
+
+impl StubDnsServer {
+    // Port 0 lets the OS pick a free port, local_addr() tells which one
+    pub async fn bind(addr: impl ToSocketAddrs) -> io::Result<StubDnsServer> {
+        Ok(StubDnsServer {
+            socket: UdpSocket::bind(addr).await?,
+            records: HashMap::new(),
+            queries: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+
+    pub fn record(mut self, name: &str, address: IpAddr, ttl: u32) -> Self {
+        self.records
+            .entry(name.to_ascii_lowercase())
+            .or_default()
+            .push((address, ttl));
+        self
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    // Counts the questions answered, and can still be read while the server runs
+    pub fn queries(&self) -> Arc<AtomicUsize> {
+        Arc::clone(&self.queries)
+    }
+
+    pub async fn run(self, shutdown: CancellationToken) -> io::Result<()> {
+        let mut buffer = [0u8; 512];
+        loop {
+            let (received, from) = tokio::select! {
+                received = self.socket.recv_from(&mut buffer) => received?,
+                _ = shutdown.cancelled() => return Ok(()),
+            };
+            let Ok(query) = Message::parse(&buffer[..received]) else {
+                continue;
+            };
+            if query.flags & QR != 0 || query.questions.is_empty() {
+                continue;
+            }
+            self.queries.fetch_add(1, Ordering::Relaxed);
+            self.socket
+                .send_to(&self.answer(query).encode(), from)
+                .await?;
+        }
+    }
+
+    fn answer(&self, query: Message) -> Message {
+        let mut flags = QR | RA | (query.flags & RD);
+        let mut answers = Vec::new();
+        for (name, record_type) in &query.questions {
+            let Some(records) = self.records.get(name) else {
+                flags |= NXDOMAIN;
+                continue;
+            };
+            for &(address, ttl) in records {
+                let data = match (address, *record_type) {
+                    (IpAddr::V4(ip), A) => ip.octets().to_vec(),
+                    (IpAddr::V6(ip), AAAA) => ip.octets().to_vec(),
+                    _ => continue,
+                };
+                answers.push(Record {
+                    name: name.clone(),
+                    record_type: *record_type,
+                    ttl,
+                    data,
+                });
+            }
+        }
+        Message {
+            id: query.id,
+            flags,
+            questions: query.questions,
+            answers,
+        }
+    }
+}
+
+#[cfg(test)]
+impl StubDnsServer {
+    // Runs the server until the returned token is cancelled. Shared by the resolver tests
+    pub fn start(self) -> (SocketAddr, Arc<AtomicUsize>, CancellationToken) {
+        let addr = self.local_addr().expect("a bound socket has an address");
+        let queries = self.queries();
+        let shutdown = CancellationToken::new();
+        tokio::spawn(self.run(shutdown.clone()));
+        (addr, queries, shutdown)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(text: &str) -> IpAddr {
+        text.parse().unwrap()
+    }
+
+    // Answers A queries with `a` (no address when None), and AAAA queries with the response code `aaaa`, or not at all
+    async fn half_broken(a: Option<[u8; 4]>, aaaa: Option<u16>) -> SocketAddr {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buffer = [0u8; 512];
+            while let Ok((received, from)) = server.recv_from(&mut buffer).await {
+                let query = Message::parse(&buffer[..received]).unwrap();
+                let (flags, answers) = match (query.questions[0].1, aaaa) {
+                    (A, _) => (
+                        QR,
+                        a.iter()
+                            .map(|address| Record {
+                                name: query.questions[0].0.clone(),
+                                record_type: A,
+                                ttl: 30,
+                                data: address.to_vec(),
+                            })
+                            .collect(),
+                    ),
+                    (_, Some(code)) => (QR | code, Vec::new()),
+                    (_, None) => continue,
+                };
+                let answer = Message {
+                    id: query.id,
+                    flags,
+                    questions: query.questions,
+                    answers,
+                };
+                server.send_to(&answer.encode(), from).await.unwrap();
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let message = Message {
+            id: 7,
+            flags: QR | RD,
+            questions: vec![("www.bing.com".to_string(), A)],
+            answers: vec![Record {
+                name: "www.bing.com".to_string(),
+                record_type: A,
+                ttl: 60,
+                data: vec![13, 107, 21, 200],
+            }],
+        };
+        assert_eq!(Message::parse(&message.encode()).unwrap(), message);
+        assert!(Message::parse(&message.encode()[..20]).is_err());
+    }
+
+    #[test]
+    fn names_follow_pointers_and_reject_loops() {
+        let mut bytes = Message {
+            id: 1,
+            flags: QR,
+            questions: vec![("Bing.com".to_string(), A)],
+            answers: Vec::new(),
+        }
+        .encode();
+        bytes[7] = 1; // One answer, named by a pointer to "www" followed by a pointer to the question at 12
+        let answer_at = bytes.len();
+        bytes.extend_from_slice(&[3, b'w', b'w', b'w', 0xC0, 12]);
+        bytes.extend_from_slice(&[0, 1, 0, 1, 0, 0, 0, 9, 0, 4, 1, 2, 3, 4]);
+        let message = Message::parse(&bytes).unwrap();
+        assert_eq!(message.questions, vec![("bing.com".to_string(), A)]);
+        assert_eq!(message.answers[0].name, "www.bing.com");
+        assert_eq!(address(&message.answers[0]), Some(ip("1.2.3.4")));
+
+        // A pointer to itself
+        bytes[answer_at + 4..answer_at + 6].copy_from_slice(&[0xC0, answer_at as u8 + 4]);
+        assert!(matches!(
+            Message::parse(&bytes),
+            Err(ResolveError::Malformed(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn resolves_both_address_families_with_the_smallest_ttl() {
+        let server = StubDnsServer::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .record("chat.openai.com", ip("104.18.33.45"), 300)
+            .record("chat.openai.com", ip("2606:4700:4400::6812:212d"), 60);
+        let (addr, queries, shutdown) = server.start();
+
+        let resolved = DnsResolver::new(addr)
+            .resolve("Chat.OpenAI.com.")
+            .await
+            .unwrap();
+        assert_eq!(
+            resolved.addresses,
+            [ip("104.18.33.45"), ip("2606:4700:4400::6812:212d")]
+        );
+        assert_eq!(resolved.ttl, Duration::from_secs(60));
+        assert_eq!(queries.load(Ordering::Relaxed), 2); // A and AAAA
+        shutdown.cancel();
+    }
+
+    #[tokio::test]
+    async fn unknown_names_are_not_found() {
+        let server = StubDnsServer::bind("127.0.0.1:0").await.unwrap();
+        let (addr, _, shutdown) = server.start();
+        let err = DnsResolver::new(addr)
+            .resolve("nowhere.test")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ResolveError::NotFound(ref name) if name == "nowhere.test"));
+        shutdown.cancel();
+    }
+
+    #[tokio::test]
+    async fn garbage_and_foreign_datagrams_are_skipped() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buffer = [0u8; 512];
+            for _ in 0..2 {
+                let (received, from) = server.recv_from(&mut buffer).await.unwrap();
+                let query = Message::parse(&buffer[..received]).unwrap();
+                server.send_to(b"garbage", from).await.unwrap();
+                let foreign = Message {
+                    id: query.id.wrapping_add(1),
+                    flags: QR,
+                    questions: query.questions.clone(),
+                    answers: Vec::new(),
+                };
+                server.send_to(&foreign.encode(), from).await.unwrap();
+                let answers = match query.questions[0].1 {
+                    A => vec![Record {
+                        name: query.questions[0].0.clone(),
+                        record_type: A,
+                        ttl: 5,
+                        data: vec![10, 0, 0, 1],
+                    }],
+                    _ => Vec::new(),
+                };
+                let answer = Message {
+                    id: query.id,
+                    flags: QR,
+                    questions: query.questions,
+                    answers,
+                };
+                server.send_to(&answer.encode(), from).await.unwrap();
+            }
+        });
+
+        let resolved = DnsResolver::new(addr).resolve("host.test").await.unwrap();
+        assert_eq!(resolved.addresses, [ip("10.0.0.1")]);
+        assert_eq!(resolved.ttl, Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn one_family_answering_is_enough() {
+        const SERVFAIL: u16 = 2;
+        for aaaa in [Some(SERVFAIL), None] {
+            let resolver = DnsResolver::new(half_broken(Some([10, 0, 0, 1]), aaaa).await)
+                .timeout(Duration::from_millis(50))
+                .attempts(1);
+            let resolved = resolver.resolve("host.test").await.unwrap();
+            assert_eq!(resolved.addresses, [ip("10.0.0.1")], "{aaaa:?}");
+            assert_eq!(resolved.ttl, Duration::from_secs(30));
+        }
+    }
+
+    #[tokio::test]
+    async fn a_failure_counts_when_the_other_family_has_no_addresses() {
+        // No IPv4 address, and the AAAA query gets no answer: the timeout is what went wrong, not the name
+        let addr = half_broken(None, None).await;
+        let resolver = DnsResolver::new(addr)
+            .timeout(Duration::from_millis(50))
+            .attempts(1);
+        let err = resolver.resolve("host.test").await.unwrap_err();
+        assert!(matches!(err, ResolveError::Timeout { .. }), "{err:?}");
+    }
+
+    #[tokio::test]
+    async fn a_silent_server_times_out_after_every_attempt() {
+        let silent = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let resolver = DnsResolver::new(silent.local_addr().unwrap())
+            .timeout(Duration::from_millis(50))
+            .attempts(2);
+        let err = resolver.resolve("host.test").await.unwrap_err();
+        assert!(
+            matches!(err, ResolveError::Timeout { after, .. } if after == Duration::from_millis(100))
+        );
+    }
+}
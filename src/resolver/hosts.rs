@@ -0,0 +1,99 @@
+use super::{normalize_name, ResolveError, Resolved, Resolver};
+use std::{collections::HashMap, net::IpAddr, path::Path, time::Duration};
+
+/*
+ * The hosts file format of /etc/hosts: an IP address, then the names that resolve to it.
+ *
+ *   127.0.0.1   localhost
+ *   ::1         localhost ip6-localhost   # comments start with '#'
+ *
+ * A name listed on several lines resolves to all of their addresses, in the order of the file.
+ * Lines that do not start with an IP address are skipped, like the C library does.
+ */
+
+#[derive(Debug, Clone, Default)]
+pub struct HostsFile {
+    entries: HashMap<String, Vec<IpAddr>>,
+}
+
+impl HostsFile {
+    // Hosts files are read again by whoever needs them, so caching an answer for a minute is plenty
+    pub const TTL: Duration = Duration::from_secs(60);
+
+    pub fn parse(text: &str) -> HostsFile {
+        let mut hosts = HostsFile::default();
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut fields = line.split_whitespace();
+            let Some(Ok(ip)) = fields.next().map(str::parse::<IpAddr>) else {
+                continue;
+            };
+            for name in fields {
+                if let Ok(name) = normalize_name(name) {
+                    let addresses = hosts.entries.entry(name).or_default();
+                    if !addresses.contains(&ip) {
+                        addresses.push(ip);
+                    }
+                }
+            }
+        }
+        hosts
+    }
+
+    pub async fn load(path: &Path) -> Result<HostsFile, ResolveError> {
+        Ok(HostsFile::parse(&tokio::fs::read_to_string(path).await?))
+    }
+
+    pub async fn system() -> Result<HostsFile, ResolveError> {
+        HostsFile::load(Path::new("/etc/hosts")).await
+    }
+
+    // Number of names
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+impl Resolver for HostsFile {
+    async fn resolve(&self, name: &str) -> Result<Resolved, ResolveError> {
+        let name = normalize_name(name)?;
+        match self.entries.get(&name) {
+            Some(addresses) => Ok(Resolved {
+                addresses: addresses.clone(),
+                ttl: HostsFile::TTL,
+            }),
+            None => Err(ResolveError::NotFound(name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn names_resolve_to_every_address_in_file_order() {
+        let hosts = HostsFile::parse(
+            "# comment line\n\
+             127.0.0.1  localhost  Dev.Test.\n\
+             ::1        localhost  # IPv6 as well\n\
+             not-an-ip  skipped\n\
+             127.0.0.1  localhost\n",
+        );
+        assert_eq!(hosts.len(), 2);
+        let resolved = hosts.resolve("LOCALHOST").await.unwrap();
+        assert_eq!(
+            resolved.addresses,
+            [
+                "127.0.0.1".parse::<IpAddr>().unwrap(),
+                "::1".parse().unwrap()
+            ]
+        );
+        assert_eq!(resolved.ttl, HostsFile::TTL);
+        assert!(hosts.resolve("dev.test").await.is_ok());
+        assert!(matches!(
+            hosts.resolve("skipped").await,
+            Err(ResolveError::NotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,586 @@
+use std::{
+    any::Any,
+    error::Error,
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tokio::{sync::Notify, time::Instant};
+
+/*
+ * A supervisor for blocking work handed to spawn_blocking.
+ *
+ * `spawn_blocking(..).await.unwrap()` waits forever and turns a panic in the job into a panic of the caller.
+ * The supervisor adds what that is missing:
+ *
+ * - a deadline per attempt: the caller stops waiting and gets TaskError::TimedOut
+ * - cooperative cancellation: a blocking thread cannot be killed, so every job gets a CancellationToken
+ *   and is expected to check it now and then. The token is cancelled on timeout and by cancel_all()
+ * - panic capture: a panicking job becomes TaskError::Panicked with the panic message
+ * - retries: a RetryPolicy decides how often a failed attempt is repeated and how long to back off in between
+ * - a report of how every task ended
+ */
+
+// Returned by CancellationToken::check(), so a job can bail out with `token.check()?`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cancelled")
+    }
+}
+
+impl Error for Cancelled {}
+
+// Clones share the same flag: cancelling one cancels all of them
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    notify: Arc<Notify>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.notify.notify_waiters();
+    }
+
+    // For blocking code, which cannot await
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.is_cancelled() {
+            Err(Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    // For async code: resolves once cancel() has been called
+    pub async fn cancelled(&self) {
+        let notified = self.notify.notified();
+        tokio::pin!(notified);
+        // Register as a waiter before checking the flag, so a cancel() in between is not missed
+        notified.as_mut().enable();
+        if self.is_cancelled() {
+            return;
+        }
+        notified.await;
+    }
+}
+
+pub type JobError = Box<dyn Error + Send + Sync>;
+
+#[derive(Debug)]
+pub enum TaskError {
+    TimedOut { task: String, after: Duration },
+    Cancelled { task: String },
+    Panicked { task: String, message: String },
+    Failed { task: String, source: JobError },
+}
+
+impl TaskError {
+    pub fn task(&self) -> &str {
+        match self {
+            TaskError::TimedOut { task, .. }
+            | TaskError::Cancelled { task }
+            | TaskError::Panicked { task, .. }
+            | TaskError::Failed { task, .. } => task,
+        }
+    }
+}
+
+impl fmt::Display for TaskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TaskError::TimedOut { task, after } => {
+                write!(f, "Task {} timed out after {:?}", task, after)
+            }
+            TaskError::Cancelled { task } => write!(f, "Task {} was cancelled", task),
+            TaskError::Panicked { task, message } => {
+                write!(f, "Task {} panicked: {}", task, message)
+            }
+            TaskError::Failed { task, .. } => write!(f, "Task {} failed", task),
+        }
+    }
+}
+
+impl Error for TaskError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TaskError::Failed { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub backoff: Duration,    // Wait before the second attempt
+    pub multiplier: f64,      // The wait grows by this factor after every further attempt
+    pub retry_timeouts: bool, // A job that timed out once will often time out again
+}
+
+impl RetryPolicy {
+    pub fn never() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            backoff: Duration::ZERO,
+            multiplier: 1.0,
+            retry_timeouts: false,
+        }
+    }
+
+    pub fn attempts(max_attempts: u32) -> Self {
+        RetryPolicy {
+            max_attempts: max_attempts.max(1),
+            ..RetryPolicy::never()
+        }
+    }
+
+    // A multiplier below 1 makes the waits shrink, 0 waits only before the second attempt.
+    // A negative or NaN multiplier counts as 0, an infinite one saturates like any other large wait
+    pub fn backoff(mut self, backoff: Duration, multiplier: f64) -> Self {
+        self.backoff = backoff;
+        self.multiplier = multiplier.max(0.0);
+        self
+    }
+
+    pub fn retry_timeouts(mut self) -> Self {
+        self.retry_timeouts = true;
+        self
+    }
+
+    // Cancellation is a decision of the caller, retrying it would defeat the point
+    fn should_retry(&self, error: &TaskError) -> bool {
+        match error {
+            TaskError::Cancelled { .. } => false,
+            TaskError::TimedOut { .. } => self.retry_timeouts,
+            TaskError::Panicked { .. } | TaskError::Failed { .. } => true,
+        }
+    }
+
+    // Saturates at Duration::MAX instead of overflowing, which tokio sleeps as "practically forever"
+    fn delay_before(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(2).min(i32::MAX as u32) as i32;
+        // max() also turns the NaN of a multiplier set through the public field into no wait at all
+        let seconds = (self.backoff.as_secs_f64() * self.multiplier.powi(exponent)).max(0.0);
+        Duration::try_from_secs_f64(seconds).unwrap_or(Duration::MAX)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Succeeded,
+    TimedOut,
+    Cancelled,
+    Panicked,
+    Failed,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self {
+            Status::Succeeded => "succeeded",
+            Status::TimedOut => "timed out",
+            Status::Cancelled => "cancelled",
+            Status::Panicked => "panicked",
+            Status::Failed => "failed",
+        };
+        // pad() instead of write!() so the report can align it with {:<9}
+        f.pad(status)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TaskOutcome {
+    pub task: String,
+    pub status: Status,
+    pub attempts: u32,
+    pub elapsed: Duration,
+    pub error: Option<String>, // Why the last attempt did not succeed
+}
+
+#[derive(Default)]
+pub struct Supervisor {
+    token: CancellationToken,
+    outcomes: Arc<Mutex<Vec<TaskOutcome>>>,
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        Supervisor::default()
+    }
+
+    // Cancels every running task and makes tasks started later fail right away
+    pub fn cancel_all(&self) {
+        self.token.cancel();
+    }
+
+    pub fn task<T, F>(&self, name: &str, job: F) -> Task<'_, F>
+    where
+        T: Send + 'static,
+        F: Fn(&CancellationToken) -> Result<T, JobError> + Send + Sync + 'static,
+    {
+        Task {
+            supervisor: self,
+            name: name.to_string(),
+            job: Arc::new(job),
+            deadline: None,
+            retry: RetryPolicy::never(),
+        }
+    }
+
+    pub fn report(&self) -> SupervisorReport {
+        SupervisorReport {
+            outcomes: self.outcomes.lock().expect("outcomes poisoned").clone(),
+        }
+    }
+
+    fn record(&self, outcome: TaskOutcome) {
+        self.outcomes
+            .lock()
+            .expect("outcomes poisoned")
+            .push(outcome);
+    }
+}
+
+pub struct Task<'a, F> {
+    supervisor: &'a Supervisor,
+    name: String,
+    job: Arc<F>, // Shared with every attempt, which is why the job is a Fn and not a FnOnce
+    deadline: Option<Duration>,
+    retry: RetryPolicy,
+}
+
+impl<F> Task<'_, F> {
+    // Applies to every attempt separately
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub async fn run<T>(self) -> Result<T, TaskError>
+    where
+        T: Send + 'static,
+        F: Fn(&CancellationToken) -> Result<T, JobError> + Send + Sync + 'static,
+    {
+        let started_at = Instant::now();
+        let mut attempt = 0;
+        let result = loop {
+            attempt += 1;
+            let error = match self.attempt().await {
+                Ok(value) => break Ok(value),
+                Err(error) => error,
+            };
+            if attempt >= self.retry.max_attempts || !self.retry.should_retry(&error) {
+                break Err(error);
+            }
+
+            // Back off, unless the supervisor is cancelled in the meantime
+            tokio::select! {
+                _ = tokio::time::sleep(self.retry.delay_before(attempt + 1)) => {}
+                _ = self.supervisor.token.cancelled() => {
+                    break Err(TaskError::Cancelled { task: self.name.clone() });
+                }
+            }
+        };
+
+        let (status, error) = match &result {
+            Ok(_) => (Status::Succeeded, None),
+            Err(error) => (
+                match error {
+                    TaskError::TimedOut { .. } => Status::TimedOut,
+                    TaskError::Cancelled { .. } => Status::Cancelled,
+                    TaskError::Panicked { .. } => Status::Panicked,
+                    TaskError::Failed { .. } => Status::Failed,
+                },
+                Some(match error.source() {
+                    Some(source) => format!("{error}: {source}"),
+                    None => error.to_string(),
+                }),
+            ),
+        };
+        self.supervisor.record(TaskOutcome {
+            task: self.name.clone(),
+            status,
+            attempts: attempt,
+            elapsed: started_at.elapsed(),
+            error,
+        });
+        result
+    }
+
+    async fn attempt<T>(&self) -> Result<T, TaskError>
+    where
+        T: Send + 'static,
+        F: Fn(&CancellationToken) -> Result<T, JobError> + Send + Sync + 'static,
+    {
+        let supervisor_token = &self.supervisor.token;
+        if supervisor_token.is_cancelled() {
+            return Err(TaskError::Cancelled {
+                task: self.name.clone(),
+            });
+        }
+
+        // Each attempt gets its own token, so cancelling a timed out attempt does not affect the next one
+        let token = CancellationToken::new();
+        let job = Arc::clone(&self.job);
+        let job_token = token.clone();
+        let handle = tokio::task::spawn_blocking(move || job(&job_token));
+
+        let deadline = async {
+            match self.deadline {
+                Some(deadline) => tokio::time::sleep(deadline).await,
+                None => std::future::pending().await,
+            }
+        };
+        let task = self.name.clone();
+        tokio::select! {
+            joined = handle => match joined {
+                Ok(Ok(value)) => Ok(value),
+                // A job that noticed the cancellation reports it as an error, keep it a cancellation
+                Ok(Err(source)) if source.is::<Cancelled>() => Err(TaskError::Cancelled { task }),
+                Ok(Err(source)) => Err(TaskError::Failed { task, source }),
+                Err(e) if e.is_panic() => Err(TaskError::Panicked {
+                    task,
+                    message: panic_message(e.into_panic()),
+                }),
+                Err(_) => Err(TaskError::Cancelled { task }),
+            },
+            // The thread keeps running until the job checks its token, we just stop waiting for it
+            _ = deadline => {
+                token.cancel();
+                Err(TaskError::TimedOut {
+                    task,
+                    after: self.deadline.unwrap_or_default(),
+                })
+            }
+            _ = supervisor_token.cancelled() => {
+                token.cancel();
+                Err(TaskError::Cancelled { task })
+            }
+        }
+    }
+}
+
+// panic!("...") carries a &str, panic!("{}", x) a String, anything else is opaque
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "unknown panic payload".to_string(),
+        },
+    }
+}
+
+pub struct SupervisorReport {
+    pub outcomes: Vec<TaskOutcome>,
+}
+
+impl SupervisorReport {
+    pub fn failed(&self) -> impl Iterator<Item = &TaskOutcome> {
+        self.outcomes
+            .iter()
+            .filter(|outcome| outcome.status != Status::Succeeded)
+    }
+}
+
+impl fmt::Display for SupervisorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .outcomes
+            .iter()
+            .map(|outcome| outcome.task.len())
+            .max()
+            .unwrap_or(0);
+        for outcome in &self.outcomes {
+            write!(
+                f,
+                "  {:<width$}  {:<9}  {} attempt(s) in {:>8.1?}",
+                outcome.task,
+                outcome.status,
+                outcome.attempts,
+                outcome.elapsed,
+                width = width
+            )?;
+            match &outcome.error {
+                Some(error) => writeln!(f, "  last error: {}", error)?,
+                None => writeln!(f)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicU32;
+
+    #[test]
+    fn backoff_grows_by_the_multiplier_and_saturates() {
+        let retry = RetryPolicy::attempts(5).backoff(Duration::from_millis(100), 2.0);
+        let delays: Vec<Duration> = (2..=4).map(|attempt| retry.delay_before(attempt)).collect();
+        assert_eq!(delays, [100, 200, 400].map(Duration::from_millis).to_vec());
+        assert_eq!(retry.delay_before(u32::MAX), Duration::MAX);
+
+        let huge = RetryPolicy::attempts(2).backoff(Duration::MAX, 1e300);
+        assert_eq!(huge.delay_before(3), Duration::MAX);
+
+        let mut nan = RetryPolicy::attempts(2);
+        nan.multiplier = f64::NAN;
+        assert_eq!(nan.delay_before(3), Duration::ZERO);
+    }
+
+    #[test]
+    fn negative_and_nan_multipliers_are_clamped_to_zero() {
+        for multiplier in [-1.0, f64::NEG_INFINITY, f64::NAN] {
+            let retry = RetryPolicy::attempts(4).backoff(Duration::from_millis(10), multiplier);
+            assert_eq!(retry.multiplier, 0.0, "{multiplier}");
+            assert_eq!(retry.delay_before(2), Duration::from_millis(10));
+            assert_eq!(retry.delay_before(3), Duration::ZERO);
+        }
+        let infinite = RetryPolicy::attempts(4).backoff(Duration::from_millis(10), f64::INFINITY);
+        assert_eq!(infinite.delay_before(2), Duration::from_millis(10));
+        assert_eq!(infinite.delay_before(3), Duration::MAX);
+    }
+
+    #[tokio::test]
+    async fn failed_attempts_are_retried() {
+        let supervisor = Supervisor::new();
+        let calls = Arc::new(AtomicU32::new(0));
+        let counter = Arc::clone(&calls);
+        let value = supervisor
+            .task("flaky", move |_| {
+                match counter.fetch_add(1, Ordering::SeqCst) {
+                    0 | 1 => Err("not yet".into()),
+                    _ => Ok(42),
+                }
+            })
+            .retry(RetryPolicy::attempts(3).backoff(Duration::from_millis(1), 2.0))
+            .run()
+            .await
+            .unwrap();
+        assert_eq!(value, 42);
+        let outcome = &supervisor.report().outcomes[0];
+        assert_eq!((outcome.status, outcome.attempts), (Status::Succeeded, 3));
+    }
+
+    #[tokio::test]
+    async fn failures_panics_and_timeouts_are_reported() {
+        let supervisor = Supervisor::new();
+        let failed = supervisor
+            .task("failing", |_| Err::<(), _>("broken".into()))
+            .retry(RetryPolicy::attempts(2))
+            .run()
+            .await;
+        assert!(matches!(failed, Err(TaskError::Failed { .. })));
+
+        let panicked = supervisor
+            .task("panicking", |_| -> Result<(), JobError> {
+                panic!("boom {}", 1)
+            })
+            .run()
+            .await;
+        assert!(
+            matches!(panicked, Err(TaskError::Panicked { ref message, .. }) if message == "boom 1")
+        );
+
+        // Timeouts are not retried unless asked to, and the job sees its token cancelled
+        let slow = supervisor
+            .task("slow", |token| {
+                while !token.is_cancelled() {
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+                token.check()?;
+                Ok(())
+            })
+            .deadline(Duration::from_millis(20))
+            .retry(RetryPolicy::attempts(3))
+            .run()
+            .await;
+        assert!(matches!(slow, Err(TaskError::TimedOut { .. })));
+
+        let report = supervisor.report();
+        let summary: Vec<(Status, u32)> = report
+            .outcomes
+            .iter()
+            .map(|outcome| (outcome.status, outcome.attempts))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (Status::Failed, 2),
+                (Status::Panicked, 1),
+                (Status::TimedOut, 1)
+            ]
+        );
+        assert_eq!(report.failed().count(), 3);
+        assert_eq!(
+            report.outcomes[0].error.as_deref(),
+            Some("Task failing failed: broken")
+        );
+    }
+
+    #[tokio::test]
+    async fn cancel_all_stops_running_and_later_tasks() {
+        let supervisor = Arc::new(Supervisor::new());
+        let running = {
+            let supervisor = Arc::clone(&supervisor);
+            tokio::spawn(async move {
+                supervisor
+                    .task("waiting", |token| {
+                        while !token.is_cancelled() {
+                            std::thread::sleep(Duration::from_millis(1));
+                        }
+                        Ok(token.check()?)
+                    })
+                    .run()
+                    .await
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        supervisor.cancel_all();
+        assert!(matches!(
+            running.await.unwrap(),
+            Err(TaskError::Cancelled { .. })
+        ));
+
+        let later = supervisor.task("later", |_| Ok(())).run().await;
+        assert!(matches!(later, Err(TaskError::Cancelled { .. })));
+        // Cancellation is never retried
+        assert!(!RetryPolicy::attempts(3).should_retry(&later.unwrap_err()));
+    }
+
+    #[tokio::test]
+    async fn cancelled_resolves_for_a_cancel_before_and_after_waiting() {
+        let token = CancellationToken::new();
+        let waiter = tokio::spawn({
+            let token = token.clone();
+            async move { token.cancelled().await }
+        });
+        tokio::task::yield_now().await;
+        token.cancel();
+        waiter.await.unwrap();
+        token.cancelled().await;
+        assert_eq!(token.check(), Err(Cancelled));
+    }
+}
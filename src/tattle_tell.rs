@@ -0,0 +1,260 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt,
+    ops::{Deref, DerefMut},
+    panic::Location,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/*
+ * TattleTell<T> is a smart pointer that tells on everybody touching the value it wraps.
+ *
+ * Deref counts a read, DerefMut counts a write, and both remember when and where (file:line) the access happened.
+ * #[track_caller] makes Location::caller() report the line that dereferenced the pointer instead of this file,
+ * which also works for the implicit derefs Rust inserts on method calls like config.len().
+ * When the TattleTell is dropped, Drop prints the collected report if report_on_drop() was requested.
+ *
+ * Counters are atomics and the log is behind a Mutex, because deref() only gets &self.
+ * That also makes TattleTell<T> Sync whenever T is, so it can audit configuration shared through an Arc.
+ *
+ * A long-lived value is read millions of times, so the log does not keep every access: it counts them per
+ * call site, which a program has only so many of, and keeps just the last RECENT accesses in a ring buffer.
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Access {
+    pub kind: AccessKind,
+    pub elapsed: Duration, // Since the TattleTell was created
+    pub location: &'static Location<'static>,
+}
+
+#[derive(Default)]
+struct AccessLog {
+    recent: VecDeque<Access>, // At most RECENT, oldest first
+    first_access: Option<Duration>,
+    by_location: BTreeMap<&'static Location<'static>, (usize, usize)>, // (reads, writes)
+}
+
+pub struct TattleTell<T> {
+    value: T,
+    name: String,
+    created_at: Instant,
+    reads: AtomicUsize,
+    writes: AtomicUsize,
+    log: Mutex<AccessLog>,
+    loud: bool,
+    report_on_drop: bool,
+}
+
+impl<T> TattleTell<T> {
+    pub const RECENT: usize = 64;
+
+    pub fn new(name: &str, value: T) -> Self {
+        TattleTell {
+            value,
+            name: name.to_string(),
+            created_at: Instant::now(),
+            reads: AtomicUsize::new(0),
+            writes: AtomicUsize::new(0),
+            log: Mutex::new(AccessLog::default()),
+            loud: false,
+            report_on_drop: false,
+        }
+    }
+
+    // Print every access as it happens
+    pub fn loud(mut self) -> Self {
+        self.loud = true;
+        self
+    }
+
+    // Print the report when the pointer goes out of scope
+    pub fn report_on_drop(mut self) -> Self {
+        self.report_on_drop = true;
+        self
+    }
+
+    pub fn reads(&self) -> usize {
+        self.reads.load(Ordering::Relaxed)
+    }
+
+    pub fn writes(&self) -> usize {
+        self.writes.load(Ordering::Relaxed)
+    }
+
+    // The last RECENT accesses, oldest first
+    pub fn accesses(&self) -> Vec<Access> {
+        let log = self.log.lock().expect("access log poisoned");
+        log.recent.iter().copied().collect()
+    }
+
+    pub fn report(&self) -> AccessReport {
+        let log = self.log.lock().expect("access log poisoned");
+        AccessReport {
+            name: self.name.clone(),
+            type_name: std::any::type_name::<T>(),
+            reads: self.reads(),
+            writes: self.writes(),
+            first_access: log.first_access,
+            last_access: log.recent.back().map(|access| access.elapsed),
+            by_location: log
+                .by_location
+                .iter()
+                .map(|(location, &counts)| (location.to_string(), counts))
+                .collect(),
+        }
+    }
+
+    fn record(&self, kind: AccessKind, location: &'static Location<'static>) {
+        let counter = match kind {
+            AccessKind::Read => &self.reads,
+            AccessKind::Write => &self.writes,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        let access = Access {
+            kind,
+            elapsed: self.created_at.elapsed(),
+            location,
+        };
+        if self.loud {
+            println!(
+                "{} ({}) {:?} at {}",
+                self.name,
+                std::any::type_name::<T>(),
+                kind,
+                location
+            );
+        }
+        let mut log = self.log.lock().expect("access log poisoned");
+        log.first_access.get_or_insert(access.elapsed);
+        let counts = log.by_location.entry(location).or_default();
+        match kind {
+            AccessKind::Read => counts.0 += 1,
+            AccessKind::Write => counts.1 += 1,
+        }
+        if log.recent.len() == Self::RECENT {
+            log.recent.pop_front();
+        }
+        log.recent.push_back(access);
+    }
+}
+
+impl<T> Deref for TattleTell<T> {
+    type Target = T; // Represents get T after dereferencing
+
+    #[track_caller]
+    fn deref(&self) -> &T {
+        self.record(AccessKind::Read, Location::caller());
+        &self.value
+    }
+}
+
+impl<T> DerefMut for TattleTell<T> {
+    #[track_caller]
+    fn deref_mut(&mut self) -> &mut T {
+        self.record(AccessKind::Write, Location::caller());
+        &mut self.value
+    }
+}
+
+impl<T> Drop for TattleTell<T> {
+    fn drop(&mut self) {
+        if self.report_on_drop {
+            print!("{}", self.report());
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AccessReport {
+    pub name: String,
+    pub type_name: &'static str,
+    pub reads: usize,
+    pub writes: usize,
+    pub first_access: Option<Duration>,
+    pub last_access: Option<Duration>,
+    pub by_location: BTreeMap<String, (usize, usize)>, // "file:line:column" -> (reads, writes)
+}
+
+impl fmt::Display for AccessReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Access report of {} ({}): {} reads, {} writes",
+            self.name, self.type_name, self.reads, self.writes
+        )?;
+        if let (Some(first), Some(last)) = (self.first_access, self.last_access) {
+            writeln!(f, "  first access after {:?}, last after {:?}", first, last)?;
+        }
+        for (location, (reads, writes)) in &self.by_location {
+            writeln!(f, "  {}: {} reads, {} writes", location, reads, writes)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_and_writes_are_counted_per_call_site() {
+        let mut config = TattleTell::new("config", vec![1, 2, 3]);
+        let mut total = 0;
+        for _ in 0..3 {
+            total += config.len();
+        }
+        config.push(4);
+        assert_eq!(total, 9);
+        assert_eq!((config.reads(), config.writes()), (3, 1));
+
+        let report = config.report();
+        assert_eq!(report.by_location.len(), 2);
+        let mut counts: Vec<(usize, usize)> = report.by_location.values().copied().collect();
+        counts.sort();
+        assert_eq!(counts, [(0, 1), (3, 0)]);
+        assert!(report
+            .by_location
+            .keys()
+            .all(|location| location.starts_with("src/tattle_tell.rs:")));
+        assert!(report.first_access <= report.last_access);
+        assert!(report.to_string().starts_with("Access report of config"));
+    }
+
+    #[test]
+    fn only_recent_accesses_are_kept() {
+        let mut counter = TattleTell::new("counter", 0u64);
+        for _ in 0..10_000 {
+            *counter += 1;
+        }
+        let _ = *counter;
+        assert_eq!(counter.writes(), 10_000);
+
+        let accesses = counter.accesses();
+        assert_eq!(accesses.len(), TattleTell::<u64>::RECENT);
+        let last = accesses.last().unwrap();
+        assert_eq!(last.kind, AccessKind::Read);
+        assert_eq!(last.location.file(), "src/tattle_tell.rs");
+        assert_eq!(counter.report().by_location.len(), 2);
+    }
+
+    #[test]
+    fn an_untouched_value_has_an_empty_report() {
+        let quiet = TattleTell::new("quiet", ());
+        let report = quiet.report();
+        assert_eq!((report.reads, report.writes), (0, 0));
+        assert_eq!(report.first_access, None);
+        assert!(report.by_location.is_empty());
+    }
+}
@@ -0,0 +1,160 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::time::Instant;
+
+/*
+ * A shared log of "who did what when", for showing how concurrent tasks interleave.
+ *
+ * Every clone writes into the same list, so each spawned task gets its own clone. The lock is a std Mutex:
+ * it is only held for a push and never across an .await, which is exactly when a std Mutex is fine in async code.
+ * Times are measured with tokio's Instant, so they follow tokio::time::pause() in a paused runtime as well.
+ */
+
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub at: Duration, // Since the timeline was created
+    pub task: String,
+    pub message: String,
+}
+
+#[derive(Clone)]
+pub struct Timeline {
+    started_at: Instant,
+    events: Arc<Mutex<Vec<Event>>>,
+}
+
+impl Timeline {
+    pub fn new() -> Self {
+        Timeline {
+            started_at: Instant::now(),
+            events: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    pub fn record(&self, task: &str, message: impl Into<String>) {
+        let event = Event {
+            at: self.started_at.elapsed(),
+            task: task.to_string(),
+            message: message.into(),
+        };
+        self.events.lock().expect("timeline poisoned").push(event);
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    // Events in the order they happened
+    pub fn events(&self) -> Vec<Event> {
+        let mut events = self.events.lock().expect("timeline poisoned").clone();
+        events.sort_by_key(|event| event.at); // Stable, so events recorded at the same instant keep their order
+        events
+    }
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Timeline::new()
+    }
+}
+
+impl fmt::Display for Timeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let events = self.events();
+        let width = events
+            .iter()
+            .map(|event| event.task.len())
+            .max()
+            .unwrap_or(0);
+        for event in events {
+            writeln!(
+                f,
+                "  +{:>5}ms  {:<width$}  {}",
+                event.at.as_millis(),
+                event.task,
+                event.message,
+                width = width
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(at: u64, task: &str, message: &str) -> Event {
+        Event {
+            at: Duration::from_millis(at),
+            task: task.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    fn messages(timeline: &Timeline) -> Vec<String> {
+        timeline
+            .events()
+            .into_iter()
+            .map(|event| format!("{} {}", event.task, event.message))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn clones_share_one_log_in_time_order() {
+        let timeline = Timeline::new();
+        let slow = tokio::spawn({
+            let timeline = timeline.clone();
+            async move {
+                timeline.record("slow", "start");
+                tokio::time::sleep(Duration::from_millis(60)).await;
+                timeline.record("slow", "done");
+            }
+        });
+        let fast = tokio::spawn({
+            let timeline = timeline.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                timeline.record("fast", "done");
+            }
+        });
+        slow.await.unwrap();
+        fast.await.unwrap();
+
+        assert_eq!(
+            messages(&timeline),
+            ["slow start", "fast done", "slow done"]
+        );
+        let events = timeline.events();
+        assert!(events[2].at >= Duration::from_millis(60));
+        assert!(timeline.elapsed() >= events[2].at);
+    }
+
+    #[test]
+    fn events_recorded_at_the_same_time_keep_their_order() {
+        let timeline = Timeline::default();
+        *timeline.events.lock().unwrap() = vec![
+            event(5, "b", "second"),
+            event(0, "a", "first"),
+            event(5, "c", "third"),
+        ];
+        assert_eq!(messages(&timeline), ["a first", "b second", "c third"]);
+    }
+
+    #[test]
+    fn display_aligns_the_task_names() {
+        let timeline = Timeline::new();
+        *timeline.events.lock().unwrap() = vec![
+            event(1200, "worker", "finished"),
+            event(3, "io", "read 4 bytes"),
+        ];
+        assert_eq!(
+            timeline.to_string(),
+            "  +    3ms  io      read 4 bytes\n  + 1200ms  worker  finished\n"
+        );
+        assert_eq!(Timeline::new().to_string(), "");
+    }
+}
@@ -0,0 +1,682 @@
+use std::{
+    error::Error,
+    fmt,
+    net::{Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
+
+/*
+ * URLs like https://user.example:8443/a/b?q=1#top, parsed into their parts and normalized,
+ * so two spellings of the same address compare (and hash) equal:
+ *
+ * - scheme and host are lowercased, a missing scheme means https: "chat.openai.com" is https://chat.openai.com/
+ * - the default port of the scheme is dropped: https://example.com:443 is https://example.com/
+ * - an empty path is "/", and "." and ".." segments are resolved: /a/./b/../c is /a/c
+ * - percent-escapes are uppercased, escapes of letters, digits and -._~ are decoded, and characters that
+ *   cannot appear in a URL (spaces, non-ASCII, ...) are escaped: /a b is /a%20b
+ * - an empty query or fragment ("?" or "#" with nothing after) is dropped
+ *
+ * Hosts are a domain name, an IPv4 address or an IPv6 address in brackets. Internationalized domain names
+ * are stored the way DNS needs them, each non-ASCII label in punycode (RFC 3492): bücher.example is
+ * xn--bcher-kva.example. unicode_host() turns them back for display. The IDNA mapping is simplified to
+ * lowercasing, there is no Unicode normalization.
+ *
+ * Only URLs with a host are supported, so mailto: or data: URLs are rejected, and so are user:password@ credentials.
+ */
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Host {
+    Domain(String), // ASCII only, punycode for internationalized labels
+    Ipv4(Ipv4Addr),
+    Ipv6(Ipv6Addr),
+}
+
+impl fmt::Display for Host {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Host::Domain(domain) => f.pad(domain),
+            Host::Ipv4(ip) => f.pad(&ip.to_string()),
+            Host::Ipv6(ip) => f.pad(&format!("[{}]", ip)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UrlError {
+    Empty,
+    InvalidScheme(String),
+    Credentials,
+    MissingHost,
+    InvalidHost { host: String, reason: String },
+    InvalidPort(String),
+}
+
+impl fmt::Display for UrlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UrlError::Empty => write!(f, "The URL is empty"),
+            UrlError::InvalidScheme(scheme) => write!(f, "{:?} is not a valid URL scheme", scheme),
+            UrlError::Credentials => {
+                write!(f, "URLs with user names or passwords are not supported")
+            }
+            UrlError::MissingHost => write!(f, "The URL has no host"),
+            UrlError::InvalidHost { host, reason } => {
+                write!(f, "{:?} is not a valid host: {}", host, reason)
+            }
+            UrlError::InvalidPort(port) => write!(f, "{:?} is not a valid port", port),
+        }
+    }
+}
+
+impl Error for UrlError {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Url {
+    scheme: String,
+    host: Host,
+    port: Option<u16>, // None for the default port of the scheme
+    path: String,
+    query: Option<String>,
+    fragment: Option<String>,
+}
+
+impl Url {
+    pub fn parse(input: &str) -> Result<Url, UrlError> {
+        let input = input.trim();
+        if input.is_empty() {
+            return Err(UrlError::Empty);
+        }
+        // The "://" of a URL inside the query, like example.com/?next=https://..., does not end a scheme
+        let scheme = input
+            .split_once("://")
+            .filter(|(scheme, _)| !scheme.contains(['/', '?', '#']));
+        let (scheme, rest) = match scheme {
+            Some((scheme, rest)) => (parse_scheme(scheme)?, rest),
+            None => ("https".to_string(), input),
+        };
+
+        // The fragment goes first, a '?' in it is not the start of a query
+        let (rest, fragment) = match rest.split_once('#') {
+            Some((rest, fragment)) => (rest, Some(fragment)),
+            None => (rest, None),
+        };
+        let (rest, query) = match rest.split_once('?') {
+            Some((rest, query)) => (rest, Some(query)),
+            None => (rest, None),
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(slash) => rest.split_at(slash),
+            None => (rest, ""),
+        };
+        if authority.contains('@') {
+            return Err(UrlError::Credentials);
+        }
+        let (host, port) = parse_authority(authority)?;
+
+        let default_port = default_port(&scheme);
+        let path = match normalize_escapes(path, "/") {
+            path if path.is_empty() => "/".to_string(),
+            path => remove_dot_segments(&path),
+        };
+        let optional = |part: Option<&str>| {
+            part.filter(|part| !part.is_empty())
+                .map(|part| normalize_escapes(part, "/?"))
+        };
+        Ok(Url {
+            port: port.filter(|&port| Some(port) != default_port),
+            scheme,
+            host,
+            path,
+            query: optional(query),
+            fragment: optional(fragment),
+        })
+    }
+
+    pub fn scheme(&self) -> &str {
+        &self.scheme
+    }
+
+    pub fn host(&self) -> &Host {
+        &self.host
+    }
+
+    // The port to connect to: the one in the URL, or else the default of the scheme
+    pub fn port_or_default(&self) -> Option<u16> {
+        self.port.or_else(|| default_port(&self.scheme))
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn query(&self) -> Option<&str> {
+        self.query.as_deref()
+    }
+
+    pub fn fragment(&self) -> Option<&str> {
+        self.fragment.as_deref()
+    }
+
+    // The host and the port unless it is the default, what an HTTP Host header carries
+    pub fn authority(&self) -> String {
+        match self.port {
+            Some(port) => format!("{}:{}", self.host, port),
+            None => self.host.to_string(),
+        }
+    }
+
+    // The path and query, what an HTTP request line asks for. The fragment never leaves the browser
+    pub fn request_target(&self) -> String {
+        match &self.query {
+            Some(query) => format!("{}?{}", self.path, query),
+            None => self.path.clone(),
+        }
+    }
+
+    // The host as people write it: punycode labels decoded, e.g. bücher.example
+    pub fn unicode_host(&self) -> String {
+        match &self.host {
+            Host::Domain(domain) => domain
+                .split('.')
+                .map(|label| match label.strip_prefix("xn--") {
+                    Some(encoded) => punycode_decode(encoded).unwrap_or_else(|| label.to_string()),
+                    None => label.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join("."),
+            host => host.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Url {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}://{}", self.scheme, self.host)?;
+        if let Some(port) = self.port {
+            write!(f, ":{}", port)?;
+        }
+        write!(f, "{}", self.path)?;
+        if let Some(query) = &self.query {
+            write!(f, "?{}", query)?;
+        }
+        if let Some(fragment) = &self.fragment {
+            write!(f, "#{}", fragment)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Url {
+    type Err = UrlError;
+
+    fn from_str(input: &str) -> Result<Url, UrlError> {
+        Url::parse(input)
+    }
+}
+
+fn default_port(scheme: &str) -> Option<u16> {
+    match scheme {
+        "http" | "ws" => Some(80),
+        "https" | "wss" => Some(443),
+        "ftp" => Some(21),
+        _ => None,
+    }
+}
+
+// A letter, then letters, digits, '+', '-' or '.'
+fn parse_scheme(scheme: &str) -> Result<String, UrlError> {
+    let mut chars = scheme.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c));
+    if !valid {
+        return Err(UrlError::InvalidScheme(scheme.to_string()));
+    }
+    Ok(scheme.to_ascii_lowercase())
+}
+
+fn parse_authority(authority: &str) -> Result<(Host, Option<u16>), UrlError> {
+    let invalid_host = |reason: &str| UrlError::InvalidHost {
+        host: authority.to_string(),
+        reason: reason.to_string(),
+    };
+    // IPv6 addresses contain colons themselves, so they come in brackets
+    let (host, port) = if let Some(rest) = authority.strip_prefix('[') {
+        let (ip, after) = rest
+            .split_once(']')
+            .ok_or_else(|| invalid_host("missing ]"))?;
+        let ip = ip
+            .parse::<Ipv6Addr>()
+            .map_err(|_| invalid_host("not an IPv6 address"))?;
+        let port = match after {
+            "" => None,
+            after => Some(
+                after
+                    .strip_prefix(':')
+                    .ok_or_else(|| invalid_host("text after the IPv6 address"))?,
+            ),
+        };
+        (Host::Ipv6(ip), port)
+    } else {
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        };
+        if host.is_empty() {
+            return Err(UrlError::MissingHost);
+        }
+        match host.parse::<Ipv4Addr>() {
+            Ok(ip) => (Host::Ipv4(ip), port),
+            Err(_) => (Host::Domain(parse_domain(host)?), port),
+        }
+    };
+
+    // "example.com:" has an empty port, which means the default one
+    let port = match port {
+        None | Some("") => None,
+        Some(port) => Some(
+            port.parse::<u16>()
+                .map_err(|_| UrlError::InvalidPort(port.to_string()))?,
+        ),
+    };
+    Ok((host, port))
+}
+
+fn parse_domain(domain: &str) -> Result<String, UrlError> {
+    let invalid = |reason: String| UrlError::InvalidHost {
+        host: domain.to_string(),
+        reason,
+    };
+    // A trailing dot only says the name is fully qualified
+    let trimmed = domain.strip_suffix('.').unwrap_or(domain);
+    let mut labels = Vec::new();
+    for label in trimmed.split('.') {
+        let label = label.to_lowercase();
+        // Encoding never makes a label shorter, so a long one is rejected before the punycode arithmetic runs
+        if label.is_empty() || label.chars().count() > 63 {
+            return Err(invalid(
+                "every label must be 1 to 63 characters long".to_string(),
+            ));
+        }
+        let label = if label.is_ascii() {
+            if let Some(encoded) = label.strip_prefix("xn--") {
+                punycode_decode(encoded)
+                    .ok_or_else(|| invalid(format!("{label:?} is not valid punycode")))?;
+            }
+            label
+        } else {
+            let encoded = punycode_encode(&label)
+                .ok_or_else(|| invalid(format!("{label:?} cannot be encoded as punycode")))?;
+            format!("xn--{encoded}")
+        };
+
+        if label.is_empty() || label.len() > 63 {
+            return Err(invalid(
+                "every label must be 1 to 63 characters long".to_string(),
+            ));
+        }
+        if !label
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+        {
+            return Err(invalid(format!(
+                "{label:?} contains characters a domain cannot have"
+            )));
+        }
+        if label.starts_with('-') || label.ends_with('-') {
+            return Err(invalid(format!("{label:?} starts or ends with a hyphen")));
+        }
+        labels.push(label);
+    }
+    let domain = labels.join(".");
+    if domain.len() > 253 {
+        return Err(invalid("longer than 253 characters".to_string()));
+    }
+    Ok(domain)
+}
+
+// Letters, digits and -._~ never need an escape
+fn is_unreserved(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"-._~".contains(&byte)
+}
+
+// Uppercases escapes, decodes needless ones and escapes what may not appear as it is.
+// `allowed` are the delimiters that are fine in this part of the URL, e.g. '/' in a path.
+fn normalize_escapes(part: &str, allowed: &str) -> String {
+    let bytes = part.as_bytes();
+    let hex = |byte: u8| (byte as char).to_digit(16);
+    let mut normalized = String::with_capacity(part.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let byte = bytes[i];
+        let escaped = match (
+            byte,
+            bytes.get(i + 1).copied().and_then(hex),
+            bytes.get(i + 2).copied().and_then(hex),
+        ) {
+            (b'%', Some(high), Some(low)) => {
+                i += 2;
+                Some((high * 16 + low) as u8)
+            }
+            _ => None,
+        };
+        match escaped {
+            Some(decoded) if is_unreserved(decoded) => normalized.push(decoded as char),
+            Some(decoded) => normalized.push_str(&format!("%{:02X}", decoded)),
+            // A '%' that does not start an escape has to be escaped itself
+            None if is_unreserved(byte)
+                || b"!$&'()*+,;=:@".contains(&byte)
+                || allowed.as_bytes().contains(&byte) =>
+            {
+                normalized.push(byte as char)
+            }
+            None => normalized.push_str(&format!("%{:02X}", byte)),
+        }
+        i += 1;
+    }
+    normalized
+}
+
+// RFC 3986 section 5.2.4, for a path that starts with '/'
+fn remove_dot_segments(path: &str) -> String {
+    let segments: Vec<&str> = path[1..].split('/').collect();
+    let mut output: Vec<&str> = Vec::new();
+    for (index, segment) in segments.iter().enumerate() {
+        match *segment {
+            "." | ".." => {
+                if *segment == ".." {
+                    output.pop();
+                }
+                // /a/b/.. is the directory /a/, so it keeps its trailing slash
+                if index == segments.len() - 1 {
+                    output.push("");
+                }
+            }
+            segment => output.push(segment),
+        }
+    }
+    format!("/{}", output.join("/"))
+}
+
+// Punycode parameters, RFC 3492 section 5
+const BASE: u32 = 36;
+const T_MIN: u32 = 1;
+const T_MAX: u32 = 26;
+const SKEW: u32 = 38;
+const DAMP: u32 = 700;
+const INITIAL_BIAS: u32 = 72;
+const INITIAL_N: u32 = 128;
+
+fn adapt(delta: u32, points: u32, first: bool) -> u32 {
+    let mut delta = if first { delta / DAMP } else { delta / 2 };
+    delta += delta / points;
+    let mut k = 0;
+    while delta > ((BASE - T_MIN) * T_MAX) / 2 {
+        delta /= BASE - T_MIN;
+        k += BASE;
+    }
+    k + (BASE - T_MIN + 1) * delta / (delta + SKEW)
+}
+
+// The threshold of the digit at position k, clamped to T_MIN..=T_MAX
+fn threshold(k: u32, bias: u32) -> u32 {
+    k.saturating_sub(bias).clamp(T_MIN, T_MAX)
+}
+
+fn encode_digit(digit: u32) -> char {
+    match digit {
+        0..=25 => (b'a' + digit as u8) as char,
+        _ => (b'0' + (digit - 26) as u8) as char,
+    }
+}
+
+fn decode_digit(c: char) -> Option<u32> {
+    match c {
+        'a'..='z' => Some(c as u32 - 'a' as u32),
+        'A'..='Z' => Some(c as u32 - 'A' as u32),
+        '0'..='9' => Some(c as u32 - '0' as u32 + 26),
+        _ => None,
+    }
+}
+
+// "bücher" -> "bcher-kva", without the "xn--" prefix. None when the arithmetic would overflow (RFC 3492 6.4)
+pub fn punycode_encode(label: &str) -> Option<String> {
+    let code_points: Vec<u32> = label.chars().map(u32::from).collect();
+    let mut output: String = label.chars().filter(char::is_ascii).collect();
+    let basic = output.len() as u32;
+    if basic > 0 {
+        output.push('-');
+    }
+
+    let (mut n, mut delta, mut bias, mut handled) = (INITIAL_N, 0u32, INITIAL_BIAS, basic);
+    while (handled as usize) < code_points.len() {
+        // The smallest code point not handled yet
+        let next = *code_points
+            .iter()
+            .filter(|&&c| c >= n)
+            .min()
+            .expect("an unhandled code point");
+        delta = delta.checked_add((next - n).checked_mul(handled + 1)?)?;
+        n = next;
+        for &c in &code_points {
+            if c < n {
+                delta = delta.checked_add(1)?;
+            }
+            if c == n {
+                let mut q = delta;
+                let mut k = BASE;
+                loop {
+                    let t = threshold(k, bias);
+                    if q < t {
+                        break;
+                    }
+                    output.push(encode_digit(t + (q - t) % (BASE - t)));
+                    q = (q - t) / (BASE - t);
+                    k += BASE;
+                }
+                output.push(encode_digit(q));
+                bias = adapt(delta, handled + 1, handled == basic);
+                delta = 0;
+                handled += 1;
+            }
+        }
+        delta = delta.checked_add(1)?;
+        n += 1;
+    }
+    Some(output)
+}
+
+// "bcher-kva" -> "bücher", None if it is not valid punycode
+pub fn punycode_decode(encoded: &str) -> Option<String> {
+    let (basic, extended) = match encoded.rfind('-') {
+        Some(dash) => (&encoded[..dash], &encoded[dash + 1..]),
+        None => ("", encoded),
+    };
+    if !basic.is_ascii() {
+        return None;
+    }
+    let mut output: Vec<char> = basic.chars().collect();
+    let (mut n, mut i, mut bias) = (INITIAL_N, 0u32, INITIAL_BIAS);
+    let mut digits = extended.chars();
+
+    while !digits.as_str().is_empty() {
+        let old_i = i;
+        let mut weight = 1u32;
+        let mut k = BASE;
+        loop {
+            let digit = decode_digit(digits.next()?)?;
+            i = i.checked_add(digit.checked_mul(weight)?)?;
+            let t = threshold(k, bias);
+            if digit < t {
+                break;
+            }
+            weight = weight.checked_mul(BASE - t)?;
+            k += BASE;
+        }
+        let length = output.len() as u32 + 1;
+        bias = adapt(i - old_i, length, old_i == 0);
+        n = n.checked_add(i / length)?;
+        i %= length;
+        output.insert(i as usize, char::from_u32(n)?);
+        i += 1;
+    }
+    Some(output.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The sample strings of RFC 3492 section 7.1
+    const SAMPLES: [(&str, &str); 10] = [
+        ("他们为什么不说中文", "ihqwcrb4cv8a8dqg056pqjye"),
+        ("他們爲什麽不說中文", "ihqwctvzc91f659drss3x8bo0yb"),
+        (
+            "почемужеонинеговорятпорусски",
+            "b1abfaaepdrnnbgefbadotcwatmq2g4l",
+        ),
+        (
+            "PorquénopuedensimplementehablarenEspañol",
+            "PorqunopuedensimplementehablarenEspaol-fmd56a",
+        ),
+        ("3年B組金八先生", "3B-ww4c5e180e575a65lsy2b"),
+        (
+            "安室奈美恵-with-SUPER-MONKEYS",
+            "-with-SUPER-MONKEYS-pc58ag80a8qai00g7n9n",
+        ),
+        ("MajiでKoiする5秒前", "MajiKoi5-783gue6qz075azm5e"),
+        ("パフィーdeルンバ", "de-jg4avhby1noc0d"),
+        ("そのスピードで", "d9juau41awczczp"),
+        (
+            "なぜみんな日本語を話してくれないのか",
+            "n8jok5ay5dzabd5bym9f0cm5685rrjetr6pdxa",
+        ),
+    ];
+
+    fn normalized(input: &str) -> String {
+        Url::parse(input).unwrap().to_string()
+    }
+
+    #[test]
+    fn punycode_matches_the_rfc_samples() {
+        for (unicode, encoded) in SAMPLES {
+            assert_eq!(punycode_encode(unicode).as_deref(), Some(encoded));
+            assert_eq!(punycode_decode(encoded).as_deref(), Some(unicode));
+        }
+    }
+
+    #[test]
+    fn punycode_decode_rejects_garbage() {
+        assert_eq!(punycode_decode("bcher-kv!"), None);
+        assert_eq!(punycode_decode("99999999999999"), None); // Overflows
+    }
+
+    #[test]
+    fn long_unicode_labels_are_an_error_not_a_panic() {
+        let url = format!("https://{}\u{10FFFF}.com/", "a".repeat(6000));
+        assert!(matches!(
+            Url::parse(&url),
+            Err(UrlError::InvalidHost { .. })
+        ));
+        let url = format!("https://{}.com/", "ü".repeat(64));
+        assert!(matches!(
+            Url::parse(&url),
+            Err(UrlError::InvalidHost { .. })
+        ));
+    }
+
+    #[test]
+    fn scheme_and_host_are_lowercased_and_https_is_the_default() {
+        assert_eq!(
+            normalized("HTTP://Example.COM/Path"),
+            "http://example.com/Path"
+        );
+        assert_eq!(normalized("chat.openai.com"), "https://chat.openai.com/");
+    }
+
+    #[test]
+    fn default_ports_are_dropped() {
+        assert_eq!(
+            normalized("https://example.com:443"),
+            "https://example.com/"
+        );
+        assert_eq!(normalized("http://example.com:80/"), "http://example.com/");
+        assert_eq!(
+            normalized("http://example.com:8080/"),
+            "http://example.com:8080/"
+        );
+        assert_eq!(
+            Url::parse("http://example.com").unwrap().port_or_default(),
+            Some(80)
+        );
+    }
+
+    #[test]
+    fn dot_segments_are_resolved() {
+        assert_eq!(
+            normalized("example.com/a/./b/../c"),
+            "https://example.com/a/c"
+        );
+        assert_eq!(normalized("example.com/../a"), "https://example.com/a");
+    }
+
+    #[test]
+    fn percent_escapes_are_normalized() {
+        assert_eq!(
+            normalized("example.com/%7euser/%2f"),
+            "https://example.com/~user/%2F"
+        );
+        assert_eq!(normalized("example.com/a b"), "https://example.com/a%20b");
+    }
+
+    #[test]
+    fn empty_query_and_fragment_are_dropped() {
+        assert_eq!(normalized("example.com/?#"), "https://example.com/");
+        let url = Url::parse("example.com/p?q=1#top").unwrap();
+        assert_eq!((url.query(), url.fragment()), (Some("q=1"), Some("top")));
+        assert_eq!(url.request_target(), "/p?q=1");
+    }
+
+    #[test]
+    fn spellings_of_the_same_address_are_equal() {
+        assert_eq!(
+            Url::parse("https://WWW.Bing.com:443").unwrap(),
+            Url::parse("www.bing.com/").unwrap()
+        );
+    }
+
+    #[test]
+    fn internationalized_hosts_are_stored_in_punycode() {
+        let url = Url::parse("https://Bücher.example/").unwrap();
+        assert_eq!(
+            url.host(),
+            &Host::Domain("xn--bcher-kva.example".to_string())
+        );
+        assert_eq!(url.unicode_host(), "bücher.example");
+    }
+
+    #[test]
+    fn ip_hosts() {
+        assert_eq!(
+            Url::parse("142.251.33.100").unwrap().host(),
+            &Host::Ipv4(Ipv4Addr::new(142, 251, 33, 100))
+        );
+        assert_eq!(normalized("http://[::1]:8080/"), "http://[::1]:8080/");
+    }
+
+    #[test]
+    fn unsupported_urls_are_rejected() {
+        assert_eq!(Url::parse("  "), Err(UrlError::Empty));
+        assert_eq!(
+            Url::parse("https://user:pw@example.com"),
+            Err(UrlError::Credentials)
+        );
+        assert!(matches!(
+            Url::parse("https://-bad-.com"),
+            Err(UrlError::InvalidHost { .. })
+        ));
+        assert!(matches!(
+            Url::parse("https://example.com:99999"),
+            Err(UrlError::InvalidPort(_))
+        ));
+    }
+}
@@ -0,0 +1,356 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    error::Error,
+    fmt, hint,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Condvar, Mutex, RwLock,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/*
+ * A fixed size pool of std threads sharing one work queue.
+ *
+ * - The queue is an Arc<Mutex<..>>: every worker pops from it, so only one of them may touch it at a time.
+ *   A Condvar lets idle workers sleep until a job arrives instead of spinning on the lock.
+ * - Results go into an Arc<RwLock<HashMap<..>>>: workers write a result once, while any number of readers
+ *   can look at the results at the same time.
+ * - shutdown() is graceful: no new jobs are accepted, queued jobs still run, then every worker is joined.
+ *   Dropping the pool does the same.
+ */
+
+pub type JobId = usize;
+
+type Job<T> = Box<dyn FnOnce() -> T + Send + 'static>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PoolError {
+    ShuttingDown,
+    JobPanicked { id: JobId },
+}
+
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PoolError::ShuttingDown => write!(f, "the pool is shutting down"),
+            PoolError::JobPanicked { id } => write!(f, "job #{} panicked", id),
+        }
+    }
+}
+
+impl Error for PoolError {}
+
+struct Queue<T> {
+    jobs: VecDeque<(JobId, Job<T>)>,
+    next_id: JobId,
+    shutting_down: bool,
+}
+
+struct Shared<T> {
+    queue: Mutex<Queue<T>>,
+    job_available: Condvar,
+    results: Arc<RwLock<HashMap<JobId, Result<T, PoolError>>>>,
+}
+
+pub struct WorkerPool<T> {
+    shared: Arc<Shared<T>>,
+    workers: Vec<JoinHandle<usize>>, // Each worker returns how many jobs it ran
+}
+
+impl<T: Send + Sync + 'static> WorkerPool<T> {
+    pub fn new(threads: usize) -> Self {
+        assert!(threads > 0, "a worker pool needs at least one thread");
+
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue {
+                jobs: VecDeque::new(),
+                next_id: 0,
+                shutting_down: false,
+            }),
+            job_available: Condvar::new(),
+            results: Arc::new(RwLock::new(HashMap::new())),
+        });
+        let workers = (0..threads)
+            .map(|id| {
+                let shared = Arc::clone(&shared);
+                thread::Builder::new()
+                    .name(format!("worker-{id}"))
+                    .spawn(move || work(&shared))
+                    .expect("Failed to spawn a worker thread")
+            })
+            .collect();
+
+        WorkerPool { shared, workers }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.workers.len()
+    }
+
+    pub fn submit<F>(&self, job: F) -> Result<JobId, PoolError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let mut queue = self.shared.queue.lock().expect("queue poisoned");
+        if queue.shutting_down {
+            return Err(PoolError::ShuttingDown);
+        }
+        let id = queue.next_id;
+        queue.next_id += 1;
+        queue.jobs.push_back((id, Box::new(job)));
+        drop(queue); // Release the lock before waking a worker that will immediately want it
+
+        self.shared.job_available.notify_one();
+        Ok(id)
+    }
+
+    // A handle on the results that stays valid after the pool is gone
+    pub fn results(&self) -> Arc<RwLock<HashMap<JobId, Result<T, PoolError>>>> {
+        Arc::clone(&self.shared.results)
+    }
+
+    // Runs everything still queued, joins the workers and returns how many jobs each of them ran
+    pub fn shutdown(mut self) -> Vec<usize> {
+        self.stop()
+    }
+}
+
+impl<T> WorkerPool<T> {
+    fn stop(&mut self) -> Vec<usize> {
+        self.shared
+            .queue
+            .lock()
+            .expect("queue poisoned")
+            .shutting_down = true;
+        self.shared.job_available.notify_all();
+
+        self.workers
+            .drain(..)
+            .map(|worker| worker.join().expect("workers catch job panics"))
+            .collect()
+    }
+}
+
+impl<T> Drop for WorkerPool<T> {
+    fn drop(&mut self) {
+        // Nothing left to do when shutdown() already ran
+        self.stop();
+    }
+}
+
+fn work<T>(shared: &Shared<T>) -> usize {
+    let mut jobs_done = 0;
+    loop {
+        let (id, job) = {
+            let mut queue = shared.queue.lock().expect("queue poisoned");
+            // wait() releases the lock while sleeping. Loop because a wakeup does not guarantee there is a job
+            loop {
+                if let Some(job) = queue.jobs.pop_front() {
+                    break job;
+                }
+                if queue.shutting_down {
+                    return jobs_done;
+                }
+                queue = shared.job_available.wait(queue).expect("queue poisoned");
+            }
+        }; // The queue lock is released here, the job runs without holding it
+
+        // A panicking job must not take the worker down with it
+        let result =
+            panic::catch_unwind(AssertUnwindSafe(job)).map_err(|_| PoolError::JobPanicked { id });
+        shared
+            .results
+            .write()
+            .expect("results poisoned")
+            .insert(id, result);
+        jobs_done += 1;
+    }
+}
+
+/*
+ * Contention benchmark: `threads` threads share one counter and perform `operations` each,
+ * one write for every `reads_per_write` reads. The same workload runs against a Mutex, a RwLock and an atomic.
+ *
+ * A Mutex serializes readers too, a RwLock lets readers overlap but has more bookkeeping,
+ * and an atomic needs no lock at all but only works for simple values like integers.
+ */
+pub struct BenchmarkResult {
+    pub name: &'static str,
+    pub elapsed: Duration,
+    pub final_value: u64,
+}
+
+pub fn contention_benchmark(
+    threads: usize,
+    operations: usize,
+    reads_per_write: usize,
+) -> Vec<BenchmarkResult> {
+    let is_write = move |i: usize| i.is_multiple_of(reads_per_write + 1);
+    let mut results = Vec::new();
+
+    let mutex = Arc::new(Mutex::new(0u64));
+    let counter = Arc::clone(&mutex);
+    let elapsed = run_threads(threads, move || {
+        for i in 0..operations {
+            let mut value = counter.lock().expect("counter poisoned");
+            if is_write(i) {
+                *value += 1;
+            } else {
+                hint::black_box(*value); // Keeps the compiler from optimizing the read away
+            }
+        }
+    });
+    results.push(BenchmarkResult {
+        name: "Mutex",
+        elapsed,
+        final_value: *mutex.lock().expect("counter poisoned"),
+    });
+
+    let rwlock = Arc::new(RwLock::new(0u64));
+    let counter = Arc::clone(&rwlock);
+    let elapsed = run_threads(threads, move || {
+        for i in 0..operations {
+            if is_write(i) {
+                *counter.write().expect("counter poisoned") += 1;
+            } else {
+                hint::black_box(*counter.read().expect("counter poisoned"));
+            }
+        }
+    });
+    results.push(BenchmarkResult {
+        name: "RwLock",
+        elapsed,
+        final_value: *rwlock.read().expect("counter poisoned"),
+    });
+
+    let atomic = Arc::new(AtomicU64::new(0));
+    let counter = Arc::clone(&atomic);
+    let elapsed = run_threads(threads, move || {
+        for i in 0..operations {
+            if is_write(i) {
+                counter.fetch_add(1, Ordering::Relaxed);
+            } else {
+                hint::black_box(counter.load(Ordering::Relaxed));
+            }
+        }
+    });
+    results.push(BenchmarkResult {
+        name: "Atomic",
+        elapsed,
+        final_value: atomic.load(Ordering::Relaxed),
+    });
+
+    results
+}
+
+// Runs the same closure on `threads` threads at once and returns the wall time until all of them finished
+fn run_threads<F>(threads: usize, f: F) -> Duration
+where
+    F: Fn() + Send + Sync + 'static,
+{
+    let f = Arc::new(f);
+    let started_at = Instant::now();
+    let handles: Vec<JoinHandle<()>> = (0..threads)
+        .map(|_| {
+            let f = Arc::clone(&f);
+            thread::spawn(move || f())
+        })
+        .collect();
+    for handle in handles {
+        handle.join().expect("benchmark thread panicked");
+    }
+
+    started_at.elapsed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_job_runs_once_and_leaves_its_result() {
+        let pool = WorkerPool::new(4);
+        assert_eq!(pool.threads(), 4);
+        let ids: Vec<JobId> = (0..20)
+            .map(|n: u64| pool.submit(move || n * n).unwrap())
+            .collect();
+        assert_eq!(ids, (0..20).collect::<Vec<_>>());
+
+        let results = pool.results();
+        let jobs_done = pool.shutdown();
+        assert_eq!(jobs_done.len(), 4);
+        assert_eq!(jobs_done.iter().sum::<usize>(), 20);
+
+        let results = results.read().unwrap();
+        assert_eq!(results.len(), 20);
+        for id in ids {
+            assert_eq!(results[&id], Ok(id as u64 * id as u64));
+        }
+    }
+
+    #[test]
+    fn shutdown_still_runs_the_queued_jobs() {
+        let pool = WorkerPool::new(1);
+        for _ in 0..5 {
+            pool.submit(|| thread::sleep(Duration::from_millis(10)))
+                .unwrap();
+        }
+        let results = pool.results();
+        assert_eq!(pool.shutdown(), [5]);
+        assert_eq!(results.read().unwrap().len(), 5);
+    }
+
+    #[test]
+    fn dropping_the_pool_waits_for_the_jobs() {
+        let results = {
+            let pool = WorkerPool::new(2);
+            for n in 0..3 {
+                pool.submit(move || {
+                    thread::sleep(Duration::from_millis(10));
+                    n
+                })
+                .unwrap();
+            }
+            pool.results()
+        };
+        assert_eq!(results.read().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn a_panicking_job_does_not_take_its_worker_down() {
+        let pool = WorkerPool::new(1);
+        let bad = pool.submit(|| panic!("job failed on purpose")).unwrap();
+        let good = pool.submit(|| "fine").unwrap();
+        let results = pool.results();
+        assert_eq!(pool.shutdown(), [2]);
+
+        let results = results.read().unwrap();
+        assert_eq!(results[&bad], Err(PoolError::JobPanicked { id: bad }));
+        assert_eq!(results[&good], Ok("fine"));
+    }
+
+    #[test]
+    fn no_jobs_are_accepted_while_shutting_down() {
+        let pool = WorkerPool::<()>::new(1);
+        pool.shared.queue.lock().unwrap().shutting_down = true;
+        assert_eq!(pool.submit(|| ()), Err(PoolError::ShuttingDown));
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one thread")]
+    fn a_pool_needs_a_thread() {
+        WorkerPool::<()>::new(0);
+    }
+
+    #[test]
+    fn every_benchmark_counts_the_same_writes() {
+        // Writes at i = 0 and 5 out of 10 operations, on each of the 4 threads
+        let results = contention_benchmark(4, 10, 4);
+        let names: Vec<&str> = results.iter().map(|result| result.name).collect();
+        assert_eq!(names, ["Mutex", "RwLock", "Atomic"]);
+        assert!(results.iter().all(|result| result.final_value == 8));
+    }
+}